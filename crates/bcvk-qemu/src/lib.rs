@@ -5,14 +5,18 @@
 //!
 //! # Features
 //!
-//! - **QEMU VM Management**: Launch VMs via direct kernel boot or ISO boot,
-//!   with virtio devices and automatic resource cleanup
+//! - **QEMU VM Management**: Launch VMs via direct kernel boot, UEFI boot
+//!   from an ESP image, or ISO boot, with virtio devices and automatic
+//!   resource cleanup
 //! - **VirtioFS Mounts**: Spawn and manage virtiofsd processes for sharing host
 //!   directories with the guest
+//! - **Software TPM**: Attach a TPM 2.0 emulated by swtpm
 //! - **SMBIOS Credentials**: Inject systemd credentials via QEMU SMBIOS interface
 //!   for passwordless authentication and configuration
 //! - **VirtIO Serial**: Configure virtio-serial devices for guest-to-host
 //!   communication (e.g., log streaming)
+//! - **QMP Control**: Every VM gets a QMP socket, with an async client for
+//!   querying status, graceful powerdown, device hotplug and events
 //!
 //! # Example
 //!
@@ -44,6 +48,8 @@
 
 mod credentials;
 mod qemu;
+mod qmp;
+mod swtpm;
 mod virtiofsd;
 
 pub use credentials::{
@@ -53,8 +59,13 @@ pub use credentials::{
 };
 
 pub use qemu::{
-    BootMode, DiskFormat, DisplayMode, MachineType, NetworkMode, QemuConfig, ResourceLimits,
-    RunningQemu, VirtioBlkDevice, VirtioSerialOut, VirtiofsMount, VHOST_VSOCK,
+    host_arch, kvm_available, Accel, BootMode, DiskFormat, DisplayMode, MachineType, NetworkMode,
+    QemuConfig, ResourceLimits, RunningQemu, UefiFirmware, VirtioBlkDevice, VirtioSerialOut,
+    VirtiofsMount, DEV_KVM, HOTPLUG_PORT_PREFIX, VHOST_VSOCK,
 };
 
+pub use qmp::{QmpClient, QmpEvent, QmpStatus, QmpTimestamp};
+
+pub use swtpm::{spawn_swtpm_async, SwtpmConfig};
+
 pub use virtiofsd::{spawn_virtiofsd_async, validate_virtiofsd_config, VirtiofsConfig};
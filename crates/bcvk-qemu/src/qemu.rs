@@ -1,7 +1,7 @@
 //! QEMU virtualization integration and VM management.
 //!
-//! Supports direct kernel boot with VirtIO devices, automatic process cleanup,
-//! and SMBIOS credential injection.
+//! Supports direct kernel boot and UEFI boot with VirtIO devices, automatic
+//! process cleanup, SMBIOS credential injection, and a QMP control socket.
 
 use std::fs::{File, OpenOptions};
 use std::future::Future;
@@ -10,8 +10,9 @@ use std::os::fd::{AsRawFd as _, OwnedFd};
 use std::os::unix::process::CommandExt as _;
 use std::pin::Pin;
 use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use camino::{Utf8Path, Utf8PathBuf};
 use cap_std_ext::cmdext::{CapStdExtCommandExt, CmdFds};
@@ -19,14 +20,22 @@ use color_eyre::eyre::{eyre, Context};
 use color_eyre::Result;
 use libc::{VMADDR_CID_ANY, VMADDR_PORT_ANY};
 use nix::sys::socket::{accept, bind, getsockname, socket, AddressFamily, SockFlag, SockType};
+use rustix::thread::CpuSet;
 use tracing::{debug, trace, warn};
 use vsock::VsockAddr;
 
-use crate::VirtiofsConfig;
+use crate::{QmpClient, SwtpmConfig, VirtiofsConfig};
 
 /// The device path for vsock allocation.
 pub const VHOST_VSOCK: &str = "/dev/vhost-vsock";
 
+/// The KVM device path.
+pub const DEV_KVM: &str = "/dev/kvm";
+
+/// Prefix of the ids of the root ports added for [`QemuConfig::hotplug_ports`];
+/// the ports are `hotplug0`, `hotplug1`, ...
+pub const HOTPLUG_PORT_PREFIX: &str = "hotplug";
+
 /// VirtIO-FS mount point configuration.
 #[derive(Debug, Clone)]
 pub struct VirtiofsMount {
@@ -100,6 +109,17 @@ pub enum NetworkMode {
         /// Port forwarding rules: "tcp::2222-:22" format.
         hostfwd: Vec<String>,
     },
+    /// User-mode networking as with [`NetworkMode::User`], plus a second NIC
+    /// on a UDP multicast socket. VMs using the same group share a private
+    /// L2 segment.
+    Socket {
+        /// Port forwarding rules for the user-mode NIC.
+        hostfwd: Vec<String>,
+        /// Multicast group and port ("239.255.66.1:5656").
+        mcast: String,
+        /// MAC address of the segment NIC.
+        mac: String,
+    },
 }
 
 impl Default for NetworkMode {
@@ -109,17 +129,96 @@ impl Default for NetworkMode {
 }
 
 /// Resource limits for QEMU processes.
-/// Note: Applied externally via taskset/ionice/nice, not QEMU args.
+/// Applied to the QEMU process before it starts, and inherited by its threads.
 #[derive(Debug, Clone, Default)]
 pub struct ResourceLimits {
-    /// CPU affinity bitmask ("0xF" for cores 0-3).
+    /// CPU affinity: a bitmask ("0xF" for cores 0-3) or a list ("0-3,8").
     pub cpu_affinity: Option<String>,
-    /// I/O priority (0=highest, 7=lowest).
+    /// Best-effort I/O priority (0=highest, 7=lowest).
     pub io_priority: Option<u8>,
     /// Nice level (-20=highest, 19=lowest).
     pub nice_level: Option<i8>,
 }
 
+/// `IOPRIO_WHO_PROCESS` from linux/ioprio.h
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+/// `IOPRIO_CLASS_BE` shifted by `IOPRIO_CLASS_SHIFT`, from linux/ioprio.h
+const IOPRIO_CLASS_BE: libc::c_int = 2 << 13;
+
+impl ResourceLimits {
+    /// Check that the limits are in range and the CPU affinity parses.
+    pub fn validate(&self) -> Result<()> {
+        self.cpu_set()?;
+        if let Some(io_priority) = self.io_priority.filter(|p| *p > 7) {
+            return Err(eyre!("Invalid I/O priority {io_priority}: must be 0-7"));
+        }
+        if let Some(nice_level) = self.nice_level.filter(|n| !(-20..=19).contains(n)) {
+            return Err(eyre!("Invalid nice level {nice_level}: must be -20 to 19"));
+        }
+        Ok(())
+    }
+
+    /// The CPUs of `cpu_affinity`.
+    fn cpus(&self) -> Result<Option<Vec<usize>>> {
+        let Some(affinity) = self.cpu_affinity.as_deref() else {
+            return Ok(None);
+        };
+        let invalid =
+            || eyre!("Invalid CPU affinity '{affinity}': use a mask (0xF) or a list (0-3,8)");
+        let mut cpus = Vec::new();
+        if let Some(mask) = affinity.strip_prefix("0x") {
+            let mask = u128::from_str_radix(mask, 16).map_err(|_| invalid())?;
+            cpus.extend((0..128).filter(|cpu| mask & (1 << cpu) != 0));
+        } else {
+            for range in affinity.split(',') {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+                let start: usize = start.trim().parse().map_err(|_| invalid())?;
+                let end: usize = end.trim().parse().map_err(|_| invalid())?;
+                if start > end {
+                    return Err(invalid());
+                }
+                cpus.extend(start..=end);
+            }
+        }
+        if cpus.is_empty() {
+            return Err(eyre!("Invalid CPU affinity '{affinity}': no CPUs selected"));
+        }
+        if let Some(cpu) = cpus.iter().find(|cpu| **cpu >= CpuSet::MAX_CPU) {
+            return Err(eyre!(
+                "Invalid CPU affinity '{affinity}': CPU {cpu} out of range"
+            ));
+        }
+        Ok(Some(cpus))
+    }
+
+    /// `cpu_affinity` as a CPU set for `sched_setaffinity`.
+    fn cpu_set(&self) -> Result<Option<CpuSet>> {
+        let Some(cpus) = self.cpus()? else {
+            return Ok(None);
+        };
+        let mut set = CpuSet::new();
+        for cpu in cpus {
+            set.set(cpu);
+        }
+        Ok(Some(set))
+    }
+}
+
+/// Set the best-effort I/O priority `level` (0-7) of the calling process, see
+/// ioprio_set(2); rustix has no wrapper for it.
+fn set_io_priority(level: u8) -> std::io::Result<()> {
+    let ioprio = IOPRIO_CLASS_BE | libc::c_int::from(level);
+    // SAFETY: ioprio_set only takes integers, so any values are memory safe.
+    // A raw syscall neither allocates nor takes locks, which makes this
+    // async-signal-safe, so it can be called between fork and exec.
+    #[allow(unsafe_code)]
+    let ret = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
 /// QEMU machine type selection.
 #[derive(Debug, Clone, Default)]
 pub enum MachineType {
@@ -131,25 +230,87 @@ pub enum MachineType {
 }
 
 impl MachineType {
-    /// Resolve to a QEMU `-machine` argument for the current host.
+    /// Resolve to a QEMU `-machine` argument for the given guest architecture
+    /// (in QEMU naming, see [`host_arch`]).
     ///
     /// Returns `None` for unknown architectures, letting QEMU use its default.
-    pub fn resolve(&self) -> Option<&str> {
+    pub fn resolve(&self, arch: &str) -> Option<&str> {
         // xref: https://github.com/coreos/coreos-assembler/blob/main/mantle/platform/qemu.go
         match self {
-            Self::Auto => match std::env::consts::ARCH {
+            Self::Auto => match arch {
                 "x86_64" => Some("q35"),
                 // gic-version=max selects the best available GIC for the host
                 "aarch64" => Some("virt,gic-version=max"),
                 "s390x" => Some("s390-ccw-virtio"),
                 // kvm-type=HV ensures bare metal KVM, not user mode
                 // ic-mode=xics for interrupt controller
-                "powerpc64" => Some("pseries,kvm-type=HV,ic-mode=xics"),
+                "ppc64" => Some("pseries,kvm-type=HV,ic-mode=xics"),
                 _ => None,
             },
             Self::Explicit(name) => Some(name.as_str()),
         }
     }
+
+    /// Whether the resolved machine has a PCI Express root complex, on which
+    /// hot-plugged PCI devices need a free root port.
+    pub fn is_pcie(&self, arch: &str) -> bool {
+        self.resolve(arch).is_some_and(|machine| {
+            let name = machine.split(',').next().unwrap_or_default();
+            name == "virt" || name.starts_with("virt-") || name.contains("q35")
+        })
+    }
+}
+
+/// CPU accelerator used to run the guest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Accel {
+    /// Hardware virtualization via KVM, with the host CPU model.
+    #[default]
+    Kvm,
+    /// Software emulation via multi-threaded TCG, with the `max` CPU model.
+    /// Works without `/dev/kvm`, but is much slower.
+    Tcg,
+}
+
+impl Accel {
+    /// Use KVM if the current process can open `/dev/kvm`, otherwise TCG.
+    pub fn detect() -> Self {
+        if kvm_available() {
+            Self::Kvm
+        } else {
+            Self::Tcg
+        }
+    }
+}
+
+/// Whether `/dev/kvm` exists and is accessible to the current process.
+pub fn kvm_available() -> bool {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(DEV_KVM)
+        .is_ok()
+}
+
+/// The host architecture in QEMU naming, as used in `qemu-system-<arch>`.
+pub fn host_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "powerpc64" => "ppc64",
+        arch => arch,
+    }
+}
+
+/// UEFI firmware images used for [`BootMode::UefiBoot`].
+#[derive(Debug, Clone)]
+pub struct UefiFirmware {
+    /// Path to the firmware code image (e.g. OVMF_CODE.fd), attached read-only.
+    pub code_path: String,
+    /// Format of the firmware code image.
+    pub code_format: DiskFormat,
+    /// Path to a writable copy of the UEFI variable store.
+    pub vars_path: String,
+    /// Format of the variable store.
+    pub vars_format: DiskFormat,
 }
 
 /// VM boot configuration.
@@ -161,8 +322,8 @@ pub enum BootMode {
     /// Note: For UKI images, we extract kernel/initramfs using objcopy rather than
     /// booting the UKI directly via OVMF. This allows us to append bcvk units to
     /// the initramfs for /etc overlay and /var setup. The tradeoff is that this
-    /// breaks the UKI signature chain, so Secure Boot is not supported for
-    /// ephemeral runs.
+    /// breaks the UKI signature chain; see [`BootMode::UefiBoot`] for booting
+    /// the UKI unmodified.
     DirectBoot {
         /// Path to kernel image.
         kernel_path: String,
@@ -182,6 +343,23 @@ pub enum BootMode {
         /// Path to the ISO image file.
         iso_path: String,
     },
+    /// Boot through UEFI firmware from an EFI System Partition image.
+    ///
+    /// The ESP is expected to contain systemd-boot and an unmodified UKI, so
+    /// this exercises the same boot chain as an installed system. Since the
+    /// UKI cannot be changed, the kernel command line is passed to
+    /// systemd-stub via the `io.systemd.stub.kernel-cmdline-extra` SMBIOS
+    /// string; the root filesystem is still served over virtiofs.
+    UefiBoot {
+        /// Firmware code and variable store.
+        firmware: UefiFirmware,
+        /// Path to the raw ESP disk image.
+        esp_path: String,
+        /// Extra kernel command line arguments.
+        kernel_cmdline: Vec<String>,
+        /// VirtIO-FS socket for root filesystem.
+        virtiofs_socket: Utf8PathBuf,
+    },
 }
 
 /// Complete QEMU VM configuration with builder pattern.
@@ -191,8 +369,14 @@ pub struct QemuConfig {
     pub memory_mb: u32,
     /// Number of vCPUs (1-256).
     pub vcpus: u32,
-    /// Machine type (default: auto-detect based on host architecture).
+    /// Machine type (default: auto-detect based on guest architecture).
     pub machine_type: MachineType,
+    /// Guest architecture in QEMU naming (e.g. `aarch64`, `ppc64`), selecting
+    /// `qemu-system-<arch>`; defaults to the host. A foreign architecture
+    /// requires [`Accel::Tcg`].
+    pub arch: Option<String>,
+    /// CPU accelerator (default: KVM).
+    pub accel: Accel,
     boot_mode: Option<BootMode>,
     /// Main VirtioFS configuration for root filesystem (handled separately from additional mounts).
     pub main_virtiofs_config: Option<VirtiofsConfig>,
@@ -216,6 +400,9 @@ pub struct QemuConfig {
     pub enable_console: bool,
     /// SMBIOS credentials for systemd.
     smbios_credentials: Vec<String>,
+    /// Files containing SMBIOS credentials for systemd, passed by path so
+    /// their contents do not appear in the QEMU command line.
+    smbios_credential_files: Vec<Utf8PathBuf>,
     /// Path to write serial console output (if set, `-serial file:<path>`
     /// is used instead of `-serial none`).
     pub serial_log: Option<String>,
@@ -229,6 +416,34 @@ pub struct QemuConfig {
 
     /// fw_cfg entries for passing config files to the guest
     fw_cfg_entries: Vec<(String, Utf8PathBuf)>,
+
+    /// Path for the QMP socket; if unset, a unique temporary path is used
+    /// and removed again when the [`RunningQemu`] is dropped.
+    pub qmp_socket: Option<Utf8PathBuf>,
+
+    /// Software TPM 2.0 to attach; swtpm is spawned along with virtiofsd.
+    pub tpm: Option<SwtpmConfig>,
+
+    /// Attach a pvpanic device, so that guest kernel panics are reported as
+    /// `GUEST_PANICKED` QMP events. Ignored on architectures without one;
+    /// QEMU reports panics natively on s390x and ppc64.
+    pub pvpanic: bool,
+
+    /// Pause the VM when the guest panics instead of shutting it down, e.g.
+    /// to dump its memory with `dump-guest-memory`.
+    pub pause_on_panic: bool,
+
+    /// Attach a hardware watchdog (i6300esb, or diag288 on s390x) that powers
+    /// off the VM when it fires, after a `WATCHDOG` QMP event.
+    pub watchdog: bool,
+
+    /// Path for a second QMP socket, for a client that waits for events
+    /// while the control socket stays available for commands.
+    pub qmp_events_socket: Option<Utf8PathBuf>,
+
+    /// Number of empty PCIe root ports for hot-plugging devices via QMP, see
+    /// [`HOTPLUG_PORT_PREFIX`]. Ignored on machines without PCI Express.
+    pub hotplug_ports: u32,
 }
 
 impl QemuConfig {
@@ -263,6 +478,27 @@ impl QemuConfig {
         }
     }
 
+    /// Create a new config that boots an ESP image through UEFI firmware.
+    pub fn new_uefi_boot(
+        memory_mb: u32,
+        vcpus: u32,
+        firmware: UefiFirmware,
+        esp_path: String,
+        virtiofs_socket: Utf8PathBuf,
+    ) -> Self {
+        Self {
+            memory_mb,
+            vcpus,
+            boot_mode: Some(BootMode::UefiBoot {
+                firmware,
+                esp_path,
+                kernel_cmdline: vec![],
+                virtiofs_socket,
+            }),
+            ..Default::default()
+        }
+    }
+
     /// Enable vsock support.
     pub fn enable_vsock(&mut self) -> Result<()> {
         let fd = OpenOptions::new()
@@ -274,10 +510,14 @@ impl QemuConfig {
         Ok(())
     }
 
-    /// Set kernel command line arguments (only for direct boot).
+    /// Set kernel command line arguments (only for direct and UEFI boot).
     pub fn set_kernel_cmdline(&mut self, cmdline: Vec<String>) -> &mut Self {
-        if let Some(BootMode::DirectBoot { kernel_cmdline, .. }) = self.boot_mode.as_mut() {
-            *kernel_cmdline = cmdline;
+        match self.boot_mode.as_mut() {
+            Some(BootMode::DirectBoot { kernel_cmdline, .. })
+            | Some(BootMode::UefiBoot { kernel_cmdline, .. }) => {
+                *kernel_cmdline = cmdline;
+            }
+            _ => {}
         }
         self
     }
@@ -342,6 +582,22 @@ impl QemuConfig {
                     return Err(eyre!("Initramfs path cannot be empty"));
                 }
             }
+            Some(BootMode::UefiBoot {
+                firmware, esp_path, ..
+            }) => {
+                for (what, path) in [
+                    ("UEFI firmware", &firmware.code_path),
+                    ("UEFI variable store", &firmware.vars_path),
+                    ("ESP image", esp_path),
+                ] {
+                    if path.is_empty() {
+                        return Err(eyre!("{what} path cannot be empty"));
+                    }
+                    if !std::path::Path::new(path).exists() {
+                        return Err(eyre!("{what} not found: {path}"));
+                    }
+                }
+            }
             None => {}
         }
 
@@ -448,13 +704,35 @@ impl QemuConfig {
         self
     }
 
+    /// Add a file containing an SMBIOS credential for systemd credential
+    /// passing, in the same format as [`Self::add_smbios_credential`].
+    pub fn add_smbios_credential_file(&mut self, path: impl Into<Utf8PathBuf>) -> &mut Self {
+        self.smbios_credential_files.push(path.into());
+        self
+    }
+
     /// Enable SSH access by configuring port forwarding.
     pub fn enable_ssh_access(&mut self, host_port: Option<u16>) -> &mut Self {
         let port = host_port.unwrap_or(2222); // Default to port 2222 on host
-        let hostfwd = format!("tcp::{}-:22", port); // Forward host port to guest port 22
-        self.network_mode = NetworkMode::User {
-            hostfwd: vec![hostfwd],
-        };
+        self.add_port_forward(port, 22) // Forward host port to guest port 22
+    }
+
+    /// Forward a TCP port on the host to a port in the guest.
+    ///
+    /// Rules accumulate; adding an identical rule again is a no-op.
+    pub fn add_port_forward(&mut self, host_port: u16, guest_port: u16) -> &mut Self {
+        let rule = format!("tcp::{}-:{}", host_port, guest_port);
+        let (NetworkMode::User { hostfwd } | NetworkMode::Socket { hostfwd, .. }) =
+            &mut self.network_mode;
+        if !hostfwd.contains(&rule) {
+            hostfwd.push(rule);
+        }
+        self
+    }
+
+    /// Set the path of the QMP control socket.
+    pub fn set_qmp_socket(&mut self, path: impl Into<Utf8PathBuf>) -> &mut Self {
+        self.qmp_socket = Some(path.into());
         self
     }
 
@@ -466,6 +744,14 @@ impl QemuConfig {
     }
 }
 
+/// A unique QMP socket path in the temporary directory.
+fn default_qmp_socket_path() -> Utf8PathBuf {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let tmpdir = Utf8PathBuf::from_path_buf(std::env::temp_dir()).unwrap_or_else(|_| "/tmp".into());
+    tmpdir.join(format!("bcvk-qemu-{}-{n}.qmp", std::process::id()))
+}
+
 /// Allocate a unique VSOCK CID.
 fn allocate_vsock_cid(vhost_fd: File) -> Result<(OwnedFd, u32)> {
     use std::os::unix::io::AsRawFd;
@@ -505,8 +791,57 @@ fn allocate_vsock_cid(vhost_fd: File) -> Result<(OwnedFd, u32)> {
     Err(eyre!("Could not find available VSOCK CID (tried 3-10000)"))
 }
 
+/// Escape a value embedded in a QEMU option string, where `,` separates keys.
+fn escape_opt_value(value: &str) -> String {
+    value.replace(',', ",,")
+}
+
+/// The pvpanic device for `arch`, if it has one.
+fn pvpanic_device(arch: &str) -> Option<&'static str> {
+    match arch {
+        "x86_64" | "aarch64" => Some("pvpanic-pci"),
+        _ => None,
+    }
+}
+
+/// QEMU arguments adding a watchdog device that powers off the VM when it fires.
+fn watchdog_args(arch: &str) -> Result<[&'static str; 4]> {
+    let device = match arch {
+        "x86_64" | "aarch64" => "i6300esb",
+        "s390x" => "diag288",
+        _ => return Err(eyre!("A watchdog is not supported on {arch}")),
+    };
+    Ok(["-device", device, "-action", "watchdog=poweroff"])
+}
+
+/// QEMU arguments for the firmware flash drives and the ESP, which is the
+/// only bootable device.
+fn uefi_drive_args(firmware: &UefiFirmware, esp_path: &str, virtio_bus: &str) -> [String; 8] {
+    [
+        "-drive".into(),
+        format!(
+            "if=pflash,unit=0,format={},readonly=on,file={}",
+            firmware.code_format.as_str(),
+            escape_opt_value(&firmware.code_path)
+        ),
+        "-drive".into(),
+        format!(
+            "if=pflash,unit=1,format={},file={}",
+            firmware.vars_format.as_str(),
+            escape_opt_value(&firmware.vars_path)
+        ),
+        "-drive".into(),
+        format!(
+            "file={},format=raw,if=none,id=esp",
+            escape_opt_value(esp_path)
+        ),
+        "-device".into(),
+        format!("virtio-blk-{virtio_bus},drive=esp,serial=esp,bootindex=0"),
+    ]
+}
+
 /// Spawn QEMU VM process with given configuration and optional extra credential.
-/// Uses KVM acceleration, memory-backend-memfd for VirtIO-FS compatibility.
+/// Uses KVM or TCG acceleration, memory-backend-memfd for VirtIO-FS compatibility.
 fn spawn(
     config: &QemuConfig,
     extra_credentials: &[String],
@@ -520,45 +855,69 @@ fn spawn(
         config.memory_mb
     );
 
+    let arch = config.arch.as_deref().unwrap_or(host_arch());
+    // s390x has no PCI by default; virtio devices sit on the channel subsystem
+    let virtio_bus = if arch == "s390x" { "ccw" } else { "pci" };
+
     let qemu = std::env::var("QEMU_BIN")
         .ok()
         .map(Ok)
         .unwrap_or_else(|| -> Result<_> {
             // RHEL only supports non-emulated, and qemu is an implementation detail
-            // of higher level virt.
+            // of higher level virt. It lacks TCG, so never use it for emulation.
             let libexec_qemu = Utf8Path::new("/usr/libexec/qemu-kvm");
-            if libexec_qemu.try_exists()? {
+            if config.accel == Accel::Kvm && arch == host_arch() && libexec_qemu.try_exists()? {
                 Ok(libexec_qemu.to_string())
             } else {
-                let arch = std::env::consts::ARCH;
                 Ok(format!("qemu-system-{arch}"))
             }
         })
         .context("Checking for qemu")?;
 
+    let limits = &config.resource_limits;
+    limits.validate()?;
+    let cpu_set = limits.cpu_set()?;
+    let io_priority = limits.io_priority;
+    let nice_level = limits.nice_level;
+    debug!("Resource limits: {limits:?}");
+
     let mut cmd = Command::new(qemu);
-    // SAFETY: This API is safe to call in a forked child.
+    // SAFETY: The closure only makes async-signal-safe syscalls and does not
+    // allocate, so it is safe to run in a forked child; the CPU set was built
+    // before forking.
     #[allow(unsafe_code)]
     unsafe {
-        cmd.pre_exec(|| {
-            rustix::process::set_parent_process_death_signal(Some(rustix::process::Signal::TERM))
-                .map_err(Into::into)
+        cmd.pre_exec(move || {
+            rustix::process::set_parent_process_death_signal(Some(rustix::process::Signal::TERM))?;
+            // Threads created by QEMU inherit all of these
+            if let Some(cpu_set) = cpu_set.as_ref() {
+                rustix::thread::sched_setaffinity(None, cpu_set)?;
+            }
+            if let Some(io_priority) = io_priority {
+                set_io_priority(io_priority)?;
+            }
+            if let Some(nice_level) = nice_level {
+                rustix::process::setpriority_process(None, nice_level.into())?;
+            }
+            Ok(())
         });
     }
 
     // Set machine type (auto-detected or explicit)
-    if let Some(machine) = config.machine_type.resolve() {
+    if let Some(machine) = config.machine_type.resolve(arch) {
         cmd.args(["-machine", machine]);
     }
 
+    match config.accel {
+        Accel::Kvm => cmd.args(["-enable-kvm", "-cpu", "host"]),
+        Accel::Tcg => cmd.args(["-accel", "tcg,thread=multi", "-cpu", "max"]),
+    };
+
     cmd.args([
         "-m",
         &memory_arg,
         "-smp",
         &config.vcpus.to_string(),
-        "-enable-kvm",
-        "-cpu",
-        "host",
         "-audio",
         "none",
         "-object",
@@ -571,6 +930,50 @@ fn spawn(
         cmd.arg("-no-reboot");
     }
 
+    if config.machine_type.is_pcie(arch) {
+        for idx in 0..config.hotplug_ports {
+            cmd.args([
+                "-device",
+                &format!(
+                    "pcie-root-port,id={HOTPLUG_PORT_PREFIX}{idx},chassis={}",
+                    idx + 1
+                ),
+            ]);
+        }
+    }
+
+    if config.pvpanic {
+        match pvpanic_device(arch) {
+            Some(device) => {
+                cmd.args(["-device", device]);
+            }
+            None => debug!("No pvpanic device on {arch}"),
+        }
+    }
+    if config.pause_on_panic {
+        cmd.args(["-action", "panic=pause"]);
+    }
+    if config.watchdog {
+        cmd.args(watchdog_args(arch)?);
+    }
+
+    if let Some(tpm) = config.tpm.as_ref() {
+        let device = match arch {
+            "x86_64" => "tpm-tis",
+            "aarch64" => "tpm-tis-device",
+            "ppc64" => "tpm-spapr",
+            _ => return Err(eyre!("TPM emulation is not supported on {arch}")),
+        };
+        cmd.args([
+            "-chardev",
+            &format!("socket,id=chrtpm,path={}", tpm.socket_path),
+            "-tpmdev",
+            "emulator,id=tpm0,chardev=chrtpm",
+            "-device",
+            &format!("{device},tpmdev=tpm0"),
+        ]);
+    }
+
     let mut cmd_fds = CmdFds::new();
     for (idx, fd) in config.fdset.iter().enumerate() {
         let fd_id = 100 + idx as u32; // Start at 100 to avoid conflicts
@@ -601,8 +1004,8 @@ fn spawn(
             ),
             "-device",
             &format!(
-                "virtio-blk-pci,drive={},serial={}",
-                drive_id, blk_device.serial
+                "virtio-blk-{},drive={},serial={}",
+                virtio_bus, drive_id, blk_device.serial
             ),
         ]);
     }
@@ -623,7 +1026,7 @@ fn spawn(
                 "-chardev",
                 &format!("socket,id=char0,path={}", virtiofs_socket),
                 "-device",
-                "vhost-user-fs-pci,queue-size=1024,chardev=char0,tag=rootfs",
+                &format!("vhost-user-fs-{virtio_bus},queue-size=1024,chardev=char0,tag=rootfs"),
             ]);
 
             // Add kernel command line
@@ -633,6 +1036,33 @@ fn spawn(
         Some(BootMode::IsoBoot { iso_path }) => {
             cmd.args(["-cdrom", iso_path]);
         }
+        Some(BootMode::UefiBoot {
+            firmware,
+            esp_path,
+            kernel_cmdline,
+            virtiofs_socket,
+        }) => {
+            cmd.args(uefi_drive_args(firmware, esp_path, virtio_bus));
+
+            cmd.args([
+                "-chardev",
+                &format!("socket,id=char0,path={}", virtiofs_socket),
+                "-device",
+                &format!("vhost-user-fs-{virtio_bus},queue-size=1024,chardev=char0,tag=rootfs"),
+            ]);
+
+            // systemd-stub appends this to the command line embedded in the UKI
+            if !kernel_cmdline.is_empty() {
+                let extra = format!(
+                    "io.systemd.stub.kernel-cmdline-extra={}",
+                    kernel_cmdline.join(" ")
+                );
+                cmd.args([
+                    "-smbios",
+                    &format!("type=11,value={}", escape_opt_value(&extra)),
+                ]);
+            }
+        }
         None => {}
     }
 
@@ -644,8 +1074,8 @@ fn spawn(
             &format!("socket,id={},path={}", char_id, mount.socket_path),
             "-device",
             &format!(
-                "vhost-user-fs-pci,queue-size=1024,chardev={},tag={}",
-                char_id, mount.tag
+                "vhost-user-fs-{},queue-size=1024,chardev={},tag={}",
+                virtio_bus, char_id, mount.tag
             ),
         ]);
     }
@@ -677,24 +1107,31 @@ fn spawn(
         ]);
     }
 
-    // Configure network (only User mode supported now)
-    match &config.network_mode {
-        NetworkMode::User { hostfwd } => {
-            let mut netdev_parts = vec!["user".to_string(), "id=net0".to_string()];
+    // Configure network; the user-mode NIC is always first
+    let (NetworkMode::User { hostfwd } | NetworkMode::Socket { hostfwd, .. }) =
+        &config.network_mode;
+    let mut netdev_parts = vec!["user".to_string(), "id=net0".to_string()];
 
-            // Add port forwarding rules
-            for fwd in hostfwd {
-                netdev_parts.push(format!("hostfwd={}", fwd));
-            }
+    // Add port forwarding rules
+    for fwd in hostfwd {
+        netdev_parts.push(format!("hostfwd={}", fwd));
+    }
 
-            let netdev_arg = netdev_parts.join(",");
-            cmd.args([
-                "-netdev",
-                &netdev_arg,
-                "-device",
-                "virtio-net-pci,netdev=net0",
-            ]);
-        }
+    let netdev_arg = netdev_parts.join(",");
+    cmd.args([
+        "-netdev",
+        &netdev_arg,
+        "-device",
+        &format!("virtio-net-{virtio_bus},netdev=net0"),
+    ]);
+
+    if let NetworkMode::Socket { mcast, mac, .. } = &config.network_mode {
+        cmd.args([
+            "-netdev",
+            &format!("socket,id=net1,mcast={mcast}"),
+            "-device",
+            &format!("virtio-net-{virtio_bus},netdev=net1,mac={mac}"),
+        ]);
     }
 
     // No GUI; serial console either to a log file or disabled.
@@ -705,6 +1142,14 @@ fn spawn(
     }
     cmd.args(["-nographic", "-display", "none"]);
 
+    // The QMP socket is always available for control, independent of the HMP monitor
+    if let Some(qmp_socket) = config.qmp_socket.as_deref() {
+        cmd.args(["-qmp", &format!("unix:{qmp_socket},server=on,wait=off")]);
+    }
+    if let Some(events_socket) = config.qmp_events_socket.as_deref() {
+        cmd.args(["-qmp", &format!("unix:{events_socket},server=on,wait=off")]);
+    }
+
     match &config.display_mode {
         DisplayMode::None => {
             // Disable monitor in non-console mode
@@ -718,29 +1163,16 @@ fn spawn(
         }
     }
 
-    // Apply resource limits
-    if let Some(affinity) = &config.resource_limits.cpu_affinity {
-        // Note: CPU affinity is typically set via taskset or systemd, not QEMU args
-        debug!("CPU affinity requested: {} (apply externally)", affinity);
-    }
-
-    if let Some(io_priority) = config.resource_limits.io_priority {
-        // Note: I/O priority is typically set via ionice, not QEMU args
-        debug!("I/O priority requested: {} (apply externally)", io_priority);
-    }
-
-    if let Some(nice_level) = config.resource_limits.nice_level {
-        // Note: Nice level is typically set via nice command, not QEMU args
-        debug!("Nice level requested: {} (apply externally)", nice_level);
-    }
-
     // Add AF_VSOCK device if enabled
     if let Some((vhostfd, guest_cid)) = vsock {
         debug!("Adding AF_VSOCK device with guest CID: {}", guest_cid);
         cmd_fds.take_fd_n(Arc::new(vhostfd), 42);
         cmd.args([
             "-device",
-            &format!("vhost-vsock-pci,guest-cid={},vhostfd=42", guest_cid),
+            &format!(
+                "vhost-vsock-{},guest-cid={},vhostfd=42",
+                virtio_bus, guest_cid
+            ),
         ]);
     }
 
@@ -749,6 +1181,10 @@ fn spawn(
         cmd.args(["-smbios", &format!("type=11,value={}", credential)]);
     }
 
+    for path in &config.smbios_credential_files {
+        cmd.args(["-smbios", &format!("type=11,path={path}")]);
+    }
+
     // Add extra credentials passed to this function
     for credential in extra_credentials {
         cmd.args(["-smbios", &format!("type=11,value={}", credential)]);
@@ -795,6 +1231,48 @@ impl std::fmt::Debug for VsockCopier {
     }
 }
 
+/// Wait for a helper daemon to create its socket, returning a future for its
+/// output.
+async fn wait_for_socket(
+    name: &str,
+    proc: tokio::process::Child,
+    socket_path: &Utf8Path,
+) -> Result<Pin<Box<dyn Future<Output = std::io::Result<Output>>>>> {
+    let query_exists = async move {
+        loop {
+            if socket_path.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    };
+    tokio::pin!(query_exists);
+    let timeout_val = Duration::from_secs(60);
+    let timeout = tokio::time::sleep(timeout_val);
+    tokio::pin!(timeout);
+    debug!("Waiting for {name} socket at {socket_path}");
+    let mut output: Pin<Box<dyn Future<Output = std::io::Result<Output>>>> =
+        Box::pin(proc.wait_with_output());
+    tokio::select! {
+        output = &mut output => {
+            tracing::trace!("{name} exited");
+            let output = output?;
+            let status = output.status;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(eyre!(
+                "{name} failed to start for socket {socket_path}\nExit status: {status:?}\nOutput: {stderr}"
+            ));
+        }
+        _ = timeout => {
+            return Err(eyre!("timed out waiting for {name} socket {} to be created (waited {timeout_val:?})", socket_path));
+        }
+        _ = query_exists => {
+        }
+    }
+    tracing::debug!("{name} socket created: {socket_path}");
+    Ok(output)
+}
+
 /// A running QEMU VM with associated processes.
 pub struct RunningQemu {
     /// The QEMU process.
@@ -802,8 +1280,21 @@ pub struct RunningQemu {
     #[allow(dead_code)]
     /// Associated virtiofsd processes.
     pub virtiofsd_processes: Vec<Pin<Box<dyn Future<Output = std::io::Result<Output>>>>>,
+    /// The swtpm process, if a TPM is attached.
+    #[allow(dead_code)]
+    swtpm_process: Option<Pin<Box<dyn Future<Output = std::io::Result<Output>>>>>,
     #[allow(dead_code)]
     sd_notification: Option<VsockCopier>,
+    /// Path to the QMP socket.
+    qmp_socket: Utf8PathBuf,
+    /// Whether we picked the QMP socket path, and so remove it on drop.
+    owns_qmp_socket: bool,
+    /// Path to the QMP socket for events, if any.
+    qmp_events_socket: Option<Utf8PathBuf>,
+    /// The guest's AF_VSOCK CID, if vsock is enabled.
+    guest_cid: Option<u32>,
+    /// When all virtiofsd daemons were ready, if any were spawned.
+    virtiofsd_ready: Option<SystemTime>,
 }
 
 impl std::fmt::Debug for RunningQemu {
@@ -814,6 +1305,12 @@ impl std::fmt::Debug for RunningQemu {
                 "virtiofsd_processes",
                 &format!("[{} futures]", self.virtiofsd_processes.len()),
             )
+            .field("swtpm_process", &self.swtpm_process.is_some())
+            .field("qmp_socket", &self.qmp_socket)
+            .field("owns_qmp_socket", &self.owns_qmp_socket)
+            .field("qmp_events_socket", &self.qmp_events_socket)
+            .field("guest_cid", &self.guest_cid)
+            .field("virtiofsd_ready", &self.virtiofsd_ready)
             .finish()
     }
 }
@@ -835,41 +1332,17 @@ impl RunningQemu {
         // Wait for all virtiofsd to be ready
         let mut virtiofsd_processes = Vec::new();
         while let Some((proc, socket_path)) = awaiting_virtiofsd.pop() {
-            let socket_path = &socket_path;
-            let query_exists = async move {
-                loop {
-                    if socket_path.exists() {
-                        break;
-                    }
-                    tokio::time::sleep(Duration::from_millis(100)).await;
-                }
-            };
-            tokio::pin!(query_exists);
-            let timeout_val = Duration::from_secs(60);
-            let timeout = tokio::time::sleep(timeout_val);
-            tokio::pin!(timeout);
-            debug!("Waiting for socket at {socket_path}");
-            let mut output: Pin<Box<dyn Future<Output = std::io::Result<Output>>>> =
-                Box::pin(proc.wait_with_output());
-            tokio::select! {
-                output = &mut output => {
-                    tracing::trace!("virtiofsd exited");
-                    let output = output?;
-                    let status = output.status;
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    return Err(eyre!(
-                        "virtiofsd failed to start for socket {socket_path}\nExit status: {status:?}\nOutput: {stderr}"
-                    ));
-                }
-                _ = timeout => {
-                    return Err(eyre!("timed out waiting for virtiofsd socket {} to be created (waited {timeout_val:?})", socket_path));
-                }
-                _ = query_exists => {
-                }
-            }
-            virtiofsd_processes.push(output);
-            tracing::debug!("virtiofsd socket created: {socket_path}");
+            virtiofsd_processes.push(wait_for_socket("virtiofsd", proc, &socket_path).await?);
         }
+        let virtiofsd_ready = (!virtiofsd_processes.is_empty()).then(SystemTime::now);
+
+        let swtpm_process = match config.tpm.as_ref() {
+            Some(tpm) => {
+                let process = crate::spawn_swtpm_async(tpm).await?;
+                Some(wait_for_socket("swtpm", process, &tpm.socket_path).await?)
+            }
+            None => None,
+        };
 
         let vsockdata = if let Some(vhost_fd) = config.vhost_fd.take() {
             // Get a unique guest CID using dynamic allocation
@@ -971,16 +1444,65 @@ impl RunningQemu {
             })
             .unwrap_or_default();
 
+        let owns_qmp_socket = config.qmp_socket.is_none();
+        let qmp_socket = config
+            .qmp_socket
+            .get_or_insert_with(default_qmp_socket_path)
+            .clone();
+
+        let qmp_events_socket = config.qmp_events_socket.clone();
+        let guest_cid = vsockdata.as_ref().map(|(_, cid)| *cid);
+
         // Spawn QEMU process with additional VSOCK credential if needed
         let qemu_process = spawn(&config, &creds, vsockdata)?;
 
         Ok(Self {
             qemu_process,
             virtiofsd_processes,
+            swtpm_process,
             sd_notification,
+            qmp_socket,
+            owns_qmp_socket,
+            qmp_events_socket,
+            guest_cid,
+            virtiofsd_ready,
         })
     }
 
+    /// When all virtiofsd daemons were ready, if any were spawned.
+    pub fn virtiofsd_ready(&self) -> Option<SystemTime> {
+        self.virtiofsd_ready
+    }
+
+    /// The guest's AF_VSOCK CID, if vsock is enabled.
+    pub fn guest_cid(&self) -> Option<u32> {
+        self.guest_cid
+    }
+
+    /// Path to the QMP control socket.
+    pub fn qmp_socket(&self) -> &Utf8Path {
+        &self.qmp_socket
+    }
+
+    /// Connect to the QMP control socket, waiting for QEMU to create it.
+    ///
+    /// QEMU only serves one QMP client at a time; drop the client to allow
+    /// others to connect.
+    pub async fn qmp(&self) -> Result<QmpClient> {
+        QmpClient::connect_with_timeout(&self.qmp_socket, Duration::from_secs(30)).await
+    }
+
+    /// Connect to the QMP events socket, if configured, to wait for events
+    /// with [`QmpClient::next_event`].
+    pub async fn qmp_events(&self) -> Result<Option<QmpClient>> {
+        let Some(path) = self.qmp_events_socket.as_deref() else {
+            return Ok(None);
+        };
+        QmpClient::connect_with_timeout(path, Duration::from_secs(30))
+            .await
+            .map(Some)
+    }
+
     /// Wait for QEMU process to exit.
     pub async fn wait(&mut self) -> Result<std::process::ExitStatus> {
         let r = self.qemu_process.wait()?;
@@ -988,6 +1510,19 @@ impl RunningQemu {
     }
 }
 
+impl Drop for RunningQemu {
+    fn drop(&mut self) {
+        // QEMU does not remove its listening sockets on exit
+        if self.owns_qmp_socket {
+            match std::fs::remove_file(&self.qmp_socket) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => debug!("Failed to remove {}: {e}", self.qmp_socket),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1015,6 +1550,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_machine_type_resolve() {
+        assert_eq!(MachineType::Auto.resolve("x86_64"), Some("q35"));
+        assert_eq!(
+            MachineType::Auto.resolve("aarch64"),
+            Some("virt,gic-version=max")
+        );
+        assert_eq!(MachineType::Auto.resolve("riscv64"), None);
+        let explicit = MachineType::Explicit("pc-q35-9.2".into());
+        assert_eq!(explicit.resolve("aarch64"), Some("pc-q35-9.2"));
+
+        assert!(MachineType::Auto.is_pcie("x86_64"));
+        assert!(MachineType::Auto.is_pcie("aarch64"));
+        assert!(!MachineType::Auto.is_pcie("s390x"));
+        assert!(!MachineType::Auto.is_pcie("ppc64"));
+        assert!(explicit.is_pcie("x86_64"));
+        assert!(!MachineType::Explicit("pc".into()).is_pcie("x86_64"));
+    }
+
     #[test]
     fn test_iso_boot_config() {
         let config = QemuConfig::new_iso_boot(2048, 2, "/test/image.iso".to_string());
@@ -1026,6 +1580,81 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_uefi_boot_config() {
+        let firmware = UefiFirmware {
+            code_path: "/test/OVMF_CODE.fd".to_string(),
+            code_format: DiskFormat::Raw,
+            vars_path: "/test/OVMF_VARS.fd".to_string(),
+            vars_format: DiskFormat::Qcow2,
+        };
+        let mut config = QemuConfig::new_uefi_boot(
+            2048,
+            2,
+            firmware,
+            "/test/esp.img".to_string(),
+            "/test/socket".into(),
+        );
+        config.set_kernel_cmdline(vec!["selinux=0".to_string()]);
+        match &config.boot_mode {
+            Some(BootMode::UefiBoot {
+                esp_path,
+                kernel_cmdline,
+                firmware,
+                ..
+            }) => {
+                assert_eq!(esp_path, "/test/esp.img");
+                assert_eq!(kernel_cmdline, &["selinux=0"]);
+                assert_eq!(firmware.vars_format.as_str(), "qcow2");
+            }
+            o => panic!("unexpected boot mode {o:?}"),
+        }
+        // Firmware files don't exist
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_uefi_drive_args() {
+        let firmware = UefiFirmware {
+            code_path: "/fw/CODE,1.fd".to_string(),
+            code_format: DiskFormat::Raw,
+            vars_path: "/run/vm/VARS.qcow2".to_string(),
+            vars_format: DiskFormat::Qcow2,
+        };
+        for (bus, esp, expected_esp, expected_device) in [
+            (
+                "pci",
+                "/run/vm/esp.img",
+                "file=/run/vm/esp.img,format=raw,if=none,id=esp",
+                "virtio-blk-pci,drive=esp,serial=esp,bootindex=0",
+            ),
+            (
+                "ccw",
+                "/run/a,b/esp.img",
+                "file=/run/a,,b/esp.img,format=raw,if=none,id=esp",
+                "virtio-blk-ccw,drive=esp,serial=esp,bootindex=0",
+            ),
+        ] {
+            let args = uefi_drive_args(&firmware, esp, bus);
+            assert_eq!(
+                args[1],
+                "if=pflash,unit=0,format=raw,readonly=on,file=/fw/CODE,,1.fd"
+            );
+            assert_eq!(
+                args[3],
+                "if=pflash,unit=1,format=qcow2,file=/run/vm/VARS.qcow2"
+            );
+            assert_eq!(args[5], expected_esp);
+            assert_eq!(args[7], expected_device);
+        }
+    }
+
+    #[test]
+    fn test_escape_opt_value() {
+        assert_eq!(escape_opt_value("a=b c"), "a=b c");
+        assert_eq!(escape_opt_value("systemd.mask=a,b"), "systemd.mask=a,,b");
+    }
+
     #[test]
     fn test_disk_format() {
         assert_eq!(DiskFormat::Raw.as_str(), "raw");
@@ -1051,4 +1680,109 @@ mod tests {
         assert_eq!(config.fw_cfg_entries[0].0, "opt/com.coreos/config");
         assert_eq!(config.fw_cfg_entries[0].1.as_str(), "/test/ignition.json");
     }
+
+    #[test]
+    fn test_port_forward() {
+        let mut config = QemuConfig::new_direct_boot(
+            1024,
+            1,
+            "/test/kernel".to_string(),
+            "/test/initramfs".to_string(),
+            "/test/socket".into(),
+        );
+        config.add_port_forward(8080, 80);
+        config.enable_ssh_access(None);
+        config.add_port_forward(8080, 80);
+
+        let NetworkMode::User { hostfwd } = &config.network_mode else {
+            panic!("unexpected network mode");
+        };
+        assert_eq!(hostfwd, &["tcp::8080-:80", "tcp::2222-:22"]);
+
+        // Forwards are kept for the user-mode NIC of a socket network
+        config.network_mode = NetworkMode::Socket {
+            hostfwd: vec![],
+            mcast: "239.255.66.1:5656".into(),
+            mac: "52:54:00:12:34:56".into(),
+        };
+        config.enable_ssh_access(None);
+        let NetworkMode::Socket { hostfwd, .. } = &config.network_mode else {
+            panic!("unexpected network mode");
+        };
+        assert_eq!(hostfwd, &["tcp::2222-:22"]);
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let limits = |affinity: &str| ResourceLimits {
+            cpu_affinity: Some(affinity.into()),
+            ..Default::default()
+        };
+        assert_eq!(ResourceLimits::default().cpus().unwrap(), None);
+        assert_eq!(limits("0xF").cpus().unwrap(), Some(vec![0, 1, 2, 3]));
+        assert_eq!(limits("0x12").cpus().unwrap(), Some(vec![1, 4]));
+        assert_eq!(limits("0-2,8").cpus().unwrap(), Some(vec![0, 1, 2, 8]));
+        assert_eq!(limits("5").cpus().unwrap(), Some(vec![5]));
+        let set = limits("0x12").cpu_set().unwrap().unwrap();
+        assert_eq!(set.count(), 2);
+        assert!(set.is_set(1) && set.is_set(4));
+        for invalid in ["", "0x0", "0xZ", "3-1", "a", "0,", "100000"] {
+            assert!(limits(invalid).validate().is_err(), "{invalid}");
+        }
+
+        let valid = ResourceLimits {
+            cpu_affinity: Some("0".into()),
+            io_priority: Some(7),
+            nice_level: Some(-20),
+        };
+        assert!(valid.validate().is_ok());
+        assert!(ResourceLimits {
+            io_priority: Some(8),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(ResourceLimits {
+            nice_level: Some(20),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_pvpanic_device() {
+        for (arch, device) in [
+            ("x86_64", Some("pvpanic-pci")),
+            ("aarch64", Some("pvpanic-pci")),
+            ("s390x", None),
+            ("ppc64", None),
+            ("riscv64", None),
+        ] {
+            assert_eq!(pvpanic_device(arch), device, "{arch}");
+        }
+    }
+
+    #[test]
+    fn test_watchdog_args() {
+        for (arch, device) in [
+            ("x86_64", "i6300esb"),
+            ("aarch64", "i6300esb"),
+            ("s390x", "diag288"),
+        ] {
+            assert_eq!(
+                watchdog_args(arch).unwrap(),
+                ["-device", device, "-action", "watchdog=poweroff"]
+            );
+        }
+        assert!(watchdog_args("ppc64").is_err());
+    }
+
+    #[test]
+    fn test_default_qmp_socket_path() {
+        let a = default_qmp_socket_path();
+        let b = default_qmp_socket_path();
+        assert_ne!(a, b);
+        assert_eq!(a.extension(), Some("qmp"));
+    }
 }
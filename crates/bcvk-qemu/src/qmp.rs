@@ -0,0 +1,352 @@
+//! QEMU Machine Protocol (QMP) client.
+//!
+//! QEMU is always started with a QMP unix socket (see [`crate::RunningQemu`]).
+//! This is a small async client for the subset of commands we need to
+//! control a running VM, plus access to asynchronous events.
+//!
+//! See <https://www.qemu.org/docs/master/interop/qmp-spec.html>.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use camino::Utf8Path;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+use tracing::{debug, trace};
+
+/// The result of `query-status`.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct QmpStatus {
+    /// Whether the guest CPUs are running.
+    pub running: bool,
+    /// The run state, e.g. `running`, `paused` or `shutdown`.
+    pub status: String,
+}
+
+/// Timestamp attached to a QMP event.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub struct QmpTimestamp {
+    /// Seconds since the epoch.
+    pub seconds: i64,
+    /// Microseconds within the second.
+    pub microseconds: i64,
+}
+
+/// An asynchronous event emitted by QEMU, e.g. `SHUTDOWN` or `DEVICE_DELETED`.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct QmpEvent {
+    /// The event name.
+    pub event: String,
+    /// Event specific data.
+    #[serde(default)]
+    pub data: Value,
+    /// When the event was emitted.
+    #[serde(default)]
+    pub timestamp: QmpTimestamp,
+}
+
+/// The error member of a failed command reply.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+struct QmpErrorDesc {
+    class: String,
+    desc: String,
+}
+
+/// A message received from the server.
+#[derive(Debug, PartialEq)]
+enum QmpMessage {
+    /// Connection greeting.
+    Greeting,
+    /// Successful command reply.
+    Return(Value),
+    /// Failed command reply.
+    Error(QmpErrorDesc),
+    /// Asynchronous event.
+    Event(QmpEvent),
+}
+
+impl QmpMessage {
+    fn parse(line: &str) -> Result<Self> {
+        let mut value: Map<String, Value> =
+            serde_json::from_str(line).with_context(|| format!("Parsing QMP message: {line}"))?;
+        if value.contains_key("QMP") {
+            Ok(Self::Greeting)
+        } else if let Some(ret) = value.remove("return") {
+            Ok(Self::Return(ret))
+        } else if let Some(err) = value.remove("error") {
+            Ok(Self::Error(serde_json::from_value(err)?))
+        } else if value.contains_key("event") {
+            Ok(Self::Event(serde_json::from_value(Value::Object(value))?))
+        } else {
+            Err(eyre!("Unexpected QMP message: {line}"))
+        }
+    }
+}
+
+/// An async client connected to a QMP socket.
+///
+/// Commands are executed one at a time; events received while waiting for a
+/// reply are queued and returned by [`QmpClient::next_event`].
+#[derive(Debug)]
+pub struct QmpClient {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    events: VecDeque<QmpEvent>,
+}
+
+impl QmpClient {
+    /// Connect to the QMP socket at `path` and negotiate capabilities.
+    pub async fn connect(path: impl AsRef<Utf8Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let stream = UnixStream::connect(path)
+            .await
+            .with_context(|| format!("Connecting to QMP socket {path}"))?;
+        let (reader, writer) = stream.into_split();
+        let mut client = Self {
+            reader: BufReader::new(reader),
+            writer,
+            events: VecDeque::new(),
+        };
+
+        match client.read_message().await? {
+            QmpMessage::Greeting => {}
+            o => return Err(eyre!("Expected QMP greeting, got {o:?}")),
+        }
+        client.execute("qmp_capabilities", None).await?;
+        debug!("Connected to QMP socket {path}");
+        Ok(client)
+    }
+
+    /// Connect to the QMP socket at `path`, retrying until QEMU has created
+    /// it or `timeout` elapses.
+    pub async fn connect_with_timeout(
+        path: impl AsRef<Utf8Path>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            match Self::connect(path).await {
+                Ok(c) => return Ok(c),
+                Err(e) if tokio::time::Instant::now() >= deadline => {
+                    return Err(e.wrap_err(format!("Timed out after {timeout:?}")));
+                }
+                Err(e) => {
+                    trace!("QMP not ready yet: {e}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        }
+    }
+
+    async fn read_message(&mut self) -> Result<QmpMessage> {
+        let mut line = String::new();
+        let n = self.reader.read_line(&mut line).await?;
+        if n == 0 {
+            return Err(eyre!("QMP connection closed"));
+        }
+        trace!("QMP <- {}", line.trim_end());
+        QmpMessage::parse(&line)
+    }
+
+    /// Execute a raw QMP command, returning the `return` member of the reply.
+    pub async fn execute(&mut self, command: &str, arguments: Option<Value>) -> Result<Value> {
+        let mut request = json!({ "execute": command });
+        if let Some(arguments) = arguments {
+            request["arguments"] = arguments;
+        }
+        let mut buf = serde_json::to_vec(&request)?;
+        trace!("QMP -> {}", String::from_utf8_lossy(&buf));
+        buf.push(b'\n');
+        self.writer.write_all(&buf).await?;
+
+        loop {
+            match self.read_message().await? {
+                QmpMessage::Return(v) => return Ok(v),
+                QmpMessage::Error(e) => {
+                    return Err(eyre!(
+                        "QMP command {command} failed: {} ({})",
+                        e.desc,
+                        e.class
+                    ));
+                }
+                QmpMessage::Event(e) => self.events.push_back(e),
+                QmpMessage::Greeting => return Err(eyre!("Unexpected QMP greeting")),
+            }
+        }
+    }
+
+    /// Query the VM run state.
+    pub async fn query_status(&mut self) -> Result<QmpStatus> {
+        let v = self.execute("query-status", None).await?;
+        Ok(serde_json::from_value(v)?)
+    }
+
+    /// Request a graceful shutdown via an ACPI power button press.
+    pub async fn system_powerdown(&mut self) -> Result<()> {
+        self.execute("system_powerdown", None).await?;
+        Ok(())
+    }
+
+    /// Terminate QEMU immediately, without shutting down the guest.
+    pub async fn quit(&mut self) -> Result<()> {
+        self.execute("quit", None).await?;
+        Ok(())
+    }
+
+    /// Pause the guest CPUs.
+    pub async fn stop(&mut self) -> Result<()> {
+        self.execute("stop", None).await?;
+        Ok(())
+    }
+
+    /// Resume the guest CPUs.
+    pub async fn cont(&mut self) -> Result<()> {
+        self.execute("cont", None).await?;
+        Ok(())
+    }
+
+    /// Run a human monitor (HMP) command and return its output.
+    pub async fn human_monitor_command(&mut self, command_line: &str) -> Result<String> {
+        let v = self
+            .execute(
+                "human-monitor-command",
+                Some(json!({ "command-line": command_line })),
+            )
+            .await?;
+        v.as_str()
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| eyre!("Unexpected human-monitor-command reply: {v}"))
+    }
+
+    /// Add a chardev connecting to the Unix socket at `path`, e.g. for a
+    /// vhost-user device.
+    pub async fn chardev_add_socket(&mut self, id: &str, path: &Utf8Path) -> Result<()> {
+        let arguments = json!({
+            "id": id,
+            "backend": {
+                "type": "socket",
+                "data": {
+                    "addr": { "type": "unix", "data": { "path": path.as_str() } },
+                    "server": false,
+                },
+            },
+        });
+        self.execute("chardev-add", Some(arguments)).await?;
+        Ok(())
+    }
+
+    /// Remove a chardev that is no longer used by any device.
+    pub async fn chardev_remove(&mut self, id: &str) -> Result<()> {
+        self.execute("chardev-remove", Some(json!({ "id": id })))
+            .await?;
+        Ok(())
+    }
+
+    /// Hot-add a device with the given driver, id and extra properties.
+    pub async fn device_add(
+        &mut self,
+        driver: &str,
+        id: &str,
+        properties: Map<String, Value>,
+    ) -> Result<()> {
+        let mut arguments = properties;
+        arguments.insert("driver".into(), driver.into());
+        arguments.insert("id".into(), id.into());
+        self.execute("device_add", Some(Value::Object(arguments)))
+            .await?;
+        Ok(())
+    }
+
+    /// Request removal of a device. Completion is signaled by a
+    /// `DEVICE_DELETED` event.
+    pub async fn device_del(&mut self, id: &str) -> Result<()> {
+        self.execute("device_del", Some(json!({ "id": id })))
+            .await?;
+        Ok(())
+    }
+
+    /// Wait for the next asynchronous event.
+    pub async fn next_event(&mut self) -> Result<QmpEvent> {
+        if let Some(e) = self.events.pop_front() {
+            return Ok(e);
+        }
+        loop {
+            match self.read_message().await? {
+                QmpMessage::Event(e) => return Ok(e),
+                o => debug!("Ignoring unsolicited QMP message: {o:?}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_greeting() {
+        let line = r#"{"QMP": {"version": {"qemu": {"micro": 0, "minor": 2, "major": 9}, "package": ""}, "capabilities": ["oob"]}}"#;
+        assert_eq!(QmpMessage::parse(line).unwrap(), QmpMessage::Greeting);
+    }
+
+    #[test]
+    fn test_parse_return() {
+        let msg = QmpMessage::parse(r#"{"return": {"status": "running", "running": true}}"#);
+        let QmpMessage::Return(v) = msg.unwrap() else {
+            panic!("expected return");
+        };
+        let status: QmpStatus = serde_json::from_value(v).unwrap();
+        assert_eq!(
+            status,
+            QmpStatus {
+                running: true,
+                status: "running".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let msg = QmpMessage::parse(
+            r#"{"error": {"class": "DeviceNotFound", "desc": "Device 'foo' not found"}}"#,
+        );
+        assert_eq!(
+            msg.unwrap(),
+            QmpMessage::Error(QmpErrorDesc {
+                class: "DeviceNotFound".into(),
+                desc: "Device 'foo' not found".into()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let line = r#"{"timestamp": {"seconds": 1700000000, "microseconds": 42}, "event": "SHUTDOWN", "data": {"guest": true, "reason": "guest-shutdown"}}"#;
+        let QmpMessage::Event(e) = QmpMessage::parse(line).unwrap() else {
+            panic!("expected event");
+        };
+        assert_eq!(e.event, "SHUTDOWN");
+        assert_eq!(e.data["reason"], "guest-shutdown");
+        assert_eq!(e.timestamp.seconds, 1700000000);
+        assert_eq!(e.timestamp.microseconds, 42);
+
+        // Events without data are valid
+        let QmpMessage::Event(e) = QmpMessage::parse(r#"{"event": "STOP"}"#).unwrap() else {
+            panic!("expected event");
+        };
+        assert_eq!(e.event, "STOP");
+        assert!(e.data.is_null());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(QmpMessage::parse("not json").is_err());
+        assert!(QmpMessage::parse(r#"{"foo": 1}"#).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Software TPM daemon management.
+//!
+//! Provides functionality for spawning swtpm daemons that emulate a TPM 2.0
+//! for QEMU guests via the `emulator` TPM backend.
+
+use camino::Utf8PathBuf;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tracing::debug;
+
+/// swtpm daemon configuration.
+#[derive(Debug, Clone)]
+pub struct SwtpmConfig {
+    /// Unix socket for the QEMU control channel.
+    pub socket_path: Utf8PathBuf,
+    /// Directory holding the TPM state; it persists across VMs using it.
+    pub state_dir: Utf8PathBuf,
+    /// Optional log file path for swtpm output.
+    pub log_file: Option<Utf8PathBuf>,
+    /// Optional explicit path to swtpm binary (overrides path search).
+    pub swtpm_binary: Option<Utf8PathBuf>,
+}
+
+/// The swtpm arguments for `config`.
+fn swtpm_args(config: &SwtpmConfig) -> Result<Vec<String>> {
+    // swtpm options are comma separated key=value lists
+    for path in [Some(&config.socket_path), Some(&config.state_dir)]
+        .into_iter()
+        .chain([config.log_file.as_ref()])
+        .flatten()
+    {
+        if path.as_str().contains(',') {
+            return Err(eyre!("swtpm paths cannot contain ',': {path}"));
+        }
+    }
+
+    let mut args = vec![
+        "socket".to_string(),
+        "--tpm2".to_string(),
+        "--tpmstate".to_string(),
+        format!("dir={},mode=0600", config.state_dir),
+        "--ctrl".to_string(),
+        format!("type=unixio,path={}", config.socket_path),
+        // Exit once QEMU closes the control channel
+        "--terminate".to_string(),
+    ];
+    if let Some(log_file) = &config.log_file {
+        args.push("--log".to_string());
+        args.push(format!("file={log_file}"));
+    }
+    Ok(args)
+}
+
+/// Spawn swtpm daemon process as tokio::process::Child.
+///
+/// Searches for binary in /usr/bin, /usr/local/bin. Creates the state and
+/// socket directories if needed. The daemon exits when QEMU disconnects, or
+/// when the spawning process exits.
+pub async fn spawn_swtpm_async(config: &SwtpmConfig) -> Result<tokio::process::Child> {
+    let args = swtpm_args(config)?;
+
+    let swtpm_binary: String = if let Some(ref path) = config.swtpm_binary {
+        if !path.exists() {
+            return Err(eyre!("Explicit swtpm binary not found at: {}", path));
+        }
+        camino::absolute_utf8(path)?.to_string()
+    } else {
+        let swtpm_paths = ["/usr/bin/swtpm", "/usr/local/bin/swtpm"];
+        swtpm_paths
+            .iter()
+            .find(|path| std::path::Path::new(path).exists())
+            .ok_or_else(|| {
+                eyre!(
+                    "swtpm binary not found. Searched paths: {}. \
+                     swtpm must be installed alongside QEMU, in the container \
+                     the VM runs in, to use a TPM.",
+                    swtpm_paths.join(", ")
+                )
+            })?
+            .to_string()
+    };
+
+    std::fs::create_dir_all(&config.state_dir)
+        .with_context(|| format!("Failed to create TPM state directory: {}", config.state_dir))?;
+    if let Some(socket_dir) = config.socket_path.parent() {
+        std::fs::create_dir_all(socket_dir)
+            .with_context(|| format!("Failed to create socket directory: {socket_dir}"))?;
+    }
+
+    let mut cmd = tokio::process::Command::new(&swtpm_binary);
+    // SAFETY: This API is safe to call in a forked child.
+    #[allow(unsafe_code)]
+    unsafe {
+        cmd.pre_exec(|| {
+            rustix::process::set_parent_process_death_signal(Some(rustix::process::Signal::TERM))
+                .map_err(Into::into)
+        });
+    }
+    cmd.args(&args);
+    cmd.stdout(std::process::Stdio::null());
+    cmd.stderr(std::process::Stdio::piped());
+
+    let child = cmd.spawn().with_context(|| {
+        format!(
+            "Failed to spawn swtpm. Binary: {}, Socket: {}, State dir: {}",
+            &swtpm_binary, config.socket_path, config.state_dir
+        )
+    })?;
+
+    debug!(
+        "Spawned swtpm: binary={}, socket={}, state_dir={}, log_file={:?}",
+        &swtpm_binary, config.socket_path, config.state_dir, config.log_file
+    );
+
+    Ok(child)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swtpm_args() {
+        let mut config = SwtpmConfig {
+            socket_path: "/run/swtpm.sock".into(),
+            state_dir: "/run/tpm-state".into(),
+            log_file: Some("/run/swtpm.log".into()),
+            swtpm_binary: None,
+        };
+        let args = swtpm_args(&config).unwrap();
+        assert_eq!(
+            args,
+            [
+                "socket",
+                "--tpm2",
+                "--tpmstate",
+                "dir=/run/tpm-state,mode=0600",
+                "--ctrl",
+                "type=unixio,path=/run/swtpm.sock",
+                "--terminate",
+                "--log",
+                "file=/run/swtpm.log"
+            ]
+        );
+
+        config.state_dir = "/run/tpm,state".into();
+        assert!(swtpm_args(&config).is_err());
+    }
+}
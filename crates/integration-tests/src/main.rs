@@ -12,6 +12,7 @@ pub(crate) use integration_tests::{
 };
 
 mod tests {
+    pub mod compose;
     pub mod libvirt_base_disks;
     pub mod libvirt_ignition;
     pub mod libvirt_port_forward;
@@ -140,6 +141,20 @@ pub(crate) fn check_journal_coverage(log_dir: &std::path::Path) -> anyhow::Resul
     Ok(())
 }
 
+/// Find an available port on the host
+pub(crate) fn find_available_port() -> anyhow::Result<u16> {
+    use std::net::TcpListener;
+
+    // Try to bind to port 0, which will allocate an available port
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+
+    // Drop the listener to free the port
+    drop(listener);
+
+    Ok(port)
+}
+
 /// Poll `condition` every `interval` until it returns `true` or `timeout` elapses.
 ///
 /// Returns `Ok(())` as soon as the condition holds, or an error describing what was
@@ -0,0 +1,68 @@
+//! Integration tests for `bcvk compose`
+
+use integration_tests::integration_test;
+use itest::TestResult;
+use xshell::cmd;
+
+use std::fs;
+
+use camino::Utf8Path;
+
+use crate::{get_bck_command, get_test_image, shell, INTEGRATION_TEST_LABEL};
+
+/// Test that VMs on an internal network resolve each other by VM name
+fn test_compose_internal_network_names() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let project = format!("bcvk-test-{}", std::process::id());
+
+    let td = tempfile::tempdir()?;
+    let file = Utf8Path::from_path(td.path())
+        .expect("temp dir path is not utf8")
+        .join("bcvk-compose.yaml");
+    fs::write(
+        &file,
+        format!(
+            r#"name: {project}
+vms:
+  - name: db
+    image: {image}
+    internal_network: {project}
+    args: ["--label={label}"]
+  - name: web
+    image: {image}
+    internal_network: {project}
+    ready: ssh
+    depends_on: [db]
+    args: ["--label={label}"]
+"#
+        ),
+    )?;
+
+    let result = (|| -> anyhow::Result<()> {
+        cmd!(sh, "{bck} compose -f {file} up").run()?;
+
+        let web = format!("{project}-web");
+        let db = format!("{project}-db");
+        let bare = cmd!(sh, "{bck} ephemeral ssh {web} getent hosts db").read()?;
+        let full = cmd!(sh, "{bck} ephemeral ssh {web} getent hosts {db}").read()?;
+        let address = bare.split_whitespace().next().unwrap_or_default();
+        assert!(address.starts_with("10.77."), "unexpected entry: {bare}");
+        assert_eq!(full.split_whitespace().next(), Some(address));
+        Ok(())
+    })();
+
+    let _ = cmd!(sh, "{bck} compose -f {file} down --force")
+        .ignore_status()
+        .run();
+    let network = format!("bcvk-{project}");
+    let _ = cmd!(sh, "podman network rm {network}")
+        .ignore_status()
+        .quiet()
+        .run();
+    result?;
+    Ok(())
+}
+integration_test!(test_compose_internal_network_names);
@@ -10,7 +10,9 @@ use itest::TestResult;
 use scopeguard::defer;
 use xshell::cmd;
 
-use crate::{get_bck_command, get_test_image, shell, LIBVIRT_INTEGRATION_TEST_LABEL};
+use crate::{
+    find_available_port, get_bck_command, get_test_image, shell, LIBVIRT_INTEGRATION_TEST_LABEL,
+};
 
 /// Test port forwarding argument parsing
 fn test_libvirt_port_forward_parsing() -> TestResult {
@@ -352,17 +354,3 @@ fn cleanup_domain(domain_name: &str) {
         Err(_) => {}
     }
 }
-
-/// Find an available port on the host
-fn find_available_port() -> anyhow::Result<u16> {
-    use std::net::TcpListener;
-
-    // Try to bind to port 0, which will allocate an available port
-    let listener = TcpListener::bind("127.0.0.1:0")?;
-    let port = listener.local_addr()?.port();
-
-    // Drop the listener to free the port
-    drop(listener);
-
-    Ok(port)
-}
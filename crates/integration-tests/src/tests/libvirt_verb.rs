@@ -1201,3 +1201,97 @@ fn test_libvirt_run_journal_output() -> TestResult {
     Ok(())
 }
 integration_test!(test_libvirt_run_journal_output);
+
+/// Test copying a file into a libvirt VM and back with `libvirt cp`
+fn test_libvirt_cp() -> TestResult {
+    use camino::Utf8Path;
+    use std::fs;
+
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let test_image = get_test_image();
+    let label = LIBVIRT_INTEGRATION_TEST_LABEL;
+    let domain_name = format!("test-cp-{}", random_suffix());
+
+    let td = tempfile::tempdir()?;
+    let base = Utf8Path::from_path(td.path()).expect("temp dir path is not utf8");
+    let src = base.join("in.txt");
+    fs::write(&src, "copied content\n")?;
+    let dest = base.join("out.txt");
+
+    cleanup_domain(&domain_name);
+    defer! {
+        cleanup_domain(&domain_name);
+    }
+
+    cmd!(
+        sh,
+        "{bck} libvirt run --name {domain_name} --label {label} --filesystem ext4 --ssh-wait {test_image}"
+    )
+    .run()?;
+
+    cmd!(sh, "{bck} libvirt cp {src} {domain_name}:/var/tmp/in.txt").run()?;
+    let content = cmd!(sh, "{bck} libvirt ssh {domain_name} -- cat /var/tmp/in.txt").read()?;
+    assert_eq!(content, "copied content");
+
+    cmd!(sh, "{bck} libvirt cp {domain_name}:/var/tmp/in.txt {dest}").run()?;
+    assert_eq!(fs::read_to_string(&dest)?, "copied content\n");
+    Ok(())
+}
+integration_test!(test_libvirt_cp);
+
+/// Test sharing a directory with a running libvirt VM with `libvirt mount add`
+fn test_libvirt_mount_add() -> TestResult {
+    use camino::Utf8Path;
+    use std::fs;
+
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let test_image = get_test_image();
+    let label = LIBVIRT_INTEGRATION_TEST_LABEL;
+    let domain_name = format!("test-mount-{}", random_suffix());
+
+    let td = tempfile::tempdir()?;
+    let share = Utf8Path::from_path(td.path()).expect("temp dir path is not utf8");
+    fs::write(share.join("hello.txt"), "shared content\n")?;
+
+    cleanup_domain(&domain_name);
+    defer! {
+        cleanup_domain(&domain_name);
+    }
+
+    cmd!(
+        sh,
+        "{bck} libvirt run --name {domain_name} --label {label} --filesystem ext4 --hotplug-mounts --ssh-wait {test_image}"
+    )
+    .run()?;
+
+    cmd!(
+        sh,
+        "{bck} libvirt mount add {domain_name} {share} /mnt/share"
+    )
+    .run()?;
+    let content = cmd!(
+        sh,
+        "{bck} libvirt ssh {domain_name} -- cat /mnt/share/hello.txt"
+    )
+    .read()?;
+    assert_eq!(content, "shared content");
+    cmd!(
+        sh,
+        "{bck} libvirt ssh {domain_name} -- touch /mnt/share/new.txt"
+    )
+    .run()?;
+    assert!(share.join("new.txt").exists());
+
+    cmd!(sh, "{bck} libvirt mount rm {domain_name} /mnt/share").run()?;
+    let mounted = cmd!(
+        sh,
+        "{bck} libvirt ssh {domain_name} -- mountpoint -q /mnt/share"
+    )
+    .ignore_status()
+    .output()?;
+    assert!(!mounted.status.success(), "/mnt/share is still mounted");
+    Ok(())
+}
+integration_test!(test_libvirt_mount_add);
@@ -25,8 +25,8 @@ use camino::Utf8Path;
 use tracing::debug;
 
 use crate::{
-    check_journal_coverage, get_bck_command, get_test_image, poll_until, shell,
-    INTEGRATION_TEST_LABEL,
+    check_journal_coverage, find_available_port, get_bck_command, get_test_image, poll_until,
+    shell, INTEGRATION_TEST_LABEL,
 };
 
 pub fn get_container_kernel_version(image: &str) -> String {
@@ -173,6 +173,284 @@ fn test_run_ephemeral_container_ssh_access() -> TestResult {
 }
 integration_test!(test_run_ephemeral_container_ssh_access);
 
+/// Test that `ephemeral stop` powers off a booted VM and its `--rm` container goes away
+fn test_run_ephemeral_stop() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let container_name = format!("bcvk-stop-test-{}", std::process::id());
+
+    cmd!(
+        sh,
+        "{bck} ephemeral run --rm --ssh-keygen --label {label} --detach --name {container_name} {image}"
+    )
+    .run()?;
+    // Wait for the guest to be up, so that it handles the ACPI power button
+    cmd!(sh, "{bck} ephemeral ssh {container_name} true").run()?;
+
+    let stdout = cmd!(sh, "{bck} ephemeral stop {container_name}").read()?;
+    assert_eq!(
+        stdout.trim(),
+        format!("VM '{container_name}' stopped successfully")
+    );
+
+    poll_until(
+        "stopped container to be removed",
+        std::time::Duration::from_secs(60),
+        std::time::Duration::from_millis(500),
+        || {
+            let status = cmd!(sh, "podman container exists {container_name}")
+                .ignore_status()
+                .output()?
+                .status;
+            Ok(!status.success())
+        },
+    )?;
+
+    // Stopping it again is an error, as it no longer exists
+    let output = cmd!(sh, "{bck} ephemeral stop {container_name}")
+        .ignore_status()
+        .output()?;
+    assert!(!output.status.success());
+    Ok(())
+}
+integration_test!(test_run_ephemeral_stop);
+
+/// Test that `ephemeral exec` keeps stdout and stderr apart and passes the exit status through
+fn test_run_ephemeral_exec() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let container_name = format!("bcvk-exec-test-{}", std::process::id());
+
+    cmd!(
+        sh,
+        "{bck} ephemeral run --rm --label {label} --detach --name {container_name} {image}"
+    )
+    .run()?;
+
+    let result = (|| -> anyhow::Result<()> {
+        // The exec listener is up once sockets.target is reached
+        poll_until(
+            "exec to be available",
+            std::time::Duration::from_secs(120),
+            std::time::Duration::from_secs(1),
+            || {
+                let output = cmd!(sh, "{bck} ephemeral exec {container_name} -- true")
+                    .ignore_status()
+                    .quiet()
+                    .output()?;
+                Ok(output.status.success())
+            },
+        )?;
+
+        let script = "echo out; echo err >&2; exit 3";
+        let output = cmd!(
+            sh,
+            "{bck} ephemeral exec {container_name} -- sh -c {script}"
+        )
+        .ignore_status()
+        .output()?;
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "out\n");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "err\n");
+        Ok(())
+    })();
+
+    let _ = cmd!(sh, "podman rm -f {container_name}")
+        .ignore_status()
+        .quiet()
+        .run();
+    result?;
+    Ok(())
+}
+integration_test!(test_run_ephemeral_exec);
+
+/// Test `ephemeral exec` with emulated guests: it works on aarch64, and fails
+/// up front on s390x, where the guest can't be given credentials
+fn test_run_ephemeral_exec_other_arches() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+
+    for arch in ["aarch64", "s390x"] {
+        let container_name = format!("bcvk-exec-{arch}-test-{}", std::process::id());
+        cmd!(
+            sh,
+            "{bck} ephemeral run --rm --label {label} --arch {arch} --detach --name {container_name} {image}"
+        )
+        .run()?;
+
+        let result = (|| -> anyhow::Result<()> {
+            let mut stdout = String::new();
+            let mut stderr = String::new();
+            // Booting under emulation is slow
+            poll_until(
+                &format!("exec on {arch}"),
+                std::time::Duration::from_secs(900),
+                std::time::Duration::from_secs(2),
+                || {
+                    let output = cmd!(sh, "{bck} ephemeral exec {container_name} -- uname -m")
+                        .ignore_status()
+                        .quiet()
+                        .output()?;
+                    stdout = String::from_utf8(output.stdout)?;
+                    stderr = String::from_utf8(output.stderr)?;
+                    Ok(output.status.success() || stderr.contains("exec is not available"))
+                },
+            )?;
+            if arch == "s390x" {
+                assert!(
+                    stderr.contains("Credentials are not supported for s390x guests"),
+                    "Unexpected error: {stderr}"
+                );
+            } else {
+                assert_eq!(stdout.trim(), arch, "stderr: {stderr}");
+            }
+            Ok(())
+        })();
+
+        let _ = cmd!(sh, "podman rm -f {container_name}")
+            .ignore_status()
+            .quiet()
+            .run();
+        result?;
+    }
+    Ok(())
+}
+integration_test!(test_run_ephemeral_exec_other_arches);
+
+/// Test copying a directory into a VM and back with `ephemeral cp`
+fn test_run_ephemeral_cp() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let container_name = format!("bcvk-cp-test-{}", std::process::id());
+
+    let td = TempDir::new()?;
+    let base = Utf8Path::from_path(td.path()).expect("temp dir path is not utf8");
+    let src = base.join("data");
+    fs::create_dir(&src)?;
+    fs::write(src.join("file.txt"), "copied content\n")?;
+    std::os::unix::fs::symlink("file.txt", src.join("link"))?;
+    let out = base.join("out");
+    fs::create_dir(&out)?;
+
+    cmd!(
+        sh,
+        "{bck} ephemeral run --rm --ssh-keygen --label {label} --detach --name {container_name} {image}"
+    )
+    .run()?;
+
+    let result = (|| -> anyhow::Result<()> {
+        cmd!(sh, "{bck} ephemeral ssh {container_name} true").run()?;
+
+        cmd!(sh, "{bck} ephemeral cp {src} {container_name}:/var/tmp").run()?;
+        let content = cmd!(
+            sh,
+            "{bck} ephemeral ssh {container_name} cat /var/tmp/data/link"
+        )
+        .read()?;
+        assert_eq!(content, "copied content");
+
+        cmd!(
+            sh,
+            "{bck} ephemeral ssh {container_name} touch /var/tmp/data/new.txt"
+        )
+        .run()?;
+        cmd!(
+            sh,
+            "{bck} ephemeral cp {container_name}:/var/tmp/data {out}"
+        )
+        .run()?;
+        assert_eq!(
+            fs::read_to_string(out.join("data/file.txt"))?,
+            "copied content\n"
+        );
+        assert!(out.join("data/new.txt").exists());
+        assert_eq!(
+            fs::read_link(out.join("data/link"))?,
+            std::path::Path::new("file.txt")
+        );
+        Ok(())
+    })();
+
+    let _ = cmd!(sh, "podman rm -f {container_name}")
+        .ignore_status()
+        .quiet()
+        .run();
+    result?;
+    Ok(())
+}
+integration_test!(test_run_ephemeral_cp);
+
+/// Test reaching a guest service through a port published with `-p`
+fn test_run_ephemeral_port_forward() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let container_name = format!("bcvk-port-test-{}", std::process::id());
+    let host_port = find_available_port()?;
+    let port_mapping = format!("{host_port}:8080");
+
+    // Publishing needs the container's own network namespace
+    let output = cmd!(
+        sh,
+        "{bck} ephemeral run --rm --label {label} --network host -p {port_mapping} {image}"
+    )
+    .ignore_status()
+    .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--port cannot be used with --network=host"),
+        "Unexpected error: {stderr}"
+    );
+
+    cmd!(
+        sh,
+        "{bck} ephemeral run --rm --ssh-keygen --label {label} --detach -p {port_mapping} --name {container_name} {image}"
+    )
+    .run()?;
+
+    let result = (|| -> anyhow::Result<()> {
+        let server = "echo port-forward-ok > /tmp/test.txt && cd /tmp && \
+            (exec python3 -m http.server 8080 > /tmp/http.log 2>&1 < /dev/null &)";
+        cmd!(sh, "{bck} ephemeral ssh {container_name} sh -c {server}").run()?;
+
+        let url = format!("http://127.0.0.1:{host_port}/test.txt");
+        let mut response = String::new();
+        poll_until(
+            "guest HTTP server via the published port",
+            std::time::Duration::from_secs(60),
+            std::time::Duration::from_secs(1),
+            || {
+                let output = cmd!(sh, "curl -sf -m 5 {url}")
+                    .ignore_status()
+                    .quiet()
+                    .output()?;
+                response = String::from_utf8(output.stdout)?;
+                Ok(output.status.success())
+            },
+        )?;
+        assert_eq!(response, "port-forward-ok\n");
+        Ok(())
+    })();
+
+    let _ = cmd!(sh, "podman rm -f {container_name}")
+        .ignore_status()
+        .quiet()
+        .run();
+    result?;
+    Ok(())
+}
+integration_test!(test_run_ephemeral_port_forward);
+
 fn test_run_ephemeral_with_instancetype() -> TestResult {
     let sh = shell()?;
     let bck = get_bck_command()?;
@@ -261,16 +539,11 @@ fn test_run_ephemeral_instancetype_invalid() -> TestResult {
 }
 integration_test!(test_run_ephemeral_instancetype_invalid);
 
-/// Test that ephemeral VMs can boot from UKI-only images (no separate vmlinuz/initramfs)
-///
-/// This tests compatibility with bootc images that only ship a Unified Kernel Image,
-/// verifying that bcvk can extract kernel/initramfs from the UKI using objcopy.
-fn test_run_ephemeral_uki_only() -> TestResult {
+/// Build the UKI-only test image from the fixture Dockerfile as `uki_image`
+fn build_uki_only_image(uki_image: &str) -> anyhow::Result<()> {
     let sh = shell()?;
     let base_image = get_test_image();
-    let uki_image = "bcvk-test-uki-only:latest";
 
-    // Build the UKI-only test image from the fixture Dockerfile
     let fixture_path =
         std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/Dockerfile.uki-only");
     let fixture_dir = fixture_path.parent().unwrap();
@@ -307,6 +580,17 @@ fn test_run_ephemeral_uki_only() -> TestResult {
         "UKI-only image should have a UKI in /boot/EFI/Linux/: {}",
         verify_stdout
     );
+    Ok(())
+}
+
+/// Test that ephemeral VMs can boot from UKI-only images (no separate vmlinuz/initramfs)
+///
+/// This tests compatibility with bootc images that only ship a Unified Kernel Image,
+/// verifying that bcvk can extract kernel/initramfs from the UKI using objcopy.
+fn test_run_ephemeral_uki_only() -> TestResult {
+    let sh = shell()?;
+    let uki_image = "bcvk-test-uki-only:latest";
+    build_uki_only_image(uki_image)?;
 
     // Run ephemeral VM from UKI-only image
     let label = INTEGRATION_TEST_LABEL;
@@ -333,6 +617,45 @@ fn test_run_ephemeral_uki_only() -> TestResult {
 }
 integration_test!(test_run_ephemeral_uki_only);
 
+/// Test that `--boot uefi` boots the unmodified UKI through OVMF and
+/// systemd-boot, with the same read-only root and writable /etc as direct boot
+fn test_run_ephemeral_uefi_boot() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let label = INTEGRATION_TEST_LABEL;
+    // A separate tag, as the UKI-only test removes its image when done
+    let uki_image = "bcvk-test-uki-uefi:latest";
+    build_uki_only_image(uki_image)?;
+
+    // systemd-boot sets LoaderInfo; the firmware booting the UKI directly does not
+    let script = "cat /sys/firmware/efi/efivars/LoaderInfo-4a67b082-0a4c-41cf-b6c7-440b29bb8c4f | tr -d '\\0' | grep -ao systemd-boot; \
+        findmnt -no OPTIONS / | tr , '\\n' | grep -x ro; \
+        touch /etc/bcvk-uefi-test && echo ETC_WRITABLE";
+    let stdout = cmd!(
+        sh,
+        "{bck} ephemeral run --rm --label {label} --boot uefi --execute {script} {uki_image}"
+    )
+    .read()?;
+
+    let lines: Vec<_> = stdout.lines().map(str::trim).collect();
+    assert!(
+        lines.contains(&"systemd-boot"),
+        "Not booted via systemd-boot: {stdout}"
+    );
+    assert!(lines.contains(&"ro"), "Root is not read-only: {stdout}");
+    assert!(
+        lines.contains(&"ETC_WRITABLE"),
+        "/etc is not writable: {stdout}"
+    );
+
+    let _ = cmd!(sh, "podman rmi -f {uki_image}")
+        .ignore_status()
+        .quiet()
+        .run();
+    Ok(())
+}
+integration_test!(test_run_ephemeral_uefi_boot);
+
 /// Test ephemeral boot with the CentOS 10 UKI image
 ///
 /// This tests a real-world UKI image that may have both UKI and traditional
@@ -607,3 +930,512 @@ fn test_run_ephemeral_journal_output() -> TestResult {
     Ok(())
 }
 integration_test!(test_run_ephemeral_journal_output);
+
+/// Test that `--credential`, `--credential-file` and `--secret` reach the guest
+/// as system credentials, and that the secret is not part of the container's
+/// configuration
+fn test_run_ephemeral_credentials() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let container_name = format!("bcvk-credentials-test-{id}");
+    let secret = format!("bcvk-test-secret-{id}");
+    let secret_value = format!("secret-value-{id}");
+
+    let tmp = TempDir::new()?;
+    let credential_file = tmp.path().join("file-credential");
+    fs::write(&credential_file, "from-file\n")?;
+
+    cmd!(sh, "podman secret create {secret} -")
+        .stdin(&secret_value)
+        .run()?;
+
+    let result = (|| -> anyhow::Result<()> {
+        cmd!(
+            sh,
+            "{bck} ephemeral run --rm --ssh-keygen --label {label} --detach --name {container_name} --credential bcvk.test.mode=test --credential-file bcvk.test.file={credential_file} --secret {secret}:bcvk.test.password {image}"
+        )
+        .run()?;
+
+        let config = cmd!(sh, "podman inspect {container_name}").read()?;
+        assert!(
+            !config.contains(&secret_value),
+            "Secret value found in the container configuration"
+        );
+
+        for (name, expected) in [
+            ("bcvk.test.mode", "test"),
+            ("bcvk.test.file", "from-file\n"),
+            ("bcvk.test.password", secret_value.as_str()),
+        ] {
+            let path = format!("/run/credentials/@system/{name}");
+            let value = cmd!(sh, "{bck} ephemeral ssh {container_name} cat {path}").read()?;
+            // read() trims the trailing newline
+            assert_eq!(value, expected.trim_end(), "credential {name}");
+        }
+        Ok(())
+    })();
+
+    let _ = cmd!(sh, "podman rm -f {container_name}")
+        .ignore_status()
+        .quiet()
+        .run();
+    let _ = cmd!(sh, "podman secret rm {secret}")
+        .ignore_status()
+        .quiet()
+        .run();
+    result?;
+    Ok(())
+}
+integration_test!(test_run_ephemeral_credentials);
+
+/// Test that `--cloud-init-user-data` provides a NoCloud seed that cloud-init
+/// in the guest applies, with the meta-data defaulting to the VM name
+fn test_run_ephemeral_cloud_init() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let label = INTEGRATION_TEST_LABEL;
+    let base_image = get_test_image();
+    let image = "bcvk-test-cloud-init:latest";
+    let container_name = format!("bcvk-cloud-init-test-{}", std::process::id());
+
+    let fixture_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures");
+    let build_arg = format!("BASE_IMAGE={base_image}");
+    cmd!(
+        sh,
+        "podman build -f {fixture_dir}/Dockerfile.cloud-init -t {image} --build-arg {build_arg} {fixture_dir}"
+    )
+    .run()?;
+
+    let tmp = TempDir::new()?;
+    let user_data = tmp.path().join("user-data");
+    // The SSH key of bcvk is installed for root, which cloud-init locks by default
+    let config = [
+        "#cloud-config",
+        "disable_root: false",
+        "write_files:",
+        "  - path: /etc/bcvk-cloud-init-test",
+        "    content: cloud-init-ok",
+    ];
+    fs::write(&user_data, config.join("\n") + "\n")?;
+
+    let result = (|| -> anyhow::Result<()> {
+        cmd!(
+            sh,
+            "{bck} ephemeral run --rm --ssh-keygen --label {label} --detach --name {container_name} --cloud-init-user-data {user_data} {image}"
+        )
+        .run()?;
+
+        // Recoverable errors such as deprecated keys exit with 2; the checks
+        // below tell whether the seed was applied
+        cmd!(
+            sh,
+            "{bck} ephemeral ssh {container_name} cloud-init status --wait"
+        )
+        .ignore_status()
+        .run()?;
+        let content = cmd!(
+            sh,
+            "{bck} ephemeral ssh {container_name} cat /etc/bcvk-cloud-init-test"
+        )
+        .read()?;
+        assert_eq!(content, "cloud-init-ok");
+        let hostname = cmd!(
+            sh,
+            "{bck} ephemeral ssh {container_name} cloud-init query local_hostname"
+        )
+        .read()?;
+        assert_eq!(hostname, container_name);
+        Ok(())
+    })();
+
+    let _ = cmd!(sh, "podman rm -f {container_name}")
+        .ignore_status()
+        .quiet()
+        .run();
+    let _ = cmd!(sh, "podman rmi -f {image}")
+        .ignore_status()
+        .quiet()
+        .run();
+    result?;
+    Ok(())
+}
+integration_test!(test_run_ephemeral_cloud_init);
+
+/// Test that `ephemeral logs` shows the guest journal of a VM started without
+/// any log options, filtered by priority, and that `--follow` shows new entries
+fn test_run_ephemeral_logs() -> TestResult {
+    use std::io::BufRead as _;
+
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let container_name = format!("bcvk-logs-test-{}", std::process::id());
+
+    cmd!(
+        sh,
+        "{bck} ephemeral run --rm --ssh-keygen --label {label} --detach --name {container_name} {image}"
+    )
+    .run()?;
+
+    let result = (|| -> anyhow::Result<()> {
+        cmd!(
+            sh,
+            "{bck} ephemeral ssh {container_name} logger -p user.err -t bcvk-logs-test first-marker"
+        )
+        .run()?;
+        let mut entries = Vec::new();
+        poll_until(
+            "logged entry in ephemeral logs",
+            std::time::Duration::from_secs(60),
+            std::time::Duration::from_secs(1),
+            || {
+                let output = cmd!(
+                    sh,
+                    "{bck} ephemeral logs {container_name} --priority err --output json"
+                )
+                .quiet()
+                .read()?;
+                entries = output
+                    .lines()
+                    .map(serde_json::from_str::<serde_json::Value>)
+                    .collect::<Result<_, _>>()?;
+                Ok(entries.iter().any(|e| e["MESSAGE"] == "first-marker"))
+            },
+        )?;
+        for entry in &entries {
+            let priority: u8 = entry["PRIORITY"].as_str().unwrap_or_default().parse()?;
+            assert!(priority <= 3, "Entry above --priority err: {entry}");
+        }
+
+        let mut follow = std::process::Command::new(&bck)
+            .args(["ephemeral", "logs", "--follow", &container_name])
+            .stdout(std::process::Stdio::piped())
+            .spawn()?;
+        let stdout = follow.stdout.take().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::BufReader::new(stdout)
+                .lines()
+                .map_while(Result::ok)
+            {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        cmd!(
+            sh,
+            "{bck} ephemeral ssh {container_name} logger -t bcvk-logs-test second-marker"
+        )
+        .run()?;
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(60);
+        let followed = loop {
+            match rx.recv_timeout(deadline.saturating_duration_since(std::time::Instant::now())) {
+                Ok(line) if line.contains("second-marker") => break true,
+                Ok(_) => continue,
+                Err(_) => break false,
+            }
+        };
+        let _ = follow.kill();
+        let _ = follow.wait();
+        assert!(
+            followed,
+            "--follow did not show the entry logged after it started"
+        );
+        Ok(())
+    })();
+
+    let _ = cmd!(sh, "podman rm -f {container_name}")
+        .ignore_status()
+        .quiet()
+        .run();
+    result?;
+    Ok(())
+}
+integration_test!(test_run_ephemeral_logs);
+
+/// Test that `--log-dir journal-file=DIR` writes journal files of the initrd
+/// and the real root that `journalctl --file` reads, complete once a
+/// foreground run exits
+fn test_run_ephemeral_journal_file() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+
+    let log_dir = tempfile::tempdir_in("/var/tmp")?;
+    let log_dir_path = log_dir.path().to_str().unwrap().to_owned();
+
+    cmd!(
+        sh,
+        "{bck} ephemeral run --rm --label {label} --log-dir journal-file={log_dir_path} --execute true {image}"
+    )
+    .run()?;
+
+    // No polling: bcvk only exits once systemd-journal-remote is done
+    for name in ["journal.journal", "journal-initrd.journal"] {
+        let path = log_dir.path().join(name);
+        let output = cmd!(sh, "journalctl --file {path} -o json").read()?;
+        let entries = output
+            .lines()
+            .map(serde_json::from_str::<serde_json::Value>)
+            .collect::<Result<Vec<_>, _>>()?;
+        assert!(!entries.is_empty(), "{name} has no entries");
+        assert!(
+            entries.iter().any(|e| e.get("MESSAGE_ID").is_some()),
+            "{name} has no structured entries"
+        );
+    }
+    Ok(())
+}
+integration_test!(test_run_ephemeral_journal_file);
+
+/// Test that a guest kernel panic stops the VM with the panic exit code, and
+/// that `ephemeral ps` reports it
+fn test_run_ephemeral_panic_in_ps() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let container_name = format!("bcvk-panic-test-{}", std::process::id());
+
+    // Without --rm, so the exited container is still listed
+    cmd!(
+        sh,
+        "{bck} ephemeral run --ssh-keygen --label {label} --detach --name {container_name} {image}"
+    )
+    .run()?;
+
+    let result = (|| -> anyhow::Result<()> {
+        // The connection dies with the guest
+        let panic = "echo c > /proc/sysrq-trigger";
+        let _ = cmd!(sh, "{bck} ephemeral ssh {container_name} sh -c {panic}")
+            .ignore_status()
+            .quiet()
+            .output()?;
+
+        let mut failure = serde_json::Value::Null;
+        poll_until(
+            "panicked VM in ephemeral ps",
+            std::time::Duration::from_secs(60),
+            std::time::Duration::from_secs(1),
+            || {
+                let output = cmd!(sh, "{bck} ephemeral ps --json").quiet().read()?;
+                let containers: Vec<serde_json::Value> = serde_json::from_str(&output)?;
+                let Some(container) = containers.into_iter().find(|c| {
+                    c["Names"]
+                        .as_array()
+                        .is_some_and(|names| names.iter().any(|n| *n == container_name))
+                }) else {
+                    anyhow::bail!("{container_name} not listed by ephemeral ps");
+                };
+                failure = container["Failure"].clone();
+                Ok(container["State"] == "exited")
+            },
+        )?;
+        assert_eq!(failure["kind"], "kernel-panic", "Failure: {failure}");
+
+        let format_arg = "{{.State.ExitCode}}";
+        let exit_code = cmd!(sh, "podman inspect --format {format_arg} {container_name}").read()?;
+        assert_eq!(exit_code, "3");
+        Ok(())
+    })();
+
+    let _ = cmd!(sh, "podman rm -f {container_name}")
+        .ignore_status()
+        .quiet()
+        .run();
+    result?;
+    Ok(())
+}
+integration_test!(test_run_ephemeral_panic_in_ps);
+
+/// Test that `--dump-on-panic DIR` writes an ELF memory dump and the kernel's
+/// System.map to DIR when the guest panics
+fn test_run_ephemeral_dump_on_panic() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let container_name = format!("bcvk-dump-test-{}", std::process::id());
+
+    let dump_dir = tempfile::tempdir_in("/var/tmp")?;
+    let dump_dir_path = dump_dir.path().to_str().unwrap().to_owned();
+
+    cmd!(
+        sh,
+        "{bck} ephemeral run --ssh-keygen --label {label} --detach --name {container_name} --memory 2G --dump-on-panic {dump_dir_path} {image}"
+    )
+    .run()?;
+
+    let result = (|| -> anyhow::Result<()> {
+        // The connection dies with the guest
+        let panic = "echo c > /proc/sysrq-trigger";
+        let _ = cmd!(sh, "{bck} ephemeral ssh {container_name} sh -c {panic}")
+            .ignore_status()
+            .quiet()
+            .output()?;
+
+        // The supervisor exits once the dump is written
+        let format_arg = "{{.State.Status}} {{.State.ExitCode}}";
+        let mut state = String::new();
+        poll_until(
+            "VM exit after dumping its memory",
+            std::time::Duration::from_secs(180),
+            std::time::Duration::from_secs(1),
+            || {
+                state = cmd!(sh, "podman inspect --format {format_arg} {container_name}")
+                    .quiet()
+                    .read()?;
+                Ok(state.starts_with("exited"))
+            },
+        )?;
+        assert_eq!(state, "exited 3");
+
+        let vmcore = fs::read(dump_dir.path().join("vmcore"))?;
+        assert!(vmcore.starts_with(b"\x7fELF"), "vmcore is not an ELF core");
+        let system_map = fs::read_to_string(dump_dir.path().join("System.map"))?;
+        assert!(
+            system_map.lines().any(|l| l.ends_with(" T panic")),
+            "System.map has no panic symbol"
+        );
+        Ok(())
+    })();
+
+    let _ = cmd!(sh, "podman rm -f {container_name}")
+        .ignore_status()
+        .quiet()
+        .run();
+    result?;
+    Ok(())
+}
+integration_test!(test_run_ephemeral_dump_on_panic);
+
+/// Test that `--max-runtime` and `--watchdog` power off a VM that runs too
+/// long or stops responding, with their distinct exit codes
+fn test_run_ephemeral_liveness_exit_codes() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let container_name = format!("bcvk-watchdog-test-{}", std::process::id());
+
+    let output = cmd!(
+        sh,
+        "{bck} ephemeral run --rm --label {label} --max-runtime 30s --execute 'sleep 600' {image}"
+    )
+    .ignore_status()
+    .output()?;
+    assert_eq!(output.status.code(), Some(7), "--max-runtime exit code");
+
+    // Without --rm, so the exit code can be inspected
+    cmd!(
+        sh,
+        "{bck} ephemeral run --ssh-keygen --label {label} --detach --name {container_name} --vcpus 1 --watchdog {image}"
+    )
+    .run()?;
+
+    let result = (|| -> anyhow::Result<()> {
+        // The guest's systemd pings the watchdog once the boot finished;
+        // degraded systems exit non-zero
+        cmd!(
+            sh,
+            "{bck} ephemeral ssh {container_name} systemctl is-system-running --wait"
+        )
+        .ignore_status()
+        .run()?;
+        // A real-time busy loop on the only CPU starves systemd; the
+        // connection dies with the VM
+        let hang = "echo -1 > /proc/sys/kernel/sched_rt_runtime_us && \
+            (exec chrt -f 99 sh -c 'while :; do :; done' > /dev/null 2>&1 < /dev/null &)";
+        let _ = cmd!(sh, "{bck} ephemeral ssh {container_name} sh -c {hang}")
+            .ignore_status()
+            .quiet()
+            .output()?;
+
+        let format_arg = "{{.State.Status}} {{.State.ExitCode}}";
+        let mut state = String::new();
+        poll_until(
+            "VM powered off by the watchdog",
+            std::time::Duration::from_secs(180),
+            std::time::Duration::from_secs(1),
+            || {
+                state = cmd!(sh, "podman inspect --format {format_arg} {container_name}")
+                    .quiet()
+                    .read()?;
+                Ok(state.starts_with("exited"))
+            },
+        )?;
+        assert_eq!(state, "exited 6", "--watchdog exit code");
+        Ok(())
+    })();
+
+    let _ = cmd!(sh, "podman rm -f {container_name}")
+        .ignore_status()
+        .quiet()
+        .run();
+    result?;
+    Ok(())
+}
+integration_test!(test_run_ephemeral_liveness_exit_codes);
+
+/// Test that `--pull` only pulls as its policy says, using a local tag that
+/// cannot be pulled from any registry
+fn test_run_ephemeral_pull_policy() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let local_tag = format!("localhost/bcvk-pull-test:{id}");
+    let absent = format!("localhost/bcvk-pull-test-absent:{id}");
+
+    cmd!(sh, "podman tag {image} {local_tag}").run()?;
+
+    let result = (|| -> anyhow::Result<()> {
+        for (policy, image, expected_error) in [
+            ("missing", &local_tag, None),
+            ("never", &local_tag, None),
+            ("always", &local_tag, Some("Failed to pull")),
+            ("missing", &absent, Some("Failed to pull")),
+            ("never", &absent, Some("--pull=never")),
+        ] {
+            let output = cmd!(
+                sh,
+                "{bck} ephemeral run --rm --label {label} --pull {policy} --karg systemd.unit=poweroff.target {image}"
+            )
+            .ignore_status()
+            .output()?;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            match expected_error {
+                None => assert!(
+                    output.status.success(),
+                    "--pull {policy} {image} failed: {stderr}"
+                ),
+                Some(error) => {
+                    assert!(
+                        !output.status.success(),
+                        "--pull {policy} {image} succeeded"
+                    );
+                    assert!(
+                        stderr.contains(error),
+                        "--pull {policy} {image}: unexpected error: {stderr}"
+                    );
+                }
+            }
+        }
+        Ok(())
+    })();
+
+    let _ = cmd!(sh, "podman rmi {local_tag}")
+        .ignore_status()
+        .quiet()
+        .run();
+    result?;
+    Ok(())
+}
+integration_test!(test_run_ephemeral_pull_policy);
@@ -153,6 +153,113 @@ fn test_run_ephemeral_ssh_exit_code() -> TestResult {
 }
 integration_test!(test_run_ephemeral_ssh_exit_code);
 
+/// Test that `--accel tcg` runs the guest under software emulation
+fn test_run_ephemeral_ssh_accel_tcg() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+
+    // Under KVM this would be "kvm"
+    let stdout = cmd!(
+        sh,
+        "{bck} ephemeral run-ssh --label {label} --accel tcg {image} -- systemd-detect-virt"
+    )
+    .read()?;
+    assert_eq!(stdout.trim(), "qemu");
+    Ok(())
+}
+integration_test!(test_run_ephemeral_ssh_accel_tcg);
+
+/// Test that `--arch` boots the variant of the image for another architecture,
+/// leaving the local image of the given name alone
+fn test_run_ephemeral_ssh_foreign_arch() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let (arch, oci_arch) = match std::env::consts::ARCH {
+        "x86_64" => ("aarch64", "arm64"),
+        _ => ("x86_64", "amd64"),
+    };
+    let format_arg = "{{.Architecture}}";
+    let before = cmd!(sh, "podman image inspect --format {format_arg} {image}").read()?;
+
+    let stdout = cmd!(
+        sh,
+        "{bck} ephemeral run-ssh --label {label} --arch {arch} {image} -- uname -m"
+    )
+    .read()?;
+    assert_eq!(stdout.trim(), arch);
+
+    let after = cmd!(sh, "podman image inspect --format {format_arg} {image}").read()?;
+    assert_eq!(before, after, "{image} was replaced by the {arch} variant");
+    // Test images are referenced by tag
+    let variant = format!("{image}-{arch}");
+    let variant_arch = cmd!(sh, "podman image inspect --format {format_arg} {variant}").read()?;
+    assert_eq!(variant_arch, oci_arch);
+    Ok(())
+}
+integration_test!(test_run_ephemeral_ssh_foreign_arch);
+
+/// Test that `--tpm` gives the guest a TPM 2.0, whose state is kept in `--tpm-state`
+///
+/// This needs swtpm on the host.
+fn test_run_ephemeral_ssh_tpm() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    let state = tempfile::tempdir()?;
+    let state_dir = state.path();
+
+    let stdout = cmd!(
+        sh,
+        "{bck} ephemeral run-ssh --label {label} --tpm --tpm-state {state_dir} {image} -- cat /sys/class/tpm/tpm0/tpm_version_major"
+    )
+    .read()?;
+    assert_eq!(stdout.trim(), "2");
+    // swtpm keeps the TPM 2.0 state in this file
+    assert!(
+        state_dir.join("tpm2-00.permall").exists(),
+        "TPM state was not written to {state_dir:?}"
+    );
+    Ok(())
+}
+integration_test!(test_run_ephemeral_ssh_tpm);
+
+/// Test booting an image from an `oci-archive:`, which is imported into a
+/// temporary store rather than the user's storage
+fn test_run_ephemeral_ssh_oci_archive() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let image = get_test_image();
+    let label = INTEGRATION_TEST_LABEL;
+    // Archives of bootc images are large, keep them off a /tmp tmpfs
+    let td = tempfile::tempdir_in("/var/tmp")?;
+    let archive = td.path().join("image.tar");
+
+    cmd!(sh, "podman save --format oci-archive -o {archive} {image}").run()?;
+    let stdout = cmd!(
+        sh,
+        "{bck} ephemeral run-ssh --label {label} oci-archive:{archive} -- echo 'booted from archive'"
+    )
+    .read()?;
+    assert!(
+        stdout.contains("booted from archive"),
+        "Expected output not found. Got: {stdout}"
+    );
+
+    let format_arg = "{{.Repository}}";
+    let images = cmd!(sh, "podman images --format {format_arg}").read()?;
+    assert!(
+        !images.lines().any(|repo| repo == "localhost/bcvk-import"),
+        "Imported image leaked into the default storage: {images}"
+    );
+    Ok(())
+}
+integration_test!(test_run_ephemeral_ssh_oci_archive);
+
 /// Test SSH functionality across different bootc images
 /// This parameterized test runs once per image in BCVK_ALL_IMAGES and verifies
 /// that our systemd version compatibility fix works correctly with both newer
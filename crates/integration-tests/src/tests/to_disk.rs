@@ -127,6 +127,80 @@ fn test_to_disk_qcow2() -> TestResult {
 }
 integration_test!(test_to_disk_qcow2);
 
+/// Fedora release whose netinst ISO is used by [`test_to_disk_via_iso`]
+const INSTALLER_ISO_RELEASE: &str = "42";
+
+/// The installer ISO for the host architecture: `BCVK_INSTALLER_ISO` if set,
+/// otherwise the Fedora netinst ISO, downloaded once into /var/tmp
+fn installer_iso() -> anyhow::Result<Utf8PathBuf> {
+    if let Ok(iso) = std::env::var("BCVK_INSTALLER_ISO") {
+        return Ok(iso.into());
+    }
+    let arch = std::env::consts::ARCH;
+    let release = INSTALLER_ISO_RELEASE;
+    let iso = Utf8PathBuf::from(format!("/var/tmp/bcvk-test-netinst-{arch}-{release}.iso"));
+    if !iso.exists() {
+        let sh = shell()?;
+        let url = format!(
+            "https://download.fedoraproject.org/pub/fedora/linux/releases/{release}/Everything/{arch}/iso/Fedora-Everything-netinst-{arch}-{release}-1.1.iso"
+        );
+        let partial = format!("{iso}.partial");
+        cmd!(sh, "curl -fsSL -o {partial} {url}").run()?;
+        std::fs::rename(&partial, &iso)?;
+    }
+    Ok(iso)
+}
+
+/// Test installing with Anaconda from an installer ISO, and that the disk is
+/// cached for the same ISO and kickstart
+fn test_to_disk_via_iso() -> TestResult {
+    let sh = shell()?;
+    let bck = get_bck_command()?;
+    let label = INTEGRATION_TEST_LABEL;
+    let image = get_test_image();
+    let iso = installer_iso()?;
+
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let base = Utf8PathBuf::try_from(temp_dir.path().to_owned()).expect("temp path is not UTF-8");
+    let kickstart = base.join("ks.cfg");
+    std::fs::write(
+        &kickstart,
+        "zerombr\nclearpart --all --initlabel\nautopart --type=plain --nohome\nrootpw --lock\n",
+    )?;
+    let disk_path = base.join("test-disk-iso.img");
+
+    cmd!(
+        sh,
+        "{bck} to-disk --label {label} --via-iso {iso} --kickstart {kickstart} {image} {disk_path}"
+    )
+    .run()?;
+
+    // Anaconda's autopart creates a root partition
+    let table: serde_json::Value =
+        serde_json::from_str(&cmd!(sh, "sfdisk --json {disk_path}").read()?)?;
+    let partitions = table["partitiontable"]["partitions"]
+        .as_array()
+        .expect("No partitions in sfdisk output");
+    assert!(
+        partitions.len() >= 2,
+        "Expected a boot and root partition: {table}"
+    );
+
+    let output = cmd!(
+        sh,
+        "{bck} to-disk --label {label} --via-iso {iso} --kickstart {kickstart} {image} {disk_path}"
+    )
+    .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Reusing existing cached disk image"),
+        "Second run should have reused the cached disk: {stdout}"
+    );
+    Ok(())
+}
+integration_test!(test_to_disk_via_iso);
+
 /// Test disk image caching functionality
 fn test_to_disk_caching() -> TestResult {
     let sh = shell()?;
@@ -51,6 +51,8 @@ struct EphemeralRunOpts {
     bind: Option<Vec<String>>,
     ro_bind: Option<Vec<String>>,
     mount_disk_files: Option<Vec<String>>,
+    port: Option<Vec<String>>,
+    internal_network: Option<String>,
     kargs: Option<Vec<String>>,
     add_swap: Option<String>,
 }
@@ -71,6 +73,12 @@ struct GetSshConnectionInfoReply {
     port: u16,
 }
 
+#[derive(Debug, Deserialize)]
+struct StopReply {
+    was_running: bool,
+    graceful: bool,
+}
+
 #[derive(Debug, Deserialize)]
 #[allow(dead_code)]
 struct ToDiskReply {
@@ -162,6 +170,13 @@ trait EphemeralProxy {
         &mut self,
         container_id: String,
     ) -> zlink::Result<Result<GetSshConnectionInfoReply, EphemeralError>>;
+
+    async fn stop(
+        &mut self,
+        container_id: String,
+        timeout: Option<u32>,
+        force: Option<bool>,
+    ) -> zlink::Result<Result<StopReply, EphemeralError>>;
 }
 
 #[zlink::proxy("io.bootc.vk.todisk")]
@@ -392,6 +407,56 @@ fn test_varlink_ephemeral_run_ps_and_ssh() -> TestResult {
 }
 integration_test!(test_varlink_ephemeral_run_ps_and_ssh);
 
+/// Run a VM with `rm`, stop it with `Stop`, and check that the container is gone.
+fn test_varlink_ephemeral_stop() -> TestResult {
+    let image = get_test_image();
+    let mut bcvk = activated_connection()?;
+
+    let run_reply = bcvk.rt.block_on(async {
+        bcvk.conn
+            .run(
+                image.clone(),
+                Some(EphemeralRunOpts {
+                    rm: Some(true),
+                    ..Default::default()
+                }),
+            )
+            .await
+    })??;
+    let id = run_reply.container_id;
+
+    // Killing does not depend on how far the guest booted
+    let reply = bcvk
+        .rt
+        .block_on(async { bcvk.conn.stop(id.clone(), Some(10), Some(true)).await })??;
+    assert!(reply.was_running, "expected {id} to be running");
+    assert!(!reply.graceful, "a forced stop must not be graceful");
+
+    crate::poll_until(
+        "stopped container to be removed",
+        std::time::Duration::from_secs(60),
+        std::time::Duration::from_millis(500),
+        || {
+            let status = Command::new("podman")
+                .args(["container", "exists", &id])
+                .status()?;
+            Ok(!status.success())
+        },
+    )
+    .inspect_err(|_| cleanup_container(&id))?;
+
+    // A removed container is an error
+    let r = bcvk
+        .rt
+        .block_on(async { bcvk.conn.stop(id.clone(), None, None).await })?;
+    assert!(
+        r.is_err(),
+        "expected an error stopping removed container {id}"
+    );
+    Ok(())
+}
+integration_test!(test_varlink_ephemeral_stop);
+
 // ===========================================================================
 // Tests: io.bootc.vk.todisk
 // ===========================================================================
@@ -5,6 +5,70 @@
 
 use crate::xml_utils::XmlWriter;
 use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+/// Guest CPU architecture, as selected with `--arch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    #[value(name = "x86_64")]
+    X86_64,
+    Aarch64,
+    S390x,
+    Ppc64le,
+}
+
+impl Arch {
+    /// The architecture of the host, if supported
+    pub fn host() -> Option<Self> {
+        match std::env::consts::ARCH {
+            "x86_64" => Some(Self::X86_64),
+            "aarch64" => Some(Self::Aarch64),
+            "s390x" => Some(Self::S390x),
+            "powerpc64" if cfg!(target_endian = "little") => Some(Self::Ppc64le),
+            _ => None,
+        }
+    }
+
+    /// Whether this differs from the host architecture (and so needs emulation)
+    pub fn is_foreign(self) -> bool {
+        Self::host() != Some(self)
+    }
+
+    /// The name used on the command line and in disk metadata
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::X86_64 => "x86_64",
+            Self::Aarch64 => "aarch64",
+            Self::S390x => "s390x",
+            Self::Ppc64le => "ppc64le",
+        }
+    }
+
+    /// The QEMU target name, as in `qemu-system-<arch>` and firmware descriptors
+    pub fn qemu_arch(self) -> &'static str {
+        match self {
+            Self::Ppc64le => "ppc64",
+            o => o.as_str(),
+        }
+    }
+
+    /// The OCI platform architecture, as used with `podman --platform`
+    pub fn oci_arch(self) -> &'static str {
+        match self {
+            Self::X86_64 => "amd64",
+            Self::Aarch64 => "arm64",
+            Self::S390x => "s390x",
+            Self::Ppc64le => "ppc64le",
+        }
+    }
+}
+
+impl std::fmt::Display for Arch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 /// Architecture configuration for libvirt domains and QEMU
 #[derive(Debug, Clone)]
@@ -97,6 +161,23 @@ pub fn is_aarch64() -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_guest_arch() {
+        let host = Arch::host().unwrap();
+        assert_eq!(host.qemu_arch(), crate::qemu::host_arch());
+        assert!(!host.is_foreign());
+
+        for arch in [Arch::X86_64, Arch::Aarch64, Arch::S390x, Arch::Ppc64le] {
+            // The CLI, serde and display names all agree
+            let parsed = <Arch as clap::ValueEnum>::from_str(arch.as_str(), false).unwrap();
+            assert_eq!(parsed, arch);
+            let json = serde_json::to_string(&arch).unwrap();
+            assert_eq!(json, format!("\"{arch}\""));
+        }
+        assert_eq!(Arch::Aarch64.oci_arch(), "arm64");
+        assert_eq!(Arch::Ppc64le.qemu_arch(), "ppc64");
+    }
+
     #[test]
     fn test_arch_detection() {
         let arch_config = ArchConfig::detect().unwrap();
@@ -0,0 +1,329 @@
+//! Detection of guests that failed to boot
+//!
+//! Rather than waiting for SSH until a timeout, the supervisor watches for
+//! signs that the guest will never come up: kernel panics (reported by the
+//! pvpanic device over QMP), kernel oopses and BUGs, and emergency shells
+//! (systemd's `emergency.target` or dracut's). These are found in the guest
+//! journal stream, which includes the kernel log, and in the unit
+//! notifications of the guest's systemd. The expiry of the hardware watchdog
+//! and of `--max-runtime` (see [`crate::watchdog`]) are reported the same way.
+//!
+//! A failure is recorded as [`SupervisorState::Failed`] along with the last
+//! [`CONSOLE_TAIL_LINES`] lines of guest output, for the host side to show.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use camino::Utf8Path;
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+use crate::crash_dump::{self, DumpFormat};
+use crate::qemu::QmpClient;
+use crate::run_ephemeral::{journal_json_to_text, SOURCE_IMAGE_PATH};
+use crate::supervisor_status::{
+    FailureKind, GuestFailure, StatusWriter, SupervisorState, SUPERVISOR_STATUS_PATH,
+};
+
+/// Number of guest output lines kept for a failure report
+pub(crate) const CONSOLE_TAIL_LINES: usize = 50;
+
+/// The most recent lines of guest output
+static TAIL: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Signaled once a failure was reported
+fn failed() -> &'static Notify {
+    static FAILED: OnceLock<Notify> = OnceLock::new();
+    FAILED.get_or_init(Notify::new)
+}
+
+/// Kernel log messages that mean the kernel is broken. Not every `BUG:` is:
+/// lockup reports such as `BUG: workqueue lockup` are common under TCG and
+/// the guest usually recovers.
+const KERNEL_PATTERNS: &[(&str, FailureKind)] = &[
+    ("Kernel panic - not syncing", FailureKind::KernelPanic),
+    ("Oops:", FailureKind::KernelOops),
+    ("kernel BUG at ", FailureKind::KernelOops),
+    (
+        "BUG: kernel NULL pointer dereference",
+        FailureKind::KernelOops,
+    ),
+    ("BUG: unable to handle", FailureKind::KernelOops),
+    ("general protection fault", FailureKind::KernelOops),
+];
+
+/// Units that are only started when the boot failed
+const EMERGENCY_UNITS: &[&str] = &["emergency.target", "dracut-emergency.service"];
+
+/// Check a unit that became active in the guest.
+pub(crate) fn classify_unit(unit: &str) -> Option<(FailureKind, String)> {
+    EMERGENCY_UNITS
+        .contains(&unit)
+        .then(|| (FailureKind::EmergencyShell, format!("Guest entered {unit}")))
+}
+
+/// Check a single guest journal entry (as produced by `journalctl -o json`).
+fn classify_journal_entry(entry: &serde_json::Value) -> Option<(FailureKind, String)> {
+    let str_field = |key: &str| entry.get(key).and_then(|v| v.as_str());
+    let message = str_field("MESSAGE")?;
+    if str_field("_TRANSPORT") == Some("kernel") {
+        return KERNEL_PATTERNS
+            .iter()
+            .find(|(pattern, _)| message.starts_with(pattern))
+            .map(|(_, kind)| (*kind, message.to_owned()));
+    }
+    // systemd logs the completed start job of the target
+    if let Some(unit) = str_field("UNIT") {
+        if str_field("JOB_TYPE") == Some("start") && str_field("JOB_RESULT") == Some("done") {
+            return classify_unit(unit);
+        }
+    }
+    // dracut without systemd in the initrd
+    if message.starts_with("Entering emergency mode") {
+        return Some((FailureKind::EmergencyShell, message.to_owned()));
+    }
+    None
+}
+
+/// Record that the guest failed, with the recent guest output.
+pub(crate) fn report(kind: FailureKind, reason: String) {
+    if GuestFailure::current().is_some() {
+        return;
+    }
+    warn!("Guest failure detected: {reason}");
+    let console = TAIL.lock().unwrap().iter().cloned().collect();
+    let failure = GuestFailure {
+        kind,
+        reason,
+        console,
+    };
+    if let Err(e) = StatusWriter::new(SUPERVISOR_STATUS_PATH).fail(failure) {
+        warn!("Failed to write guest failure status: {e}");
+    }
+    failed().notify_one();
+}
+
+/// Keep a line of the guest journal, reporting a failure if it shows one.
+pub(crate) fn observe_journal_line(line: &str) {
+    let Ok(entry) = serde_json::from_str::<serde_json::Value>(line) else {
+        return;
+    };
+    if let Some(text) = journal_json_to_text(line) {
+        let mut tail = TAIL.lock().unwrap();
+        if tail.len() == CONSOLE_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(text.trim_end().to_owned());
+    }
+    if let Some((kind, reason)) = classify_journal_entry(&entry) {
+        report(kind, reason);
+    }
+}
+
+/// How long to wait for QEMU to report a panic already seen in the journal
+const PANIC_EVENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Watch the QMP events of the VM until QEMU exits, reporting a failure
+/// when the guest panicked or its watchdog fired, or when it ran for longer
+/// than `max_runtime`, and stopping QEMU once any failure was reported.
+///
+/// With `dump_format`, the memory of a panicked guest is dumped first.
+pub(crate) async fn watch_qmp_events(
+    mut qmp: QmpClient,
+    dump_format: Option<DumpFormat>,
+    max_runtime: Option<Duration>,
+) {
+    let mut panicked = false;
+    let deadline = async {
+        match max_runtime {
+            Some(d) => tokio::time::sleep(d).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            event = qmp.next_event() => match event {
+                Ok(e) if e.event == "GUEST_PANICKED" => {
+                    debug!("Got {e:?}");
+                    panicked = true;
+                    if let Some(format) = dump_format {
+                        dump(&mut qmp, format).await;
+                    }
+                    report(
+                        FailureKind::KernelPanic,
+                        "Guest kernel panicked (reported by QEMU)".to_owned(),
+                    );
+                }
+                // QEMU powers off the VM right after this
+                Ok(e) if e.event == "WATCHDOG" => {
+                    debug!("Got {e:?}");
+                    report(
+                        FailureKind::Watchdog,
+                        "The guest stopped responding and its watchdog fired".to_owned(),
+                    );
+                }
+                Ok(e) => debug!("Ignoring QMP event {}", e.event),
+                Err(e) => {
+                    debug!("QMP event stream ended: {e}");
+                    return;
+                }
+            },
+            _ = failed().notified() => break,
+            _ = &mut deadline => {
+                let limit = max_runtime.unwrap_or_default();
+                report(
+                    FailureKind::Timeout,
+                    format!("The VM ran longer than --max-runtime ({}s)", limit.as_secs()),
+                );
+                break;
+            }
+        }
+    }
+    // The kernel log may show the panic before pvpanic reports it
+    let journal_panic = GuestFailure::current().is_some_and(|f| f.kind == FailureKind::KernelPanic);
+    if let Some(format) = dump_format.filter(|_| journal_panic && !panicked) {
+        let wait = async {
+            while let Ok(e) = qmp.next_event().await {
+                if e.event == "GUEST_PANICKED" {
+                    return true;
+                }
+            }
+            false
+        };
+        match tokio::time::timeout(PANIC_EVENT_TIMEOUT, wait).await {
+            Ok(true) => dump(&mut qmp, format).await,
+            Ok(false) => return,
+            Err(_) => warn!("QEMU did not report the guest panic; not dumping guest memory"),
+        }
+    }
+    // There is no point in waiting for a timeout; the reply may be lost if
+    // QEMU exits first.
+    debug!("Stopping QEMU after guest failure");
+    if let Err(e) = qmp.quit().await {
+        debug!("QMP quit: {e}");
+    }
+}
+
+/// Dump the memory of the panicked guest, logging failures.
+async fn dump(qmp: &mut QmpClient, format: DumpFormat) {
+    let source_root = Utf8Path::new(SOURCE_IMAGE_PATH);
+    if let Err(e) = crash_dump::dump_in_container(qmp, source_root, format).await {
+        warn!("Failed to dump guest memory: {e:#}");
+    }
+}
+
+/// Describe a guest failure for the user, with the recent guest output.
+pub(crate) fn describe(failure: &GuestFailure) -> String {
+    let mut r = format!("{}: {}", failure.kind.summary(), failure.reason);
+    if !failure.console.is_empty() {
+        r.push_str("\nLast guest output:\n");
+        for line in &failure.console {
+            r.push_str("  ");
+            r.push_str(line);
+            r.push('\n');
+        }
+    }
+    r
+}
+
+/// Check whether a supervisor state is a failure.
+pub(crate) fn as_failure(state: Option<&SupervisorState>) -> Option<&GuestFailure> {
+    match state {
+        Some(SupervisorState::Failed(failure)) => Some(failure),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(json: &str) -> Option<FailureKind> {
+        classify_journal_entry(&serde_json::from_str(json).unwrap()).map(|(kind, _)| kind)
+    }
+
+    #[test]
+    fn test_classify_journal_entry() {
+        assert_eq!(
+            classify(
+                r#"{"MESSAGE":"Kernel panic - not syncing: VFS: Unable to mount root fs","_TRANSPORT":"kernel"}"#
+            ),
+            Some(FailureKind::KernelPanic)
+        );
+        assert_eq!(
+            classify(
+                r#"{"MESSAGE":"BUG: kernel NULL pointer dereference, address: 0000000000000000","_TRANSPORT":"kernel"}"#
+            ),
+            Some(FailureKind::KernelOops)
+        );
+        // Only the kernel itself counts
+        assert_eq!(
+            classify(r#"{"MESSAGE":"BUG: in my app","_TRANSPORT":"journal"}"#),
+            None
+        );
+        assert_eq!(
+            classify(r#"{"MESSAGE":"kernel BUG at mm/slub.c:4321!","_TRANSPORT":"kernel"}"#),
+            Some(FailureKind::KernelOops)
+        );
+        // Warnings the guest recovers from, e.g. under TCG
+        for message in [
+            "BUG: workqueue lockup - pool cpus=0 node=0 flags=0x0 nice=0 stuck for 33s!",
+            "BUG: soft lockup - CPU#0 stuck for 22s! [swapper/0:1]",
+        ] {
+            let entry = serde_json::json!({"MESSAGE": message, "_TRANSPORT": "kernel"});
+            assert_eq!(classify(&entry.to_string()), None, "{message}");
+        }
+        assert_eq!(
+            classify(
+                r#"{"MESSAGE":"Reached target emergency.target - Emergency Mode.","UNIT":"emergency.target","JOB_TYPE":"start","JOB_RESULT":"done"}"#
+            ),
+            Some(FailureKind::EmergencyShell)
+        );
+        assert_eq!(
+            classify(
+                r#"{"MESSAGE":"Stopped target emergency.target.","UNIT":"emergency.target","JOB_TYPE":"stop","JOB_RESULT":"done"}"#
+            ),
+            None
+        );
+        assert_eq!(
+            classify(r#"{"MESSAGE":"Entering emergency mode. Exit the shell to continue."}"#),
+            Some(FailureKind::EmergencyShell)
+        );
+        assert_eq!(
+            classify(
+                r#"{"MESSAGE":"Started sshd.service","UNIT":"sshd.service","JOB_TYPE":"start","JOB_RESULT":"done"}"#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_classify_unit() {
+        assert!(classify_unit("emergency.target").is_some());
+        assert!(classify_unit("dracut-emergency.service").is_some());
+        assert!(classify_unit("multi-user.target").is_none());
+    }
+
+    #[test]
+    fn test_describe() {
+        let failure = GuestFailure {
+            kind: FailureKind::EmergencyShell,
+            reason: "Guest entered emergency.target".into(),
+            console: vec!["systemd[1]: Failed to mount /sysroot.".into()],
+        };
+        assert_eq!(
+            describe(&failure),
+            "Guest failed to boot: Guest entered emergency.target\n\
+             Last guest output:\n  systemd[1]: Failed to mount /sysroot.\n"
+        );
+        assert_eq!(failure.kind.exit_code(), 5);
+        assert_eq!(
+            FailureKind::from_exit_code(5),
+            Some(FailureKind::EmergencyShell)
+        );
+        assert_eq!(FailureKind::from_exit_code(7), Some(FailureKind::Timeout));
+        assert_eq!(FailureKind::from_exit_code(1), None);
+    }
+}
@@ -0,0 +1,653 @@
+//! Boot-time profiling of VMs (`--boot-profile`)
+//!
+//! A boot profile records when each phase of starting a VM was reached, as
+//! seen from the host, together with the guest's own view of its boot from
+//! systemd. Phases of ephemeral VMs that happen inside the container (kernel
+//! extraction, virtiofsd and QEMU startup, systemd notifications) are appended
+//! to [`EVENTS_PATH`] by the container, if [`start_recording`] created it, and
+//! collected by the host once the VM is reachable via SSH. Host and container share a clock, so all phases use
+//! wall-clock timestamps.
+//!
+//! Profiles are written as JSON, or as HTML if the file name ends in `.html`.
+//! `bcvk boot-profile` compares the profiles of different images.
+
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use comfy_table::{presets::UTF8_FULL, Table};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Phases recorded inside the container, as JSON lines of [`Event`]
+pub(crate) const EVENTS_PATH: &str = "/run/bcvk-boot-profile.jsonl";
+
+/// Guest command printing the boot timestamps of the manager and the startup
+/// time of each unit, once the boot finished or after 10 minutes, so that a
+/// boot job that never completes does not block the caller forever
+pub(crate) const GUEST_COMMAND: &str = "timeout 600 systemctl is-system-running --wait >/dev/null; \
+     systemctl show -p InitRDTimestampMonotonic -p UserspaceTimestampMonotonic -p FinishTimestampMonotonic; \
+     systemd-analyze blame --no-pager";
+
+/// Number of units in comparisons, slowest first
+const MAX_UNITS: usize = 20;
+
+/// Options for `bcvk boot-profile`
+#[derive(Debug, Parser)]
+pub struct BootProfileOpts {
+    /// Profiles written with --boot-profile (JSON)
+    #[clap(required = true)]
+    pub profiles: Vec<Utf8PathBuf>,
+
+    /// Write an HTML report to FILE instead of printing a table
+    #[clap(long, value_name = "FILE")]
+    pub html: Option<Utf8PathBuf>,
+}
+
+/// A phase reached at some time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Event {
+    /// Name of the phase
+    pub(crate) phase: String,
+    /// Seconds since the Unix epoch
+    pub(crate) time: f64,
+}
+
+fn unix_time(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+fn append_event(event: &Event) -> Result<()> {
+    let mut line = serde_json::to_string(event)?;
+    line.push('\n');
+    let mut file = match OpenOptions::new().append(true).open(EVENTS_PATH) {
+        Ok(file) => file,
+        // Not profiling this boot
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Enable recording inside the container, for `--boot-profile`.
+pub(crate) fn start_recording() {
+    if let Err(e) = std::fs::File::create(EVENTS_PATH) {
+        debug!("Failed to create {EVENTS_PATH}: {e}");
+    }
+}
+
+/// Record inside the container that `phase` was reached at `time`, if
+/// [`start_recording`] was called.
+///
+/// Profiling is best-effort, so failures are only logged.
+pub(crate) fn record_at(phase: &str, time: SystemTime) {
+    let event = Event {
+        phase: phase.to_owned(),
+        time: unix_time(time),
+    };
+    if let Err(e) = append_event(&event) {
+        debug!("Failed to record boot phase {phase}: {e}");
+    }
+}
+
+/// Record inside the container that `phase` was reached now.
+pub(crate) fn record(phase: &str) {
+    record_at(phase, SystemTime::now())
+}
+
+/// Startup time of a guest unit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct UnitTime {
+    /// Name of the unit
+    pub(crate) unit: String,
+    /// Seconds the unit took to start
+    pub(crate) seconds: f64,
+}
+
+/// The guest's view of its boot, like `systemd-analyze time` and `blame`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct GuestTimes {
+    /// Seconds in the kernel before the initrd (or userspace) started
+    pub(crate) kernel: Option<f64>,
+    /// Seconds in the initrd
+    pub(crate) initrd: Option<f64>,
+    /// Seconds from switching to the root filesystem to finishing the boot
+    pub(crate) userspace: Option<f64>,
+    /// Startup time of each unit, slowest first
+    pub(crate) units: Vec<UnitTime>,
+}
+
+/// Parse a systemd timespan such as `1min 2.345s` or `523ms`.
+fn parse_timespan(s: &str) -> Option<f64> {
+    let mut total = 0.0;
+    for part in s.split_whitespace() {
+        let split = part.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let (value, unit) = part.split_at(split);
+        let value: f64 = value.parse().ok()?;
+        total += match unit {
+            "us" | "µs" => value / 1e6,
+            "ms" => value / 1e3,
+            "s" => value,
+            "min" => value * 60.0,
+            "h" => value * 3600.0,
+            "d" => value * 86400.0,
+            _ => return None,
+        };
+    }
+    Some(total)
+}
+
+impl GuestTimes {
+    /// Parse the output of [`GUEST_COMMAND`].
+    pub(crate) fn parse(output: &str) -> Self {
+        let mut times = Self::default();
+        let (mut initrd, mut userspace, mut finish) = (0u64, 0u64, 0u64);
+        for line in output.lines() {
+            if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim().parse().unwrap_or_default();
+                match key {
+                    "InitRDTimestampMonotonic" => initrd = value,
+                    "UserspaceTimestampMonotonic" => userspace = value,
+                    "FinishTimestampMonotonic" => finish = value,
+                    _ => {}
+                }
+                continue;
+            }
+            // The timespan can contain spaces, the unit name cannot
+            let Some((time, unit)) = line.trim().rsplit_once(' ') else {
+                continue;
+            };
+            if let Some(seconds) = parse_timespan(time) {
+                times.units.push(UnitTime {
+                    unit: unit.to_owned(),
+                    seconds,
+                });
+            }
+        }
+
+        // Monotonic timestamps in microseconds since the kernel started; zero
+        // if not reached (e.g. no initrd)
+        let secs = |usec: u64| usec as f64 / 1e6;
+        if initrd > 0 {
+            times.kernel = Some(secs(initrd));
+            times.initrd = (userspace > initrd).then(|| secs(userspace - initrd));
+        } else if userspace > 0 {
+            times.kernel = Some(secs(userspace));
+        }
+        if finish > userspace && userspace > 0 {
+            times.userspace = Some(secs(finish - userspace));
+        }
+        times
+    }
+}
+
+/// A phase of a profile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Phase {
+    /// Name of the phase
+    pub(crate) name: String,
+    /// Seconds since the start of the profile
+    pub(crate) seconds: f64,
+}
+
+/// A boot profile, as written by `--boot-profile`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct BootProfile {
+    /// The image that was booted
+    pub(crate) image: String,
+    /// When profiling started, in seconds since the Unix epoch
+    pub(crate) start: f64,
+    /// The phases in the order they were reached
+    pub(crate) phases: Vec<Phase>,
+    /// The guest's view of its boot
+    pub(crate) guest: GuestTimes,
+}
+
+/// Collects the phases of a VM while it starts.
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    image: String,
+    start: SystemTime,
+    events: Vec<Event>,
+}
+
+impl Recorder {
+    /// Start profiling the boot of `image`.
+    pub(crate) fn new(image: &str) -> Self {
+        Self {
+            image: image.to_owned(),
+            start: SystemTime::now(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Record that `phase` was reached now.
+    pub(crate) fn mark(&mut self, phase: &str) {
+        self.events.push(Event {
+            phase: phase.to_owned(),
+            time: unix_time(SystemTime::now()),
+        });
+    }
+
+    /// Add the events recorded inside the container (see [`EVENTS_PATH`]).
+    pub(crate) fn add_container_events(&mut self, jsonl: &str) {
+        for line in jsonl.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(event) => self.events.push(event),
+                Err(e) => debug!("Ignoring invalid boot profile event: {e}"),
+            }
+        }
+    }
+
+    /// Finish the profile with the guest's view of its boot.
+    pub(crate) fn finish(mut self, guest: GuestTimes) -> BootProfile {
+        let start = unix_time(self.start);
+        self.events.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let phases = self
+            .events
+            .into_iter()
+            .map(|e| Phase {
+                name: e.phase,
+                seconds: e.time - start,
+            })
+            .collect();
+        BootProfile {
+            image: self.image,
+            start,
+            phases,
+            guest,
+        }
+    }
+}
+
+impl BootProfile {
+    fn load(path: &Utf8Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Reading {path}"))?;
+        serde_json::from_str(&contents).with_context(|| format!("Parsing boot profile {path}"))
+    }
+
+    /// Write the profile to `path`, as HTML if it ends in `.html`, and as
+    /// JSON otherwise.
+    pub(crate) fn write(&self, path: &Utf8Path) -> Result<()> {
+        let contents = if path.extension() == Some("html") {
+            render_html(&[(self.image.clone(), self)])
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+        std::fs::write(path, contents).with_context(|| format!("Writing {path}"))?;
+        eprintln!("Wrote boot profile to {path}");
+        Ok(())
+    }
+}
+
+/// Groups of rows in a comparison; bars are scaled per section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    /// Host-side phases, in seconds since the start
+    Phases,
+    /// Guest boot stages, in seconds
+    Guest,
+    /// Unit startup times, in seconds
+    Units,
+}
+
+impl Section {
+    fn title(self) -> &'static str {
+        match self {
+            Section::Phases => "Phase (seconds since start)",
+            Section::Guest => "Guest boot (seconds)",
+            Section::Units => "Unit startup (seconds)",
+        }
+    }
+}
+
+/// A row in a comparison, with the value of each profile
+#[derive(Debug, PartialEq)]
+struct Row {
+    section: Section,
+    name: String,
+    values: Vec<Option<f64>>,
+}
+
+/// The rows comparing `profiles`.
+fn comparison_rows(profiles: &[&BootProfile]) -> Vec<Row> {
+    let mut rows: Vec<Row> = Vec::new();
+    let mut push = |section: Section, name: &str, i: usize, value: Option<f64>| {
+        let index = match rows
+            .iter()
+            .position(|r| r.section == section && r.name == name)
+        {
+            Some(index) => index,
+            None => {
+                rows.push(Row {
+                    section,
+                    name: name.to_owned(),
+                    values: vec![None; profiles.len()],
+                });
+                rows.len() - 1
+            }
+        };
+        // A phase reached repeatedly (e.g. a target) counts when first reached
+        let value = rows[index].values[i].or(value);
+        rows[index].values[i] = value;
+    };
+
+    for (i, profile) in profiles.iter().enumerate() {
+        for phase in &profile.phases {
+            push(Section::Phases, &phase.name, i, Some(phase.seconds));
+        }
+        let guest = &profile.guest;
+        push(Section::Guest, "kernel", i, guest.kernel);
+        push(Section::Guest, "initrd", i, guest.initrd);
+        push(Section::Guest, "userspace", i, guest.userspace);
+        for unit in &guest.units {
+            push(Section::Units, &unit.unit, i, Some(unit.seconds));
+        }
+    }
+
+    // Keep phases in the order reached, and only the slowest units
+    let max = |r: &Row| r.values.iter().flatten().copied().fold(0.0, f64::max);
+    let (mut units, mut rows): (Vec<_>, Vec<_>) =
+        rows.into_iter().partition(|r| r.section == Section::Units);
+    units.sort_by(|a, b| max(b).total_cmp(&max(a)));
+    units.truncate(MAX_UNITS);
+    rows.extend(units);
+    rows
+}
+
+fn format_value(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{v:.3}"))
+        .unwrap_or_else(|| "-".into())
+}
+
+/// The change from the first to the second value, if both are present
+fn delta(values: &[Option<f64>]) -> Option<f64> {
+    match values {
+        [Some(a), Some(b)] => Some(b - a),
+        _ => None,
+    }
+}
+
+fn print_table(profiles: &[(String, &BootProfile)]) {
+    let rows = comparison_rows(&profiles.iter().map(|(_, p)| *p).collect::<Vec<_>>());
+    let compare = profiles.len() == 2;
+    let mut header = vec![String::new()];
+    header.extend(profiles.iter().map(|(label, _)| label.clone()));
+    if compare {
+        header.push("DELTA".into());
+    }
+
+    let mut table = Table::new();
+    table.load_style(UTF8_FULL).set_header(header);
+    let mut section = None;
+    for row in rows {
+        if section != Some(row.section) {
+            section = Some(row.section);
+            table.add_row(vec![row.section.title().to_uppercase()]);
+        }
+        let mut cells = vec![row.name.clone()];
+        cells.extend(row.values.iter().map(|v| format_value(*v)));
+        if compare {
+            cells.push(
+                delta(&row.values)
+                    .map(|d| format!("{d:+.3}"))
+                    .unwrap_or_else(|| "-".into()),
+            );
+        }
+        table.add_row(cells);
+    }
+    println!("{table}");
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Render an HTML report comparing `profiles`, with a bar for each value.
+fn render_html(profiles: &[(String, &BootProfile)]) -> String {
+    let rows = comparison_rows(&profiles.iter().map(|(_, p)| *p).collect::<Vec<_>>());
+    let compare = profiles.len() == 2;
+
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>bcvk boot profile</title>\n<style>\n\
+         body { font-family: sans-serif; }\n\
+         table { border-collapse: collapse; }\n\
+         th, td { padding: 2px 8px; text-align: left; }\n\
+         th.section { padding-top: 1em; border-bottom: 1px solid #888; }\n\
+         td.value { width: 16em; }\n\
+         div.bar { background: #4a90d9; height: 0.8em; }\n\
+         .slower { color: #c00; }\n\
+         .faster { color: #080; }\n\
+         </style>\n</head>\n<body>\n<h1>Boot profile</h1>\n<table>\n<tr><th></th>",
+    );
+    for (label, _) in profiles {
+        html.push_str(&format!("<th>{}</th>", escape_html(label)));
+    }
+    if compare {
+        html.push_str("<th>Delta</th>");
+    }
+    html.push_str("</tr>\n");
+
+    let columns = profiles.len() + 1 + usize::from(compare);
+    let mut section = None;
+    for row in &rows {
+        if section != Some(row.section) {
+            section = Some(row.section);
+            html.push_str(&format!(
+                "<tr><th class=\"section\" colspan=\"{columns}\">{}</th></tr>\n",
+                row.section.title()
+            ));
+        }
+        let scale = rows
+            .iter()
+            .filter(|r| r.section == row.section)
+            .flat_map(|r| r.values.iter().flatten())
+            .copied()
+            .fold(0.0, f64::max);
+        html.push_str(&format!("<tr><td>{}</td>", escape_html(&row.name)));
+        for value in &row.values {
+            let width = match value {
+                Some(v) if scale > 0.0 => v / scale * 100.0,
+                _ => 0.0,
+            };
+            html.push_str(&format!(
+                "<td class=\"value\">{}<div class=\"bar\" style=\"width: {width:.1}%\"></div></td>",
+                format_value(*value)
+            ));
+        }
+        if compare {
+            let cell = match delta(&row.values) {
+                Some(d) if d > 0.0 => format!("<td class=\"slower\">{d:+.3}</td>"),
+                Some(d) if d < 0.0 => format!("<td class=\"faster\">{d:+.3}</td>"),
+                d => format!("<td>{}</td>", format_value(d)),
+            };
+            html.push_str(&cell);
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+impl BootProfileOpts {
+    /// Show or compare the profiles
+    pub fn run(self) -> Result<()> {
+        let profiles = self
+            .profiles
+            .iter()
+            .map(|path| BootProfile::load(path))
+            .collect::<Result<Vec<_>>>()?;
+        // Label by image, unless the same image was profiled repeatedly
+        let images: HashSet<_> = profiles.iter().map(|p| p.image.as_str()).collect();
+        let same_image = images.len() < profiles.len();
+        let labeled = self
+            .profiles
+            .iter()
+            .zip(&profiles)
+            .map(|(path, profile)| {
+                let label = if same_image {
+                    path.to_string()
+                } else {
+                    profile.image.clone()
+                };
+                (label, profile)
+            })
+            .collect::<Vec<_>>();
+
+        match self.html {
+            Some(path) => {
+                std::fs::write(&path, render_html(&labeled))
+                    .with_context(|| format!("Writing {path}"))?;
+                println!("Wrote boot profile report to {path}");
+            }
+            None => print_table(&labeled),
+        }
+        Ok(())
+    }
+}
+
+/// Check that `--boot-profile` can be written before booting a VM.
+pub(crate) fn validate_path(path: &Utf8Path) -> Result<()> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_str().is_empty())
+        .unwrap_or(Utf8Path::new("."));
+    if !parent.is_dir() {
+        return Err(eyre!("--boot-profile: directory {parent} does not exist"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timespan() {
+        assert_eq!(parse_timespan("523ms"), Some(0.523));
+        assert_eq!(parse_timespan("2.5s"), Some(2.5));
+        assert_eq!(parse_timespan("1min 2.5s"), Some(62.5));
+        assert_eq!(parse_timespan("1h 2min"), Some(3720.0));
+        assert_eq!(parse_timespan("foo.service"), None);
+        assert_eq!(parse_timespan("5x"), None);
+    }
+
+    #[test]
+    fn test_guest_times() {
+        let output = indoc::indoc! {"
+            InitRDTimestampMonotonic=1500000
+            UserspaceTimestampMonotonic=4000000
+            FinishTimestampMonotonic=9250000
+            1min 2.500s dnf-makecache.service
+                 523ms systemd-udev-trigger.service
+            12us sys-kernel-tracing.mount
+        "};
+        let times = GuestTimes::parse(output);
+        assert_eq!(times.kernel, Some(1.5));
+        assert_eq!(times.initrd, Some(2.5));
+        assert_eq!(times.userspace, Some(5.25));
+        let units: Vec<_> = times.units.iter().map(|u| u.unit.as_str()).collect();
+        assert_eq!(
+            units,
+            [
+                "dnf-makecache.service",
+                "systemd-udev-trigger.service",
+                "sys-kernel-tracing.mount"
+            ]
+        );
+        assert_eq!(times.units[0].seconds, 62.5);
+
+        // Without an initrd, and before the boot finished
+        let times = GuestTimes::parse("InitRDTimestampMonotonic=0\nUserspaceTimestampMonotonic=800000\nFinishTimestampMonotonic=0\n");
+        assert_eq!(times.kernel, Some(0.8));
+        assert_eq!(times.initrd, None);
+        assert_eq!(times.userspace, None);
+    }
+
+    #[test]
+    fn test_recorder() {
+        let mut recorder = Recorder::new("localhost/test");
+        recorder.mark("podman-start");
+        let start = unix_time(recorder.start);
+        let container = [
+            Event {
+                phase: "qemu-started".into(),
+                time: start + 2.0,
+            },
+            Event {
+                phase: "kernel-extracted".into(),
+                time: start + 1.0,
+            },
+        ]
+        .iter()
+        .map(|e| serde_json::to_string(e).unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+        recorder.add_container_events(&format!("{container}\ngarbage\n"));
+        let profile = recorder.finish(GuestTimes::default());
+        let phases: Vec<_> = profile.phases.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(phases, ["podman-start", "kernel-extracted", "qemu-started"]);
+        assert!((profile.phases[2].seconds - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_comparison_rows() {
+        let profile = |image: &str, seconds: f64, unit: &str| BootProfile {
+            image: image.into(),
+            start: 0.0,
+            phases: vec![
+                Phase {
+                    name: "qemu-started".into(),
+                    seconds: 1.0,
+                },
+                Phase {
+                    name: "ssh-ready".into(),
+                    seconds,
+                },
+            ],
+            guest: GuestTimes {
+                kernel: Some(1.0),
+                units: vec![UnitTime {
+                    unit: unit.into(),
+                    seconds,
+                }],
+                ..Default::default()
+            },
+        };
+        let a = profile("a", 5.0, "a.service");
+        let b = profile("b", 7.0, "b.service");
+        let rows = comparison_rows(&[&a, &b]);
+        let ssh = rows.iter().find(|r| r.name == "ssh-ready").unwrap();
+        assert_eq!(ssh.values, [Some(5.0), Some(7.0)]);
+        assert_eq!(delta(&ssh.values), Some(2.0));
+        // Units only in one profile are kept, slowest first
+        let units: Vec<_> = rows
+            .iter()
+            .filter(|r| r.section == Section::Units)
+            .map(|r| (r.name.as_str(), r.values.clone()))
+            .collect();
+        assert_eq!(
+            units,
+            [
+                ("b.service", vec![None, Some(7.0)]),
+                ("a.service", vec![Some(5.0), None])
+            ]
+        );
+
+        let html = render_html(&[("<a>".into(), &a), ("b".into(), &b)]);
+        assert!(html.contains("<th>&lt;a&gt;</th>"));
+        assert!(html.contains("class=\"slower\">+2.000"));
+    }
+}
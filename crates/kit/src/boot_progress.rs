@@ -3,6 +3,7 @@ use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use std::{fs::File, io::BufRead, time::Duration};
 
 use crate::supervisor_status::{StatusWriter, SupervisorState, SupervisorStatus};
+use crate::{boot_failure, boot_profile};
 
 const SSH_ACCESS: &str = "ssh-access.target";
 
@@ -39,22 +40,36 @@ pub async fn monitor_boot_progress(piper: File, status_writer: StatusWriter) ->
         tracing::debug!("Got systemd notification: {k}={v}");
         match k {
             "READY" => {
-                let state = SupervisorState::ReachedTarget(v.to_owned());
+                // systemd sends READY=1 once it finished booting
+                let state = if v == "1" {
+                    boot_profile::record("systemd-ready");
+                    SupervisorState::Ready
+                } else {
+                    SupervisorState::ReachedTarget(v.to_owned())
+                };
                 status_writer.update(SupervisorStatus {
                     state: Some(state),
                     ssh_access,
                     running: true,
+                    ..Default::default()
                 })?;
             }
             "X_SYSTEMD_UNIT_ACTIVE" => {
                 let state = SupervisorState::ReachedTarget(v.to_owned());
                 if v == SSH_ACCESS {
                     ssh_access = true;
+                    boot_profile::record("ssh-access");
+                } else {
+                    boot_profile::record(&format!("unit:{v}"));
+                }
+                if let Some((kind, reason)) = boot_failure::classify_unit(v) {
+                    boot_failure::report(kind, reason);
                 }
                 status_writer.update(SupervisorStatus {
                     state: Some(state),
                     ssh_access,
                     running: true,
+                    ..Default::default()
                 })?;
             }
             _ => {
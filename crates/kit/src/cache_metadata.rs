@@ -4,9 +4,10 @@
 //! using extended attributes (xattrs). This enables efficient caching by allowing bcvk to detect
 //! when a disk image can be reused instead of regenerating it.
 //!
-//! The cache system stores two separate xattrs:
+//! The cache system stores separate xattrs:
 //! - A SHA256 hash of all build inputs for cache validation
 //! - The container image digest for visibility and tracking
+//! - The guest architecture the disk was built for
 
 use crate::install_options::InstallOptions;
 use cap_std_ext::cap_std::{self, fs::Dir};
@@ -24,6 +25,23 @@ const BOOTC_CACHE_HASH_XATTR: &str = "user.bootc.cache_hash";
 /// Extended attribute name for storing container image digest
 const BOOTC_IMAGE_DIGEST_XATTR: &str = "user.bootc.image_digest";
 
+/// Extended attribute name for storing the disk's architecture
+const BOOTC_ARCH_XATTR: &str = "user.bootc.arch";
+
+/// The SHA256 digest of `data`, as `sha256:<hex>`
+pub(crate) fn sha256_digest(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    format!(
+        "sha256:{}",
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>()
+    )
+}
+
 /// Build inputs used to generate a cache hash
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CacheInputs {
@@ -52,6 +70,18 @@ struct CacheInputs {
     /// Kernel arguments used during installation
     kernel_args: Vec<String>,
 
+    /// Architecture the disk is built for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arch: Option<String>,
+
+    /// Installer ISO of an Anaconda install
+    #[serde(skip_serializing_if = "Option::is_none")]
+    via_iso: Option<String>,
+
+    /// SHA256 digest of the kickstart of an Anaconda install
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kickstart: Option<String>,
+
     /// Version of the cache format for future compatibility
     version: u32,
 }
@@ -83,6 +113,15 @@ pub struct DiskImageMetadata {
 
     /// Version of the metadata format for future compatibility
     pub version: u32,
+
+    /// Architecture the disk was built for (e.g. "x86_64", "aarch64")
+    pub arch: Option<String>,
+
+    /// Path of the installer ISO, for disks installed with `--via-iso`
+    pub via_iso: Option<String>,
+
+    /// SHA256 digest of the kickstart, for disks installed with `--via-iso`
+    pub kickstart: Option<String>,
 }
 
 impl DiskImageMetadata {
@@ -96,20 +135,14 @@ impl DiskImageMetadata {
             root_size: self.root_size.clone(),
             composefs_backend: self.composefs_backend,
             kernel_args: self.kernel_args.clone(),
+            arch: self.arch.clone(),
+            via_iso: self.via_iso.clone(),
+            kickstart: self.kickstart.clone(),
             version: self.version,
         };
 
         let json = serde_json::to_string(&inputs).expect("Failed to serialize cache inputs");
-        let mut hasher = Sha256::new();
-        hasher.update(json.as_bytes());
-        format!(
-            "sha256:{}",
-            hasher
-                .finalize()
-                .iter()
-                .map(|b| format!("{b:02x}"))
-                .collect::<String>()
-        )
+        sha256_digest(json.as_bytes())
     }
 
     /// Write metadata to a file using extended attributes via rustix
@@ -133,6 +166,16 @@ impl DiskImageMetadata {
         )
         .with_context(|| "Failed to set image digest xattr")?;
 
+        if let Some(arch) = self.arch.as_deref() {
+            rustix::fs::fsetxattr(
+                file,
+                BOOTC_ARCH_XATTR,
+                arch.as_bytes(),
+                rustix::fs::XattrFlags::empty(),
+            )
+            .with_context(|| "Failed to set arch xattr")?;
+        }
+
         tracing::debug!(
             "Wrote cache hash {} and image digest {} to disk image",
             cache_hash,
@@ -191,6 +234,9 @@ impl DiskImageMetadata {
             root_size: options.root_size.clone(),
             kernel_args: options.karg.clone(),
             composefs_backend: options.composefs_backend,
+            arch: None,
+            via_iso: None,
+            kickstart: None,
         }
     }
 }
@@ -206,19 +252,16 @@ pub(crate) enum ValidationError {
 }
 
 /// Check if a cached disk image can be reused by comparing cache hashes
+/// with the metadata of the current request
 pub fn check_cached_disk(
     path: &Path,
-    image_digest: &str,
-    source_imgref: &str,
-    install_options: &InstallOptions,
+    expected_meta: &DiskImageMetadata,
 ) -> Result<Result<(), ValidationError>> {
     if !path.exists() {
         tracing::debug!("Disk image {:?} does not exist", path);
         return Ok(Err(ValidationError::MissingFile));
     }
 
-    // Create metadata for the current request to compute expected hash
-    let expected_meta = DiskImageMetadata::from(install_options, image_digest, source_imgref);
     let expected_hash = expected_meta.compute_cache_hash();
 
     // Read the cache hash from the disk image
@@ -337,6 +380,38 @@ mod tests {
             metadata5.compute_cache_hash(),
             "Different source imgrefs with same digest should generate different cache hashes"
         );
+
+        // Different architecture should generate different hash
+        let metadata6 = DiskImageMetadata {
+            arch: Some("aarch64".to_string()),
+            ..metadata1.clone()
+        };
+        let metadata7 = DiskImageMetadata {
+            arch: Some("x86_64".to_string()),
+            ..metadata1.clone()
+        };
+        assert_ne!(
+            metadata6.compute_cache_hash(),
+            metadata7.compute_cache_hash()
+        );
+
+        // An ISO install differs from a bootc install, and by ISO and kickstart
+        let iso = DiskImageMetadata {
+            via_iso: Some("/srv/boot.iso".to_string()),
+            kickstart: Some(sha256_digest(b"autopart\n")),
+            ..metadata1.clone()
+        };
+        let other_iso = DiskImageMetadata {
+            via_iso: Some("/srv/other.iso".to_string()),
+            ..iso.clone()
+        };
+        let other_kickstart = DiskImageMetadata {
+            kickstart: Some(sha256_digest(b"autopart --type=plain\n")),
+            ..iso.clone()
+        };
+        for m in [&other_iso, &other_kickstart, &metadata1] {
+            assert_ne!(iso.compute_cache_hash(), m.compute_cache_hash());
+        }
     }
 
     #[test]
@@ -349,6 +424,9 @@ mod tests {
             root_size: Some("20G".to_string()),
             kernel_args: vec!["console=ttyS0".to_string()],
             composefs_backend: false,
+            arch: Some("aarch64".to_string()),
+            via_iso: None,
+            kickstart: None,
             version: 1,
         };
 
@@ -360,6 +438,7 @@ mod tests {
         assert_eq!(inputs.filesystem, deserialized.filesystem);
         assert_eq!(inputs.root_size, deserialized.root_size);
         assert_eq!(inputs.kernel_args, deserialized.kernel_args);
+        assert_eq!(inputs.arch, deserialized.arch);
         assert_eq!(inputs.version, deserialized.version);
         Ok(())
     }
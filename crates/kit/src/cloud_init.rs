@@ -0,0 +1,239 @@
+//! cloud-init NoCloud datasource support
+//!
+//! The user-data and meta-data are passed to the guest on a small read-only
+//! FAT disk labeled `cidata`, which cloud-init's NoCloud datasource finds by
+//! its label. The image is created in-process, so neither privileges nor
+//! external tools are needed.
+
+use std::collections::HashMap;
+use std::process::Stdio;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Volume label of a NoCloud seed disk
+pub(crate) const CIDATA_LABEL: &str = "cidata";
+
+/// Size of the seed disk (a 2.88MB floppy image)
+const SEED_SIZE_KB: u64 = 2880;
+
+/// Room for the FAT metadata on the seed disk
+const SEED_OVERHEAD_KB: u64 = 64;
+
+/// Options for providing a cloud-init NoCloud datasource
+#[derive(Parser, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CloudInitOpts {
+    /// cloud-init user-data file (e.g. #cloud-config) for a NoCloud datasource
+    #[clap(long, value_name = "FILE")]
+    pub cloud_init_user_data: Option<Utf8PathBuf>,
+
+    /// cloud-init meta-data file (default: instance-id and local-hostname from the VM name)
+    #[clap(long, value_name = "FILE", requires = "cloud_init_user_data")]
+    pub cloud_init_meta_data: Option<Utf8PathBuf>,
+}
+
+impl CloudInitOpts {
+    /// Whether a datasource was requested
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.cloud_init_user_data.is_some()
+    }
+
+    /// The absolute paths of the user-data and meta-data files, checking
+    /// they exist
+    pub(crate) fn resolve(&self) -> Result<Option<(Utf8PathBuf, Option<Utf8PathBuf>)>> {
+        let canonicalize = |path: &Utf8Path| {
+            path.canonicalize_utf8()
+                .with_context(|| format!("Opening cloud-init data {path}"))
+        };
+        let Some(user_data) = self.cloud_init_user_data.as_deref() else {
+            return Ok(None);
+        };
+        let meta_data = self
+            .cloud_init_meta_data
+            .as_deref()
+            .map(canonicalize)
+            .transpose()?;
+        Ok(Some((canonicalize(user_data)?, meta_data)))
+    }
+}
+
+/// The meta-data used without `--cloud-init-meta-data`
+fn default_meta_data(hostname: &str) -> String {
+    format!("instance-id: {hostname}\nlocal-hostname: {hostname}\n")
+}
+
+/// Create a NoCloud seed disk at `dest`. Without `meta_data`, the instance
+/// is named after `hostname`.
+pub(crate) fn create_seed(
+    dest: &Utf8Path,
+    user_data: &Utf8Path,
+    meta_data: Option<&Utf8Path>,
+    hostname: &str,
+) -> Result<()> {
+    let read = |path: &Utf8Path| std::fs::read(path).with_context(|| format!("Reading {path}"));
+    let user_data = read(user_data)?;
+    let meta_data = match meta_data {
+        Some(path) => read(path)?,
+        None => default_meta_data(hostname).into_bytes(),
+    };
+
+    let payload = (user_data.len() + meta_data.len()) as u64;
+    if payload > (SEED_SIZE_KB - SEED_OVERHEAD_KB) * 1024 {
+        return Err(eyre!(
+            "cloud-init data is too large ({payload} bytes), at most {}KiB are supported",
+            SEED_SIZE_KB - SEED_OVERHEAD_KB
+        ));
+    }
+
+    crate::esp::create_floppy_image(
+        dest,
+        SEED_SIZE_KB,
+        CIDATA_LABEL,
+        &[("user-data", &user_data), ("meta-data", &meta_data)],
+    )?;
+    debug!("Created cloud-init seed {dest}");
+    Ok(())
+}
+
+/// Whether the image labels declare cloud-init support
+fn labels_have_cloud_init(labels: &HashMap<String, String>) -> bool {
+    labels
+        .get("cloud-init")
+        .is_some_and(|v| !v.is_empty() && v != "0" && v != "false")
+}
+
+/// Check if the container image has cloud-init
+///
+/// Checks for a `cloud-init` label first, like `coreos.ignition` for
+/// Ignition; as few images set one, falls back to looking for the
+/// cloud-init binary in the image, which may be in the additional image
+/// store `image_store`.
+pub(crate) fn check_cloud_init_support(
+    image: &str,
+    image_store: Option<&Utf8Path>,
+) -> Result<bool> {
+    let output = crate::podman::command(image_store)
+        .args(["image", "inspect", "--format", "{{json .Labels}}", image])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to inspect image for labels")?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "Failed to inspect image {} for labels: {}",
+            image,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    // Images without labels have null here
+    let labels: Option<HashMap<String, String>> =
+        serde_json::from_slice(&output.stdout).context("Failed to parse image labels as JSON")?;
+    if labels.as_ref().is_some_and(labels_have_cloud_init) {
+        debug!("Image {image} has a cloud-init label");
+        return Ok(true);
+    }
+
+    let status = crate::podman::command(image_store)
+        .args([
+            "run",
+            "--rm",
+            "--network=none",
+            "--entrypoint=/usr/bin/test",
+            "--",
+            image,
+            "-x",
+            "/usr/bin/cloud-init",
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("Running podman")?;
+    debug!("Image {image} has cloud-init binary: {status}");
+    Ok(status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_meta_data() {
+        assert_eq!(
+            default_meta_data("vm1"),
+            "instance-id: vm1\nlocal-hostname: vm1\n"
+        );
+    }
+
+    #[test]
+    fn test_create_seed() {
+        use std::io::Read as _;
+
+        let td = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(td.path()).unwrap();
+        let user_data = dir.join("user-data");
+        std::fs::write(&user_data, "#cloud-config\n").unwrap();
+        let seed = dir.join("seed.img");
+        create_seed(&seed, &user_data, None, "vm1").unwrap();
+
+        let disk = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&seed)
+            .unwrap();
+        let fs = fatfs::FileSystem::new(disk, fatfs::FsOptions::new()).unwrap();
+        assert!(fs.volume_label().eq_ignore_ascii_case(CIDATA_LABEL));
+        let mut meta_data = String::new();
+        fs.root_dir()
+            .open_file("meta-data")
+            .unwrap()
+            .read_to_string(&mut meta_data)
+            .unwrap();
+        assert_eq!(meta_data, default_meta_data("vm1"));
+
+        let large = dir.join("large");
+        std::fs::write(&large, vec![b'#'; SEED_SIZE_KB as usize * 1024]).unwrap();
+        assert!(create_seed(&seed, &large, None, "vm1").is_err());
+    }
+
+    #[test]
+    fn test_labels_have_cloud_init() {
+        let labels = |v: &str| HashMap::from([("cloud-init".to_string(), v.to_string())]);
+        assert!(labels_have_cloud_init(&labels("1")));
+        assert!(labels_have_cloud_init(&labels("true")));
+        assert!(!labels_have_cloud_init(&labels("")));
+        assert!(!labels_have_cloud_init(&labels("false")));
+        assert!(!labels_have_cloud_init(&HashMap::new()));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let opts = CloudInitOpts::default();
+        assert!(!opts.is_enabled());
+        assert!(opts.resolve().unwrap().is_none());
+
+        let td = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(td.path()).unwrap();
+        let user_data = dir.join("user-data");
+        std::fs::write(&user_data, "#cloud-config\n").unwrap();
+        let opts = CloudInitOpts {
+            cloud_init_user_data: Some(user_data.clone()),
+            cloud_init_meta_data: None,
+        };
+        let (u, m) = opts.resolve().unwrap().unwrap();
+        assert_eq!(u, user_data.canonicalize_utf8().unwrap());
+        assert!(m.is_none());
+
+        let opts = CloudInitOpts {
+            cloud_init_user_data: Some(user_data),
+            cloud_init_meta_data: Some(dir.join("missing")),
+        };
+        assert!(opts.resolve().is_err());
+
+        // meta-data alone is meaningless
+        assert!(CloudInitOpts::try_parse_from(["test", "--cloud-init-meta-data=/tmp/m"]).is_err());
+    }
+}
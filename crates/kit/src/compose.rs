@@ -0,0 +1,657 @@
+//! Declarative multi-VM topologies of ephemeral VMs
+//!
+//! `bcvk compose` manages a group of ephemeral VMs (a "project") described in
+//! a YAML file, like `podman compose` does for containers:
+//!
+//! ```yaml
+//! name: cluster
+//! vms:
+//!   - name: db
+//!     image: localhost/db-bootc
+//!     itype: u1.medium
+//!     internal_network: cluster
+//!   - name: web
+//!     image: localhost/web-bootc
+//!     ports: ["8080:80"]
+//!     binds: ["./config:config"]
+//!     internal_network: cluster
+//!     depends_on: [db]
+//! ```
+//!
+//! `up` starts the VMs in dependency order via `bcvk ephemeral run`, waiting
+//! for each to be ready (per its supervisor status) before starting the next.
+//! The containers are named `<project>-<vm>` and labeled with the project, so
+//! `down`, `ps` and `logs` only need the project name. On an internal network
+//! the guests resolve each other by both names.
+
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use comfy_table::{presets::UTF8_FULL, Table};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::ephemeral::{self, ContainerListEntry};
+use crate::internal_network::validate_name;
+use crate::run_ephemeral::{run_detached, RunEphemeralOpts};
+use crate::run_ephemeral_ssh;
+use crate::supervisor_status::{SupervisorState, SupervisorStatus};
+
+/// Label holding the project of a container
+const PROJECT_LABEL: &str = "bcvk.compose.project";
+
+/// Options for `bcvk compose`
+#[derive(Debug, Parser)]
+pub struct ComposeOpts {
+    /// Compose file describing the VMs
+    #[clap(short = 'f', long, default_value = "bcvk-compose.yaml")]
+    pub file: Utf8PathBuf,
+
+    /// Project name (default: `name` in the compose file, or its directory name)
+    #[clap(short = 'p', long)]
+    pub project_name: Option<String>,
+
+    #[clap(subcommand)]
+    pub command: ComposeCommands,
+}
+
+/// Operations on the VMs of a compose project
+#[derive(Debug, Subcommand)]
+pub enum ComposeCommands {
+    /// Start the VMs in dependency order, waiting for each to be ready
+    Up,
+
+    /// Stop and remove the VMs, in reverse order
+    Down {
+        /// Kill the VMs immediately instead of asking the guests to power off
+        #[clap(long, short = 'f')]
+        force: bool,
+
+        /// Timeout in seconds for graceful shutdown of each VM before killing it
+        #[clap(long, default_value = "60")]
+        timeout: u32,
+    },
+
+    /// List the VMs of the project
+    Ps {
+        /// Output as structured JSON instead of table format
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Show the console output of the VMs
+    Logs {
+        /// Follow the output
+        #[clap(long, short = 'f')]
+        follow: bool,
+
+        /// VMs to show (default: all)
+        vms: Vec<String>,
+    },
+}
+
+/// When a VM counts as started, so that VMs depending on it can start.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Readiness {
+    /// systemd in the guest finished booting
+    #[default]
+    Booted,
+    /// The guest accepts SSH connections
+    Ssh,
+    /// As soon as the VM was started
+    None,
+}
+
+/// A VM in the compose file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VmSpec {
+    /// Name of the VM, unique in the project
+    name: String,
+    /// Container image to boot
+    image: String,
+    /// Instance type, see `--itype`
+    itype: Option<String>,
+    /// Memory size, see `--memory`
+    memory: Option<String>,
+    /// Number of vCPUs
+    vcpus: Option<u32>,
+    /// Host directories to mount read-write (`HOST_PATH[:NAME]`)
+    #[serde(default)]
+    binds: Vec<String>,
+    /// Host directories to mount read-only (`HOST_PATH[:NAME]`)
+    #[serde(default)]
+    ro_binds: Vec<String>,
+    /// Additional kernel arguments
+    #[serde(default)]
+    kargs: Vec<String>,
+    /// Ignition config to inject
+    ignition: Option<Utf8PathBuf>,
+    /// Guest ports to publish on the host (`HOST_PORT:GUEST_PORT`)
+    #[serde(default)]
+    ports: Vec<String>,
+    /// Internal network to join, see `--internal-network`
+    internal_network: Option<String>,
+    /// VMs that must be ready before this one is started
+    #[serde(default)]
+    depends_on: Vec<String>,
+    /// When the VM counts as ready
+    #[serde(default)]
+    ready: Readiness,
+    /// Additional `bcvk ephemeral run` options
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+/// A compose file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ComposeFile {
+    /// Project name
+    name: Option<String>,
+    /// The VMs; they are started in this order, except where dependencies
+    /// require otherwise
+    vms: Vec<VmSpec>,
+}
+
+/// Resolve a host path relative to the directory of the compose file.
+fn resolve_path(base: &Utf8Path, path: &str) -> String {
+    if Utf8Path::new(path).is_absolute() {
+        path.to_owned()
+    } else {
+        base.join(path.trim_start_matches("./")).to_string()
+    }
+}
+
+/// Resolve the host path of a `HOST_PATH[:NAME]` bind spec.
+fn resolve_bind(base: &Utf8Path, spec: &str) -> String {
+    match spec.split_once(':') {
+        Some((path, name)) => format!("{}:{name}", resolve_path(base, path)),
+        None => resolve_path(base, spec),
+    }
+}
+
+impl VmSpec {
+    /// The `bcvk ephemeral run` arguments for this VM.
+    fn run_args(&self, project: &str, base: &Utf8Path) -> Vec<String> {
+        let mut args = vec![
+            "--detach".to_string(),
+            "--ssh-keygen".to_string(),
+            // Makes the console available to `bcvk compose logs`
+            "--console".to_string(),
+            format!("--name={}", container_name(project, &self.name)),
+            format!("--label={PROJECT_LABEL}={project}"),
+        ];
+        if let Some(itype) = &self.itype {
+            args.push(format!("--itype={itype}"));
+        }
+        if let Some(memory) = &self.memory {
+            args.push(format!("--memory={memory}"));
+        }
+        if let Some(vcpus) = self.vcpus {
+            args.push(format!("--vcpus={vcpus}"));
+        }
+        for bind in &self.binds {
+            args.push(format!("--bind={}", resolve_bind(base, bind)));
+        }
+        for bind in &self.ro_binds {
+            args.push(format!("--ro-bind={}", resolve_bind(base, bind)));
+        }
+        for karg in &self.kargs {
+            args.push(format!("--karg={karg}"));
+        }
+        if let Some(ignition) = &self.ignition {
+            args.push(format!(
+                "--ignition={}",
+                resolve_path(base, ignition.as_str())
+            ));
+        }
+        for port in &self.ports {
+            args.push(format!("--port={port}"));
+        }
+        if let Some(network) = &self.internal_network {
+            args.push(format!("--internal-network={network}"));
+            // Guests resolve each other by VM name, not container name
+            args.push(format!("--network-alias={}", self.name));
+        }
+        args.extend(self.args.iter().cloned());
+        // Everything after this is the image
+        args.push("--".to_string());
+        args.push(self.image.clone());
+        args
+    }
+
+    /// Options for starting this VM.
+    fn run_opts(&self, project: &str, base: &Utf8Path) -> Result<RunEphemeralOpts> {
+        let args = self.run_args(project, base);
+        debug!("Options for VM {}: {args:?}", self.name);
+        RunEphemeralOpts::try_parse_from(std::iter::once("run".to_string()).chain(args))
+            .with_context(|| format!("Invalid options for VM '{}'", self.name))
+    }
+}
+
+/// Name of the container of `vm`
+fn container_name(project: &str, vm: &str) -> String {
+    format!("{project}-{vm}")
+}
+
+impl ComposeFile {
+    fn load(path: &Utf8Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Reading {path}"))?;
+        serde_yaml::from_str(&contents).with_context(|| format!("Parsing {path}"))
+    }
+
+    /// The VMs in the order to start them.
+    ///
+    /// VMs are started in file order, but only once all their dependencies
+    /// have been started.
+    fn start_order(&self) -> Result<Vec<&VmSpec>> {
+        let mut names = HashSet::new();
+        for vm in &self.vms {
+            validate_name("VM", &vm.name)?;
+            if !names.insert(vm.name.as_str()) {
+                return Err(eyre!("Duplicate VM '{}'", vm.name));
+            }
+        }
+        for vm in &self.vms {
+            if let Some(dep) = vm.depends_on.iter().find(|d| !names.contains(d.as_str())) {
+                return Err(eyre!("VM '{}' depends on unknown VM '{dep}'", vm.name));
+            }
+        }
+
+        let mut started = HashSet::new();
+        let mut order = Vec::new();
+        while order.len() < self.vms.len() {
+            let next = self.vms.iter().find(|vm| {
+                !started.contains(vm.name.as_str())
+                    && vm.depends_on.iter().all(|d| started.contains(d.as_str()))
+            });
+            let Some(next) = next else {
+                let cycle = self
+                    .vms
+                    .iter()
+                    .filter(|vm| !started.contains(vm.name.as_str()))
+                    .map(|vm| vm.name.as_str())
+                    .collect::<Vec<_>>();
+                return Err(eyre!("Dependency cycle between VMs: {}", cycle.join(", ")));
+            };
+            started.insert(next.name.as_str());
+            order.push(next);
+        }
+        Ok(order)
+    }
+}
+
+/// Wait for the guest in `container_name` to finish booting.
+fn wait_for_booted(container_name: &str, timeout: Duration) -> Result<()> {
+    let mut monitor = run_ephemeral_ssh::spawn_status_monitor(container_name)?;
+    let stdout = monitor.stdout.take().unwrap();
+    let (tx, rx) = std::sync::mpsc::channel::<SupervisorStatus>();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            match serde_json::from_str::<SupervisorStatus>(&line) {
+                Ok(status) => {
+                    if tx.send(status).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => debug!("Ignoring invalid status line: {e}"),
+            }
+        }
+    });
+
+    let start = Instant::now();
+    let r = loop {
+        let remaining = timeout.saturating_sub(start.elapsed());
+        match rx.recv_timeout(remaining) {
+            Ok(status) if status.state == Some(SupervisorState::Ready) => break Ok(()),
+            Ok(SupervisorStatus {
+                state: Some(SupervisorState::Failed(failure)),
+                ..
+            }) => break Err(failure.into()),
+            Ok(status) if !status.running => {
+                break Err(eyre!("VM '{container_name}' exited while booting"))
+            }
+            Ok(status) => debug!("{container_name}: {:?}", status.state),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                break Err(eyre!(
+                    "Timeout waiting for VM '{container_name}' to boot after {}s",
+                    timeout.as_secs()
+                ))
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                break Err(eyre!("VM '{container_name}' stopped while booting"))
+            }
+        }
+    };
+    let _ = monitor.kill();
+    let _ = monitor.wait();
+    r
+}
+
+/// Start a VM and wait for it to be ready.
+fn start_vm(vm: &VmSpec, project: &str, base: &Utf8Path) -> Result<()> {
+    let name = container_name(project, &vm.name);
+    let opts = vm.run_opts(project, base)?;
+    let container_id = run_detached(opts).with_context(|| format!("Starting VM '{}'", vm.name))?;
+    debug!("Started {name} as {container_id}");
+
+    let mut timeout = run_ephemeral_ssh::SSH_TIMEOUT;
+    if run_ephemeral_ssh::is_container_emulated(&name) {
+        timeout *= crate::run_ephemeral::TCG_TIMEOUT_SCALE;
+    }
+    match vm.ready {
+        Readiness::Booted => {
+            eprintln!("Waiting for {name} to boot...");
+            wait_for_booted(&name, timeout)?;
+        }
+        Readiness::Ssh => {
+            let progress = crate::boot_progress::create_boot_progress_bar();
+            let (_, progress) =
+                run_ephemeral_ssh::wait_for_ssh_ready(&name, Some(timeout), progress)?;
+            progress.finish_and_clear();
+        }
+        Readiness::None => {}
+    }
+    println!("Started {name}");
+    Ok(())
+}
+
+/// The containers of `project`, oldest first.
+fn project_containers(project: &str) -> Result<Vec<ContainerListEntry>> {
+    ephemeral::list_ephemeral_containers_with_label(&format!("{PROJECT_LABEL}={project}"))
+}
+
+/// The VM name of a project container.
+fn vm_name<'a>(project: &str, container: &'a ContainerListEntry) -> &'a str {
+    let name = container.names.first().map(String::as_str).unwrap_or("");
+    name.strip_prefix(project)
+        .and_then(|n| n.strip_prefix('-'))
+        .unwrap_or(name)
+}
+
+fn up(file: &ComposeFile, project: &str, base: &Utf8Path) -> Result<()> {
+    let order = file.start_order()?;
+    let existing = project_containers(project)?;
+    for vm in order {
+        let name = container_name(project, &vm.name);
+        if let Some(c) = existing.iter().find(|c| c.names.contains(&name)) {
+            if c.state == "running" {
+                println!("{name} is already running");
+                continue;
+            }
+            debug!("Removing stopped container {name}");
+            let result = ephemeral::remove_single_container(&c.id);
+            if let Some(error) = result.error {
+                return Err(eyre!("Failed to remove stopped container {name}: {error}"));
+            }
+        }
+        start_vm(vm, project, base).with_context(|| {
+            format!("Bringing up project '{project}'; use `bcvk compose down` to clean up")
+        })?;
+    }
+    Ok(())
+}
+
+fn down(project: &str, timeout: u32, force: bool) -> Result<()> {
+    let containers = project_containers(project)?;
+    if containers.is_empty() {
+        println!("No VMs found for project '{project}'");
+        return Ok(());
+    }
+    let timeout = Duration::from_secs(timeout.into());
+    // Stop dependent VMs first
+    for container in containers.iter().rev() {
+        let name = container.names.first().unwrap_or(&container.id);
+        if let Err(e) = ephemeral::stop_ephemeral_vm(&container.id, timeout, force) {
+            tracing::warn!("Failed to stop {name}: {e:#}");
+        }
+        let result = ephemeral::remove_single_container(&container.id);
+        match result.error {
+            None => println!("Removed {name}"),
+            Some(error) => eprintln!("Failed to remove {name}: {error}"),
+        }
+    }
+    Ok(())
+}
+
+fn ps(project: &str, json: bool) -> Result<()> {
+    let containers = project_containers(project)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&containers)?);
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.load_style(UTF8_FULL).set_header(vec![
+        "VM",
+        "CONTAINER ID",
+        "IMAGE",
+        "CREATED",
+        "STATUS",
+    ]);
+    for container in &containers {
+        table.add_row(vec![
+            vm_name(project, container).to_string(),
+            container.id.chars().take(12).collect(),
+            container.image.clone(),
+            container.created_at.clone(),
+            container.state.clone(),
+        ]);
+    }
+    println!("{table}");
+    Ok(())
+}
+
+/// Copy lines from `reader` to stdout, prefixed with `prefix`.
+fn prefix_lines(prefix: &str, reader: impl Read) {
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else { break };
+        println!("{prefix}{line}");
+    }
+}
+
+fn logs(project: &str, vms: &[String], follow: bool) -> Result<()> {
+    let containers = project_containers(project)?;
+    if let Some(vm) = vms.iter().find(|vm| {
+        !containers
+            .iter()
+            .any(|c| vm_name(project, c) == vm.as_str())
+    }) {
+        return Err(eyre!("VM '{vm}' not found in project '{project}'"));
+    }
+    let containers = containers
+        .iter()
+        .filter(|c| vms.is_empty() || vms.iter().any(|vm| vm_name(project, c) == vm))
+        .collect::<Vec<_>>();
+    let width = containers
+        .iter()
+        .map(|c| vm_name(project, c).len())
+        .max()
+        .unwrap_or(0);
+
+    // Stream the logs of all VMs concurrently, as with --follow they never end
+    std::thread::scope(|s| -> Result<()> {
+        let mut children: Vec<std::process::Child> = Vec::new();
+        for container in &containers {
+            let prefix = format!("{:width$} | ", vm_name(project, container));
+            let mut cmd = Command::new("podman");
+            cmd.arg("logs");
+            if follow {
+                cmd.arg("--follow");
+            }
+            cmd.args(["--", &container.id]);
+            let spawned = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
+            let mut child = match spawned {
+                Ok(child) => child,
+                Err(e) => {
+                    // Don't leave the readers of the other VMs waiting forever
+                    for child in children.iter_mut() {
+                        let _ = child.kill();
+                    }
+                    return Err(e).context("Running podman logs");
+                }
+            };
+            let stdout = child.stdout.take().unwrap();
+            let stderr = child.stderr.take().unwrap();
+            let out_prefix = prefix.clone();
+            s.spawn(move || prefix_lines(&out_prefix, stdout));
+            s.spawn(move || prefix_lines(&prefix, stderr));
+            children.push(child);
+        }
+        for mut child in children {
+            child.wait()?;
+        }
+        Ok(())
+    })
+}
+
+impl ComposeOpts {
+    /// The project name, and the compose file if it was needed to find it.
+    fn project(&self) -> Result<(String, Option<ComposeFile>)> {
+        let (project, file) = match &self.project_name {
+            Some(name) => (name.clone(), None),
+            None => {
+                let file = ComposeFile::load(&self.file)?;
+                let name = match &file.name {
+                    Some(name) => name.clone(),
+                    None => self
+                        .file
+                        .canonicalize_utf8()?
+                        .parent()
+                        .and_then(|p| p.file_name())
+                        .ok_or_else(|| eyre!("Cannot determine project name, use --project-name"))?
+                        .to_string(),
+                };
+                (name, Some(file))
+            }
+        };
+        validate_name("project", &project)?;
+        Ok((project, file))
+    }
+
+    /// Execute the compose subcommand
+    pub fn run(self) -> Result<()> {
+        let (project, file) = self.project()?;
+        match self.command {
+            ComposeCommands::Up => {
+                let file = match file {
+                    Some(file) => file,
+                    None => ComposeFile::load(&self.file)?,
+                };
+                let path = self.file.canonicalize_utf8()?;
+                let base = path.parent().unwrap_or(Utf8Path::new("/"));
+                up(&file, &project, base)
+            }
+            ComposeCommands::Down { force, timeout } => down(&project, timeout, force),
+            ComposeCommands::Ps { json } => ps(&project, json),
+            ComposeCommands::Logs { follow, vms } => logs(&project, &vms, follow),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = indoc::indoc! {r#"
+        name: cluster
+        vms:
+          - name: web
+            image: localhost/web
+            ports: ["8080:80"]
+            binds: ["./config:config", "/srv/data"]
+            depends_on: [db]
+            ready: ssh
+          - name: db
+            image: localhost/db
+            itype: u1.small
+            kargs: [console=ttyS0]
+            ignition: db.ign
+            internal_network: cluster
+          - name: monitor
+            image: localhost/monitor
+            ready: none
+            args: ["--add-swap=1G"]
+    "#};
+
+    #[test]
+    fn test_parse() {
+        let file: ComposeFile = serde_yaml::from_str(EXAMPLE).unwrap();
+        assert_eq!(file.name.as_deref(), Some("cluster"));
+        assert_eq!(file.vms.len(), 3);
+        assert_eq!(file.vms[0].ready, Readiness::Ssh);
+        assert_eq!(file.vms[1].ready, Readiness::Booted);
+        assert_eq!(file.vms[2].ready, Readiness::None);
+
+        let unknown = "vms:\n  - name: a\n    image: x\n    bogus: 1\n";
+        assert!(serde_yaml::from_str::<ComposeFile>(unknown).is_err());
+    }
+
+    #[test]
+    fn test_start_order() {
+        let file: ComposeFile = serde_yaml::from_str(EXAMPLE).unwrap();
+        let order = file
+            .start_order()
+            .unwrap()
+            .iter()
+            .map(|vm| vm.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order, ["db", "web", "monitor"]);
+
+        let cycle = "vms:\n  - {name: a, image: x, depends_on: [b]}\n  - {name: b, image: x, depends_on: [a]}\n";
+        let file: ComposeFile = serde_yaml::from_str(cycle).unwrap();
+        assert!(file.start_order().is_err());
+        let unknown = "vms:\n  - {name: a, image: x, depends_on: [c]}\n";
+        let file: ComposeFile = serde_yaml::from_str(unknown).unwrap();
+        assert!(file.start_order().is_err());
+        let duplicate = "vms:\n  - {name: a, image: x}\n  - {name: a, image: y}\n";
+        let file: ComposeFile = serde_yaml::from_str(duplicate).unwrap();
+        assert!(file.start_order().is_err());
+    }
+
+    #[test]
+    fn test_run_opts() {
+        let file: ComposeFile = serde_yaml::from_str(EXAMPLE).unwrap();
+        let base = Utf8Path::new("/work");
+
+        let web = file.vms[0].run_opts("cluster", base).unwrap();
+        assert_eq!(web.image, "localhost/web");
+        assert_eq!(web.podman.name.as_deref(), Some("cluster-web"));
+        assert_eq!(web.podman.label, ["bcvk.compose.project=cluster"]);
+        assert!(web.podman.detach && web.common.ssh_keygen && web.common.console);
+        assert_eq!(web.bind_mounts, ["/work/config:config", "/srv/data"]);
+        assert_eq!(web.port_mappings.len(), 1);
+
+        let db = file.vms[1].run_opts("cluster", base).unwrap();
+        assert_eq!(db.ignition_config.as_deref(), Some("/work/db.ign"));
+        assert_eq!(db.kernel_args, ["console=ttyS0"]);
+        assert_eq!(db.internal_network.as_deref(), Some("cluster"));
+        assert_eq!(db.network_aliases, ["db"]);
+        assert!(db.common.itype.is_some());
+
+        let monitor = file.vms[2].run_opts("cluster", base).unwrap();
+        assert_eq!(monitor.add_swap.as_deref(), Some("1G"));
+    }
+
+    #[test]
+    fn test_cli() {
+        use clap::CommandFactory;
+        ComposeOpts::command().debug_assert();
+
+        // The file option and the subcommands' -f are distinct
+        let opts = ComposeOpts::try_parse_from(["compose", "-f", "c.yaml", "down", "-f"]).unwrap();
+        assert_eq!(opts.file, "c.yaml");
+        assert!(matches!(
+            opts.command,
+            ComposeCommands::Down { force: true, .. }
+        ));
+    }
+}
@@ -3,6 +3,7 @@ use color_eyre::Result;
 use tokio::signal::unix::SignalKind;
 use tracing::debug;
 
+use crate::ephemeral_exec::GuestExecOpts;
 use crate::run_ephemeral::RunEphemeralOpts;
 
 #[derive(Parser)]
@@ -21,6 +22,24 @@ pub enum ContainerCommands {
 
     /// Monitor VM status file using inotify
     MonitorStatus(MonitorStatusOpts),
+
+    /// Request a graceful ACPI powerdown of the VM via QMP
+    Powerdown,
+
+    /// Run a command in the VM over AF_VSOCK
+    Exec(GuestExecOpts),
+
+    /// Run virtiofsd for a hot-plugged share until QEMU disconnects
+    MountServe(crate::mount::ServeOpts),
+
+    /// Hot-add the device of a share started with mount-serve
+    MountAttach(crate::mount::HotplugOpts),
+
+    /// Hot-remove the device of a share
+    MountDetach(crate::mount::HotplugOpts),
+
+    /// Output the captured guest journal as JSON lines
+    JournalCat(crate::ephemeral_logs::CatOpts),
 }
 
 #[derive(Parser)]
@@ -38,6 +57,10 @@ pub async fn run_ephemeral_in_container() -> Result<()> {
     let config_json = std::env::var("BCK_CONFIG")?;
     let opts: RunEphemeralOpts = serde_json::from_str(&config_json)?;
 
+    if opts.install_iso.is_some() {
+        return crate::iso_install::run_impl(opts).await;
+    }
+
     // Call existing run_impl
     crate::run_ephemeral::run_impl(opts).await
 }
@@ -77,6 +100,11 @@ pub fn monitor_status(_opts: MonitorStatusOpts) -> Result<()> {
     crate::status_monitor::monitor_and_stream_status()
 }
 
+pub async fn powerdown() -> Result<()> {
+    let mut qmp = crate::qemu::QmpClient::connect(crate::run_ephemeral::QMP_SOCKET_PATH).await?;
+    qmp.system_powerdown().await
+}
+
 pub async fn run(opts: ContainerEntrypointOpts) -> Result<()> {
     let signals = [libc::SIGTERM, libc::SIGINT, libc::SIGRTMIN() + 3];
     let mut signal_joinset = tokio::task::JoinSet::new();
@@ -102,6 +130,26 @@ pub async fn run(opts: ContainerEntrypointOpts) -> Result<()> {
                 ContainerCommands::MonitorStatus(monitor_opts) => {
                     tokio::task::spawn_blocking(move || monitor_status(monitor_opts)).await?
                 }
+                ContainerCommands::Powerdown => powerdown().await,
+                ContainerCommands::Exec(exec_opts) => {
+                    tokio::task::spawn_blocking(move || {
+                        crate::ephemeral_exec::exec_in_container(exec_opts)
+                    })
+                    .await?
+                }
+                ContainerCommands::MountServe(serve_opts) => {
+                    crate::mount::serve(serve_opts).await
+                }
+                ContainerCommands::MountAttach(attach_opts) => {
+                    crate::mount::attach(attach_opts).await
+                }
+                ContainerCommands::MountDetach(detach_opts) => {
+                    crate::mount::detach(detach_opts).await
+                }
+                ContainerCommands::JournalCat(cat_opts) => {
+                    tokio::task::spawn_blocking(move || crate::ephemeral_logs::cat_ring(cat_opts))
+                        .await?
+                }
             }
         } => r
     }
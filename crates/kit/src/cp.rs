@@ -0,0 +1,310 @@
+//! Copy files to and from running VMs
+//!
+//! Shared implementation of `bcvk ephemeral cp` and `bcvk libvirt cp`.
+//! Files are streamed as a tarball over an SSH session running `tar` in the
+//! guest, so directories, symlinks and file modes are preserved without any
+//! temporary archive on either side.
+
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tracing::debug;
+
+/// Options for copying files between the host and a VM.
+#[derive(Parser, Debug)]
+pub struct CpOpts {
+    /// Source path; use NAME:PATH for a path in the VM
+    pub src: CpLocation,
+
+    /// Destination path; use NAME:PATH for a path in the VM
+    pub dst: CpLocation,
+}
+
+/// One side of a copy: a path on the host or in a VM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpLocation {
+    /// A path on the host
+    Local(Utf8PathBuf),
+    /// A path in the named VM
+    Guest {
+        /// Name of the VM
+        name: String,
+        /// Path inside the VM
+        path: Utf8PathBuf,
+    },
+}
+
+impl FromStr for CpLocation {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // Like `podman cp`, paths starting with / or . are always local, which
+        // allows copying host files with a colon in their name.
+        if s.starts_with('/') || s.starts_with('.') {
+            return Ok(Self::Local(s.into()));
+        }
+        match s.split_once(':') {
+            Some(("", _)) => Err(eyre!("Missing VM name in '{s}'")),
+            Some((_, "")) => Err(eyre!("Missing path in '{s}'")),
+            Some((name, path)) => Ok(Self::Guest {
+                name: name.to_string(),
+                path: path.into(),
+            }),
+            None => Ok(Self::Local(s.into())),
+        }
+    }
+}
+
+/// A resolved copy between the host and a single VM.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CpTransfer {
+    /// Copy from the host into the VM
+    ToGuest {
+        /// Name of the VM
+        name: String,
+        /// Host source path
+        src: Utf8PathBuf,
+        /// Destination path in the VM
+        dst: Utf8PathBuf,
+    },
+    /// Copy from the VM to the host
+    FromGuest {
+        /// Name of the VM
+        name: String,
+        /// Source path in the VM
+        src: Utf8PathBuf,
+        /// Host destination path
+        dst: Utf8PathBuf,
+    },
+}
+
+impl CpOpts {
+    /// Determine the direction of the copy.
+    pub(crate) fn transfer(&self) -> Result<CpTransfer> {
+        match (&self.src, &self.dst) {
+            (CpLocation::Local(src), CpLocation::Guest { name, path }) => Ok(CpTransfer::ToGuest {
+                name: name.clone(),
+                src: src.clone(),
+                dst: path.clone(),
+            }),
+            (CpLocation::Guest { name, path }, CpLocation::Local(dst)) => {
+                Ok(CpTransfer::FromGuest {
+                    name: name.clone(),
+                    src: path.clone(),
+                    dst: dst.clone(),
+                })
+            }
+            (CpLocation::Local(_), CpLocation::Local(_)) => Err(eyre!(
+                "One of the source or destination must be a path in a VM (NAME:PATH)"
+            )),
+            (CpLocation::Guest { .. }, CpLocation::Guest { .. }) => {
+                Err(eyre!("Copying directly between VMs is not supported"))
+            }
+        }
+    }
+}
+
+/// Split a path into the directory to run tar in and the entry to archive.
+fn split_path(path: &Utf8Path) -> Result<(&Utf8Path, &str)> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| eyre!("Cannot copy '{path}': path has no file name"))?;
+    let dir = match path.parent() {
+        Some(p) if !p.as_str().is_empty() => p,
+        _ => Utf8Path::new("."),
+    };
+    Ok((dir, name))
+}
+
+fn quote(s: &str) -> Result<String> {
+    Ok(shlex::try_quote(s)?.into_owned())
+}
+
+/// Shell script run in the guest to write a tarball of `src` to stdout.
+fn guest_create_script(src: &Utf8Path) -> Result<String> {
+    let (dir, name) = split_path(src)?;
+    Ok(format!(
+        "tar -C {} -cf - -- {}",
+        quote(dir.as_str())?,
+        quote(name)?
+    ))
+}
+
+/// Shell script run in the guest to extract a tarball containing `entry`
+/// from stdin to `dst`.
+///
+/// As with `cp`, if `dst` is an existing directory the entry is placed inside
+/// it, otherwise the entry is renamed to `dst`.
+fn guest_extract_script(entry: &str, dst: &Utf8Path) -> Result<String> {
+    let dst = quote(dst.as_str())?;
+    let entry = quote(entry)?;
+    Ok(format!(
+        "dst={dst}; \
+         if [ -d \"$dst\" ]; then exec tar -C \"$dst\" -xpf -; fi; \
+         t=$(mktemp -d -p \"$(dirname -- \"$dst\")\" .bcvk-cp.XXXXXX) || exit 1; \
+         tar -C \"$t\" -xpf - && mv -f -- \"$t\"/{entry} \"$dst\"; \
+         rc=$?; rm -rf -- \"$t\"; exit $rc"
+    ))
+}
+
+/// Run `producer` with its stdout connected to the stdin of `consumer`.
+fn pipe(mut producer: Command, mut consumer: Command, what: &str) -> Result<()> {
+    debug!("Streaming {producer:?} | {consumer:?}");
+    producer.stdout(Stdio::piped());
+    let mut child = producer.spawn().context("Spawning tar producer")?;
+    let stdout = child.stdout.take().expect("piped stdout");
+    consumer.stdin(stdout);
+    let consumer_status = consumer.status().context("Running tar consumer");
+    let producer_status = child.wait()?;
+    let consumer_status = consumer_status?;
+    // The producer fails first for e.g. a missing source, which in turn makes
+    // the consumer fail on an empty archive, so report it first.
+    if !producer_status.success() {
+        return Err(eyre!("Failed to read {what}: {producer_status}"));
+    }
+    if !consumer_status.success() {
+        return Err(eyre!("Failed to write {what}: {consumer_status}"));
+    }
+    Ok(())
+}
+
+/// Extract a tarball containing `entry` from `producer` to the host path `dst`.
+fn extract_local(producer: Command, entry: &str, dst: &Utf8Path) -> Result<()> {
+    if dst.is_dir() {
+        let mut tar = Command::new("tar");
+        tar.args(["-C", dst.as_str(), "-xpf", "-"]);
+        return pipe(producer, tar, entry);
+    }
+
+    let parent = match dst.parent() {
+        Some(p) if !p.as_str().is_empty() => p,
+        _ => Utf8Path::new("."),
+    };
+    if !parent.is_dir() {
+        return Err(eyre!("Destination directory '{parent}' does not exist"));
+    }
+    // Extract next to the destination, then move into place
+    let tmpdir = tempfile::Builder::new()
+        .prefix(".bcvk-cp.")
+        .tempdir_in(parent)
+        .with_context(|| format!("Creating temporary directory in {parent}"))?;
+    let tmppath = Utf8Path::from_path(tmpdir.path()).ok_or_else(|| eyre!("Non-UTF8 path"))?;
+    let mut tar = Command::new("tar");
+    tar.args(["-C", tmppath.as_str(), "-xpf", "-"]);
+    pipe(producer, tar, entry)?;
+    std::fs::rename(tmppath.join(entry), dst).with_context(|| format!("Renaming to {dst}"))?;
+    Ok(())
+}
+
+impl CpTransfer {
+    /// Name of the VM involved in the copy.
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            CpTransfer::ToGuest { name, .. } | CpTransfer::FromGuest { name, .. } => name,
+        }
+    }
+
+    /// Perform the copy.
+    ///
+    /// `guest_shell` returns a command running the given shell script in the
+    /// VM, with stdin and stdout connected to the script.
+    pub(crate) fn run(&self, guest_shell: impl Fn(&str) -> Result<Command>) -> Result<()> {
+        match self {
+            CpTransfer::ToGuest { src, dst, .. } => {
+                if src.symlink_metadata().is_err() {
+                    return Err(eyre!("Source '{src}' does not exist"));
+                }
+                let (dir, entry) = split_path(src)?;
+                let mut tar = Command::new("tar");
+                tar.args(["-C", dir.as_str(), "-cf", "-", "--", entry]);
+                let guest = guest_shell(&guest_extract_script(entry, dst)?)?;
+                pipe(tar, guest, src.as_str())
+            }
+            CpTransfer::FromGuest { src, dst, .. } => {
+                let (_, entry) = split_path(src)?;
+                let guest = guest_shell(&guest_create_script(src)?)?;
+                extract_local(guest, entry, dst)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_location() {
+        assert_eq!(
+            "myvm:/var/log".parse::<CpLocation>().unwrap(),
+            CpLocation::Guest {
+                name: "myvm".into(),
+                path: "/var/log".into()
+            }
+        );
+        assert_eq!(
+            "foo".parse::<CpLocation>().unwrap(),
+            CpLocation::Local("foo".into())
+        );
+        // Explicit paths are local even when containing a colon
+        assert_eq!(
+            "./a:b".parse::<CpLocation>().unwrap(),
+            CpLocation::Local("./a:b".into())
+        );
+        assert_eq!(
+            "/tmp/a:b".parse::<CpLocation>().unwrap(),
+            CpLocation::Local("/tmp/a:b".into())
+        );
+        assert!(":/foo".parse::<CpLocation>().is_err());
+        assert!("myvm:".parse::<CpLocation>().is_err());
+    }
+
+    #[test]
+    fn test_transfer() {
+        let opts = CpOpts::try_parse_from(["cp", "vm:/root/cov", "."]).unwrap();
+        assert_eq!(
+            opts.transfer().unwrap(),
+            CpTransfer::FromGuest {
+                name: "vm".into(),
+                src: "/root/cov".into(),
+                dst: ".".into()
+            }
+        );
+        assert_eq!(opts.transfer().unwrap().name(), "vm");
+
+        let opts = CpOpts::try_parse_from(["cp", "a", "b"]).unwrap();
+        assert!(opts.transfer().is_err());
+        let opts = CpOpts::try_parse_from(["cp", "vm1:/a", "vm2:/b"]).unwrap();
+        assert!(opts.transfer().is_err());
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(
+            split_path("/var/log/".into()).unwrap(),
+            (Utf8Path::new("/var"), "log")
+        );
+        assert_eq!(
+            split_path("foo".into()).unwrap(),
+            (Utf8Path::new("."), "foo")
+        );
+        assert!(split_path("/".into()).is_err());
+    }
+
+    #[test]
+    fn test_guest_scripts() {
+        let script = guest_create_script("/var/lib/my app".into()).unwrap();
+        assert_eq!(
+            shlex::split(&script).unwrap(),
+            ["tar", "-C", "/var/lib", "-cf", "-", "--", "my app"]
+        );
+        let script = guest_extract_script("it's", "/root/dest dir".into()).unwrap();
+        let words = shlex::split(&script).unwrap();
+        assert_eq!(words[0], "dst=/root/dest dir;");
+        assert!(words.contains(&"$t/it's".to_string()));
+    }
+}
@@ -29,6 +29,17 @@ fn write_file(writer: &mut impl Write, path: &str, content: &[u8]) -> io::Result
     Ok(())
 }
 
+const ETC_OVERLAY_UNIT: &[u8] = include_bytes!("units/bcvk-etc-overlay.service");
+const VAR_EPHEMERAL_UNIT: &[u8] = include_bytes!("units/bcvk-var-ephemeral.service");
+
+/// Units making `/etc` and `/var` writable without touching the read-only
+/// virtiofs root, pulled into `initrd-fs.target`.  These are also passed as
+/// credentials for `--boot uefi`, where the initramfs cannot be changed.
+pub(crate) const OVERLAY_UNITS: [(&str, &[u8]); 2] = [
+    ("bcvk-etc-overlay.service", ETC_OVERLAY_UNIT),
+    ("bcvk-var-ephemeral.service", VAR_EPHEMERAL_UNIT),
+];
+
 /// CPIO entry: either a directory or a regular file (0644).
 enum Entry {
     Dir(&'static str),
@@ -64,11 +75,11 @@ pub fn create_initramfs_units_cpio() -> io::Result<Vec<u8>> {
         // Service units
         File(
             "usr/lib/systemd/system/bcvk-etc-overlay.service",
-            include_bytes!("units/bcvk-etc-overlay.service"),
+            ETC_OVERLAY_UNIT,
         ),
         File(
             "usr/lib/systemd/system/bcvk-var-ephemeral.service",
-            include_bytes!("units/bcvk-var-ephemeral.service"),
+            VAR_EPHEMERAL_UNIT,
         ),
         File(
             "usr/lib/systemd/system/bcvk-copy-units.service",
@@ -0,0 +1,259 @@
+//! Guest memory dumps when the guest kernel panics
+//!
+//! With `--dump-on-panic DIR`, a panic reported by the pvpanic device pauses
+//! an ephemeral VM instead of ending it, and the guest memory is written to
+//! `DIR/vmcore` by the supervisor with QMP `dump-guest-memory`. The
+//! `vmlinux` and `System.map` of the image's kernel are copied alongside, so
+//! `crash DIR/vmlinux DIR/vmcore` can be run right away.
+//!
+//! Libvirt VMs are configured with `<on_crash>coredump-destroy</on_crash>`
+//! instead, for libvirt to write the dump to its `auto_dump_path` when the
+//! guest panics; only the kernel debug files go to `DIR`.
+
+use std::process::Command;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use cap_std_ext::cap_std::fs::Dir;
+use clap::Parser;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::qemu::QmpClient;
+
+/// File name of the memory dump
+pub(crate) const VMCORE: &str = "vmcore";
+
+/// Where the dump directory is mounted in containers
+pub(crate) const CONTAINER_DUMP_DIR: &str = "/run/bcvk-dump";
+
+/// Kernel files needed by `crash`, from `/usr/lib/modules/<version>` or,
+/// for `vmlinux` usually, from the kernel debuginfo
+const KERNEL_DEBUG_FILES: &[&str] = &["vmlinux", "System.map"];
+
+/// Where the kernel debuginfo installs files for a kernel version
+const DEBUG_MODULES_DIR: &str = "usr/lib/debug/lib/modules";
+
+/// Format of a guest memory dump
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum DumpFormat {
+    /// ELF core file
+    #[default]
+    Elf,
+    /// kdump-compressed (zlib), as written by makedumpfile
+    Kdump,
+}
+
+impl DumpFormat {
+    /// The format name used by QMP `dump-guest-memory` and `virsh dump`
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            DumpFormat::Elf => "elf",
+            DumpFormat::Kdump => "kdump-zlib",
+        }
+    }
+}
+
+/// Options for capturing guest memory when the guest kernel panics
+#[derive(Parser, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DumpOpts {
+    /// Write a guest memory dump and the kernel's vmlinux and System.map to DIR when the guest kernel panics
+    #[clap(long, value_name = "DIR")]
+    pub dump_on_panic: Option<Utf8PathBuf>,
+
+    /// Format of the guest memory dump
+    #[clap(long, value_enum, default_value_t, requires = "dump_on_panic")]
+    pub dump_format: DumpFormat,
+}
+
+impl DumpOpts {
+    /// The absolute path of the dump directory, created if needed
+    pub(crate) fn prepare_dir(&self) -> Result<Option<Utf8PathBuf>> {
+        let Some(dir) = self.dump_on_panic.as_deref() else {
+            return Ok(None);
+        };
+        std::fs::create_dir_all(dir).with_context(|| format!("Creating {dir}"))?;
+        let dir = dir
+            .canonicalize_utf8()
+            .with_context(|| format!("Opening {dir}"))?;
+        Ok(Some(dir))
+    }
+}
+
+/// Copy the kernel debug files of the kernel in `root` into `dest`.
+///
+/// Missing files are not an error, as `vmlinux` is only present with the
+/// kernel debuginfo installed in the image.
+pub(crate) fn copy_kernel_debug_files(root: &Dir, dest: &Utf8Path) -> Result<()> {
+    let kernel = crate::kernel::find_kernel(root)?.ok_or_else(|| eyre!("No kernel found"))?;
+    let Some(version) = kernel.version() else {
+        warn!(
+            "Kernel {} is not in /usr/lib/modules; not copying vmlinux and System.map",
+            kernel.kernel_path
+        );
+        return Ok(());
+    };
+    for name in KERNEL_DEBUG_FILES {
+        let candidates = [
+            format!("usr/lib/modules/{version}/{name}"),
+            format!("{DEBUG_MODULES_DIR}/{version}/{name}"),
+        ];
+        let Some(src) = candidates.iter().find(|p| root.is_file(p.as_str())) else {
+            warn!(
+                "No {name} found for kernel {version}; it needs the kernel debuginfo in the image"
+            );
+            continue;
+        };
+        let target = dest.join(name);
+        let mut src = root.open(src).with_context(|| format!("Opening {src}"))?;
+        let mut target_file =
+            std::fs::File::create(&target).with_context(|| format!("Creating {target}"))?;
+        std::io::copy(&mut src, &mut target_file).with_context(|| format!("Writing {target}"))?;
+        debug!("Copied {target}");
+    }
+    Ok(())
+}
+
+/// Copy the kernel debug files out of a container image into `dest`.
+///
+/// Mounting the image needs the user namespace of the podman storage when
+/// not running as root, so this runs
+/// [`copy_kernel_debug_files_from_mounted_image`] in a `podman unshare`
+/// child process.
+pub(crate) fn copy_kernel_debug_files_from_image(image: &str, dest: &Utf8Path) -> Result<()> {
+    let self_exe = std::env::current_exe().context("Finding the bcvk binary")?;
+    let mut cmd = if rustix::process::getuid().is_root() {
+        Command::new(self_exe)
+    } else {
+        let mut cmd = Command::new("podman");
+        cmd.arg("unshare").arg(self_exe);
+        cmd
+    };
+    let status = cmd
+        .args([
+            "debug-internals",
+            "copy-kernel-debug-files",
+            image,
+            dest.as_str(),
+        ])
+        .status()
+        .context("Running bcvk to copy kernel debug files")?;
+    if !status.success() {
+        return Err(eyre!("Failed to copy kernel files from {image}: {status}"));
+    }
+    Ok(())
+}
+
+/// Copy the kernel debug files of the kernel [`crate::kernel::find_kernel`]
+/// finds in a container image into `dest`, mounting the image with
+/// `podman image mount`.
+pub(crate) fn copy_kernel_debug_files_from_mounted_image(
+    image: &str,
+    dest: &Utf8Path,
+) -> Result<()> {
+    let output = Command::new("podman")
+        .args(["image", "mount", image])
+        .output()
+        .context("Running podman image mount")?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "Failed to mount {image}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let mountpoint = String::from_utf8(output.stdout)
+        .context("Parsing podman image mount output")?
+        .trim()
+        .to_owned();
+    let result = Dir::open_ambient_dir(&mountpoint, cap_std_ext::cap_std::ambient_authority())
+        .with_context(|| format!("Opening {mountpoint}"))
+        .and_then(|root| copy_kernel_debug_files(&root, dest));
+    if let Err(e) = Command::new("podman")
+        .args(["image", "unmount", image])
+        .output()
+    {
+        warn!("Failed to unmount {image}: {e}");
+    }
+    result
+}
+
+/// Dump the memory of a panicked guest into [`CONTAINER_DUMP_DIR`], along with
+/// the kernel debug files of the image mounted at `source_root`.
+pub(crate) async fn dump_in_container(
+    qmp: &mut QmpClient,
+    source_root: &Utf8Path,
+    format: DumpFormat,
+) -> Result<()> {
+    let dest = Utf8Path::new(CONTAINER_DUMP_DIR);
+    let root = Dir::open_ambient_dir(source_root, cap_std_ext::cap_std::ambient_authority())
+        .with_context(|| format!("Opening {source_root}"))?;
+    if let Err(e) = copy_kernel_debug_files(&root, dest) {
+        warn!("Failed to copy kernel debug files: {e:#}");
+    }
+    let vmcore = dest.join(VMCORE);
+    dump_guest_memory(qmp, &vmcore, format).await?;
+    info!("Wrote guest memory dump to {VMCORE} in the --dump-on-panic directory");
+    Ok(())
+}
+
+/// Write the guest memory to `path` with QMP; returns when the dump is complete.
+pub(crate) async fn dump_guest_memory(
+    qmp: &mut QmpClient,
+    path: &Utf8Path,
+    format: DumpFormat,
+) -> Result<()> {
+    qmp.execute(
+        "dump-guest-memory",
+        Some(serde_json::json!({
+            "paging": false,
+            "protocol": format!("file:{path}"),
+            "format": format.as_str(),
+        })),
+    )
+    .await
+    .with_context(|| format!("Dumping guest memory to {path}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cap_std_ext::cap_std;
+    use cap_std_ext::cap_tempfile;
+
+    #[test]
+    fn test_copy_kernel_debug_files() -> Result<()> {
+        let root = cap_tempfile::tempdir(cap_std::ambient_authority())?;
+        let version = "6.12.0-100.fc41.x86_64";
+        root.create_dir_all(format!("usr/lib/modules/{version}"))?;
+        root.create_dir_all(format!("{DEBUG_MODULES_DIR}/{version}"))?;
+        for (path, contents) in [
+            ("vmlinuz", "kernel"),
+            ("initramfs.img", "initramfs"),
+            ("System.map", "map"),
+        ] {
+            root.write(format!("usr/lib/modules/{version}/{path}"), contents)?;
+        }
+        root.write(format!("{DEBUG_MODULES_DIR}/{version}/vmlinux"), "debug")?;
+
+        let dest = tempfile::tempdir()?;
+        let dest = Utf8Path::from_path(dest.path()).unwrap();
+        copy_kernel_debug_files(&root, dest)?;
+        assert_eq!(std::fs::read_to_string(dest.join("vmlinux"))?, "debug");
+        assert_eq!(std::fs::read_to_string(dest.join("System.map"))?, "map");
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_opts() {
+        let opts = DumpOpts::try_parse_from(["test", "--dump-on-panic=/tmp/d"]).unwrap();
+        assert_eq!(opts.dump_format, DumpFormat::Elf);
+        let opts =
+            DumpOpts::try_parse_from(["test", "--dump-on-panic=/tmp/d", "--dump-format=kdump"])
+                .unwrap();
+        assert_eq!(opts.dump_format.as_str(), "kdump-zlib");
+        assert!(DumpOpts::try_parse_from(["test", "--dump-format=elf"]).is_err());
+    }
+}
@@ -4,9 +4,417 @@
 //! Provides functions for injecting configuration into VMs via systemd credentials
 //! using SMBIOS firmware variables (preferred) or kernel command-line arguments.
 //! Supports SSH keys, mount units, environment configuration, and AF_VSOCK setup.
+//!
+//! User-provided credentials (`--credential`, `--credential-file`, `--secret`)
+//! are written to files which QEMU reads, so their values never appear in
+//! process arguments or domain XML; large ones are passed via fw_cfg.
+
+use std::io::Write as _;
+use std::os::unix::fs::OpenOptionsExt as _;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::arch::Arch;
 
 // Re-export credential functions from bcvk-qemu that are used within kit
 pub use bcvk_qemu::{
     generate_virtiofs_mount_unit, guest_path_to_unit_name, key_to_root_tmpfiles_d,
     smbios_cred_for_root_ssh, smbios_creds_for_storage_opts, storage_opts_tmpfiles_d_lines,
 };
+
+/// Directory in the ephemeral container where `--credential-file` files and
+/// `--secret` secrets are mounted, one file per credential
+pub(crate) const CREDENTIALS_MOUNT: &str = "/run/bcvk-credentials";
+
+/// fw_cfg directory systemd imports credentials from
+const FW_CFG_CREDENTIALS_DIR: &str = "opt/io.systemd.credentials/";
+
+/// QEMU limits fw_cfg names to 55 bytes
+const FW_CFG_NAME_MAX: usize = 55;
+
+/// Largest encoded credential passed via SMBIOS; all SMBIOS structures have
+/// to fit into 64KiB with 32-bit entry points, so larger ones use fw_cfg.
+const SMBIOS_CREDENTIAL_MAX: usize = 16 * 1024;
+
+/// Options for passing systemd credentials to the guest
+#[derive(Parser, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CredentialOpts {
+    /// Pass a systemd credential to the guest; the value is visible to other users, use --secret for secrets
+    #[clap(long = "credential", value_name = "NAME=VALUE")]
+    pub credentials: Vec<String>,
+
+    /// Pass the contents of a host file as a systemd credential to the guest
+    #[clap(long = "credential-file", value_name = "NAME=PATH")]
+    pub credential_files: Vec<String>,
+
+    /// Pass a podman secret as a systemd credential to the guest, named like the secret unless NAME is given
+    #[clap(long = "secret", value_name = "SECRET[:NAME]")]
+    pub secrets: Vec<String>,
+}
+
+/// Where the value of a credential comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum CredentialSource {
+    /// Given on the command line
+    Value(String),
+    /// Contents of a host file
+    File(Utf8PathBuf),
+    /// A podman secret
+    Secret(String),
+}
+
+/// A credential requested on the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Credential {
+    /// systemd credential name
+    pub(crate) name: String,
+    /// Where its value comes from
+    pub(crate) source: CredentialSource,
+}
+
+/// How QEMU is told about a credential written by [`write_credential`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum CredentialArg {
+    /// `-smbios type=11,path=PATH`
+    Smbios(Utf8PathBuf),
+    /// `-fw_cfg name=NAME,file=PATH`
+    FwCfg(String, Utf8PathBuf),
+}
+
+impl CredentialArg {
+    /// The QEMU arguments
+    pub(crate) fn qemu_args(&self) -> [String; 2] {
+        match self {
+            CredentialArg::Smbios(path) => ["-smbios".into(), format!("type=11,path={path}")],
+            CredentialArg::FwCfg(name, path) => {
+                ["-fw_cfg".into(), format!("name={name},file={path}")]
+            }
+        }
+    }
+
+    /// Pass the credential to the VM of `qemu_config`
+    pub(crate) fn add_to_qemu_config(self, qemu_config: &mut crate::qemu::QemuConfig) {
+        match self {
+            CredentialArg::Smbios(path) => qemu_config.add_smbios_credential_file(path),
+            CredentialArg::FwCfg(name, path) => qemu_config.add_fw_cfg(name, path),
+        };
+    }
+}
+
+/// Check that `name` is a valid systemd credential name, which can also be
+/// used as a file name and in QEMU options
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.len() > 255 {
+        return Err(eyre!("Invalid credential name '{name}'"));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| matches!(c, '/' | '=' | ',' | ':') || c.is_whitespace() || c.is_control())
+    {
+        return Err(eyre!(
+            "Invalid credential name '{name}': cannot contain {c:?}"
+        ));
+    }
+    Ok(())
+}
+
+impl CredentialOpts {
+    /// Parse and validate the credentials
+    pub(crate) fn parse(&self) -> Result<Vec<Credential>> {
+        let mut r = Vec::new();
+        for spec in &self.credentials {
+            let (name, value) = spec
+                .split_once('=')
+                .ok_or_else(|| eyre!("Invalid --credential '{spec}', expected NAME=VALUE"))?;
+            r.push(Credential {
+                name: name.to_owned(),
+                source: CredentialSource::Value(value.to_owned()),
+            });
+        }
+        for spec in &self.credential_files {
+            let (name, path) = spec
+                .split_once('=')
+                .ok_or_else(|| eyre!("Invalid --credential-file '{spec}', expected NAME=PATH"))?;
+            r.push(Credential {
+                name: name.to_owned(),
+                source: CredentialSource::File(path.into()),
+            });
+        }
+        for spec in &self.secrets {
+            let (secret, name) = spec.split_once(':').unwrap_or((spec, spec));
+            if secret.is_empty() || secret.contains(',') {
+                return Err(eyre!("Invalid --secret '{spec}', expected SECRET[:NAME]"));
+            }
+            r.push(Credential {
+                name: name.to_owned(),
+                source: CredentialSource::Secret(secret.to_owned()),
+            });
+        }
+
+        let mut names = std::collections::HashSet::new();
+        for cred in &r {
+            validate_name(&cred.name)?;
+            if !names.insert(cred.name.as_str()) {
+                return Err(eyre!("Credential '{}' is given more than once", cred.name));
+            }
+        }
+        Ok(r)
+    }
+
+    /// podman arguments making the credential files and secrets available in
+    /// the ephemeral container at [`CREDENTIALS_MOUNT`]
+    pub(crate) fn podman_args(&self, arch: Option<Arch>) -> Result<Vec<String>> {
+        let creds = self.parse()?;
+        if !creds.is_empty() {
+            check_arch(arch)?;
+        }
+        let mut args = Vec::new();
+        for cred in creds {
+            let target = format!("{CREDENTIALS_MOUNT}/{}", cred.name);
+            match cred.source {
+                CredentialSource::Value(_) => {}
+                CredentialSource::File(path) => {
+                    let path = path
+                        .canonicalize_utf8()
+                        .with_context(|| format!("Opening credential file {path}"))?;
+                    if path.as_str().contains(':') {
+                        return Err(eyre!("Credential file paths cannot contain ':': {path}"));
+                    }
+                    args.extend(["-v".into(), format!("{path}:{target}:ro")]);
+                }
+                CredentialSource::Secret(secret) => {
+                    args.push(format!(
+                        "--secret={secret},type=mount,mode=0400,target={target}"
+                    ));
+                }
+            }
+        }
+        Ok(args)
+    }
+
+    /// Pass the credentials to QEMU, writing them to `dir`; runs in the
+    /// ephemeral container, see [`Self::podman_args`]
+    pub(crate) fn add_to_qemu_config(
+        &self,
+        dir: &Utf8Path,
+        arch: Option<Arch>,
+        qemu_config: &mut crate::qemu::QemuConfig,
+    ) -> Result<()> {
+        for cred in self.parse()? {
+            let value = match cred.source {
+                CredentialSource::Value(value) => value.into_bytes(),
+                CredentialSource::File(_) | CredentialSource::Secret(_) => {
+                    let path = Utf8Path::new(CREDENTIALS_MOUNT).join(&cred.name);
+                    std::fs::read(&path).with_context(|| format!("Reading {path}"))?
+                }
+            };
+            write_credential(dir, &cred.name, &value, arch)?.add_to_qemu_config(qemu_config);
+        }
+        Ok(())
+    }
+}
+
+/// Read the value of a podman secret
+pub(crate) fn read_podman_secret(secret: &str) -> Result<Vec<u8>> {
+    let output = std::process::Command::new("podman")
+        .args([
+            "secret",
+            "inspect",
+            "--showsecret",
+            "--format={{.SecretData}}",
+        ])
+        .arg("--")
+        .arg(secret)
+        .output()
+        .context("Running podman secret inspect")?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "Reading podman secret '{secret}': {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    // The template output ends with a newline
+    let mut data = output.stdout;
+    if data.last() == Some(&b'\n') {
+        data.pop();
+    }
+    Ok(data)
+}
+
+/// SMBIOS and fw_cfg are only available on x86_64 and aarch64 guests
+pub(crate) fn check_arch(arch: Option<Arch>) -> Result<()> {
+    if !matches!(arch, Some(Arch::X86_64 | Arch::Aarch64)) {
+        let arch = arch.map(Arch::as_str).unwrap_or(std::env::consts::ARCH);
+        return Err(eyre!("Credentials are not supported for {arch} guests"));
+    }
+    Ok(())
+}
+
+/// Write credential `name` into `dir`, readable only by the current user,
+/// returning how to pass it to QEMU.
+pub(crate) fn write_credential(
+    dir: &Utf8Path,
+    name: &str,
+    value: &[u8],
+    arch: Option<Arch>,
+) -> Result<CredentialArg> {
+    check_arch(arch)?;
+    let smbios = format!(
+        "io.systemd.credential.binary:{name}={}",
+        data_encoding::BASE64.encode(value)
+    );
+    let (path, contents, arg) = if smbios.len() <= SMBIOS_CREDENTIAL_MAX {
+        let path = dir.join(format!("{name}.smbios"));
+        (path.clone(), smbios.as_bytes(), CredentialArg::Smbios(path))
+    } else {
+        let fw_cfg_name = format!("{FW_CFG_CREDENTIALS_DIR}{name}");
+        if fw_cfg_name.len() > FW_CFG_NAME_MAX {
+            return Err(eyre!(
+                "Credential '{name}' is too large for SMBIOS, and its name too long for fw_cfg (at most {} characters)",
+                FW_CFG_NAME_MAX - FW_CFG_CREDENTIALS_DIR.len()
+            ));
+        }
+        let path = dir.join(name);
+        (path.clone(), value, CredentialArg::FwCfg(fw_cfg_name, path))
+    };
+
+    std::fs::create_dir_all(dir).with_context(|| format!("Creating {dir}"))?;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)
+        .and_then(|mut f| f.write_all(contents))
+        .with_context(|| format!("Writing {path}"))?;
+    Ok(arg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(args: &[&str]) -> CredentialOpts {
+        CredentialOpts::try_parse_from(std::iter::once("test").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let creds = opts(&[
+            "--credential=foo=bar=baz",
+            "--credential-file=cert=/etc/cert.pem",
+            "--secret=token",
+            "--secret=dbpass:db.password",
+        ])
+        .parse()
+        .unwrap();
+        assert_eq!(
+            creds,
+            [
+                Credential {
+                    name: "foo".into(),
+                    source: CredentialSource::Value("bar=baz".into())
+                },
+                Credential {
+                    name: "cert".into(),
+                    source: CredentialSource::File("/etc/cert.pem".into())
+                },
+                Credential {
+                    name: "token".into(),
+                    source: CredentialSource::Secret("token".into())
+                },
+                Credential {
+                    name: "db.password".into(),
+                    source: CredentialSource::Secret("dbpass".into())
+                },
+            ]
+        );
+
+        for bad in [
+            &["--credential=foo"][..],
+            &["--credential=a/b=c"],
+            &["--credential==c"],
+            &["--credential-file=x"],
+            &["--secret=s,target=/etc:x"],
+            &["--credential=x=1", "--secret=x"],
+        ] {
+            assert!(opts(bad).parse().is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_podman_args() {
+        let td = tempfile::tempdir().unwrap();
+        let file = Utf8Path::from_path(td.path()).unwrap().join("cert.pem");
+        std::fs::write(&file, "cert").unwrap();
+        let args = opts(&[
+            "--credential=plain=1",
+            &format!("--credential-file=cert={file}"),
+            "--secret=dbpass:db.password",
+        ])
+        .podman_args(Some(Arch::X86_64))
+        .unwrap();
+        assert_eq!(
+            args,
+            [
+                "-v".to_string(),
+                format!(
+                    "{}:{CREDENTIALS_MOUNT}/cert:ro",
+                    file.canonicalize_utf8().unwrap()
+                ),
+                format!(
+                    "--secret=dbpass,type=mount,mode=0400,target={CREDENTIALS_MOUNT}/db.password"
+                ),
+            ]
+        );
+        // The value is never part of the arguments
+        assert!(!args.iter().any(|a| a.contains("plain")));
+
+        let missing = format!("--credential-file=cert={file}.missing");
+        assert!(opts(&[&missing]).podman_args(Some(Arch::X86_64)).is_err());
+        assert!(opts(&["--secret=s"])
+            .podman_args(Some(Arch::S390x))
+            .is_err());
+        assert!(opts(&[]).podman_args(Some(Arch::S390x)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_credential() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let td = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(td.path()).unwrap().join("creds");
+        let arch = Some(Arch::X86_64);
+
+        let arg = write_credential(&dir, "small", b"hello", arch).unwrap();
+        let path = dir.join("small.smbios");
+        assert_eq!(arg, CredentialArg::Smbios(path.clone()));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "io.systemd.credential.binary:small=aGVsbG8="
+        );
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(
+            arg.qemu_args(),
+            ["-smbios".to_string(), format!("type=11,path={path}")]
+        );
+
+        let large = vec![b'x'; SMBIOS_CREDENTIAL_MAX];
+        let arg = write_credential(&dir, "large", &large, arch).unwrap();
+        let path = dir.join("large");
+        assert_eq!(
+            arg,
+            CredentialArg::FwCfg("opt/io.systemd.credentials/large".into(), path.clone())
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), large);
+
+        let long_name = "x".repeat(40);
+        assert!(write_credential(&dir, &long_name, &large, arch).is_err());
+        assert!(write_credential(&dir, "small", b"hello", Some(Arch::S390x)).is_err());
+    }
+}
@@ -4,21 +4,35 @@
 //! Ephemeral VMs are temporary, non-persistent VMs that are useful for testing, development,
 //! and CI/CD workflows.
 
+use std::io::BufRead;
 use std::process::Command;
+use std::time::{Duration, Instant};
 
+use bootc_utils::CommandRunExt;
 use clap::Subcommand;
 use color_eyre::{eyre::eyre, Result};
 use comfy_table::{presets::UTF8_FULL, Table};
 use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
 
 // Re-export the existing implementations
+use crate::cp;
+use crate::ephemeral_exec;
+use crate::ephemeral_logs;
+use crate::mount;
 use crate::run_ephemeral;
 use crate::run_ephemeral_ssh;
 use crate::ssh;
+use crate::supervisor_status::{
+    FailureKind, GuestFailure, SupervisorState, SupervisorStatus, SUPERVISOR_STATUS_PATH,
+};
 
 /// Label used to identify bcvk ephemeral containers
 const EPHEMERAL_LABEL: &str = "bcvk.ephemeral=1";
 
+/// Default time in seconds given to the guest to power off before it is killed
+pub(crate) const DEFAULT_STOP_TIMEOUT: u32 = 60;
+
 /// SSH connection options for accessing running VMs.
 ///
 /// Provides secure shell access to VMs running within containers,
@@ -39,6 +53,21 @@ pub struct SshOpts {
     pub args: Vec<String>,
 }
 
+/// Options for stopping an ephemeral VM.
+#[derive(clap::Parser, Debug)]
+pub struct StopOpts {
+    /// Name or ID of the container running the target VM
+    pub container_name: String,
+
+    /// Kill the VM immediately instead of asking the guest to power off
+    #[clap(long, short = 'f')]
+    pub force: bool,
+
+    /// Timeout in seconds for graceful shutdown before killing the VM
+    #[clap(long, default_value_t = DEFAULT_STOP_TIMEOUT)]
+    pub timeout: u32,
+}
+
 /// Container list entry for ephemeral VMs
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -60,6 +89,14 @@ pub struct ContainerListEntry {
 
     /// Container command
     pub command: Vec<String>,
+
+    /// Exit code of the container, if it exited
+    #[serde(default)]
+    pub exit_code: i32,
+
+    /// Why the guest failed to boot, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<GuestFailure>,
 }
 
 /// Ephemeral VM operations
@@ -75,7 +112,9 @@ pub struct ContainerListEntry {
 
     bcvk ephemeral run -d --rm --ssh-keygen --name myvm quay.io/fedora/fedora-bootc:42
     bcvk ephemeral ssh myvm
-    podman stop myvm
+    bcvk ephemeral exec myvm -- systemctl is-system-running
+    bcvk ephemeral cp myvm:/var/log/myapp ./logs
+    bcvk ephemeral stop myvm
 
   Run a single command and capture its exit code (CI pattern):
 
@@ -86,6 +125,10 @@ pub struct ContainerListEntry {
     bcvk ephemeral run -d --console --name myvm quay.io/fedora/fedora-bootc:42
     podman logs -f myvm
 
+  Follow the guest journal of a running VM:
+
+    bcvk ephemeral logs -f --unit sshd myvm
+
 # Custom container images (Containerfile)
 
   Any image built from a bootc-compatible base can be used directly — no push
@@ -101,6 +144,11 @@ pub struct ContainerListEntry {
 
     bcvk ephemeral run-ssh --bind .:src quay.io/fedora/fedora-bootc:42
 
+  Share another directory with a running VM, without rebooting it:
+
+    bcvk ephemeral mount add myvm ./logs /mnt/logs
+    bcvk ephemeral mount rm myvm /mnt/logs
+
 # Disk image inspection (virtio-blk)
 
   Attach an existing disk image (e.g. a bootc-generated one) as a virtio-blk
@@ -140,6 +188,26 @@ pub enum EphemeralCommands {
     #[clap(name = "ssh")]
     Ssh(SshOpts),
 
+    /// Copy files to and from a running VM
+    #[clap(name = "cp")]
+    Cp(cp::CpOpts),
+
+    /// Run a command in a running VM without SSH
+    #[clap(name = "exec")]
+    Exec(ephemeral_exec::ExecOpts),
+
+    /// Show the guest journal of a running VM
+    #[clap(name = "logs")]
+    Logs(ephemeral_logs::LogsOpts),
+
+    /// Gracefully stop a running ephemeral VM
+    #[clap(name = "stop")]
+    Stop(StopOpts),
+
+    /// Add or remove host directory shares of a running VM
+    #[clap(name = "mount", subcommand)]
+    Mount(mount::MountCommands),
+
     /// List ephemeral VM containers
     #[clap(name = "ps")]
     Ps {
@@ -172,7 +240,10 @@ impl EphemeralCommands {
                 ssh::connect_via_container(&opts.container_name, opts.args)
             }
             EphemeralCommands::Ps { json } => {
-                let containers = list_ephemeral_containers()?;
+                let mut containers = list_ephemeral_containers()?;
+                for container in containers.iter_mut() {
+                    container.failure = container_guest_failure(container);
+                }
 
                 if json {
                     let json_output = serde_json::to_string_pretty(&containers)?;
@@ -202,11 +273,16 @@ impl EphemeralCommands {
                             container.image.clone()
                         };
 
+                        let status = match container.failure {
+                            Some(failure) => format!("{} ({})", container.state, failure.kind),
+                            None => container.state,
+                        };
+
                         table.add_row(vec![
                             id.to_string(),
                             image,
                             container.created_at,
-                            container.state,
+                            status,
                             names,
                         ]);
                     }
@@ -215,22 +291,296 @@ impl EphemeralCommands {
                 }
                 Ok(())
             }
+            EphemeralCommands::Cp(opts) => copy_ephemeral(opts),
+            EphemeralCommands::Exec(opts) => ephemeral_exec::run(opts),
+            EphemeralCommands::Logs(opts) => ephemeral_logs::run(opts),
+            EphemeralCommands::Stop(opts) => {
+                let timeout = Duration::from_secs(opts.timeout.into());
+                match stop_ephemeral_vm(&opts.container_name, timeout, opts.force)? {
+                    StopOutcome::NotRunning => {
+                        println!("VM '{}' is not running", opts.container_name)
+                    }
+                    StopOutcome::PoweredOff => {
+                        println!("VM '{}' stopped successfully", opts.container_name)
+                    }
+                    StopOutcome::Killed => println!("VM '{}' killed", opts.container_name),
+                }
+                Ok(())
+            }
+            EphemeralCommands::Mount(command) => mount::run_ephemeral(command),
             EphemeralCommands::RmAll { force } => remove_all_ephemeral_containers(force),
         }
     }
 }
 
+/// Copy files to or from an ephemeral VM over SSH.
+fn copy_ephemeral(opts: cp::CpOpts) -> Result<()> {
+    let transfer = opts.transfer()?;
+    let container_name = transfer.name();
+    ssh::verify_container_running(container_name)?;
+    let options = ssh::SshConnectionOptions {
+        allocate_tty: false,
+        forward_stdin: true,
+        ..Default::default()
+    };
+    transfer.run(|script| {
+        ssh::build_podman_ssh_command(container_name, &[script.to_string()], &options)
+    })
+}
+
+/// How an ephemeral VM was stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StopOutcome {
+    /// The container was not running
+    NotRunning,
+    /// The guest powered off on its own
+    PoweredOff,
+    /// The container was killed
+    Killed,
+}
+
+/// How to stop a container, depending on its state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StopAction {
+    /// Nothing to do
+    None,
+    /// Ask the guest to power off, killing it after the timeout
+    Powerdown,
+    /// Kill the container right away
+    Kill,
+}
+
+/// Decide how to stop a container in `state` (as in `podman inspect`'s
+/// `.State.Status`).
+///
+/// A paused container cannot react to a power off request, so it is killed.
+fn stop_action(state: &str, force: bool) -> StopAction {
+    match state {
+        "running" if force => StopAction::Kill,
+        "running" => StopAction::Powerdown,
+        "paused" => StopAction::Kill,
+        _ => StopAction::None,
+    }
+}
+
+/// Stop an ephemeral VM, giving the guest a chance to shut down cleanly.
+///
+/// Unless `force` is set, the guest is asked to power off and we wait up to
+/// `timeout` for the supervisor to report that QEMU exited. Killing QEMU
+/// outright can lose data written to `--bind` mounts or `--mount-disk-file`
+/// disks, so that is only done as a last resort.
+pub(crate) fn stop_ephemeral_vm(
+    container_name: &str,
+    timeout: Duration,
+    force: bool,
+) -> Result<StopOutcome> {
+    let output = Command::new("podman")
+        .args([
+            "inspect",
+            "--format",
+            "{{.State.Status}}",
+            "--",
+            container_name,
+        ])
+        .output()?;
+    if !output.status.success() {
+        return Err(eyre!("VM '{}' not found", container_name));
+    }
+    let state = String::from_utf8_lossy(&output.stdout);
+    let action = stop_action(state.trim(), force);
+    debug!("VM '{container_name}' is {}: {action:?}", state.trim());
+    if action == StopAction::None {
+        return Ok(StopOutcome::NotRunning);
+    }
+    // Look this up first, as the container may be removed once stopped
+    let membership = crate::internal_network::Membership::of_container(container_name)?;
+    let outcome = stop_running_vm(container_name, action, timeout)?;
+    if let Some(membership) = membership {
+        if let Err(e) = membership.leave() {
+            warn!("{e:#}");
+        }
+    }
+    Ok(outcome)
+}
+
+/// Stop a running or paused VM as `action` says.
+fn stop_running_vm(
+    container_name: &str,
+    action: StopAction,
+    timeout: Duration,
+) -> Result<StopOutcome> {
+    if action == StopAction::Powerdown {
+        match request_powerdown(container_name) {
+            Ok(()) => {
+                if wait_for_vm_stopped(container_name, timeout)? {
+                    return Ok(StopOutcome::PoweredOff);
+                }
+                warn!(
+                    "VM '{}' did not power off within {}s, killing it",
+                    container_name,
+                    timeout.as_secs()
+                );
+            }
+            Err(e) => warn!("Failed to request power off of VM '{container_name}': {e:#}"),
+        }
+    }
+
+    Command::new("podman")
+        .args(["kill", "--", container_name])
+        .run_capture_stderr()
+        .map_err(|e| eyre!("Failed to kill VM '{}': {}", container_name, e))?;
+    Ok(StopOutcome::Killed)
+}
+
+/// Ask the guest to power off.
+///
+/// This sends an ACPI power button press via QMP; if that is unavailable
+/// we fall back to `systemctl poweroff` over SSH.
+fn request_powerdown(container_name: &str) -> Result<()> {
+    let r = Command::new("podman")
+        .args([
+            "exec",
+            "--",
+            container_name,
+            run_ephemeral::ENTRYPOINT,
+            "powerdown",
+        ])
+        .run_capture_stderr();
+    let Err(e) = r else {
+        return Ok(());
+    };
+    debug!("ACPI powerdown failed: {e}; trying systemctl poweroff via SSH");
+    // The connection drops as the guest shuts down, so the exit status isn't meaningful
+    let options = ssh::SshConnectionOptions::for_connectivity_test();
+    ssh::connect(
+        container_name,
+        vec!["systemctl".to_string(), "poweroff".to_string()],
+        &options,
+    )?;
+    Ok(())
+}
+
+/// Wait for the supervisor to report that QEMU has exited.
+///
+/// Returns `false` if the VM is still running after `timeout`.
+fn wait_for_vm_stopped(container_name: &str, timeout: Duration) -> Result<bool> {
+    let start = Instant::now();
+    let mut monitor = run_ephemeral_ssh::spawn_status_monitor(container_name)?;
+    let stdout = monitor
+        .stdout
+        .take()
+        .ok_or_else(|| eyre!("Status monitor for VM '{container_name}' has no stdout"))?;
+    let (tx, rx) = std::sync::mpsc::channel::<bool>();
+    std::thread::spawn(move || {
+        for line in std::io::BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            match serde_json::from_str::<SupervisorStatus>(&line) {
+                Ok(status) if !status.running => {
+                    let _ = tx.send(true);
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => debug!("Ignoring invalid status line: {e}"),
+            }
+        }
+        let _ = tx.send(false);
+    });
+
+    let r = rx.recv_timeout(timeout);
+    let _ = monitor.kill();
+    let _ = monitor.wait();
+    match r {
+        Ok(true) => return Ok(true),
+        Ok(false) => {}
+        Err(_) => return Ok(false),
+    }
+
+    // The monitor exited without a final status, most likely because the
+    // container is going away; wait for that.
+    debug!("Status monitor exited, waiting for container to stop");
+    while start.elapsed() < timeout {
+        if !run_ephemeral_ssh::is_container_running(container_name)? {
+            return Ok(true);
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    Ok(false)
+}
+
+/// Read the current supervisor status of a running ephemeral VM.
+pub(crate) fn read_supervisor_status(container_name: &str) -> Result<SupervisorStatus> {
+    let status: SupervisorStatus = Command::new("podman")
+        .args(["exec", "--", container_name, "cat", SUPERVISOR_STATUS_PATH])
+        .run_and_parse_json()
+        .map_err(|e| eyre!("Failed to read status of {container_name}: {e}"))?;
+    Ok(status)
+}
+
+/// Find out whether the guest of an ephemeral VM container failed to boot.
+///
+/// While the container runs, the details are in the supervisor status.
+/// The supervisor exits right after a failure though, and then only its
+/// exit code is left; the guest output is in `podman logs`.
+pub(crate) fn container_guest_failure(container: &ContainerListEntry) -> Option<GuestFailure> {
+    if container.state == "running" {
+        return match read_supervisor_status(&container.id) {
+            Ok(SupervisorStatus {
+                state: Some(SupervisorState::Failed(failure)),
+                ..
+            }) => Some(failure),
+            Ok(_) => None,
+            Err(e) => {
+                debug!("{e}");
+                None
+            }
+        };
+    }
+    if container.state != "exited" {
+        return None;
+    }
+    FailureKind::from_exit_code(container.exit_code).map(|kind| GuestFailure {
+        kind,
+        reason: format!("{} ({kind}); see podman logs", kind.summary()),
+        console: Vec::new(),
+    })
+}
+
+/// Find out whether the guest of the ephemeral VM container `container_id`
+/// failed, e.g. after losing the SSH connection to it.
+pub(crate) fn guest_failure_of(container_id: &str) -> Option<GuestFailure> {
+    let containers = list_ephemeral_containers()
+        .map_err(|e| debug!("{e}"))
+        .ok()?;
+    let container = containers.iter().find(|c| c.id == container_id)?;
+    container_guest_failure(container)
+}
+
 /// List ephemeral VM containers with bcvk.ephemeral=1 label
 pub(crate) fn list_ephemeral_containers() -> Result<Vec<ContainerListEntry>> {
-    use bootc_utils::CommandRunExt;
+    let containers: Vec<ContainerListEntry> = Command::new("podman")
+        .args([
+            "ps",
+            "--all",
+            "--format",
+            "json",
+            &format!("--filter=label={}", EPHEMERAL_LABEL),
+        ])
+        .run_and_parse_json()
+        .map_err(|e| eyre!("Failed to list ephemeral containers: {}", e))?;
+    Ok(containers)
+}
 
+/// List ephemeral VM containers that also have `label` (`key=value`), oldest first
+pub(crate) fn list_ephemeral_containers_with_label(label: &str) -> Result<Vec<ContainerListEntry>> {
     let containers: Vec<ContainerListEntry> = Command::new("podman")
         .args([
             "ps",
             "--all",
+            "--sort=created",
             "--format",
             "json",
             &format!("--filter=label={}", EPHEMERAL_LABEL),
+            &format!("--filter=label={label}"),
         ])
         .run_and_parse_json()
         .map_err(|e| eyre!("Failed to list ephemeral containers: {}", e))?;
@@ -253,15 +603,32 @@ pub(crate) struct RemoveContainerResult {
 /// Runs `podman rm -f` for the given container ID. This is the building
 /// block used by both the CLI (`rm-all`) and the varlink `Rm` method.
 pub(crate) fn remove_single_container(container_id: &str) -> RemoveContainerResult {
+    let membership = match crate::internal_network::Membership::of_container(container_id) {
+        Ok(membership) => membership,
+        Err(e) => {
+            return RemoveContainerResult {
+                id: container_id.to_owned(),
+                removed: false,
+                error: Some(format!("{e:#}")),
+            }
+        }
+    };
     let result = Command::new("podman")
         .args(["rm", "-f", "--", container_id])
         .output();
     match result {
-        Ok(output) if output.status.success() => RemoveContainerResult {
-            id: container_id.to_owned(),
-            removed: true,
-            error: None,
-        },
+        Ok(output) if output.status.success() => {
+            if let Some(membership) = membership {
+                if let Err(e) = membership.leave() {
+                    warn!("{e:#}");
+                }
+            }
+            RemoveContainerResult {
+                id: container_id.to_owned(),
+                removed: true,
+                error: None,
+            }
+        }
         Ok(output) => RemoveContainerResult {
             id: container_id.to_owned(),
             removed: false,
@@ -276,13 +643,19 @@ pub(crate) fn remove_single_container(container_id: &str) -> RemoveContainerResu
 }
 
 /// Remove the given ephemeral containers, returning per-container results
+///
+/// Image stores of imported images that are no longer used are removed too.
 pub(crate) fn remove_ephemeral_containers(
     containers: &[ContainerListEntry],
 ) -> Vec<RemoveContainerResult> {
-    containers
+    let results = containers
         .iter()
         .map(|container| remove_single_container(&container.id))
-        .collect()
+        .collect();
+    if let Err(e) = crate::image_archive::prune_stores() {
+        warn!("Failed to prune unused image stores: {e:#}");
+    }
+    results
 }
 
 /// Remove all ephemeral VM containers
@@ -335,3 +708,41 @@ fn remove_all_ephemeral_containers(force: bool) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_stop_opts() {
+        let opts = StopOpts::try_parse_from(["stop", "myvm"]).unwrap();
+        assert_eq!(opts.container_name, "myvm");
+        assert!(!opts.force);
+        assert_eq!(opts.timeout, DEFAULT_STOP_TIMEOUT);
+
+        let opts = StopOpts::try_parse_from(["stop", "-f", "--timeout=5", "myvm"]).unwrap();
+        assert!(opts.force);
+        assert_eq!(opts.timeout, 5);
+
+        assert!(StopOpts::try_parse_from(["stop"]).is_err());
+        assert!(StopOpts::try_parse_from(["stop", "--timeout=-1", "myvm"]).is_err());
+    }
+
+    #[test]
+    fn test_stop_action() {
+        let cases = [
+            ("running", false, StopAction::Powerdown),
+            ("running", true, StopAction::Kill),
+            ("paused", false, StopAction::Kill),
+            ("paused", true, StopAction::Kill),
+            ("exited", false, StopAction::None),
+            ("exited", true, StopAction::None),
+            ("created", false, StopAction::None),
+            ("stopping", false, StopAction::None),
+        ];
+        for (state, force, expected) in cases {
+            assert_eq!(stop_action(state, force), expected, "{state} force={force}");
+        }
+    }
+}
@@ -0,0 +1,525 @@
+//! Run commands in ephemeral VMs over AF_VSOCK
+//!
+//! `bcvk ephemeral exec` works like `podman exec`, but for the VM inside the
+//! container. Unlike `bcvk ephemeral ssh` no SSH key is needed and the exit
+//! status of the command is returned verbatim.
+//!
+//! The guest listens on [`EXEC_VSOCK_PORT`] via a socket-activated unit
+//! injected with SMBIOS credentials, and only accepts connections presenting
+//! a random token generated for each VM. The token is passed to the guest as
+//! the `bcvk.exec-token` credential and kept in [`EXEC_TOKEN_PATH`] in the
+//! container, so only those who can `podman exec` into the container can run
+//! commands; other users reaching the guest CID can't.
+//!
+//! A command is run with two connections, see `units/bcvk-exec-handler.sh`.
+//! Both start with the token line. One streams the stderr of the command
+//! (`stderr SESSION`), and the other sends the request (`exec SESSION NONCE`,
+//! a line with the number of `systemd-run` arguments, then each argument
+//! base64 encoded on its own line) followed by stdin. The two meet at a FIFO
+//! named after the random session in the guest, and give up if the other
+//! does not show up in time. The guest runs the command with `systemd-run
+//! --pipe` (or `--pty`, where stderr is the terminal and there is no stderr
+//! connection, so the session is `-`) and after its output writes
+//! `<nonce>:<exit status>`. As the nonce is never visible to the command, it
+//! can't be confused with output.
+
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::fd::AsFd;
+use std::os::unix::fs::OpenOptionsExt as _;
+use std::process::Command;
+
+use camino::Utf8Path;
+use clap::Parser;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tracing::debug;
+use vsock::{VsockAddr, VsockStream};
+
+use crate::arch::Arch;
+use crate::run_ephemeral;
+
+/// AF_VSOCK port of the guest exec listener; must match `bcvk-exec.socket`
+pub(crate) const EXEC_VSOCK_PORT: u32 = 9022;
+
+/// Socket unit for the guest exec listener
+pub(crate) const EXEC_SOCKET_UNIT: &str = include_str!("units/bcvk-exec.socket");
+
+/// Per-connection service for the guest exec listener
+pub(crate) const EXEC_SERVICE_UNIT: &str = include_str!("units/bcvk-exec@.service");
+
+/// Script handling a connection, run by [`EXEC_SERVICE_UNIT`]
+const EXEC_HANDLER: &str = include_str!("units/bcvk-exec-handler.sh");
+
+/// Where the token authenticating exec connections is kept in the container
+const EXEC_TOKEN_PATH: &str = "/run/bcvk-exec-token";
+
+/// Name of the guest credential holding the token
+const EXEC_TOKEN_CREDENTIAL: &str = "bcvk.exec-token";
+
+/// Options describing the command to run in the guest.
+#[derive(Parser, Debug, Clone, Default)]
+pub struct GuestExecOpts {
+    /// Keep STDIN open and forward it to the command
+    #[clap(short = 'i', long)]
+    pub interactive: bool,
+
+    /// Allocate a pseudo-TTY for the command
+    #[clap(short = 't', long)]
+    pub tty: bool,
+
+    /// Set environment variables for the command (key=value)
+    #[clap(short = 'e', long = "env")]
+    pub env: Vec<String>,
+
+    /// Working directory for the command inside the VM
+    #[clap(short = 'w', long)]
+    pub workdir: Option<String>,
+
+    /// User to run the command as inside the VM (name or UID)
+    #[clap(short = 'u', long)]
+    pub user: Option<String>,
+
+    /// Command and arguments to run
+    #[clap(trailing_var_arg = true, required = true)]
+    pub command: Vec<String>,
+}
+
+impl GuestExecOpts {
+    /// Render back to command line arguments, for re-executing inside the container.
+    fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.interactive {
+            args.push("--interactive".to_string());
+        }
+        if self.tty {
+            args.push("--tty".to_string());
+        }
+        for env in &self.env {
+            args.push(format!("--env={env}"));
+        }
+        if let Some(workdir) = &self.workdir {
+            args.push(format!("--workdir={workdir}"));
+        }
+        if let Some(user) = &self.user {
+            args.push(format!("--user={user}"));
+        }
+        args.push("--".to_string());
+        args.extend(self.command.iter().cloned());
+        args
+    }
+
+    /// Arguments for `systemd-run` in the guest.
+    fn systemd_run_args(&self, extra_env: &[String]) -> Vec<String> {
+        let mut args = ["--quiet", "--wait", "--collect", "--service-type=exec"]
+            .map(String::from)
+            .to_vec();
+        args.push(if self.tty { "--pty" } else { "--pipe" }.to_string());
+        for env in extra_env.iter().chain(self.env.iter()) {
+            args.push(format!("--setenv={env}"));
+        }
+        if let Some(workdir) = &self.workdir {
+            args.push(format!("--working-directory={workdir}"));
+        }
+        if let Some(user) = &self.user {
+            args.push(format!("--uid={user}"));
+        }
+        args.push("--".to_string());
+        args.extend(self.command.iter().cloned());
+        args
+    }
+}
+
+/// Options for running a command in an ephemeral VM.
+#[derive(Parser, Debug)]
+pub struct ExecOpts {
+    /// Name or ID of the container running the target VM
+    pub container_name: String,
+
+    /// The command to run in the VM
+    #[clap(flatten)]
+    pub guest: GuestExecOpts,
+}
+
+/// Run a command in an ephemeral VM, exiting with its exit status.
+pub fn run(opts: ExecOpts) -> Result<()> {
+    let status = crate::ephemeral::read_supervisor_status(&opts.container_name)?;
+    if let Some(reason) = status.exec_unavailable {
+        return Err(eyre!(
+            "exec is not available for VM '{}': {reason}",
+            opts.container_name
+        ));
+    }
+
+    let mut cmd = Command::new("podman");
+    cmd.arg("exec");
+    if opts.guest.interactive {
+        cmd.arg("-i");
+    }
+    if opts.guest.tty {
+        cmd.arg("-t");
+    }
+    cmd.args([
+        "--",
+        &opts.container_name,
+        run_ephemeral::ENTRYPOINT,
+        "exec",
+    ]);
+    cmd.args(opts.guest.to_args());
+    debug!("{cmd:?}");
+
+    let status = cmd.status().context("Failed to run podman exec")?;
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// Set up the guest exec listener: its units are added to `smbios_creds`,
+/// and a new token is written to [`EXEC_TOKEN_PATH`] and, as a secret
+/// credential, into `credentials_dir` for QEMU.
+pub(crate) fn configure_guest(
+    smbios_creds: &mut Vec<String>,
+    credentials_dir: &Utf8Path,
+    arch: Option<Arch>,
+    qemu_config: &mut crate::qemu::QemuConfig,
+) -> Result<()> {
+    for (name, unit) in [
+        ("bcvk-exec.socket", EXEC_SOCKET_UNIT),
+        ("bcvk-exec@.service", EXEC_SERVICE_UNIT),
+    ] {
+        let encoded = data_encoding::BASE64.encode(unit.as_bytes());
+        smbios_creds.push(format!(
+            "io.systemd.credential.binary:systemd.extra-unit.{name}={encoded}"
+        ));
+    }
+    let encoded_dropin = data_encoding::BASE64.encode(b"[Unit]\nWants=bcvk-exec.socket\n");
+    smbios_creds.push(format!(
+        "io.systemd.credential.binary:systemd.unit-dropin.sockets.target~bcvk-exec={encoded_dropin}"
+    ));
+    let encoded_handler = data_encoding::BASE64.encode(EXEC_HANDLER.as_bytes());
+    smbios_creds.push(format!(
+        "io.systemd.credential.binary:bcvk.exec-handler={encoded_handler}"
+    ));
+
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(EXEC_TOKEN_PATH)
+        .and_then(|mut f| f.write_all(token.as_bytes()))
+        .with_context(|| format!("Writing {EXEC_TOKEN_PATH}"))?;
+    // Written to a file, so the token does not show up in QEMU's arguments
+    crate::credentials::write_credential(
+        credentials_dir,
+        EXEC_TOKEN_CREDENTIAL,
+        token.as_bytes(),
+        arch,
+    )?
+    .add_to_qemu_config(qemu_config);
+    Ok(())
+}
+
+/// The request streaming the stderr of the command of `session`
+fn stderr_request(token: &str, session: &str) -> String {
+    format!("{token}\nstderr {session}\n")
+}
+
+/// The request running `systemd-run` with `args`; without a session, stderr
+/// is not separated from the output.
+fn exec_request(token: &str, session: Option<&str>, nonce: &str, args: &[String]) -> String {
+    let session = session.unwrap_or("-");
+    let mut request = format!("{token}\nexec {session} {nonce}\n{}\n", args.len());
+    for arg in args {
+        request.push_str(&data_encoding::BASE64.encode(arg.as_bytes()));
+        request.push('\n');
+    }
+    request
+}
+
+/// Scans the output stream for the exit status trailer.
+#[derive(Debug)]
+struct TrailerScanner {
+    marker: Vec<u8>,
+    pending: Vec<u8>,
+    trailer: Option<Vec<u8>>,
+}
+
+impl TrailerScanner {
+    fn new(nonce: &str) -> Self {
+        Self {
+            marker: format!("{nonce}:").into_bytes(),
+            pending: Vec::new(),
+            trailer: None,
+        }
+    }
+
+    /// Feed received data, returning the part that is command output.
+    fn feed(&mut self, data: &[u8]) -> Vec<u8> {
+        if let Some(trailer) = self.trailer.as_mut() {
+            trailer.extend_from_slice(data);
+            return Vec::new();
+        }
+        self.pending.extend_from_slice(data);
+        if let Some(pos) = self
+            .pending
+            .windows(self.marker.len())
+            .position(|w| w == self.marker)
+        {
+            let trailer = self.pending.split_off(pos);
+            self.trailer = Some(trailer[self.marker.len()..].to_vec());
+            return std::mem::take(&mut self.pending);
+        }
+        // Hold back anything that may be the start of the marker
+        let keep = (self.marker.len() - 1).min(self.pending.len());
+        let rest = self.pending.split_off(self.pending.len() - keep);
+        std::mem::replace(&mut self.pending, rest)
+    }
+
+    /// Whether the trailer was received
+    fn is_complete(&self) -> bool {
+        self.trailer.is_some()
+    }
+
+    /// Parse the exit status once the stream is complete.
+    fn finish(self) -> Result<i32> {
+        let trailer = self
+            .trailer
+            .ok_or_else(|| eyre!("Connection to VM closed before the command completed"))?;
+        let trailer = String::from_utf8_lossy(&trailer);
+        trailer
+            .trim()
+            .parse()
+            .map_err(|_| eyre!("Invalid exit status from VM: {trailer:?}"))
+    }
+}
+
+/// Put the terminal into raw mode, restoring it when dropped.
+#[derive(Debug)]
+struct RawTerminal {
+    orig: rustix::termios::Termios,
+}
+
+impl RawTerminal {
+    fn new() -> Result<Self> {
+        let stdin = std::io::stdin();
+        let orig = rustix::termios::tcgetattr(stdin.as_fd())?;
+        let mut raw = orig.clone();
+        raw.make_raw();
+        rustix::termios::tcsetattr(stdin.as_fd(), rustix::termios::OptionalActions::Now, &raw)?;
+        Ok(Self { orig })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = rustix::termios::tcsetattr(
+            std::io::stdin().as_fd(),
+            rustix::termios::OptionalActions::Now,
+            &self.orig,
+        );
+    }
+}
+
+/// Run the command in the guest over AF_VSOCK, returning its exit status.
+///
+/// This runs inside the container, and is invoked via `podman exec`.
+fn exec_in_guest(opts: &GuestExecOpts) -> Result<i32> {
+    let cid = std::fs::read_to_string(run_ephemeral::GUEST_CID_PATH)
+        .context("vsock is not available for this VM")?;
+    let cid: u32 = cid.trim().parse().context("Parsing guest CID")?;
+    let token =
+        std::fs::read_to_string(EXEC_TOKEN_PATH).context("exec is not available for this VM")?;
+    let connect = || {
+        VsockStream::connect(&VsockAddr::new(cid, EXEC_VSOCK_PORT))
+            .context("Connecting to the exec service in the VM (has it finished booting?)")
+    };
+
+    // With a terminal, stderr is the terminal too; otherwise it gets its own
+    // connection, made first so the guest has it when the command starts
+    let session = (!opts.tty).then(|| uuid::Uuid::new_v4().simple().to_string());
+    let stderr_copier = match session.as_deref() {
+        Some(session) => {
+            let mut stream = connect()?;
+            stream.write_all(stderr_request(&token, session).as_bytes())?;
+            stream.shutdown(Shutdown::Write)?;
+            let handle = stream.try_clone()?;
+            let copier = std::thread::spawn(move || {
+                std::io::copy(&mut stream, &mut std::io::stderr().lock())
+            });
+            Some((handle, copier))
+        }
+        None => None,
+    };
+
+    let mut stream = connect()?;
+
+    let mut extra_env = Vec::new();
+    if opts.tty {
+        if let Ok(term) = std::env::var("TERM") {
+            extra_env.push(format!("TERM={term}"));
+        }
+        if let Ok(size) = rustix::termios::tcgetwinsize(std::io::stdout().as_fd()) {
+            extra_env.push(format!("COLUMNS={}", size.ws_col));
+            extra_env.push(format!("LINES={}", size.ws_row));
+        }
+    }
+
+    let nonce = uuid::Uuid::new_v4().simple().to_string();
+    let args = opts.systemd_run_args(&extra_env);
+    stream.write_all(exec_request(&token, session.as_deref(), &nonce, &args).as_bytes())?;
+
+    let raw_terminal = if opts.tty && rustix::termios::isatty(std::io::stdin().as_fd()) {
+        Some(RawTerminal::new()?)
+    } else {
+        None
+    };
+
+    if opts.interactive {
+        let mut writer = stream.try_clone()?;
+        std::thread::spawn(move || {
+            let r = std::io::copy(&mut std::io::stdin().lock(), &mut writer);
+            debug!("stdin copy finished: {r:?}");
+            let _ = writer.shutdown(Shutdown::Write);
+        });
+    } else {
+        stream.shutdown(Shutdown::Write)?;
+    }
+
+    let mut scanner = TrailerScanner::new(&nonce);
+    let mut stdout = std::io::stdout().lock();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        let output = scanner.feed(&buf[..n]);
+        stdout.write_all(&output)?;
+        stdout.flush()?;
+    }
+    drop(raw_terminal);
+    if let Some((handle, copier)) = stderr_copier {
+        // Without a command, nothing ends the stderr stream
+        if !scanner.is_complete() {
+            let _ = handle.shutdown(Shutdown::Both);
+        }
+        let r = copier
+            .join()
+            .map_err(|_| eyre!("Copying stderr of the command panicked"))?;
+        r.context("Copying stderr of the command")?;
+    }
+
+    scanner.finish()
+}
+
+/// Entry point inside the container; exits with the guest command's status.
+pub(crate) fn exec_in_container(opts: GuestExecOpts) -> Result<()> {
+    let code = exec_in_guest(&opts)?;
+    std::process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_units_match_port() {
+        assert!(EXEC_SOCKET_UNIT.contains(&format!("ListenStream=vsock::{EXEC_VSOCK_PORT}\n")));
+        assert!(EXEC_SERVICE_UNIT.contains("StandardInput=socket"));
+        assert!(EXEC_SERVICE_UNIT.contains("ExecStart=/bin/sh %d/bcvk.exec-handler\n"));
+        assert!(EXEC_HANDLER.contains(&format!("$CREDENTIALS_DIRECTORY/{EXEC_TOKEN_CREDENTIAL}")));
+    }
+
+    #[test]
+    fn test_requests() {
+        assert_eq!(stderr_request("t0k", "5e55"), "t0k\nstderr 5e55\n");
+        let args = ["--pipe", "--", "sh", "-c", "echo 'a'\n"].map(String::from);
+        assert_eq!(
+            exec_request("t0k", Some("5e55"), "n0", &args),
+            "t0k\nexec 5e55 n0\n5\nLS1waXBl\nLS0=\nc2g=\nLWM=\nZWNobyAnYScK\n"
+        );
+        assert_eq!(exec_request("t0k", None, "n0", &[]), "t0k\nexec - n0\n0\n");
+    }
+
+    #[test]
+    fn test_systemd_run_args() {
+        let opts = GuestExecOpts {
+            env: vec!["FOO=bar baz".into()],
+            workdir: Some("/srv".into()),
+            user: Some("core".into()),
+            command: vec!["ls".into(), "-l".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            opts.systemd_run_args(&[]),
+            [
+                "--quiet",
+                "--wait",
+                "--collect",
+                "--service-type=exec",
+                "--pipe",
+                "--setenv=FOO=bar baz",
+                "--working-directory=/srv",
+                "--uid=core",
+                "--",
+                "ls",
+                "-l"
+            ]
+        );
+
+        let opts = GuestExecOpts {
+            tty: true,
+            command: vec!["bash".into()],
+            ..Default::default()
+        };
+        let args = opts.systemd_run_args(&["TERM=xterm".into()]);
+        assert!(args.contains(&"--pty".to_string()));
+        assert!(args.contains(&"--setenv=TERM=xterm".to_string()));
+    }
+
+    #[test]
+    fn test_to_args_roundtrip() {
+        let opts = GuestExecOpts {
+            interactive: true,
+            tty: true,
+            env: vec!["A=1".into()],
+            workdir: Some("/tmp".into()),
+            user: Some("1000".into()),
+            command: vec!["sh".into(), "-c".into(), "exit 3".into()],
+        };
+        let mut args = vec!["exec".to_string()];
+        args.extend(opts.to_args());
+        let parsed = GuestExecOpts::try_parse_from(args).unwrap();
+        assert!(parsed.interactive && parsed.tty);
+        assert_eq!(parsed.env, opts.env);
+        assert_eq!(parsed.workdir, opts.workdir);
+        assert_eq!(parsed.user, opts.user);
+        assert_eq!(parsed.command, opts.command);
+    }
+
+    #[test]
+    fn test_trailer_scanner() {
+        let nonce = "0123456789abcdef";
+        let mut scanner = TrailerScanner::new(nonce);
+        let mut output = Vec::new();
+        // Split the trailer across reads
+        let stream = format!("hello\nworld{nonce}:42\n");
+        for chunk in stream.as_bytes().chunks(3) {
+            output.extend(scanner.feed(chunk));
+        }
+        assert!(scanner.is_complete());
+        assert_eq!(output, b"hello\nworld");
+        assert_eq!(scanner.finish().unwrap(), 42);
+
+        // Output that resembles but doesn't match the marker is passed through
+        let mut scanner = TrailerScanner::new(nonce);
+        let mut output = scanner.feed(b"0123:");
+        output.extend(scanner.feed(format!("{nonce}:0\n").as_bytes()));
+        assert_eq!(output, b"0123:");
+        assert_eq!(scanner.finish().unwrap(), 0);
+
+        // No trailer means the connection was lost
+        let mut scanner = TrailerScanner::new(nonce);
+        scanner.feed(b"partial output");
+        assert!(scanner.finish().is_err());
+    }
+}
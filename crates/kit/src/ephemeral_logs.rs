@@ -0,0 +1,508 @@
+//! Guest journal capture for ephemeral VMs
+//!
+//! The supervisor always streams the guest journal (as `journalctl -o json`
+//! lines) into a bounded ring inside the container, so that `bcvk ephemeral
+//! logs` works for any VM, even one started detached without `--log-dir` or
+//! `--output journal`.
+//!
+//! The ring is a pair of files: once [`JOURNAL_RING_PATH`] grows past
+//! [`JOURNAL_RING_MAX_BYTES`] it is renamed to [`JOURNAL_RING_ROTATED_PATH`],
+//! replacing the previous one, and a new file is started. Readers output the
+//! rotated file followed by the current one.
+
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt as _;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use clap::Parser;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tracing::debug;
+
+use crate::run_ephemeral::{self, journal_json_to_text};
+
+/// Directory of the journal ring inside the container
+pub(crate) const JOURNAL_RING_DIR: &str = "/run/bcvk-journal";
+
+/// The file currently written by the supervisor
+pub(crate) const JOURNAL_RING_PATH: &str = "/run/bcvk-journal/journal.json";
+
+/// The previous file, replaced on each rotation
+pub(crate) const JOURNAL_RING_ROTATED_PATH: &str = "/run/bcvk-journal/journal.json.1";
+
+/// Size at which the ring is rotated; at most twice this is kept
+const JOURNAL_RING_MAX_BYTES: u64 = 4 * 1024 * 1024;
+
+/// How often `--follow` checks for new entries
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Writes journal lines into the ring, rotating it as needed.
+#[derive(Debug)]
+pub(crate) struct JournalRing {
+    file: tokio::fs::File,
+    size: u64,
+}
+
+impl JournalRing {
+    /// Create the ring directory and start an empty ring.
+    pub(crate) async fn create() -> Result<Self> {
+        tokio::fs::create_dir_all(JOURNAL_RING_DIR)
+            .await
+            .with_context(|| format!("Creating {JOURNAL_RING_DIR}"))?;
+        let file = tokio::fs::File::create(JOURNAL_RING_PATH)
+            .await
+            .with_context(|| format!("Creating {JOURNAL_RING_PATH}"))?;
+        Ok(Self { file, size: 0 })
+    }
+
+    /// Append a single journal line (without the newline).
+    pub(crate) async fn write_line(&mut self, line: &str) -> Result<()> {
+        use tokio::io::AsyncWriteExt as _;
+        if self.size >= JOURNAL_RING_MAX_BYTES {
+            tokio::fs::rename(JOURNAL_RING_PATH, JOURNAL_RING_ROTATED_PATH).await?;
+            self.file = tokio::fs::File::create(JOURNAL_RING_PATH).await?;
+            self.size = 0;
+        }
+        let line = format!("{line}\n");
+        self.file.write_all(line.as_bytes()).await?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Options for the container side of `bcvk ephemeral logs`.
+#[derive(Parser, Debug)]
+pub struct CatOpts {
+    /// Keep waiting for new entries
+    #[clap(long)]
+    pub follow: bool,
+}
+
+/// Copy all of `file` from `offset` to `out`, returning the new offset.
+fn copy_from(file: &mut std::fs::File, offset: u64, out: &mut impl Write) -> Result<u64> {
+    file.seek(SeekFrom::Start(offset))?;
+    let n = std::io::copy(file, out)?;
+    Ok(offset + n)
+}
+
+/// Follows the current file of the ring across rotations.
+#[derive(Debug)]
+struct RingFollower {
+    path: PathBuf,
+    file: std::fs::File,
+    ino: u64,
+    offset: u64,
+}
+
+impl RingFollower {
+    /// Output the current file at `path`, and start following it.
+    fn open(path: &Path, out: &mut impl Write) -> Result<Self> {
+        let mut file = std::fs::File::open(path)?;
+        let ino = file.metadata()?.ino();
+        let offset = copy_from(&mut file, 0, out)?;
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            ino,
+            offset,
+        })
+    }
+
+    /// Output the entries written since the last call.
+    fn poll(&mut self, out: &mut impl Write) -> Result<()> {
+        // Check for a rotation first: once the file was renamed, nothing is
+        // written to it anymore, so draining it below loses no entries.
+        let current = match std::fs::metadata(&self.path) {
+            Ok(m) => m.ino(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => self.ino,
+            Err(e) => return Err(e.into()),
+        };
+        self.offset = copy_from(&mut self.file, self.offset, out)?;
+        if current != self.ino {
+            debug!("Journal ring rotated");
+            *self = Self::open(&self.path, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Output the raw JSON lines of the ring; runs inside the container.
+pub(crate) fn cat_ring(opts: CatOpts) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    // The rotated file may be replaced while we read it; tolerate that,
+    // at worst a chunk of entries is skipped.
+    if let Ok(mut f) = std::fs::File::open(JOURNAL_RING_ROTATED_PATH) {
+        std::io::copy(&mut f, &mut stdout)?;
+    }
+    let mut follower =
+        RingFollower::open(Path::new(JOURNAL_RING_PATH), &mut stdout).with_context(|| {
+            format!("Opening {JOURNAL_RING_PATH}; is this VM's journal being captured?")
+        })?;
+    stdout.flush()?;
+    if !opts.follow {
+        return Ok(());
+    }
+
+    loop {
+        std::thread::sleep(FOLLOW_INTERVAL);
+        follower.poll(&mut stdout)?;
+        stdout.flush()?;
+    }
+}
+
+/// Output format of `bcvk ephemeral logs`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogsOutput {
+    /// Timestamp, unit and message, similar to `journalctl -o short`
+    #[default]
+    Short,
+    /// The entries as produced by `journalctl -o json`, one per line
+    Json,
+}
+
+/// Options for showing the guest journal of an ephemeral VM.
+#[derive(Parser, Debug)]
+pub struct LogsOpts {
+    /// Name or ID of the container running the target VM
+    pub container_name: String,
+
+    /// Keep printing new entries as they arrive
+    #[clap(long, short = 'f')]
+    pub follow: bool,
+
+    /// Only show entries of this systemd unit (".service" is implied)
+    #[clap(long, short = 'u')]
+    pub unit: Option<String>,
+
+    /// Only show entries up to this priority (emerg..debug or 0..7)
+    #[clap(long, short = 'p', value_parser = parse_priority)]
+    pub priority: Option<u8>,
+
+    /// Only show entries since this time ("YYYY-MM-DD [HH:MM[:SS]]", RFC 3339, or relative like "-10min")
+    #[clap(long, short = 'S', value_parser = parse_since)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Output format
+    #[clap(long, short = 'o', value_enum, default_value_t)]
+    pub output: LogsOutput,
+}
+
+/// Parse a syslog priority name or number, as `journalctl --priority` does.
+fn parse_priority(s: &str) -> Result<u8> {
+    const NAMES: [&str; 8] = [
+        "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+    ];
+    if let Some(n) = NAMES.iter().position(|&name| name == s) {
+        return Ok(n as u8);
+    }
+    match s.parse::<u8>() {
+        Ok(n) if n <= 7 => Ok(n),
+        _ => Err(eyre!(
+            "Invalid priority {s:?}; expected one of {} or 0..7",
+            NAMES.join(", ")
+        )),
+    }
+}
+
+/// Parse a `--since` value relative to `now`.
+fn parse_since_at(s: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let s = s.trim();
+    if s == "now" {
+        return Ok(now);
+    }
+    if let Some(relative) = s.strip_prefix('-') {
+        let split = relative
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(relative.len());
+        let (n, unit) = relative.split_at(split);
+        let n: i64 = n.parse().map_err(|_| eyre!("Invalid time {s:?}"))?;
+        let delta = match unit.trim() {
+            "s" | "sec" => chrono::Duration::seconds(n),
+            "m" | "min" => chrono::Duration::minutes(n),
+            "h" => chrono::Duration::hours(n),
+            "d" => chrono::Duration::days(n),
+            other => return Err(eyre!("Invalid time unit {other:?} in {s:?}")),
+        };
+        return Ok(now - delta);
+    }
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.with_timezone(&Utc));
+    }
+    let local = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(|| eyre!("Invalid time {s:?}"))?;
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or_else(|| eyre!("Invalid local time {s:?}"))
+}
+
+fn parse_since(s: &str) -> Result<DateTime<Utc>> {
+    parse_since_at(s, Utc::now())
+}
+
+/// Selects journal entries, like the matching `journalctl` options.
+#[derive(Debug, Default)]
+struct JournalFilter {
+    unit: Option<String>,
+    priority: Option<u8>,
+    since_usec: Option<i64>,
+}
+
+impl JournalFilter {
+    fn new(opts: &LogsOpts) -> Self {
+        let unit = opts.unit.as_ref().map(|u| {
+            if u.contains('.') {
+                u.clone()
+            } else {
+                format!("{u}.service")
+            }
+        });
+        Self {
+            unit,
+            priority: opts.priority,
+            since_usec: opts.since.map(|t| t.timestamp_micros()),
+        }
+    }
+
+    fn matches(&self, entry: &serde_json::Value) -> bool {
+        let str_field = |key: &str| entry.get(key).and_then(|v| v.as_str());
+        let num_field = |key: &str| str_field(key).and_then(|v| v.parse::<i64>().ok());
+        if let Some(unit) = &self.unit {
+            // Like `journalctl -u`, include what systemd logs about the unit
+            let unit = Some(unit.as_str());
+            if str_field("_SYSTEMD_UNIT") != unit && str_field("UNIT") != unit {
+                return false;
+            }
+        }
+        if let Some(priority) = self.priority {
+            // Entries without a priority are shown, as journald defaults to info
+            if num_field("PRIORITY").is_some_and(|p| p > priority.into()) {
+                return false;
+            }
+        }
+        if let Some(since) = self.since_usec {
+            if num_field("__REALTIME_TIMESTAMP").is_some_and(|t| t < since) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Format an entry for `--output short`.
+fn format_short(entry: &serde_json::Value, line: &str) -> Option<String> {
+    let text = journal_json_to_text(line)?;
+    let timestamp = entry
+        .get("__REALTIME_TIMESTAMP")
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse::<i64>().ok())
+        .and_then(DateTime::from_timestamp_micros);
+    match timestamp {
+        Some(t) => Some(format!(
+            "{} {text}",
+            t.with_timezone(&Local).format("%b %d %H:%M:%S")
+        )),
+        None => Some(text),
+    }
+}
+
+/// Show the guest journal of an ephemeral VM.
+pub fn run(opts: LogsOpts) -> Result<()> {
+    crate::ssh::verify_container_running(&opts.container_name)?;
+    let filter = JournalFilter::new(&opts);
+
+    let mut cmd = Command::new("podman");
+    cmd.args([
+        "exec",
+        "--",
+        &opts.container_name,
+        run_ephemeral::ENTRYPOINT,
+        "journal-cat",
+    ]);
+    if opts.follow {
+        cmd.arg("--follow");
+    }
+    cmd.stdout(Stdio::piped());
+    debug!("{cmd:?}");
+    let mut child = cmd.spawn().context("Failed to run podman exec")?;
+
+    let stdout = child.stdout.take().unwrap();
+    let mut out = std::io::stdout().lock();
+    let mut reader = BufReader::new(stdout);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        // Lines may be split by rotation or a partial write; skip invalid ones
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        let Ok(entry) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+        let r = match opts.output {
+            LogsOutput::Json => writeln!(out, "{line}"),
+            LogsOutput::Short => match format_short(&entry, line) {
+                Some(text) => out.write_all(text.as_bytes()),
+                None => continue,
+            },
+        };
+        match r {
+            Ok(()) => {}
+            // e.g. piped into `head`
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => break,
+            Err(e) => return Err(e.into()),
+        }
+        if opts.follow {
+            out.flush()?;
+        }
+    }
+    out.flush()?;
+    drop(reader);
+
+    let _ = child.kill();
+    let status = child.wait()?;
+    if !status.success() && !opts.follow {
+        return Err(eyre!(
+            "Failed to read the journal of '{}': {status}",
+            opts.container_name
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_follower_rotation() {
+        let td = tempfile::tempdir().unwrap();
+        let path = td.path().join("journal.json");
+        let rotated = td.path().join("journal.json.1");
+        let append = |path: &Path, line: &str| {
+            let mut f = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .unwrap();
+            writeln!(f, "{line}").unwrap();
+        };
+
+        append(&path, "1");
+        let mut out = Vec::new();
+        let mut follower = RingFollower::open(&path, &mut out).unwrap();
+        append(&path, "2");
+        follower.poll(&mut out).unwrap();
+
+        // Entries written right before a rotation are not lost
+        append(&path, "3");
+        std::fs::rename(&path, &rotated).unwrap();
+        append(&path, "4");
+        follower.poll(&mut out).unwrap();
+        append(&path, "5");
+        follower.poll(&mut out).unwrap();
+        // Nothing new
+        follower.poll(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1\n2\n3\n4\n5\n");
+    }
+
+    fn entry(json: &str) -> serde_json::Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_parse_priority() {
+        assert_eq!(parse_priority("err").unwrap(), 3);
+        assert_eq!(parse_priority("debug").unwrap(), 7);
+        assert_eq!(parse_priority("4").unwrap(), 4);
+        assert!(parse_priority("8").is_err());
+        assert!(parse_priority("error").is_err());
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let now = DateTime::parse_from_rfc3339("2025-06-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(parse_since_at("now", now).unwrap(), now);
+        assert_eq!(
+            parse_since_at("-10min", now).unwrap(),
+            now - chrono::Duration::minutes(10)
+        );
+        assert_eq!(
+            parse_since_at("-2h", now).unwrap(),
+            now - chrono::Duration::hours(2)
+        );
+        assert_eq!(
+            parse_since_at("2025-06-01T11:00:00+01:00", now).unwrap(),
+            now - chrono::Duration::hours(2)
+        );
+        assert!(parse_since_at("2025-06-01 10:30", now).is_ok());
+        assert!(parse_since_at("2025-06-01", now).is_ok());
+        assert!(parse_since_at("-10y", now).is_err());
+        assert!(parse_since_at("yesterday-ish", now).is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let sshd = entry(
+            r#"{"MESSAGE":"hi","_SYSTEMD_UNIT":"sshd.service","PRIORITY":"6","__REALTIME_TIMESTAMP":"2000"}"#,
+        );
+        let started = entry(r#"{"MESSAGE":"Started sshd.service","UNIT":"sshd.service"}"#);
+        let kernel = entry(r#"{"MESSAGE":"oops","PRIORITY":"2","__REALTIME_TIMESTAMP":"1000"}"#);
+
+        let all = JournalFilter::default();
+        assert!([&sshd, &started, &kernel].iter().all(|e| all.matches(e)));
+
+        let unit = JournalFilter {
+            unit: Some("sshd.service".into()),
+            ..Default::default()
+        };
+        assert!(unit.matches(&sshd));
+        assert!(unit.matches(&started));
+        assert!(!unit.matches(&kernel));
+
+        let priority = JournalFilter {
+            priority: Some(3),
+            ..Default::default()
+        };
+        assert!(!priority.matches(&sshd));
+        assert!(priority.matches(&kernel));
+
+        let since = JournalFilter {
+            since_usec: Some(1500),
+            ..Default::default()
+        };
+        assert!(since.matches(&sshd));
+        assert!(!since.matches(&kernel));
+    }
+
+    #[test]
+    fn test_unit_suffix() {
+        let opts = LogsOpts::try_parse_from(["logs", "vm", "-u", "sshd"]).unwrap();
+        assert_eq!(
+            JournalFilter::new(&opts).unit.as_deref(),
+            Some("sshd.service")
+        );
+        let opts = LogsOpts::try_parse_from(["logs", "vm", "-u", "sshd.socket"]).unwrap();
+        assert_eq!(
+            JournalFilter::new(&opts).unit.as_deref(),
+            Some("sshd.socket")
+        );
+    }
+}
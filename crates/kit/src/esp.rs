@@ -0,0 +1,246 @@
+//! EFI System Partition creation for UEFI-booted ephemeral VMs.
+//!
+//! This builds a small throwaway FAT32 image containing systemd-boot and an
+//! unmodified UKI. mtools is used so that no loop devices or privileges are
+//! required.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::process::Command;
+
+use bootc_utils::CommandRunExt;
+use camino::{Utf8Path, Utf8PathBuf};
+use cap_std_ext::cap_std::fs::Dir;
+use color_eyre::eyre::{eyre, Context, Result};
+use tracing::debug;
+
+/// Where systemd-boot binaries live (relative to the root)
+const SYSTEMD_BOOT_DIR: &str = "usr/lib/systemd/boot/efi";
+
+/// Minimum ESP size; FAT32 needs at least ~33MiB worth of clusters
+const MIN_ESP_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Extra free space added on top of the payload
+const ESP_SLACK: u64 = 32 * 1024 * 1024;
+
+/// The EFI architecture suffix used in removable-media boot paths
+/// and systemd-boot filenames.
+fn efi_arch_suffix(arch: &str) -> Option<&'static str> {
+    match arch {
+        "x86_64" => Some("x64"),
+        "aarch64" => Some("aa64"),
+        _ => None,
+    }
+}
+
+/// The fallback boot path loaded by the firmware, e.g. `EFI/BOOT/BOOTX64.EFI`.
+pub(crate) fn efi_boot_path(arch: &str) -> Option<String> {
+    efi_arch_suffix(arch).map(|s| format!("EFI/BOOT/BOOT{}.EFI", s.to_ascii_uppercase()))
+}
+
+/// Find systemd-boot in the given root, returning its path relative to the root.
+pub(crate) fn find_systemd_boot(root: &Dir, arch: &str) -> Option<Utf8PathBuf> {
+    let suffix = efi_arch_suffix(arch)?;
+    let path = Utf8Path::new(SYSTEMD_BOOT_DIR).join(format!("systemd-boot{suffix}.efi"));
+    root.exists(&path).then_some(path)
+}
+
+/// Compute the ESP image size for the given payload size in bytes.
+fn esp_size(payload: u64) -> u64 {
+    (payload + ESP_SLACK)
+        .next_multiple_of(1024 * 1024)
+        .max(MIN_ESP_SIZE)
+}
+
+/// Run an mtools command against the image.
+pub(crate) fn mtools(tool: &str, image: &Utf8Path, args: &[&str]) -> Result<()> {
+    Command::new(tool)
+        .arg("-i")
+        .arg(image.as_str())
+        .args(args)
+        .run_capture_stderr()
+        .map_err(|e| eyre!("{tool} failed: {e}"))
+}
+
+/// Create a raw ESP image at `dest` containing the UKI, for booting on `arch`.
+///
+/// The UKI is placed in `EFI/Linux/` where systemd-boot discovers it as a
+/// Type #2 boot loader entry. If `systemd_boot` is provided it is installed
+/// as the fallback boot loader; otherwise the UKI itself is, which the
+/// firmware can execute directly.
+pub(crate) fn create_esp(
+    dest: &Utf8Path,
+    arch: &str,
+    uki: &Utf8Path,
+    systemd_boot: Option<&Utf8Path>,
+) -> Result<()> {
+    let boot_path = efi_boot_path(arch)
+        .ok_or_else(|| eyre!("UEFI boot is not supported on architecture {arch}"))?;
+    let uki_name = uki
+        .file_name()
+        .ok_or_else(|| eyre!("Invalid UKI path: {uki}"))?;
+
+    let mut payload = std::fs::metadata(uki)
+        .with_context(|| format!("Querying {uki}"))?
+        .len();
+    if let Some(sdboot) = systemd_boot {
+        payload += std::fs::metadata(sdboot)
+            .with_context(|| format!("Querying {sdboot}"))?
+            .len();
+    } else {
+        // The UKI is copied twice
+        payload *= 2;
+    }
+    let size = esp_size(payload);
+    debug!("Creating {size} byte ESP at {dest}");
+
+    let f = std::fs::File::create(dest).with_context(|| format!("Creating {dest}"))?;
+    f.set_len(size).context("Allocating ESP")?;
+    drop(f);
+
+    mtools("mformat", dest, &["-F", "-v", "ESP", "::"])?;
+    mtools("mmd", dest, &["::/EFI", "::/EFI/BOOT", "::/EFI/Linux"])?;
+    mtools(
+        "mcopy",
+        dest,
+        &[uki.as_str(), &format!("::/EFI/Linux/{uki_name}")],
+    )?;
+    let loader = systemd_boot.unwrap_or(uki);
+    mtools(
+        "mcopy",
+        dest,
+        &[loader.as_str(), &format!("::/{boot_path}")],
+    )?;
+
+    debug!("Installed {loader} as {boot_path} and {uki} in ESP");
+    Ok(())
+}
+
+/// Format `disk` as a FAT filesystem labeled `label`, containing `files` as
+/// (name, contents) pairs in its root directory.
+fn write_fat_image(
+    disk: &mut (impl Read + Write + Seek),
+    label: &str,
+    files: &[(&str, &[u8])],
+) -> Result<()> {
+    // FAT labels are upper case and padded with spaces
+    let label = label.to_ascii_uppercase();
+    let mut volume_label = [b' '; 11];
+    volume_label
+        .get_mut(..label.len())
+        .ok_or_else(|| eyre!("FAT label {label} is too long"))?
+        .copy_from_slice(label.as_bytes());
+    fatfs::format_volume(
+        &mut *disk,
+        fatfs::FormatVolumeOptions::new().volume_label(volume_label),
+    )
+    .context("Formatting FAT image")?;
+    disk.seek(SeekFrom::Start(0))?;
+    let fs = fatfs::FileSystem::new(&mut *disk, fatfs::FsOptions::new())?;
+    for (name, contents) in files {
+        fs.root_dir()
+            .create_file(name)
+            .and_then(|mut f| f.write_all(contents))
+            .with_context(|| format!("Writing {name} to FAT image"))?;
+    }
+    fs.unmount()?;
+    Ok(())
+}
+
+/// Create a FAT floppy image of `size_kb` labeled `label` at `dest`,
+/// containing `files` as (name, contents) pairs.
+///
+/// The image is built in-process, so unlike the ESP it does not need mtools.
+pub(crate) fn create_floppy_image(
+    dest: &Utf8Path,
+    size_kb: u64,
+    label: &str,
+    files: &[(&str, &[u8])],
+) -> Result<()> {
+    let mut f = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest)
+        .with_context(|| format!("Creating {dest}"))?;
+    f.set_len(size_kb * 1024).context("Allocating image")?;
+    write_fat_image(&mut f, label, files).with_context(|| format!("Writing {dest}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cap_std_ext::cap_std;
+    use cap_std_ext::cap_tempfile;
+    use cap_std_ext::dirext::CapStdExtDirExt;
+
+    #[test]
+    fn test_write_fat_image() {
+        use std::io::Cursor;
+
+        let mut disk = Cursor::new(vec![0u8; 1440 * 1024]);
+        write_fat_image(
+            &mut disk,
+            "cidata",
+            &[("user-data", b"#cloud-config\n"), ("meta-data", b"")],
+        )
+        .unwrap();
+        disk.set_position(0);
+        let fs = fatfs::FileSystem::new(&mut disk, fatfs::FsOptions::new()).unwrap();
+        assert_eq!(fs.volume_label(), "CIDATA");
+        let mut contents = String::new();
+        fs.root_dir()
+            .open_file("user-data")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "#cloud-config\n");
+        let names: Vec<_> = fs
+            .root_dir()
+            .iter()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["user-data", "meta-data"]);
+        drop(fs);
+
+        let mut disk = Cursor::new(vec![0u8; 1440 * 1024]);
+        assert!(write_fat_image(&mut disk, "much-too-long", &[]).is_err());
+    }
+
+    #[test]
+    fn test_efi_boot_path() {
+        assert_eq!(
+            efi_boot_path("x86_64").as_deref(),
+            Some("EFI/BOOT/BOOTX64.EFI")
+        );
+        assert_eq!(
+            efi_boot_path("aarch64").as_deref(),
+            Some("EFI/BOOT/BOOTAA64.EFI")
+        );
+        assert_eq!(efi_boot_path("s390x"), None);
+    }
+
+    #[test]
+    fn test_esp_size() {
+        assert_eq!(esp_size(0), MIN_ESP_SIZE);
+        let size = esp_size(100 * 1024 * 1024 + 1);
+        assert!(size >= 100 * 1024 * 1024 + ESP_SLACK);
+        assert_eq!(size % (1024 * 1024), 0);
+    }
+
+    #[test]
+    fn test_find_systemd_boot() -> Result<()> {
+        let tempdir = cap_tempfile::tempdir(cap_std::ambient_authority())?;
+        assert!(find_systemd_boot(&tempdir, "x86_64").is_none());
+
+        tempdir.create_dir_all(SYSTEMD_BOOT_DIR)?;
+        tempdir.atomic_write(
+            "usr/lib/systemd/boot/efi/systemd-bootx64.efi",
+            b"fake systemd-boot",
+        )?;
+        let path = find_systemd_boot(&tempdir, "x86_64").expect("should find systemd-boot");
+        assert_eq!(path, "usr/lib/systemd/boot/efi/systemd-bootx64.efi");
+        assert!(find_systemd_boot(&tempdir, "aarch64").is_none());
+        Ok(())
+    }
+}
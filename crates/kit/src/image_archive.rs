@@ -0,0 +1,278 @@
+//! Images from archives and OCI layouts
+//!
+//! Ephemeral VMs mount their image with `podman run --mount=type=image`, so
+//! it has to be in containers-storage. Images given with the `oci:`,
+//! `oci-archive:` or `docker-archive:` transports are imported into a
+//! temporary store rather than the user's storage, and the podman commands
+//! using the image read that as an additional image store
+//! (`STORAGE_OPTS=additionalimagestore=...`, see [`crate::podman::command`]).
+//!
+//! Each store is a directory below [`stores_dir`]. While bcvk sets up a VM
+//! the store is locked; containers using it afterwards carry its path in the
+//! [`IMAGE_STORE_LABEL`] label, and [`prune_stores`] removes the stores that
+//! are neither locked nor used by any container.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::process::Command;
+
+use bootc_utils::CommandRunExt;
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use rustix::fd::OwnedFd;
+use rustix::fs::FlockOperation;
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+/// Label of containers using an imported image, set to the store directory
+pub(crate) const IMAGE_STORE_LABEL: &str = "bcvk.image-store";
+
+/// Transports of images that are imported rather than used from containers-storage
+const ARCHIVE_TRANSPORTS: &[&str] = &["oci:", "oci-archive:", "docker-archive:"];
+
+/// Repository of imported images, tagged with their short ID
+const IMPORTED_REPOSITORY: &str = "localhost/bcvk-import";
+
+/// Check whether `image` has to be imported, see [`ImportedImage::import`].
+pub(crate) fn is_archive_ref(image: &str) -> bool {
+    ARCHIVE_TRANSPORTS.iter().any(|t| image.starts_with(t))
+}
+
+/// Where temporary image stores are created; large, so not in a tmpfs.
+fn stores_dir() -> Result<Utf8PathBuf> {
+    let base = dirs::cache_dir().unwrap_or_else(|| "/var/tmp".into());
+    let base = Utf8PathBuf::try_from(base)?;
+    Ok(base.join("bcvk/image-stores"))
+}
+
+/// podman operating on the store in `dir`
+fn store_podman(dir: &Utf8Path) -> Command {
+    let mut cmd = Command::new("podman");
+    cmd.arg(format!("--root={}", dir.join("storage")))
+        .arg(format!("--runroot={}", dir.join("run")));
+    cmd
+}
+
+/// Remove a store that is not in use.
+fn remove_store(dir: &Utf8Path) {
+    debug!("Removing image store {dir}");
+    // Layers of rootless podman belong to subordinate IDs, so let podman
+    // remove them first
+    match store_podman(dir).args(["rmi", "--all", "--force"]).output() {
+        Ok(o) if !o.status.success() => {
+            debug!(
+                "Removing images from {dir}: {}",
+                String::from_utf8_lossy(&o.stderr).trim()
+            );
+        }
+        Ok(_) => {}
+        Err(e) => debug!("Running podman rmi: {e}"),
+    }
+    if let Err(e) = std::fs::remove_dir_all(dir) {
+        warn!("Failed to remove image store {dir}: {e}");
+    }
+}
+
+/// An image imported into a temporary store.
+///
+/// The store is removed when this is dropped, unless it was handed over to a
+/// container with [`ImportedImage::keep`].
+#[derive(Debug)]
+pub(crate) struct ImportedImage {
+    dir: Utf8PathBuf,
+    /// The name of the image in the store
+    pub(crate) name: String,
+    lock: Option<OwnedFd>,
+}
+
+impl ImportedImage {
+    /// Import `imgref` (e.g. `oci-archive:/path/image.tar`) into a new store.
+    pub(crate) fn import(imgref: &str) -> Result<Self> {
+        if let Err(e) = prune_stores() {
+            warn!("Failed to prune unused image stores: {e:#}");
+        }
+        let base = stores_dir()?;
+        std::fs::create_dir_all(&base).with_context(|| format!("Creating {base}"))?;
+        let dir = tempfile::Builder::new()
+            .prefix("store-")
+            .tempdir_in(&base)
+            .with_context(|| format!("Creating image store in {base}"))?
+            .keep();
+        let dir = Utf8PathBuf::try_from(dir)?;
+        let lock: OwnedFd = File::create(dir.join("lock"))
+            .context("Creating lock file")?
+            .into();
+        rustix::fs::flock(&lock, FlockOperation::LockExclusive).context("Locking image store")?;
+        // From here on, the store is removed on errors
+        let mut imported = Self {
+            dir,
+            name: String::new(),
+            lock: Some(lock),
+        };
+
+        let mut cmd = store_podman(&imported.dir);
+        cmd.args(["pull", "--", imgref]);
+        crate::images::run_pull(cmd, imgref)?;
+        let output = store_podman(&imported.dir)
+            .args(["images", "--quiet", "--no-trunc"])
+            .output()
+            .context("Running podman images")?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let id = stdout
+            .lines()
+            .next()
+            .and_then(|id| id.trim().strip_prefix("sha256:"))
+            .ok_or_else(|| eyre!("No image found after importing {imgref}"))?;
+        // A name avoids a bare ID as the image of containers and installed systems
+        let name = format!("{IMPORTED_REPOSITORY}:{}", &id[..12.min(id.len())]);
+        let status = store_podman(&imported.dir)
+            .args(["tag", id, &name])
+            .status()
+            .context("Running podman tag")?;
+        if !status.success() {
+            return Err(eyre!("Failed to tag imported image {id}"));
+        }
+        debug!("Imported {imgref} as {name} into {}", imported.dir);
+        imported.name = name;
+        Ok(imported)
+    }
+
+    /// The containers-storage directory of the store
+    pub(crate) fn graph_root(&self) -> Utf8PathBuf {
+        self.dir.join("storage")
+    }
+
+    /// The label recording the store on containers using it
+    pub(crate) fn label(&self) -> String {
+        format!("{IMAGE_STORE_LABEL}={}", self.dir)
+    }
+
+    /// Keep the store for the container labeled with [`ImportedImage::label`].
+    ///
+    /// Returns the lock of the store, which must be held until the container
+    /// was created; it is inherited by podman.
+    pub(crate) fn keep(mut self) -> Result<OwnedFd> {
+        let lock = self.lock.take().expect("store is locked");
+        rustix::io::fcntl_setfd(&lock, rustix::io::FdFlags::empty())?;
+        Ok(lock)
+    }
+}
+
+impl Drop for ImportedImage {
+    fn drop(&mut self) {
+        if self.lock.is_some() {
+            remove_store(&self.dir);
+        }
+    }
+}
+
+/// The stores used by containers, from their [`IMAGE_STORE_LABEL`]
+fn used_stores() -> Result<HashSet<String>> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Entry {
+        #[serde(default)]
+        labels: Option<HashMap<String, String>>,
+    }
+    let containers: Vec<Entry> = Command::new("podman")
+        .args([
+            "ps",
+            "--all",
+            "--format",
+            "json",
+            &format!("--filter=label={IMAGE_STORE_LABEL}"),
+        ])
+        .run_and_parse_json()
+        .map_err(|e| eyre!("Failed to list containers: {e}"))?;
+    Ok(containers
+        .into_iter()
+        .filter_map(|c| c.labels?.remove(IMAGE_STORE_LABEL))
+        .collect())
+}
+
+/// How an image store found by [`prune_stores`] is in use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StoreUse {
+    /// Locked by a bcvk process setting up a VM
+    Locked,
+    /// Used by a container, from its [`IMAGE_STORE_LABEL`]
+    Container,
+    /// Not in use; the store is removed
+    Unused,
+}
+
+/// How the store `dir` is in use, given whether its lock could be taken and
+/// the stores used by containers.
+fn store_use(dir: &Utf8Path, lock_taken: bool, used: &HashSet<String>) -> StoreUse {
+    if !lock_taken {
+        StoreUse::Locked
+    } else if used.contains(dir.as_str()) {
+        StoreUse::Container
+    } else {
+        StoreUse::Unused
+    }
+}
+
+/// Remove the image stores that no container uses anymore.
+pub(crate) fn prune_stores() -> Result<()> {
+    let base = stores_dir()?;
+    let entries = match base.read_dir_utf8() {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Reading {base}")),
+    };
+    // Lock the stores first: a store that is unlocked and not used by a
+    // container now cannot be used later.
+    let mut stores = Vec::new();
+    for entry in entries {
+        let dir = entry?.into_path();
+        let Ok(lock) = File::open(dir.join("lock")) else {
+            // Just being created
+            continue;
+        };
+        let lock_taken = rustix::fs::flock(&lock, FlockOperation::NonBlockingLockExclusive).is_ok();
+        stores.push((dir, lock_taken, lock));
+    }
+    if !stores.iter().any(|(_, lock_taken, _)| *lock_taken) {
+        return Ok(());
+    }
+    let used = used_stores()?;
+    for (dir, lock_taken, _lock) in stores {
+        match store_use(&dir, lock_taken, &used) {
+            StoreUse::Unused => remove_store(&dir),
+            state => debug!("Keeping image store {dir}: {state:?}"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_archive_ref() {
+        assert!(is_archive_ref("oci:/srv/build/layout"));
+        assert!(is_archive_ref("oci:/srv/build/layout:v1"));
+        assert!(is_archive_ref("oci-archive:image.tar"));
+        assert!(is_archive_ref("docker-archive:/tmp/image.tar"));
+        assert!(!is_archive_ref("quay.io/fedora/fedora-bootc:42"));
+        assert!(!is_archive_ref("localhost/oci:latest"));
+        assert!(!is_archive_ref("containers-storage:localhost/foo"));
+    }
+
+    #[test]
+    fn test_store_use() {
+        let used: HashSet<String> = ["/cache/bcvk/image-stores/store-used".to_owned()].into();
+        for (dir, lock_taken, expected) in [
+            ("store-used", false, StoreUse::Locked),
+            ("store-new", false, StoreUse::Locked),
+            ("store-used", true, StoreUse::Container),
+            ("store-old", true, StoreUse::Unused),
+        ] {
+            let dir = Utf8Path::new("/cache/bcvk/image-stores").join(dir);
+            assert_eq!(store_use(&dir, lock_taken, &used), expected, "{dir}");
+        }
+    }
+}
@@ -4,12 +4,20 @@
 //! podman integration with both table and JSON output formats.
 
 use std::collections::HashMap;
-use std::process::Command;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::time::Duration;
 
 use bootc_utils::CommandRunExt;
-use color_eyre::{eyre::eyre, Result};
+use camino::Utf8Path;
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
 use comfy_table::{presets::UTF8_FULL, Table};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use serde::{Deserialize, Serialize};
+use tracing::debug;
 
 /// Command-line options for image management operations.
 #[derive(clap::Subcommand, Debug)]
@@ -113,6 +121,10 @@ pub struct ImageInspect {
 
     /// Image creation timestamp
     pub created: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// OCI platform architecture (e.g. "amd64", "arm64")
+    #[serde(default)]
+    pub architecture: String,
 }
 
 /// Format a datetime as relative time (e.g., "2 hours ago", "3 days ago").
@@ -201,13 +213,147 @@ pub fn list() -> Result<Vec<ImageListEntry>> {
 
 /// Inspect a container image and return metadata.
 pub fn inspect(name: &str) -> Result<ImageInspect> {
-    let mut r: Vec<ImageInspect> = Command::new("podman")
+    inspect_in(name, None)
+}
+
+/// Inspect a container image that may be in the additional image store
+/// `image_store` (see [`crate::podman::command`]).
+pub(crate) fn inspect_in(name: &str, image_store: Option<&Utf8Path>) -> Result<ImageInspect> {
+    let mut r: Vec<ImageInspect> = crate::podman::command(image_store)
         .args(["image", "inspect", "--", name])
         .run_and_parse_json()
         .map_err(|e| eyre!("{e}"))?;
     r.pop().ok_or_else(|| eyre!("No such image"))
 }
 
+/// When to pull the image consumed by a command, like `podman run --pull`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PullPolicy {
+    /// Pull the image if it is not in local storage
+    #[default]
+    Missing,
+    /// Always pull the image
+    Always,
+    /// Never pull; fail if the image is not in local storage
+    Never,
+    /// Pull the image if the registry has a newer one than local storage
+    Newer,
+}
+
+impl PullPolicy {
+    /// The value of `podman pull --policy`
+    fn as_str(self) -> &'static str {
+        match self {
+            PullPolicy::Missing => "missing",
+            PullPolicy::Always => "always",
+            PullPolicy::Never => "never",
+            PullPolicy::Newer => "newer",
+        }
+    }
+}
+
+/// Options for commands that consume an image from local storage.
+#[derive(clap::Parser, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PullOpts {
+    /// When to pull the image: if missing from local storage, always, never, or if the registry has a newer one
+    #[clap(long = "pull", value_enum, default_value_t)]
+    pub policy: PullPolicy,
+}
+
+/// Whether to pull an image under `policy`, depending on whether it is
+/// `present` in local storage.
+///
+/// A missing image is not pulled with [`PullPolicy::Never`]; callers turn
+/// that into an error.
+pub(crate) fn should_pull(policy: PullPolicy, present: bool) -> bool {
+    match policy {
+        PullPolicy::Never => false,
+        PullPolicy::Missing => !present,
+        PullPolicy::Always | PullPolicy::Newer => true,
+    }
+}
+
+/// Check whether `name` is in local storage.
+pub fn exists(name: &str) -> Result<bool> {
+    let status = Command::new("podman")
+        .args(["image", "exists", "--", name])
+        .status()
+        .context("Running podman image exists")?;
+    Ok(status.success())
+}
+
+/// Make sure `name` is in local storage, pulling it as `policy` says.
+///
+/// A newer image has a different digest, so disks cached for the previous
+/// one are regenerated by the caller's `check_cached_disk`.
+pub fn pull(name: &str, policy: PullPolicy) -> Result<()> {
+    let present = exists(name)?;
+    if !should_pull(policy, present) {
+        if !present {
+            return Err(eyre!(
+                "Image {name} is not in local storage and --pull=never was given"
+            ));
+        }
+        debug!("Image {name} is present, not pulling");
+        return Ok(());
+    }
+    let previous = present
+        .then(|| inspect(name).ok())
+        .flatten()
+        .map(|i| i.digest);
+    let mut cmd = Command::new("podman");
+    cmd.args(["pull", &format!("--policy={}", policy.as_str()), "--", name]);
+    run_pull(cmd, name)?;
+    let current = inspect(name)?.digest;
+    match previous {
+        Some(previous) if previous != current => {
+            eprintln!("Pulled {name}: updated from {previous} to {current}");
+        }
+        Some(_) => debug!("Image {name} is up to date"),
+        None => eprintln!("Pulled {name} ({current})"),
+    }
+    Ok(())
+}
+
+/// Run a `podman pull` command for `name`, showing its progress on a spinner.
+pub(crate) fn run_pull(mut cmd: Command, name: &str) -> Result<()> {
+    let mut child = cmd
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Running podman pull")?;
+
+    let pb = ProgressBar::new_spinner();
+    pb.set_draw_target(ProgressDrawTarget::stderr());
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} {prefix} {msg} [{elapsed}]")
+            .unwrap(),
+    );
+    pb.enable_steady_tick(Duration::from_millis(100));
+    pb.set_prefix(format!("Pulling {name}:"));
+    pb.set_message("Resolving...");
+
+    // podman prints one line per step without a terminal, e.g. `Copying blob <digest>`
+    let mut output = Vec::new();
+    let stderr = child.stderr.take().expect("stderr is piped");
+    for line in BufReader::new(stderr).lines() {
+        let line = line.context("Reading podman pull output")?;
+        let line = line.trim();
+        if !line.is_empty() {
+            pb.set_message(line.to_owned());
+            output.push(line.to_owned());
+        }
+    }
+    let status = child.wait().context("Waiting for podman pull")?;
+    pb.finish_and_clear();
+    if !status.success() {
+        return Err(eyre!("Failed to pull {name}: {}", output.join("\n")));
+    }
+    Ok(())
+}
+
 /// Get container image size in bytes for disk space planning.
 pub fn get_image_size(name: &str) -> Result<u64> {
     tracing::debug!("Getting size for image: {}", name);
@@ -249,6 +395,36 @@ LOGO="fedora-logo-icon"
         }
     }
 
+    #[test]
+    fn test_pull_policy() {
+        use clap::Parser;
+
+        assert_eq!(PullOpts::parse_from(["test"]).policy, PullPolicy::Missing);
+        let opts = PullOpts::parse_from(["test", "--pull=newer"]);
+        assert_eq!(opts.policy.as_str(), "newer");
+        assert!(PullOpts::try_parse_from(["test", "--pull=sometimes"]).is_err());
+    }
+
+    #[test]
+    fn test_should_pull() {
+        for (policy, present, expected) in [
+            (PullPolicy::Missing, false, true),
+            (PullPolicy::Missing, true, false),
+            (PullPolicy::Always, false, true),
+            (PullPolicy::Always, true, true),
+            (PullPolicy::Never, false, false),
+            (PullPolicy::Never, true, false),
+            (PullPolicy::Newer, false, true),
+            (PullPolicy::Newer, true, true),
+        ] {
+            assert_eq!(
+                should_pull(policy, present),
+                expected,
+                "{policy:?} with present={present}"
+            );
+        }
+    }
+
     #[test]
     fn test_disk_size_calculation_logic() {
         // Test the logic used in calculate_disk_size
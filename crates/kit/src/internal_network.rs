@@ -0,0 +1,414 @@
+//! Private networks shared between ephemeral VMs
+//!
+//! With `--internal-network NAME` an ephemeral VM gets a second NIC on a
+//! private L2 segment shared by all VMs started with the same NAME, e.g. to
+//! test clustered services.
+//!
+//! The containers of all VMs on a network are attached to the podman network
+//! `bcvk-NAME`, on which QEMU joins the NIC to a UDP multicast group. The MAC
+//! and IPv4 address of each VM are derived from its name. A hosts file listing
+//! the VMs that joined is kept on the host and shared read-only with the
+//! guests, so they can resolve each other by VM name. A VM is removed from it
+//! when it is stopped or removed with bcvk, found by [`NETWORK_LABEL`].
+
+use std::fs::Permissions;
+use std::io::Write as _;
+use std::net::Ipv4Addr;
+use std::os::unix::fs::PermissionsExt as _;
+use std::process::Command;
+
+use camino::Utf8PathBuf;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+/// Multicast group of the segment NICs; it is private to each podman network
+pub(crate) const MCAST_GROUP: &str = "239.255.66.1:5656";
+
+/// Label of the containers of VMs on an internal network, with its name
+pub(crate) const NETWORK_LABEL: &str = "bcvk.internal-network";
+
+/// Name of the host mount sharing the hosts file; must match
+/// `bcvk-internal-network.sh`
+pub(crate) const HOSTS_MOUNT_NAME: &str = "bcvk-network";
+
+/// Guest unit configuring the segment NIC
+pub(crate) const NETWORK_UNIT: &str = include_str!("units/bcvk-internal-network.service");
+
+/// Script run by [`NETWORK_UNIT`], passed as the credential [`NETWORK_SCRIPT_CREDENTIAL`]
+pub(crate) const NETWORK_SCRIPT: &str = include_str!("units/bcvk-internal-network.sh");
+
+/// Credential with [`NETWORK_SCRIPT`]
+pub(crate) const NETWORK_SCRIPT_CREDENTIAL: &str = "bcvk.internal-network";
+
+/// Prefix length of the segment; addresses are in 10.77.0.0/16
+const PREFIX_LEN: u8 = 16;
+
+/// Start of a new hosts file
+const HOSTS_HEADER: &str = indoc::indoc! {"
+    127.0.0.1 localhost localhost.localdomain
+    ::1 localhost localhost.localdomain
+"};
+
+/// Name of the podman network for the internal network `name`
+pub(crate) fn podman_network(name: &str) -> String {
+    format!("bcvk-{name}")
+}
+
+/// Network and VM names are used in podman network names, paths and the
+/// hosts file, so restrict them to what podman accepts for names.
+pub(crate) fn validate_name(kind: &str, name: &str) -> Result<()> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(eyre!(
+            "Invalid {kind} name '{name}': use letters, digits, '-', '_' and '.'"
+        ));
+    }
+    Ok(())
+}
+
+/// The addresses of a VM on an internal network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Member {
+    /// MAC address of the segment NIC
+    pub(crate) mac: String,
+    /// IPv4 address of the segment NIC
+    pub(crate) address: Ipv4Addr,
+}
+
+impl Member {
+    /// Derive the addresses of VM `vm` on network `network`.
+    pub(crate) fn new(network: &str, vm: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(network.as_bytes());
+        hasher.update([0]);
+        hasher.update(vm.as_bytes());
+        let digest = hasher.finalize();
+        // Locally administered addresses in the QEMU OUI
+        let mac = format!(
+            "52:54:00:{:02x}:{:02x}:{:02x}",
+            digest[0], digest[1], digest[2]
+        );
+        // Skip the network and broadcast addresses
+        let address = Ipv4Addr::new(10, 77, digest[3], digest[4] % 254 + 1);
+        Self { mac, address }
+    }
+
+    /// Drop-in for [`NETWORK_UNIT`] configuring this member
+    pub(crate) fn unit_dropin(&self) -> String {
+        format!(
+            "[Service]\nEnvironment=BCVK_NET_MAC={} BCVK_NET_ADDRESS={}/{PREFIX_LEN}\n",
+            self.mac, self.address
+        )
+    }
+}
+
+/// Host directory with the shared hosts file of `network`
+fn state_dir(network: &str) -> Result<Utf8PathBuf> {
+    let base = dirs::runtime_dir().unwrap_or_else(|| "/run".into());
+    let base = Utf8PathBuf::try_from(base)?;
+    Ok(base.join("bcvk/networks").join(network))
+}
+
+/// Add (or replace) the entry of `vm`, also named `aliases`, in `hosts`. The
+/// MAC address of each VM is kept in a comment after its names, so collisions
+/// of both addresses are found.
+fn update_hosts(hosts: &str, vm: &str, aliases: &[String], member: &Member) -> Result<String> {
+    let hosts = if hosts.is_empty() {
+        HOSTS_HEADER
+    } else {
+        hosts
+    };
+    let mut updated = String::new();
+    for line in hosts.lines() {
+        let (entry, comment) = line.split_once('#').unwrap_or((line, ""));
+        let mut fields = entry.split_whitespace();
+        if let (Some(addr), Some(name)) = (fields.next(), fields.next()) {
+            if name == vm {
+                continue;
+            }
+            if addr.parse().ok() == Some(member.address) {
+                return Err(eyre!(
+                    "Address {} of VM '{vm}' is already used by '{name}', use another name",
+                    member.address
+                ));
+            }
+            if comment.trim() == member.mac {
+                return Err(eyre!(
+                    "MAC address {} of VM '{vm}' is already used by '{name}', use another name",
+                    member.mac
+                ));
+            }
+            if let Some(alias) = std::iter::once(name)
+                .chain(fields)
+                .find(|n| aliases.iter().any(|a| a == n))
+            {
+                return Err(eyre!(
+                    "Name '{alias}' of VM '{vm}' is already used by '{name}'"
+                ));
+            }
+        }
+        updated.push_str(line);
+        updated.push('\n');
+    }
+    updated.push_str(&format!("{} {vm}", member.address));
+    for alias in aliases {
+        updated.push(' ');
+        updated.push_str(alias);
+    }
+    updated.push_str(&format!(" # {}\n", member.mac));
+    Ok(updated)
+}
+
+/// Remove the entry of `vm` from `hosts`.
+fn remove_from_hosts(hosts: &str, vm: &str) -> String {
+    hosts
+        .lines()
+        .filter(|line| line.split_whitespace().nth(1) != Some(vm))
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+/// Lock the state directory `dir` of a network, serializing VMs joining and
+/// leaving concurrently.
+fn lock(dir: &Utf8PathBuf) -> Result<std::fs::File> {
+    let lock = std::fs::File::create(dir.join("lock")).context("Creating lock file")?;
+    rustix::fs::flock(&lock, rustix::fs::FlockOperation::LockExclusive)
+        .context("Locking internal network")?;
+    Ok(lock)
+}
+
+/// Read the hosts file in `dir`, which is empty if missing.
+fn read_hosts(dir: &Utf8PathBuf) -> Result<String> {
+    let hosts_path = dir.join("hosts");
+    match std::fs::read_to_string(&hosts_path) {
+        Ok(hosts) => Ok(hosts),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e).with_context(|| format!("Reading {hosts_path}")),
+    }
+}
+
+/// Replace the hosts file in `dir` atomically, as running guests may be reading it.
+fn write_hosts(dir: &Utf8PathBuf, hosts: &str) -> Result<()> {
+    let hosts_path = dir.join("hosts");
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(hosts.as_bytes())?;
+    tmp.as_file()
+        .set_permissions(Permissions::from_mode(0o644))?;
+    tmp.persist(&hosts_path)
+        .with_context(|| format!("Writing {hosts_path}"))?;
+    Ok(())
+}
+
+fn ensure_podman_network(name: &str) -> Result<()> {
+    let exists = Command::new("podman")
+        .args(["network", "exists", "--", name])
+        .status()
+        .context("Running podman network exists")?
+        .success();
+    if exists {
+        return Ok(());
+    }
+
+    debug!("Creating podman network {name}");
+    let output = Command::new("podman")
+        .args(["network", "create", "--label=bcvk.network=1", "--", name])
+        .output()
+        .context("Running podman network create")?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "Failed to create podman network {name}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(())
+}
+
+/// Host side of joining VM `vm` to `network`: creates the podman network if
+/// needed and adds the VM, with its `aliases`, to the shared hosts file.
+/// Returns the directory to share with the guest.
+pub(crate) fn join(network: &str, vm: &str, aliases: &[String]) -> Result<Utf8PathBuf> {
+    validate_name("internal network", network)?;
+    validate_name("VM", vm)?;
+    for alias in aliases {
+        validate_name("network alias", alias)?;
+    }
+
+    let dir = state_dir(network)?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("Creating {dir}"))?;
+    let _lock = lock(&dir)?;
+
+    ensure_podman_network(&podman_network(network))?;
+
+    let member = Member::new(network, vm);
+    let hosts = update_hosts(&read_hosts(&dir)?, vm, aliases, &member)?;
+    write_hosts(&dir, &hosts)?;
+    debug!(
+        "VM {vm} joined internal network {network} with {} ({})",
+        member.address, member.mac
+    );
+    Ok(dir)
+}
+
+/// Host side of VM `vm` leaving `network`: removes it from the shared hosts file.
+fn leave(network: &str, vm: &str) -> Result<()> {
+    let dir = state_dir(network)?;
+    if !dir.try_exists()? {
+        return Ok(());
+    }
+    let _lock = lock(&dir)?;
+    let hosts = read_hosts(&dir)?;
+    let updated = remove_from_hosts(&hosts, vm);
+    if updated != hosts {
+        write_hosts(&dir, &updated)?;
+        debug!("VM {vm} left internal network {network}");
+    }
+    Ok(())
+}
+
+/// The internal network a VM container joined
+#[derive(Debug)]
+pub(crate) struct Membership {
+    network: String,
+    vm: String,
+}
+
+impl Membership {
+    /// The membership of `container`, if it joined an internal network.
+    pub(crate) fn of_container(container: &str) -> Result<Option<Self>> {
+        let format = format!("{{{{index .Config.Labels \"{NETWORK_LABEL}\"}}}} {{{{.Name}}}}");
+        let output = Command::new("podman")
+            .args([
+                "inspect",
+                "--type=container",
+                "--format",
+                &format,
+                "--",
+                container,
+            ])
+            .output()
+            .context("Running podman inspect")?;
+        if !output.status.success() {
+            return Err(eyre!(
+                "Failed to inspect container {container}: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        let output = String::from_utf8_lossy(&output.stdout);
+        // The label is rendered as "<no value>" without an internal network
+        match output.split_whitespace().collect::<Vec<_>>()[..] {
+            [network, vm] => Ok(Some(Self {
+                network: network.to_owned(),
+                vm: vm.to_owned(),
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    /// Remove the VM from the hosts file of its network.
+    pub(crate) fn leave(&self) -> Result<()> {
+        leave(&self.network, &self.vm).with_context(|| {
+            format!(
+                "Removing VM '{}' from internal network {}",
+                self.vm, self.network
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_member() {
+        let a = Member::new("cluster", "node1");
+        assert_eq!(a, Member::new("cluster", "node1"));
+        assert_ne!(a, Member::new("cluster", "node2"));
+        assert_ne!(a, Member::new("other", "node1"));
+        assert!(a.mac.starts_with("52:54:00:"));
+        assert_eq!(a.mac.len(), 17);
+        let [n0, n1, _, host] = a.address.octets();
+        assert_eq!((n0, n1), (10, 77));
+        assert!((1..=254).contains(&host));
+        assert!(a
+            .unit_dropin()
+            .contains(&format!("BCVK_NET_ADDRESS={}/16", a.address)));
+        assert!(NETWORK_UNIT.contains(&format!(
+            "ExecStart=/bin/sh %d/{NETWORK_SCRIPT_CREDENTIAL}\n"
+        )));
+        assert!(NETWORK_SCRIPT.contains(&format!("/run/virtiofs-mnt-{HOSTS_MOUNT_NAME}/hosts")));
+    }
+
+    fn member(host: u8) -> Member {
+        Member {
+            mac: format!("52:54:00:00:00:{host:02x}"),
+            address: Ipv4Addr::new(10, 77, 1, host),
+        }
+    }
+
+    #[test]
+    fn test_update_hosts() {
+        let (a, b) = (member(1), member(2));
+        let hosts = update_hosts("", "node1", &[], &a).unwrap();
+        assert!(hosts.starts_with(HOSTS_HEADER));
+        assert!(hosts.ends_with("10.77.1.1 node1 # 52:54:00:00:00:01\n"));
+        let hosts = update_hosts(&hosts, "node2", &[], &b).unwrap();
+        assert!(hosts.ends_with(
+            "10.77.1.1 node1 # 52:54:00:00:00:01\n10.77.1.2 node2 # 52:54:00:00:00:02\n"
+        ));
+        // Rejoining replaces the entry
+        let rejoined = update_hosts(&hosts, "node1", &[], &a).unwrap();
+        assert_eq!(rejoined.matches("node1").count(), 1);
+        assert!(rejoined.ends_with(
+            "10.77.1.2 node2 # 52:54:00:00:00:02\n10.77.1.1 node1 # 52:54:00:00:00:01\n"
+        ));
+
+        // Address and MAC collisions are reported
+        let same_mac = Member {
+            address: member(3).address,
+            ..b.clone()
+        };
+        let same_address = Member {
+            mac: member(3).mac,
+            ..b.clone()
+        };
+        for (what, colliding) in [("address", same_address), ("MAC", same_mac)] {
+            let err = update_hosts(&hosts, "node3", &[], &colliding).unwrap_err();
+            assert!(
+                err.to_string().contains("already used by 'node2'"),
+                "{what}"
+            );
+        }
+
+        // Leaving removes only the entry of the VM
+        let left = remove_from_hosts(&hosts, "node1");
+        assert!(left.starts_with(HOSTS_HEADER));
+        assert!(!left.contains("node1"));
+        assert!(left.ends_with("10.77.1.2 node2 # 52:54:00:00:00:02\n"));
+        assert_eq!(remove_from_hosts(&left, "node1"), left);
+        assert_eq!(remove_from_hosts(&left, "node2"), HOSTS_HEADER);
+
+        // Aliases are listed after the VM name, and must be unique
+        let aliases = ["db".to_string()];
+        let hosts = update_hosts(&left, "cluster-db", &aliases, &member(3)).unwrap();
+        assert!(hosts.ends_with("10.77.1.3 cluster-db db # 52:54:00:00:00:03\n"));
+        let rejoined = update_hosts(&hosts, "cluster-db", &aliases, &member(3)).unwrap();
+        assert_eq!(rejoined, hosts);
+        assert!(update_hosts(&hosts, "other-db", &aliases, &member(4)).is_err());
+        assert!(update_hosts(&hosts, "node4", &["node2".to_string()], &member(4)).is_err());
+        assert!(!remove_from_hosts(&hosts, "cluster-db").contains("db"));
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("VM", "node-1.test_a").is_ok());
+        for name in ["", "-x", ".x", "a b", "a/b", "a:b"] {
+            assert!(validate_name("VM", name).is_err(), "{name}");
+        }
+    }
+}
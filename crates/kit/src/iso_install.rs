@@ -0,0 +1,341 @@
+//! Install bootc images to disk with Anaconda, booted from an installer ISO
+//!
+//! This implements `bcvk to-disk --via-iso`. The installer ISO is booted in
+//! the same container environment as ephemeral VMs, with the target disk
+//! attached via virtio-blk and the host container storage shared read-only
+//! over virtiofs. The kickstart is passed on a small disk labeled `OEMDRV`,
+//! which Anaconda searches for automatically; bcvk appends an
+//! `ostreecontainer` command that installs the image from the host storage,
+//! then `poweroff`, so the install is complete once the VM exits.
+
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tracing::debug;
+
+use crate::qemu;
+use crate::run_ephemeral::{run_detached, RunEphemeralOpts};
+use crate::to_disk::ToDiskOpts;
+
+/// Path of the installer ISO inside the container
+pub(crate) const INSTALL_ISO_PATH: &str = "/run/install.iso";
+
+/// Disk label Anaconda searches for a `ks.cfg`
+const OEMDRV_LABEL: &str = "OEMDRV";
+
+/// Size of the kickstart disk (a 1.44MB floppy image)
+const OEMDRV_SIZE_KB: u64 = 1440;
+
+/// virtiofs tag of the host container storage (see `--bind-storage-ro`)
+const HOSTSTORAGE_TAG: &str = "mount_hoststorage";
+
+/// Where the kickstart `%pre` mounts the host container storage
+const HOSTSTORAGE_MOUNT: &str = "/run/bcvk-hoststorage";
+
+/// How long the installation may take with KVM, unless `--max-runtime` is given
+const INSTALL_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Generate the kickstart for installing `image`, updated from `target_imgref`.
+///
+/// The user's kickstart provides everything else (partitioning, users, ...);
+/// the installation source, target disk and `poweroff` are appended. The host
+/// storage is added as an additional image store, as it is read-only.
+fn generate_kickstart(
+    user_kickstart: &str,
+    image: &str,
+    target_imgref: &str,
+    target_transport: &str,
+) -> Result<String> {
+    let image = shlex::try_quote(image)?;
+    let target_imgref = shlex::try_quote(target_imgref)?;
+    let target_transport = shlex::try_quote(target_transport)?;
+    Ok(format!(
+        r#"{user_kickstart}
+
+# Added by bcvk
+text
+ignoredisk --only-use=disk/by-id/virtio-output
+ostreecontainer --transport=containers-storage --url={image} --no-signature-verification
+poweroff
+
+%pre --erroronfail
+mkdir -p {HOSTSTORAGE_MOUNT}
+mount -t virtiofs -o ro {HOSTSTORAGE_TAG} {HOSTSTORAGE_MOUNT}
+mkdir -p /etc/containers
+cat > /etc/containers/storage.conf <<'EOF'
+[storage]
+driver = "overlay"
+
+[storage.options]
+additionalimagestores = ["{HOSTSTORAGE_MOUNT}"]
+EOF
+%end
+
+%post --erroronfail
+bootc switch --mutate-in-place --transport {target_transport} {target_imgref}
+%end
+"#
+    ))
+}
+
+/// Create a FAT image labeled `OEMDRV` containing `ks.cfg`.
+fn create_kickstart_disk(dest: &Utf8Path, kickstart: &Utf8Path) -> Result<()> {
+    let contents = std::fs::read(kickstart).with_context(|| format!("Reading {kickstart}"))?;
+    crate::esp::create_floppy_image(dest, OEMDRV_SIZE_KB, OEMDRV_LABEL, &[("ks.cfg", &contents)])
+}
+
+/// Wait up to `timeout` for the installer container to exit, returning an
+/// error with its recent output if it failed.
+///
+/// On timeout the container is killed, e.g. if Anaconda stopped at a prompt
+/// because the kickstart is incomplete.
+fn wait_for_installer(container_id: &str, timeout: Duration) -> Result<()> {
+    let mut child = Command::new("podman")
+        .args(["wait", "--", container_id])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Running podman wait")?;
+    let deadline = Instant::now() + timeout;
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            let _ = Command::new("podman")
+                .args(["kill", "--", container_id])
+                .output();
+            return Err(eyre!(
+                "ISO installation did not finish within {}s, killed the installer",
+                timeout.as_secs()
+            ));
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    let output = child
+        .wait_with_output()
+        .context("Waiting for podman wait")?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "podman wait failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    let exit_code: i32 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .context("Parsing podman wait output")?;
+    if exit_code == 0 {
+        return Ok(());
+    }
+
+    let logs = Command::new("podman")
+        .args(["logs", "--tail=20", "--", container_id])
+        .output()
+        .context("Running podman logs")?;
+    Err(eyre!(
+        "ISO installation failed with exit code {exit_code}:\n{}{}",
+        String::from_utf8_lossy(&logs.stdout),
+        String::from_utf8_lossy(&logs.stderr)
+    ))
+}
+
+/// Install `opts.source_image` to `opts.target_disk` by booting the
+/// installer `iso` with the given kickstart.
+pub(crate) fn install(opts: &ToDiskOpts, iso: &Utf8Path, kickstart: &Utf8Path) -> Result<()> {
+    let iso = iso
+        .canonicalize_utf8()
+        .with_context(|| format!("Opening installer ISO {iso}"))?;
+    let user_kickstart = std::fs::read_to_string(kickstart)
+        .with_context(|| format!("Reading kickstart {kickstart}"))?;
+    let target_transport = opts
+        .install
+        .target_transport
+        .as_deref()
+        .unwrap_or("registry");
+    let kickstart = generate_kickstart(
+        &user_kickstart,
+        &opts.source_image,
+        opts.target_imgref(),
+        target_transport,
+    )?;
+
+    // Large enough files should not end up in a tmpfs /tmp, so use /var/tmp
+    // like the rest of the ephemeral flow.
+    let td = tempfile::tempdir_in("/var/tmp")?;
+    let td_path = Utf8Path::from_path(td.path()).ok_or_else(|| eyre!("Non-UTF8 tempdir"))?;
+    let ks_path = td_path.join("ks.cfg");
+    std::fs::write(&ks_path, kickstart).with_context(|| format!("Writing {ks_path}"))?;
+    let oemdrv: Utf8PathBuf = td_path.join("oemdrv.img");
+    create_kickstart_disk(&oemdrv, &ks_path)?;
+    debug!("Created kickstart disk {oemdrv}");
+
+    let ephemeral_opts = RunEphemeralOpts {
+        host_dns_servers: None,
+        install_iso: Some(iso.clone()),
+        image: opts.source_image.clone(),
+        common: opts.additional.common.clone(),
+        podman: crate::run_ephemeral::CommonPodmanOptions {
+            detach: true,
+            label: opts.additional.label.clone(),
+            ..Default::default()
+        },
+        add_swap: None,
+        bind_mounts: Vec::new(),
+        ro_bind_mounts: Vec::new(),
+        systemd_units_dir: None,
+        bind_storage_ro: true,
+        mount_disk_files: vec![
+            format!(
+                "{}:output:{}",
+                opts.target_disk,
+                opts.additional.format.as_str()
+            ),
+            format!("{oemdrv}:oemdrv:raw"),
+        ],
+        port_mappings: Vec::new(),
+        internal_network: None,
+        network_aliases: Vec::new(),
+        kernel_args: Vec::new(),
+        boot: Default::default(),
+        ignition_config: None,
+        cloud_init: Default::default(),
+        boot_profile: None,
+        debug_entrypoint: None,
+        image_store: None,
+    };
+
+    let timeout = match opts.additional.common.liveness.max_runtime {
+        Some(max_runtime) => max_runtime,
+        None if opts.additional.common.resolve_accel()? == qemu::Accel::Tcg => {
+            INSTALL_TIMEOUT * crate::run_ephemeral::TCG_TIMEOUT_SCALE
+        }
+        None => INSTALL_TIMEOUT,
+    };
+
+    let container_id = run_detached(ephemeral_opts)?;
+    tracing::info!(
+        "Installing {} from {iso}, waiting for the installer to power off...",
+        opts.source_image
+    );
+    let result = wait_for_installer(&container_id, timeout);
+
+    debug!("Cleaning up installer container...");
+    let _ = Command::new("podman")
+        .args(["rm", "-f", "--", &container_id])
+        .output();
+    result
+}
+
+/// Installer VM execution inside the container: boots the ISO with the disks
+/// and host storage passed in by [`install`] and waits for it to power off.
+pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
+    debug!("Running installer ISO inside container");
+    let accel = opts.common.resolve_accel()?;
+
+    let mut qemu_config = qemu::QemuConfig::new_iso_boot(
+        opts.common.memory_mb()?,
+        opts.common.vcpus()?,
+        INSTALL_ISO_PATH.to_string(),
+    );
+    qemu_config.accel = accel;
+    qemu_config.arch = opts.common.arch.map(|a| a.qemu_arch().to_owned());
+    qemu_config.resource_limits = opts.common.resource_limits();
+    // A reboot (e.g. from a kickstart without poweroff) also ends the install
+    qemu_config.no_reboot = true;
+
+    if opts.bind_storage_ro {
+        let name = "hoststorage";
+        qemu_config.add_virtiofs(
+            qemu::VirtiofsConfig {
+                socket_path: format!("/run/inner-shared/virtiofs-{name}.sock").into(),
+                shared_dir: format!("/run/host-mounts/{name}").into(),
+                debug: false,
+                readonly: true,
+                log_file: Some(format!("/run/virtiofsd-{name}.log").into()),
+                virtiofsd_binary: opts.common.virtiofsd_binary.as_deref().map(Into::into),
+            },
+            HOSTSTORAGE_TAG,
+        );
+    }
+
+    for blk_device in crate::run_ephemeral::disk_files_from_env()? {
+        qemu_config.add_virtio_blk_device(
+            blk_device.disk_file,
+            blk_device.serial,
+            blk_device.format,
+        );
+    }
+
+    qemu_config.set_console(opts.common.console);
+    if let Ok(console_path) = std::env::var("BCVK_CONSOLE_PATH") {
+        if !console_path.is_empty() {
+            qemu_config.serial_log = Some(console_path);
+        }
+    }
+    qemu_config.set_qmp_socket(crate::run_ephemeral::QMP_SOCKET_PATH);
+    qemu_config.tpm = opts.common.tpm_config();
+    opts.common.credentials.add_to_qemu_config(
+        Utf8Path::new(crate::run_ephemeral::QEMU_CREDENTIALS_DIR),
+        opts.common.guest_arch(),
+        &mut qemu_config,
+    )?;
+
+    let mut qemu = qemu::RunningQemu::spawn(qemu_config).await?;
+    let exit_status = qemu.wait().await?;
+    if !exit_status.success() {
+        return Err(eyre!("QEMU exited with non-zero status: {exit_status}"));
+    }
+    debug!("Installer completed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_kickstart() {
+        let user = "zerombr\nclearpart --all --initlabel\nautopart --type=plain\n";
+        let image = "quay.io/fedora/fedora-bootc:42";
+        let ks = generate_kickstart(user, image, image, "registry").unwrap();
+        assert!(ks.starts_with(user));
+        assert!(ks.contains(
+            "ostreecontainer --transport=containers-storage --url=quay.io/fedora/fedora-bootc:42 --no-signature-verification\n"
+        ));
+        assert!(ks.contains("\npoweroff\n"));
+        assert!(ks.contains(&format!(
+            "mount -t virtiofs -o ro {HOSTSTORAGE_TAG} {HOSTSTORAGE_MOUNT}\n"
+        )));
+        assert!(ks.contains(
+            "bootc switch --mutate-in-place --transport registry quay.io/fedora/fedora-bootc:42\n"
+        ));
+        // Each section is closed
+        assert_eq!(ks.matches("%end").count(), 2);
+
+        // Image references are quoted for the kickstart parser
+        let ks =
+            generate_kickstart("", "localhost/my image", "localhost/my image", "registry").unwrap();
+        let line = ks
+            .lines()
+            .find(|l| l.starts_with("ostreecontainer"))
+            .unwrap();
+        let words = shlex::split(line).unwrap();
+        assert_eq!(words[2], "--url=localhost/my image");
+
+        // A local copy of another architecture is updated from the source image
+        let ks = generate_kickstart(
+            "",
+            "quay.io/fedora/fedora-bootc:42-aarch64",
+            "quay.io/fedora/fedora-bootc:42",
+            "registry",
+        )
+        .unwrap();
+        assert!(ks.contains("--url=quay.io/fedora/fedora-bootc:42-aarch64 "));
+        assert!(ks.contains(
+            "bootc switch --mutate-in-place --transport registry quay.io/fedora/fedora-bootc:42\n"
+        ));
+    }
+}
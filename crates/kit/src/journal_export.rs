@@ -0,0 +1,146 @@
+//! Conversion of the guest journal stream to native journal files
+//!
+//! The guest streams its journal as `journalctl -o json` lines. For
+//! `--log-dir journal-export=DIR` each entry is converted to the
+//! [Journal Export Format](https://systemd.io/JOURNAL_EXPORT_FORMATS/), and for
+//! `--log-dir journal-file=DIR` that is piped into `systemd-journal-remote` on
+//! the host, which writes journal files usable with `journalctl --file`.
+
+use std::process::{Child, Command, Stdio};
+
+use camino::Utf8Path;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use rustix::fd::OwnedFd;
+use tracing::debug;
+
+/// Where distributions install `systemd-journal-remote`
+const JOURNAL_REMOTE_PATHS: &[&str] = &[
+    "/usr/lib/systemd/systemd-journal-remote",
+    "/lib/systemd/systemd-journal-remote",
+];
+
+/// Append a single field in export format.
+fn push_field(out: &mut Vec<u8>, key: &str, value: &[u8]) {
+    out.extend_from_slice(key.as_bytes());
+    // Values with newlines (or not valid UTF-8 in the JSON) use the binary form
+    if value.contains(&b'\n') || std::str::from_utf8(value).is_err() {
+        out.push(b'\n');
+        out.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        out.push(b'=');
+    }
+    out.extend_from_slice(value);
+    out.push(b'\n');
+}
+
+/// A field value as encoded by `journalctl -o json`: a string, or an array of
+/// bytes for binary data.
+fn json_value_bytes(value: &serde_json::Value) -> Option<Vec<u8>> {
+    match value {
+        serde_json::Value::String(s) => Some(s.as_bytes().to_vec()),
+        serde_json::Value::Array(bytes) => bytes
+            .iter()
+            .map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok()))
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Convert a single JSON journal line into an entry in Journal Export Format.
+///
+/// Fields with several values (arrays of values) are repeated, and fields
+/// omitted by journalctl for their size (`null`) are skipped. Fails if the
+/// line is not a JSON object.
+pub(crate) fn journal_json_to_export(line: &str) -> Result<Vec<u8>> {
+    let obj = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(line)
+        .context("Parsing journal JSON line")?;
+    let mut out = Vec::new();
+    for (key, value) in &obj {
+        if let Some(bytes) = json_value_bytes(value) {
+            push_field(&mut out, key, &bytes);
+            continue;
+        }
+        if let serde_json::Value::Array(values) = value {
+            for bytes in values.iter().filter_map(json_value_bytes) {
+                push_field(&mut out, key, &bytes);
+            }
+        }
+    }
+    out.push(b'\n');
+    Ok(out)
+}
+
+/// Find `systemd-journal-remote` on the host.
+pub(crate) fn find_journal_remote() -> Result<&'static Utf8Path> {
+    JOURNAL_REMOTE_PATHS
+        .iter()
+        .map(Utf8Path::new)
+        .find(|p| p.exists())
+        .ok_or_else(|| {
+            eyre!(
+                "systemd-journal-remote not found (searched {}); it is needed for \
+                 --log-dir journal-file, use journal-export to write export files instead",
+                JOURNAL_REMOTE_PATHS.join(", ")
+            )
+        })
+}
+
+/// Spawn `systemd-journal-remote` writing the journal file `dest`, returning
+/// the write end of the pipe it reads export format from, and the process.
+///
+/// It finishes writing the journal file and exits once all copies of the
+/// returned fd are closed, i.e. when the VM is gone; see [`wait_journal_remote`].
+pub(crate) fn spawn_journal_remote(dest: &std::path::Path) -> Result<(OwnedFd, Child)> {
+    let binary = find_journal_remote()?;
+    let (read, write) = rustix::pipe::pipe_with(rustix::pipe::PipeFlags::CLOEXEC)?;
+    let mut cmd = Command::new(binary);
+    cmd.args(["--split-mode=none", "--output"])
+        .arg(dest)
+        .arg("-")
+        .stdin(Stdio::from(read))
+        .stdout(Stdio::null());
+    debug!("{cmd:?}");
+    let child = cmd.spawn().with_context(|| format!("Spawning {binary}"))?;
+    Ok((write, child))
+}
+
+/// Wait for a `systemd-journal-remote` from [`spawn_journal_remote`] to
+/// finish writing its journal file, after closing all copies of its pipe.
+pub(crate) fn wait_journal_remote(mut child: Child) -> Result<()> {
+    let status = child.wait().context("Waiting for systemd-journal-remote")?;
+    if !status.success() {
+        return Err(eyre!("systemd-journal-remote failed: {status}"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_json_to_export() {
+        let line = r#"{"MESSAGE":"hello","PRIORITY":"6","__REALTIME_TIMESTAMP":"1000"}"#;
+        assert_eq!(
+            journal_json_to_export(line).unwrap(),
+            b"MESSAGE=hello\nPRIORITY=6\n__REALTIME_TIMESTAMP=1000\n\n"
+        );
+
+        // Newlines and binary data use the length-prefixed form
+        let line = r#"{"MESSAGE":"a\nb","X":[255,1]}"#;
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\nX\n");
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.extend_from_slice(&[255, 1, b'\n', b'\n']);
+        assert_eq!(journal_json_to_export(line).unwrap(), expected);
+
+        // Repeated fields, and fields too large for journalctl
+        let line = r#"{"TAG":["a",[98]],"BIG":null}"#;
+        assert_eq!(journal_json_to_export(line).unwrap(), b"TAG=a\nTAG=b\n\n");
+
+        assert!(journal_json_to_export("not json").is_err());
+        assert!(journal_json_to_export("[1]").is_err());
+    }
+}
@@ -37,6 +37,20 @@ pub struct KernelInfo {
     pub is_uki: bool,
 }
 
+impl KernelInfo {
+    /// The kernel version, if the kernel is in `/usr/lib/modules/<version>`
+    /// (i.e. it is not a UKI in the ESP).
+    pub fn version(&self) -> Option<&str> {
+        let mut components = self
+            .kernel_path
+            .strip_prefix(format!("usr/lib/{MODULES_DIR}"))
+            .ok()?
+            .components();
+        let version = components.next()?.as_str();
+        components.next().map(|_| version)
+    }
+}
+
 /// Find kernel/initramfs in a container image root directory.
 ///
 /// UKIs take precedence over traditional kernels. This handles older images
@@ -265,6 +279,7 @@ mod tests {
             .unwrap()
             .as_str()
             .contains("initramfs.img"));
+        assert_eq!(info.version(), Some("6.12.0-100.fc41.x86_64"));
         Ok(())
     }
 
@@ -278,6 +293,7 @@ mod tests {
         assert!(info.is_uki);
         assert!(info.kernel_path.as_str().contains("fedora-6.12.0.efi"));
         assert!(info.initramfs_path.is_none());
+        assert_eq!(info.version(), None);
         Ok(())
     }
 
@@ -12,6 +12,19 @@ use color_eyre::Result;
 use std::fs;
 use tracing::{debug, info};
 
+/// The cache metadata of a base disk; like libvirt domains, base disks are
+/// for the host architecture.
+fn base_disk_metadata(
+    source_image: &str,
+    image_digest: &str,
+    install_options: &InstallOptions,
+) -> DiskImageMetadata {
+    DiskImageMetadata {
+        arch: crate::arch::Arch::host().map(|a| a.to_string()),
+        ..DiskImageMetadata::from(install_options, image_digest, source_image)
+    }
+}
+
 /// Find or create a base disk for the given parameters
 pub fn find_or_create_base_disk(
     source_image: &str,
@@ -20,7 +33,7 @@ pub fn find_or_create_base_disk(
     connect_uri: Option<&str>,
     virtiofsd_binary: Option<&str>,
 ) -> Result<Utf8PathBuf> {
-    let metadata = DiskImageMetadata::from(install_options, image_digest, source_image);
+    let metadata = base_disk_metadata(source_image, image_digest, install_options);
     let cache_hash = metadata.compute_cache_hash();
 
     // Extract short hash for filename (first 16 chars after "sha256:")
@@ -41,13 +54,8 @@ pub fn find_or_create_base_disk(
     if base_disk_path.exists() {
         debug!("Checking existing base disk: {:?}", base_disk_path);
 
-        if crate::cache_metadata::check_cached_disk(
-            base_disk_path.as_std_path(),
-            image_digest,
-            source_image,
-            install_options,
-        )?
-        .is_ok()
+        if crate::cache_metadata::check_cached_disk(base_disk_path.as_std_path(), &metadata)?
+            .is_ok()
         {
             return Ok(base_disk_path);
         } else {
@@ -134,9 +142,7 @@ fn create_base_disk(
     // If we got here, bootc install succeeded - verify metadata was written
     let metadata_valid = crate::cache_metadata::check_cached_disk(
         temp_disk_path.as_std_path(),
-        image_digest,
-        source_image,
-        install_options,
+        &base_disk_metadata(source_image, image_digest, install_options),
     )
     .context("Querying cached disk")?;
 
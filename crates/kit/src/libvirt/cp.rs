@@ -0,0 +1,79 @@
+//! libvirt cp command - copy files to and from a bootc domain
+//!
+//! Streams a tarball over SSH, using the private key stored in the domain
+//! metadata when the domain was created.
+
+use std::fs::Permissions;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt as _;
+use std::process::Command;
+
+use clap::Parser;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+
+use crate::cp::CpOpts;
+use crate::domain_list::DomainLister;
+
+/// Options for copying files to and from a libvirt domain
+#[derive(Debug, Parser)]
+pub struct LibvirtCpOpts {
+    /// Source and destination paths
+    #[clap(flatten)]
+    pub cp: CpOpts,
+
+    /// SSH username to use for connection
+    #[clap(long, default_value = "root")]
+    pub user: String,
+}
+
+/// Execute the libvirt cp command
+pub fn run(global_opts: &crate::libvirt::LibvirtOptions, opts: LibvirtCpOpts) -> Result<()> {
+    let transfer = opts.cp.transfer()?;
+    let name = transfer.name();
+
+    let lister = match global_opts.connect.as_ref() {
+        Some(uri) => DomainLister::with_connection(uri.clone()),
+        None => DomainLister::new(),
+    };
+    let vm = lister
+        .get_domain_info(name)
+        .map_err(|_| eyre!("VM '{}' not found", name))?;
+    if !vm.is_running() {
+        return Err(eyre!(
+            "VM '{}' is not running (current state: {})",
+            name,
+            vm.state
+        ));
+    }
+    let private_key = vm.ssh_private_key.ok_or_else(|| {
+        eyre!("No SSH private key found in domain '{name}' metadata. Domain was not created with --generate-ssh-key or --ssh-key.")
+    })?;
+    let ssh_port = vm
+        .ssh_port
+        .ok_or_else(|| eyre!("No SSH port found in domain '{name}' metadata"))?;
+
+    let mut keyfile = tempfile::NamedTempFile::new().context("Creating temporary SSH key file")?;
+    keyfile
+        .as_file()
+        .set_permissions(Permissions::from_mode(0o600))?;
+    // SSH requires the key to end with a newline
+    keyfile.write_all(format!("{}\n", private_key.trim_end()).as_bytes())?;
+    keyfile.flush()?;
+
+    let common_opts = crate::ssh::CommonSshOptions {
+        connect_timeout: 5,
+        ..Default::default()
+    };
+    transfer.run(|script| {
+        let mut cmd = Command::new("ssh");
+        cmd.arg("-i")
+            .arg(keyfile.path())
+            .args(["-p", &ssh_port.to_string()]);
+        common_opts.apply_to_command(&mut cmd);
+        cmd.args(["-o", "BatchMode=yes"]);
+        cmd.arg(format!("{}@127.0.0.1", opts.user));
+        cmd.args(["--", script]);
+        Ok(cmd)
+    })
+}
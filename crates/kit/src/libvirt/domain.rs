@@ -6,6 +6,7 @@
 use crate::arch::ArchConfig;
 use crate::common_opts::DEFAULT_MEMORY_USER_STR;
 use crate::libvirt::run::FirmwareType;
+use crate::qemu::Accel;
 use crate::run_ephemeral::default_vcpus;
 use crate::xml_utils::XmlWriter;
 use color_eyre::{eyre::eyre, Result};
@@ -23,6 +24,54 @@ pub struct VirtiofsFilesystem {
     pub readonly: bool,
 }
 
+impl VirtiofsFilesystem {
+    /// Write the `<filesystem>` device element
+    pub fn write_xml(&self, writer: &mut XmlWriter) -> Result<()> {
+        writer.start_element(
+            "filesystem",
+            &[("type", "mount"), ("accessmode", "passthrough")],
+        )?;
+        writer.write_empty_element("driver", &[("type", "virtiofs"), ("queue", "1024")])?;
+        if self.readonly {
+            writer.write_empty_element("readonly", &[])?;
+        }
+        writer.write_empty_element("source", &[("dir", &self.source_dir)])?;
+        writer.write_empty_element("target", &[("dir", &self.tag)])?;
+        writer.end_element("filesystem")
+    }
+
+    /// Generate the device XML, e.g. for `virsh attach-device`
+    pub fn to_xml(&self) -> Result<String> {
+        let mut writer = XmlWriter::new();
+        self.write_xml(&mut writer)?;
+        writer.into_string()
+    }
+
+    /// Find the filesystem with virtiofs tag `tag` in the domain XML
+    pub fn find_in_domain(dom: &crate::xml_utils::XmlNode, tag: &str) -> Option<Self> {
+        let devices = dom.find("devices")?;
+        devices
+            .children
+            .iter()
+            .filter(|n| n.name == "filesystem")
+            .find_map(|fs| {
+                let target = fs.find("target")?.attributes.get("dir")?;
+                if target != tag {
+                    return None;
+                }
+                Some(Self {
+                    source_dir: fs
+                        .find("source")
+                        .and_then(|s| s.attributes.get("dir"))
+                        .cloned()
+                        .unwrap_or_default(),
+                    tag: tag.to_owned(),
+                    readonly: fs.find("readonly").is_some(),
+                })
+            })
+    }
+}
+
 /// Configuration for firmware debug log output
 #[derive(Debug, Clone)]
 pub enum FirmwareLogOutput {
@@ -50,6 +99,7 @@ pub struct DomainBuilder {
     virtiofs_filesystems: Vec<VirtiofsFilesystem>,
     firmware: Option<FirmwareType>,
     tpm: bool,
+    accel: Accel,
     ovmf_code_path: Option<String>, // Custom OVMF_CODE path for secure boot
     ovmf_code_format: Option<String>, // Format of OVMF_CODE (raw, qcow2)
     nvram_template: Option<String>, // Custom NVRAM template with enrolled keys
@@ -59,6 +109,9 @@ pub struct DomainBuilder {
     serial_console_log: Option<String>, // Serial console log file path (ttyS0 — UEFI/bootloader)
     fw_cfg_entries: Vec<(String, String)>, // fw_cfg entries (name, file_path)
     ignition_disk_path: Option<String>, // Path to Ignition config for virtio-blk injection
+    cloud_init_disk_path: Option<String>, // cloud-init NoCloud seed disk (read-only virtio-blk)
+    coredump_on_crash: bool,        // pvpanic device, libvirt dumping a panicked guest's memory
+    hotplug_ports: bool,            // spare PCIe root ports for `bcvk libvirt mount add`
     journal_channel_file: Option<String>, // virtserialport "org.bcvk.journal" → host file (append)
     journal_initrd_channel_file: Option<String>, // virtserialport "org.bcvk.journal.initrd" → host file (append)
 }
@@ -87,6 +140,7 @@ impl DomainBuilder {
             virtiofs_filesystems: Vec::new(),
             firmware: None, // Defaults to UEFI
             tpm: true,      // Default to enabled
+            accel: Accel::Kvm,
             ovmf_code_path: None,
             ovmf_code_format: None,
             nvram_template: None,
@@ -96,6 +150,9 @@ impl DomainBuilder {
             serial_console_log: None,
             fw_cfg_entries: Vec::new(),
             ignition_disk_path: None,
+            cloud_init_disk_path: None,
+            coredump_on_crash: false,
+            hotplug_ports: false,
             journal_channel_file: None,
             journal_initrd_channel_file: None,
         }
@@ -180,6 +237,12 @@ impl DomainBuilder {
         self
     }
 
+    /// Set the CPU accelerator; TCG uses a `qemu` domain with an emulated CPU
+    pub fn with_accel(mut self, accel: Accel) -> Self {
+        self.accel = accel;
+        self
+    }
+
     /// Set custom OVMF_CODE path and format for secure boot
     ///
     /// Format must be specified (either "raw" or "qcow2") and should come from
@@ -242,6 +305,26 @@ impl DomainBuilder {
         self
     }
 
+    /// Attach a cloud-init NoCloud seed disk (labeled `cidata`) read-only
+    pub fn with_cloud_init_disk(mut self, disk_path: String) -> Self {
+        self.cloud_init_disk_path = Some(disk_path);
+        self
+    }
+
+    /// Attach a pvpanic device and have libvirt dump the memory of a
+    /// panicked guest to its `auto_dump_path` before destroying it
+    pub fn with_coredump_on_crash(mut self, coredump: bool) -> Self {
+        self.coredump_on_crash = coredump;
+        self
+    }
+
+    /// Add spare PCIe root ports for hot-plugged shares, as libvirt only adds
+    /// as many as needed for the devices at definition time
+    pub fn with_hotplug_ports(mut self, enable: bool) -> Self {
+        self.hotplug_ports = enable;
+        self
+    }
+
     /// Stream the guest's `org.bcvk.journal` virtserialport to a host file (append mode).
     ///
     /// Emits a `<channel type='file'>` element in the domain XML, which libvirt attaches
@@ -275,11 +358,15 @@ impl DomainBuilder {
         let mut writer = XmlWriter::new();
 
         // Root domain element
+        let domain_type = match self.accel {
+            Accel::Kvm => "kvm",
+            Accel::Tcg => "qemu",
+        };
         let domain_attrs = if self.qemu_args.is_empty() && self.fw_cfg_entries.is_empty() {
-            vec![("type", "kvm")]
+            vec![("type", domain_type)]
         } else {
             vec![
-                ("type", "kvm"),
+                ("type", domain_type),
                 ("xmlns:qemu", "http://libvirt.org/schemas/domain/qemu/1.0"),
             ]
         };
@@ -397,8 +484,13 @@ impl DomainBuilder {
 
         writer.end_element("features")?;
 
-        // Architecture-specific CPU configuration
-        writer.write_empty_element("cpu", &[("mode", arch_config.cpu_mode())])?;
+        // Architecture-specific CPU configuration; the host CPU can't be used
+        // under emulation, so use the most capable emulated model instead
+        let cpu_mode = match self.accel {
+            Accel::Kvm => arch_config.cpu_mode(),
+            Accel::Tcg => "maximum",
+        };
+        writer.write_empty_element("cpu", &[("mode", cpu_mode)])?;
 
         // Clock and lifecycle configuration
         writer.start_element("clock", &[("offset", "utc")])?;
@@ -407,7 +499,12 @@ impl DomainBuilder {
 
         writer.write_text_element("on_poweroff", "destroy")?;
         writer.write_text_element("on_reboot", "restart")?;
-        writer.write_text_element("on_crash", "destroy")?;
+        let on_crash = if self.coredump_on_crash {
+            "coredump-destroy"
+        } else {
+            "destroy"
+        };
+        writer.write_text_element("on_crash", on_crash)?;
 
         // Devices section
         writer.start_element("devices", &[])?;
@@ -444,6 +541,17 @@ impl DomainBuilder {
             writer.end_element("disk")?;
         }
 
+        // cloud-init seed disk, found by the guest via its filesystem label
+        if let Some(ref seed_disk) = self.cloud_init_disk_path {
+            writer.start_element("disk", &[("type", "file"), ("device", "disk")])?;
+            writer.write_empty_element("driver", &[("name", "qemu"), ("type", "raw")])?;
+            writer.write_empty_element("source", &[("file", seed_disk)])?;
+            writer.write_empty_element("target", &[("dev", "vdc"), ("bus", "virtio")])?;
+            writer.write_text_element("serial", crate::cloud_init::CIDATA_LABEL)?;
+            writer.write_empty_element("readonly", &[])?;
+            writer.end_element("disk")?;
+        }
+
         // Network
         let network_config = self.network.as_deref().unwrap_or("default");
         match network_config {
@@ -568,17 +676,7 @@ impl DomainBuilder {
 
         // Virtiofs filesystems
         for filesystem in &self.virtiofs_filesystems {
-            writer.start_element(
-                "filesystem",
-                &[("type", "mount"), ("accessmode", "passthrough")],
-            )?;
-            writer.write_empty_element("driver", &[("type", "virtiofs"), ("queue", "1024")])?;
-            if filesystem.readonly {
-                writer.write_empty_element("readonly", &[])?;
-            }
-            writer.write_empty_element("source", &[("dir", &filesystem.source_dir)])?;
-            writer.write_empty_element("target", &[("dir", &filesystem.tag)])?;
-            writer.end_element("filesystem")?;
+            filesystem.write_xml(&mut writer)?;
         }
 
         // TPM device
@@ -588,6 +686,26 @@ impl DomainBuilder {
             writer.end_element("tpm")?;
         }
 
+        // pvpanic, for libvirt to notice guest kernel panics
+        if self.coredump_on_crash {
+            let model = match arch_config.arch {
+                "x86_64" => "isa",
+                _ => "pvpanic",
+            };
+            writer.write_empty_element("panic", &[("model", model)])?;
+        }
+
+        // Spare root ports for `bcvk libvirt mount add`; other machines
+        // hot-plug without them
+        if self.hotplug_ports && matches!(arch_config.arch, "x86_64" | "aarch64") {
+            for _ in 0..crate::mount::HOTPLUG_PORTS {
+                writer.write_empty_element(
+                    "controller",
+                    &[("type", "pci"), ("model", "pcie-root-port")],
+                )?;
+            }
+        }
+
         writer.end_element("devices")?;
 
         // QEMU commandline section (if we have QEMU args or fw_cfg entries)
@@ -807,6 +925,45 @@ mod tests {
         assert!(!xml_bios.contains("secure=\"yes\""));
     }
 
+    #[test]
+    fn test_coredump_on_crash() {
+        let xml = DomainBuilder::new()
+            .with_name("test-crash")
+            .build_xml()
+            .unwrap();
+        assert!(xml.contains("<on_crash>destroy</on_crash>"));
+        assert!(!xml.contains("<panic"));
+
+        let xml = DomainBuilder::new()
+            .with_name("test-crash")
+            .with_coredump_on_crash(true)
+            .build_xml()
+            .unwrap();
+        assert!(xml.contains("<on_crash>coredump-destroy</on_crash>"));
+        assert!(xml.contains("<panic model="));
+    }
+
+    #[test]
+    fn test_hotplug_ports() {
+        let xml = DomainBuilder::new()
+            .with_name("test-hotplug")
+            .build_xml()
+            .unwrap();
+        assert!(!xml.contains("pcie-root-port"));
+
+        let xml = DomainBuilder::new()
+            .with_name("test-hotplug")
+            .with_hotplug_ports(true)
+            .build_xml()
+            .unwrap();
+        let ports = xml.matches("model=\"pcie-root-port\"").count();
+        let expected = match std::env::consts::ARCH {
+            "x86_64" | "aarch64" => crate::mount::HOTPLUG_PORTS as usize,
+            _ => 0,
+        };
+        assert_eq!(ports, expected);
+    }
+
     #[test]
     fn test_tpm_configuration() {
         // Test TPM enabled (default)
@@ -841,6 +998,24 @@ mod tests {
         assert!(!xml_disabled.contains("backend type=\"emulator\""));
     }
 
+    #[test]
+    fn test_accel_configuration() {
+        let xml = DomainBuilder::new()
+            .with_name("test-kvm")
+            .build_xml()
+            .unwrap();
+        assert!(xml.contains("<domain type=\"kvm\">"));
+        assert!(!xml.contains("<cpu mode=\"maximum\"/>"));
+
+        let xml_tcg = DomainBuilder::new()
+            .with_name("test-tcg")
+            .with_accel(Accel::Tcg)
+            .build_xml()
+            .unwrap();
+        assert!(xml_tcg.contains("<domain type=\"qemu\">"));
+        assert!(xml_tcg.contains("<cpu mode=\"maximum\"/>"));
+    }
+
     #[test]
     fn test_secure_boot_with_custom_firmware() {
         let xml = DomainBuilder::new()
@@ -910,6 +1085,31 @@ mod tests {
         assert!(xml_ro.contains("target dir=\"hoststorage\""));
     }
 
+    #[test]
+    fn test_find_virtiofs_filesystem() {
+        let filesystem = VirtiofsFilesystem {
+            source_dir: "/host/storage".to_string(),
+            tag: "hoststorage".to_string(),
+            readonly: true,
+        };
+        let xml = DomainBuilder::new()
+            .with_name("test-virtiofs-find")
+            .with_virtiofs_filesystem(filesystem.clone())
+            .build_xml()
+            .unwrap();
+        let dom = crate::xml_utils::parse_xml_dom(&xml).unwrap();
+
+        let found = VirtiofsFilesystem::find_in_domain(&dom, "hoststorage").unwrap();
+        assert_eq!(found.source_dir, filesystem.source_dir);
+        assert!(found.readonly);
+        assert!(VirtiofsFilesystem::find_in_domain(&dom, "other").is_none());
+
+        // The device XML alone is what virsh attach-device expects
+        let device = filesystem.to_xml().unwrap();
+        assert!(device.starts_with("<filesystem "));
+        assert!(device.contains("target dir=\"hoststorage\""));
+    }
+
     #[test]
     fn test_domain_xml_console_log() {
         let xml = DomainBuilder::new()
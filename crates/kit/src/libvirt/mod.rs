@@ -5,6 +5,7 @@
 //! - `list`: List bootc domains with metadata
 //! - `upload`: Upload bootc disk images to libvirt with metadata annotations
 //! - `list-volumes`: List available bootc volumes with metadata
+//! - `mount`: Hot-plug host directory shares into a running domain
 
 use clap::Subcommand;
 
@@ -26,10 +27,12 @@ pub const LIBVIRT_DEFAULT_DISK_SIZE: &str = "20G";
 
 pub mod base_disks;
 pub mod base_disks_cli;
+pub mod cp;
 pub mod domain;
 pub mod inspect;
 pub mod list;
 pub mod list_volumes;
+pub mod mount;
 pub mod print_firmware;
 pub mod rm;
 pub mod rm_all;
@@ -189,6 +192,13 @@ pub enum LibvirtSubcommands {
     /// SSH to libvirt domain with embedded SSH key
     Ssh(ssh::LibvirtSshOpts),
 
+    /// Copy files to and from a running domain
+    Cp(cp::LibvirtCpOpts),
+
+    /// Add or remove host directory shares of a running domain
+    #[clap(subcommand)]
+    Mount(crate::mount::MountCommands),
+
     /// List bootc domains with metadata
     List(list::LibvirtListOpts),
 
@@ -0,0 +1,156 @@
+//! libvirt mount command - hot-plug virtiofs shares into a running domain
+//!
+//! The filesystem device is attached with `virsh attach-device --live`, which
+//! makes libvirt start a virtiofsd for it; the guest side is shared with
+//! `bcvk ephemeral mount`, see [`crate::mount`].
+
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tracing::debug;
+
+use crate::domain_list::DomainLister;
+use crate::libvirt::domain::VirtiofsFilesystem;
+use crate::mount::{MountAddOpts, MountCommands, MountRmOpts};
+
+/// Execute the libvirt mount command
+pub fn run(global_opts: &crate::libvirt::LibvirtOptions, command: MountCommands) -> Result<()> {
+    match command {
+        MountCommands::Add(opts) => add(global_opts, opts),
+        MountCommands::Remove(opts) => rm(global_opts, opts),
+    }
+}
+
+/// Get the XML of a running domain
+fn running_domain_xml(
+    global_opts: &crate::libvirt::LibvirtOptions,
+    name: &str,
+) -> Result<crate::xml_utils::XmlNode> {
+    let lister = match global_opts.connect.as_ref() {
+        Some(uri) => DomainLister::with_connection(uri.clone()),
+        None => DomainLister::new(),
+    };
+    let state = lister
+        .get_domain_state(name)
+        .map_err(|_| eyre!("VM '{}' not found", name))?;
+    if state != "running" {
+        return Err(eyre!(
+            "VM '{}' is not running (current state: {})",
+            name,
+            state
+        ));
+    }
+    lister.get_domain_xml(name)
+}
+
+/// Run a guest script over SSH
+fn guest_script(
+    global_opts: &crate::libvirt::LibvirtOptions,
+    name: &str,
+    script: &str,
+) -> Result<()> {
+    let ssh_opts = crate::libvirt::run::batch_ssh_opts(name);
+    let ssh_config = ssh_opts.extract_ssh_config(global_opts)?;
+    let (temp_key, parsed_extra_options) = ssh_opts.prepare_ssh_session(&ssh_config)?;
+    let mut cmd = ssh_opts.build_ssh_command(&ssh_config, &temp_key, parsed_extra_options);
+    cmd.arg("--").arg(script);
+    let output = cmd.output().context("Running ssh")?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "Command failed in the guest: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Attach or detach a filesystem device of the running domain
+fn virsh_device(
+    global_opts: &crate::libvirt::LibvirtOptions,
+    verb: &str,
+    name: &str,
+    filesystem: &VirtiofsFilesystem,
+) -> Result<()> {
+    let mut xml = tempfile::NamedTempFile::new().context("Creating device XML file")?;
+    std::io::Write::write_all(&mut xml, filesystem.to_xml()?.as_bytes())?;
+    let output = global_opts
+        .virsh_command()
+        .arg(verb)
+        .arg(name)
+        .arg(xml.path())
+        .arg("--live")
+        .output()
+        .with_context(|| format!("Running virsh {verb}"))?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "virsh {verb} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+fn add(global_opts: &crate::libvirt::LibvirtOptions, opts: MountAddOpts) -> Result<()> {
+    let host_dir = opts.host_dir()?;
+    let tag = crate::mount::tag(&opts.guest_path);
+    let dom = running_domain_xml(global_opts, &opts.name)?;
+    if dom.find("memoryBacking").is_none() {
+        return Err(eyre!(
+            "VM '{}' has no shared memory backing, which virtiofs requires",
+            opts.name
+        ));
+    }
+    if VirtiofsFilesystem::find_in_domain(&dom, &tag).is_some() {
+        return Err(eyre!(
+            "A share is already mounted at {} in VM '{}'",
+            opts.guest_path,
+            opts.name
+        ));
+    }
+    if opts.ro {
+        let version = crate::libvirt::status::parse_libvirt_version()?;
+        if !crate::libvirt::status::supports_readonly_virtiofs(&version) {
+            return Err(eyre!(
+                "Read-only shares require libvirt 11.0 or later for readonly virtiofs support"
+            ));
+        }
+    }
+
+    let filesystem = VirtiofsFilesystem {
+        source_dir: host_dir.to_string(),
+        tag: tag.clone(),
+        readonly: opts.ro,
+    };
+    debug!("Sharing {host_dir} at {} with tag {tag}", opts.guest_path);
+    virsh_device(global_opts, "attach-device", &opts.name, &filesystem)?;
+
+    let script = crate::mount::guest_add_script(&tag, &opts.guest_path, opts.ro)?;
+    if let Err(e) = guest_script(global_opts, &opts.name, &script) {
+        let _ = virsh_device(global_opts, "detach-device", &opts.name, &filesystem);
+        return Err(e.wrap_err(format!("Mounting {} in the guest", opts.guest_path)));
+    }
+    println!(
+        "Mounted {host_dir} at {} in VM '{}'",
+        opts.guest_path, opts.name
+    );
+    Ok(())
+}
+
+fn rm(global_opts: &crate::libvirt::LibvirtOptions, opts: MountRmOpts) -> Result<()> {
+    crate::mount::validate_guest_path(&opts.guest_path)?;
+    let tag = crate::mount::tag(&opts.guest_path);
+    let dom = running_domain_xml(global_opts, &opts.name)?;
+    let filesystem = VirtiofsFilesystem::find_in_domain(&dom, &tag).ok_or_else(|| {
+        eyre!(
+            "No share is mounted at {} in VM '{}'",
+            opts.guest_path,
+            opts.name
+        )
+    })?;
+
+    let script = crate::mount::guest_rm_script(&opts.guest_path)?;
+    guest_script(global_opts, &opts.name, &script)
+        .wrap_err_with(|| format!("Unmounting {} in the guest", opts.guest_path))?;
+    virsh_device(global_opts, "detach-device", &opts.name, &filesystem)?;
+    println!("Removed {} from VM '{}'", opts.guest_path, opts.name);
+    Ok(())
+}
@@ -109,7 +109,7 @@ fn remove_vm_impl(
         }
     }
 
-    // Remove Ignition config file if it exists (stored in metadata)
+    // Remove Ignition config, cloud-init seed and credential files if they exist (stored in metadata)
     // Parse domain XML to get the ignition persistent path
     if let Ok(xml_output) = global_opts
         .virsh_command()
@@ -125,6 +125,22 @@ fn remove_vm_impl(
                         let _ = std::fs::remove_file(ignition_path); // Don't fail if this fails
                     }
                 }
+                if let Some(node) = dom.find("bootc:cloud-init-seed-path") {
+                    let seed_path = node.text_content().trim();
+                    if !seed_path.is_empty() && std::path::Path::new(seed_path).exists() {
+                        debug!("Removing cloud-init seed disk: {}", seed_path);
+                        let _ = std::fs::remove_file(seed_path);
+                    }
+                }
+                // Credentials may be secrets, so they go along with the VM
+                if let Some(node) = dom.find("bootc:credentials-dir") {
+                    let credentials_dir = node.text_content().trim();
+                    if !credentials_dir.is_empty() && std::path::Path::new(credentials_dir).exists()
+                    {
+                        debug!("Removing credentials: {}", credentials_dir);
+                        let _ = std::fs::remove_dir_all(credentials_dir);
+                    }
+                }
             }
         }
     }
@@ -8,15 +8,18 @@ use camino::{Utf8Path, Utf8PathBuf};
 use clap::{Parser, ValueEnum};
 use color_eyre::eyre::{self, eyre};
 use color_eyre::{eyre::Context, Result};
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::Write;
 use std::str::FromStr;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use crate::common_opts::MemoryOpts;
 use crate::domain_list::DomainLister;
 use crate::install_options::InstallOptions;
 use crate::libvirt::domain::VirtiofsFilesystem;
+use crate::qemu::Accel;
+use crate::run_ephemeral::AccelMode;
 use crate::utils::parse_memory_to_mb;
 use crate::xml_utils;
 
@@ -92,7 +95,7 @@ pub enum FirmwareType {
 }
 
 /// Port mapping from host to VM
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PortMapping {
     pub host_port: u16,
     pub guest_port: u16,
@@ -253,6 +256,10 @@ pub struct LibvirtRunOpts {
     #[clap(long = "bind-ro", action = clap::ArgAction::Append)]
     pub bind_mounts_ro: Vec<BindMount>,
 
+    /// Reserve PCIe root ports for shares added later with `bcvk libvirt mount add`
+    #[clap(long)]
+    pub hotplug_mounts: bool,
+
     /// Network mode for the VM
     #[clap(long, default_value = "user")]
     pub network: String,
@@ -286,14 +293,22 @@ pub struct LibvirtRunOpts {
     #[clap(long)]
     pub disable_tpm: bool,
 
+    /// CPU accelerator: `kvm`, `tcg` (software emulation), or `auto` to fall back to TCG without KVM
+    #[clap(long, value_enum, default_value = "auto")]
+    pub accel: AccelMode,
+
     /// Enable firmware debug log (captures OVMF/EDK2 DEBUG output via isa-debugcon)
     #[clap(long)]
     pub firmware_log: bool,
 
-    /// Directory containing secure boot keys (required for uefi-secure)
+    /// Directory containing secure boot keys, e.g. from `bcvk secureboot keygen` (requires uefi-secure)
     #[clap(long)]
     pub secure_boot_keys: Option<Utf8PathBuf>,
 
+    /// Do not check that the image's EFI binaries are signed by the --secure-boot-keys db key
+    #[clap(long, requires = "secure_boot_keys")]
+    pub skip_signature_check: bool,
+
     /// User-defined labels for organizing VMs (comma not allowed in labels)
     #[clap(long)]
     pub label: Vec<String>,
@@ -310,6 +325,18 @@ pub struct LibvirtRunOpts {
     #[clap(long = "ignition")]
     pub ignition_config: Option<Utf8PathBuf>,
 
+    #[clap(flatten)]
+    pub cloud_init: crate::cloud_init::CloudInitOpts,
+
+    #[clap(flatten)]
+    pub credentials: crate::credentials::CredentialOpts,
+
+    #[clap(flatten)]
+    pub dump: crate::crash_dump::DumpOpts,
+
+    #[clap(flatten)]
+    pub pull: crate::images::PullOpts,
+
     /// Path to virtiofsd binary (overrides auto-detection for disk creation)
     #[clap(long = "virtiofsd", env = "VIRTIOFSD_BIN")]
     pub virtiofsd_binary: Option<String>,
@@ -340,9 +367,20 @@ pub struct LibvirtRunOpts {
     ///           `--log-dir=journal=/tmp/logs/`
     #[clap(long, value_name = "STREAMS=DIR")]
     pub log_dir: Option<crate::run_ephemeral::LogDir>,
+
+    /// Write a boot-time profile to FILE once SSH is ready (JSON, or HTML if FILE ends in .html)
+    #[clap(long, value_name = "FILE")]
+    pub boot_profile: Option<Utf8PathBuf>,
 }
 
 impl LibvirtRunOpts {
+    /// The keys to check the image's signatures against before installing
+    fn signature_check_keys(&self) -> Option<&Utf8Path> {
+        self.secure_boot_keys
+            .as_deref()
+            .filter(|_| !self.skip_signature_check)
+    }
+
     /// Validate that labels don't contain commas
     fn validate_labels(&self) -> Result<()> {
         for label in &self.label {
@@ -375,6 +413,70 @@ impl LibvirtRunOpts {
     }
 }
 
+/// Resolve `--accel` by asking libvirt whether it can run KVM domains, which
+/// also works for remote connections.
+fn resolve_accel(global_opts: &crate::libvirt::LibvirtOptions, accel: AccelMode) -> Result<Accel> {
+    let kvm_supported = || {
+        global_opts
+            .virsh_command()
+            .args(["domcapabilities", "--virttype", "kvm"])
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+    };
+    match accel {
+        AccelMode::Auto if kvm_supported() => Ok(Accel::Kvm),
+        AccelMode::Auto => {
+            tracing::warn!(
+                "KVM is not available to libvirt; falling back to software emulation (TCG), which is much slower"
+            );
+            Ok(Accel::Tcg)
+        }
+        AccelMode::Kvm if kvm_supported() => Ok(Accel::Kvm),
+        AccelMode::Kvm => Err(eyre!(
+            "KVM is not available to libvirt; use --accel=tcg for software emulation"
+        )),
+        AccelMode::Tcg => Ok(Accel::Tcg),
+    }
+}
+
+/// SSH options for running commands in a domain non-interactively
+pub(crate) fn batch_ssh_opts(domain_name: &str) -> crate::libvirt::ssh::LibvirtSshOpts {
+    crate::libvirt::ssh::LibvirtSshOpts {
+        domain_name: domain_name.to_string(),
+        user: "root".to_string(),
+        command: vec![],
+        strict_host_keys: false,
+        timeout: 5,
+        log_level: "ERROR".to_string(),
+        extra_options: vec![],
+        suppress_output: true,
+    }
+}
+
+/// Query the guest's boot times for `--boot-profile`.
+fn guest_boot_times(
+    global_opts: &crate::libvirt::LibvirtOptions,
+    domain_name: &str,
+) -> Result<crate::boot_profile::GuestTimes> {
+    let ssh_opts = batch_ssh_opts(domain_name);
+    let ssh_config = ssh_opts.extract_ssh_config(global_opts)?;
+    let (temp_key, parsed_extra_options) = ssh_opts.prepare_ssh_session(&ssh_config)?;
+    let mut cmd = ssh_opts.build_ssh_command(&ssh_config, &temp_key, parsed_extra_options);
+    cmd.arg("--").arg(crate::boot_profile::GUEST_COMMAND);
+    println!("Waiting for the guest to finish booting...");
+    let output = cmd.output().context("Running ssh")?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "Failed to query guest boot times: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(crate::boot_profile::GuestTimes::parse(
+        &String::from_utf8_lossy(&output.stdout),
+    ))
+}
+
 /// Wait for SSH to become available on a libvirt domain.
 ///
 /// Uses the same `wait_for_readiness` polling loop as the ephemeral path
@@ -392,16 +494,7 @@ fn wait_for_ssh_ready(
     );
 
     // Do expensive setup once: verify domain, extract SSH config, create temp key.
-    let ssh_opts = crate::libvirt::ssh::LibvirtSshOpts {
-        domain_name: domain_name.to_string(),
-        user: "root".to_string(),
-        command: vec![],
-        strict_host_keys: false,
-        timeout: 5,
-        log_level: "ERROR".to_string(),
-        extra_options: vec![],
-        suppress_output: true,
-    };
+    let ssh_opts = batch_ssh_opts(domain_name);
     ssh_opts.verify_domain_running(global_opts)?;
     let ssh_config = ssh_opts.extract_ssh_config(global_opts)?;
     let (temp_key, parsed_extra_options) = ssh_opts.prepare_ssh_session(&ssh_config)?;
@@ -434,6 +527,19 @@ pub fn run(global_opts: &crate::libvirt::LibvirtOptions, mut opts: LibvirtRunOpt
     // Validate labels don't contain commas
     opts.validate_labels()?;
 
+    if let Some(path) = opts.boot_profile.as_deref() {
+        crate::boot_profile::validate_path(path)?;
+    }
+    let mut profile = opts
+        .boot_profile
+        .is_some()
+        .then(|| crate::boot_profile::Recorder::new(&opts.image));
+
+    // libvirt's dumps on panic are always ELF
+    if opts.dump.dump_format != crate::crash_dump::DumpFormat::Elf {
+        return Err(eyre!("--dump-format is only supported for ephemeral VMs"));
+    }
+
     // Validate --log-dir early (before any expensive work).
     if let Some(ref ld) = opts.log_dir {
         if !ld.path.is_absolute() {
@@ -442,6 +548,12 @@ pub fn run(global_opts: &crate::libvirt::LibvirtOptions, mut opts: LibvirtRunOpt
                 ld.path
             ));
         }
+        // QEMU writes the channels directly, there is nothing to convert them
+        if ld.wants_journal_export() {
+            return Err(color_eyre::eyre::eyre!(
+                "--log-dir streams `journal-export` and `journal-file` are only supported for ephemeral VMs"
+            ));
+        }
     }
 
     let connect_uri = global_opts.connect.as_deref();
@@ -483,6 +595,9 @@ pub fn run(global_opts: &crate::libvirt::LibvirtOptions, mut opts: LibvirtRunOpt
         vm_name, opts.image
     );
 
+    // Pull first, so that a newer image gets a new base disk
+    images::pull(&opts.image, opts.pull.policy)?;
+
     // Get the image digest for caching
     let inspect = images::inspect(&opts.image)?;
     let image_digest = inspect.digest.to_string();
@@ -504,6 +619,27 @@ pub fn run(global_opts: &crate::libvirt::LibvirtOptions, mut opts: LibvirtRunOpt
         }
     }
 
+    // A binary not signed by the enrolled db key would only fail in the
+    // firmware, after the (possibly slow) installation
+    if let Some(keys) = opts.signature_check_keys() {
+        let results = crate::secureboot::verify_image(&opts.image, keys)
+            .context("Verifying Secure Boot signatures")?;
+        crate::secureboot::check_results(&opts.image, &results)?;
+    }
+
+    if opts.cloud_init.resolve()?.is_some()
+        && !crate::cloud_init::check_cloud_init_support(&opts.image, None)?
+    {
+        return Err(eyre!(
+            "Image does not have cloud-init. See man bcvk-libvirt-run for details."
+        ));
+    }
+
+    // Validate credentials early, they are only read when defining the domain
+    if !opts.credentials.parse()?.is_empty() {
+        crate::credentials::check_arch(crate::arch::Arch::host())?;
+    }
+
     if opts.update_from_host {
         opts.bind_storage_ro = true;
         opts.install.target_transport = Some(UPDATE_FROM_HOST_TRANSPORT.to_owned());
@@ -518,6 +654,8 @@ pub fn run(global_opts: &crate::libvirt::LibvirtOptions, mut opts: LibvirtRunOpt
         debug!("Added ignition.platform.id=qemu kernel argument to install options");
     }
 
+    let accel = resolve_accel(global_opts, opts.accel)?;
+
     // Phase 1: Find or create a base disk image
     let base_disk_path = crate::libvirt::base_disks::find_or_create_base_disk(
         &opts.image,
@@ -529,6 +667,9 @@ pub fn run(global_opts: &crate::libvirt::LibvirtOptions, mut opts: LibvirtRunOpt
     .with_context(|| "Failed to find or create base disk")?;
 
     println!("Using base disk image: {}", base_disk_path);
+    if let Some(profile) = profile.as_mut() {
+        profile.mark("base-disk-ready");
+    }
 
     // Phase 2: Clone the base disk to create a VM-specific disk (or use base directly if transient)
     let disk_path = if opts.transient {
@@ -546,8 +687,18 @@ pub fn run(global_opts: &crate::libvirt::LibvirtOptions, mut opts: LibvirtRunOpt
     println!("Creating libvirt domain...");
 
     // Create the domain directly (simpler than using libvirt/create for files)
-    create_libvirt_domain_from_disk(&vm_name, &disk_path, &image_digest, &opts, global_opts)
-        .with_context(|| "Failed to create libvirt domain")?;
+    create_libvirt_domain_from_disk(
+        &vm_name,
+        &disk_path,
+        &image_digest,
+        &opts,
+        accel,
+        global_opts,
+    )
+    .with_context(|| "Failed to create libvirt domain")?;
+    if let Some(profile) = profile.as_mut() {
+        profile.mark("domain-started");
+    }
 
     // VM is now managed by libvirt, no need to track separately
 
@@ -608,15 +759,27 @@ pub fn run(global_opts: &crate::libvirt::LibvirtOptions, mut opts: LibvirtRunOpt
         }
     }
 
+    let ssh_wait_timeout = match accel {
+        Accel::Kvm => SSH_WAIT_TIMEOUT_SECONDS,
+        Accel::Tcg => SSH_WAIT_TIMEOUT_SECONDS * u64::from(crate::run_ephemeral::TCG_TIMEOUT_SCALE),
+    };
+    // Profiling also needs SSH to query the guest
+    if opts.ssh_wait || opts.ssh || profile.is_some() {
+        wait_for_ssh_ready(global_opts, &vm_name, ssh_wait_timeout)?;
+    }
+    if let (Some(mut profile), Some(path)) = (profile, opts.boot_profile.as_deref()) {
+        profile.mark("ssh-ready");
+        let written = guest_boot_times(global_opts, &vm_name)
+            .and_then(|guest| profile.finish(guest).write(path));
+        if let Err(e) = written {
+            warn!("Failed to write boot profile: {e:#}");
+        }
+    }
+
     if opts.ssh_wait {
-        // Wait for SSH to be ready and verify connectivity
-        wait_for_ssh_ready(global_opts, &vm_name, SSH_WAIT_TIMEOUT_SECONDS)?;
         println!("Ready; use bcvk libvirt ssh to connect");
         Ok(())
     } else if opts.ssh {
-        // Wait for SSH then enter interactive shell
-        wait_for_ssh_ready(global_opts, &vm_name, SSH_WAIT_TIMEOUT_SECONDS)?;
-
         // Use the libvirt SSH functionality directly
         let ssh_opts = crate::libvirt::ssh::LibvirtSshOpts {
             domain_name: vm_name,
@@ -1005,6 +1168,34 @@ fn check_libvirt_readonly_support() -> Result<()> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_remove_dir_on_error() {
+        let td = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(td.path()).unwrap().join("creds");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("secret"), "x").unwrap();
+        RemoveDirOnError::new(dir.clone()).disarm();
+        assert!(dir.exists());
+        drop(RemoveDirOnError::new(dir.clone()));
+        assert!(!dir.exists());
+        // Nothing written yet
+        drop(RemoveDirOnError::new(dir.clone()));
+    }
+
+    #[test]
+    fn test_signature_check_keys() {
+        let parse = |args: &[&str]| {
+            LibvirtRunOpts::try_parse_from(["run"].iter().chain(args).chain(&["img"]))
+        };
+        let opts = parse(&[]).unwrap();
+        assert_eq!(opts.signature_check_keys(), None);
+        let opts = parse(&["--secure-boot-keys=/keys"]).unwrap();
+        assert_eq!(opts.signature_check_keys(), Some(Utf8Path::new("/keys")));
+        let opts = parse(&["--secure-boot-keys=/keys", "--skip-signature-check"]).unwrap();
+        assert_eq!(opts.signature_check_keys(), None);
+        assert!(parse(&["--skip-signature-check"]).is_err());
+    }
+
     #[test]
     fn test_parse_volume_mount_valid() {
         let result = parse_volume_mount("/tmp:mytag");
@@ -1152,12 +1343,44 @@ fn check_ignition_support(image: &str) -> Result<bool> {
     Ok(false)
 }
 
+/// Removes a directory written for a domain unless the domain was created,
+/// as nothing else would clean it up.
+struct RemoveDirOnError {
+    path: Option<Utf8PathBuf>,
+}
+
+impl RemoveDirOnError {
+    fn new(path: Utf8PathBuf) -> Self {
+        Self { path: Some(path) }
+    }
+
+    /// Keep the directory
+    fn disarm(mut self) {
+        self.path = None;
+    }
+}
+
+impl Drop for RemoveDirOnError {
+    fn drop(&mut self) {
+        let Some(path) = self.path.take() else {
+            return;
+        };
+        debug!("Removing {path}");
+        if let Err(e) = std::fs::remove_dir_all(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove {path}: {e}");
+            }
+        }
+    }
+}
+
 /// Create a libvirt domain directly from a disk image file
 fn create_libvirt_domain_from_disk(
     domain_name: &str,
     disk_path: &Utf8Path,
     image_digest: &str,
     opts: &LibvirtRunOpts,
+    accel: Accel,
     global_opts: &crate::libvirt::LibvirtOptions,
 ) -> Result<()> {
     use crate::libvirt::domain::DomainBuilder;
@@ -1233,7 +1456,8 @@ fn create_libvirt_domain_from_disk(
         .with_transient_disk(opts.transient)
         .with_network("none") // Use QEMU args for SSH networking instead
         .with_firmware(opts.firmware)
-        .with_tpm(!opts.disable_tpm);
+        .with_tpm(!opts.disable_tpm)
+        .with_accel(accel);
     if opts.firmware_log {
         domain_builder =
             domain_builder.with_firmware_log(crate::libvirt::domain::FirmwareLogOutput::Console);
@@ -1340,6 +1564,7 @@ fn create_libvirt_domain_from_disk(
         &mut smbios_creds,
         &mut mount_unit_names,
     )?;
+    domain_builder = domain_builder.with_hotplug_ports(opts.hotplug_mounts);
 
     // Add container storage mount if requested
     if opts.bind_storage_ro {
@@ -1440,6 +1665,31 @@ fn create_libvirt_domain_from_disk(
             );
     }
 
+    // Attach a cloud-init NoCloud seed disk, kept in the pool like the Ignition config
+    if let Some((user_data, meta_data)) = opts.cloud_init.resolve()? {
+        let pool_path = get_libvirt_storage_pool_path(global_opts.connect.as_deref())
+            .context("Failed to get libvirt storage pool path for cloud-init seed")?;
+        let seed_path = pool_path.join(format!("{}_cidata.img", domain_name));
+        crate::cloud_init::create_seed(&seed_path, &user_data, meta_data.as_deref(), domain_name)
+            .context("Failed to create cloud-init seed disk")?;
+        debug!("Created cloud-init seed disk {}", seed_path);
+
+        domain_builder = domain_builder
+            .with_cloud_init_disk(seed_path.to_string())
+            .with_metadata("bootc:cloud-init-seed-path", seed_path.as_str());
+    }
+
+    // libvirt dumps the memory of a panicked guest itself; the kernel debug
+    // files are copied now, while the image is at hand
+    if let Some(dump_dir) = opts.dump.prepare_dir()? {
+        if let Err(e) =
+            crate::crash_dump::copy_kernel_debug_files_from_image(&opts.image, &dump_dir)
+        {
+            warn!("Failed to copy kernel debug files to {dump_dir}: {e:#}");
+        }
+        domain_builder = domain_builder.with_coredump_on_crash(true);
+    }
+
     // Create a dropin for remote-fs.target that wants all virtiofs mount units.
     // We use remote-fs.target because virtiofs is conceptually similar to a remote
     // filesystem - it requires virtio transport infrastructure, like NFS needs network.
@@ -1515,6 +1765,44 @@ fn create_libvirt_domain_from_disk(
         qemu_args.push(format!("type=11,value={}", extra_cred));
     }
 
+    // User credentials are written to files in the storage pool, so that
+    // their values are neither in the domain XML nor in the QEMU command line
+    let credentials = opts.credentials.parse()?;
+    let mut credentials_guard = None;
+    if !credentials.is_empty() {
+        use crate::credentials::{CredentialArg, CredentialSource};
+
+        let pool_path = get_libvirt_storage_pool_path(global_opts.connect.as_deref())
+            .context("Failed to get libvirt storage pool path for credentials")?;
+        let credentials_dir = pool_path.join(format!("{}_credentials", domain_name));
+        credentials_guard = Some(RemoveDirOnError::new(credentials_dir.clone()));
+        for cred in credentials {
+            let value = match cred.source {
+                CredentialSource::Value(value) => value.into_bytes(),
+                CredentialSource::File(path) => std::fs::read(&path)
+                    .with_context(|| format!("Reading credential file {}", path))?,
+                CredentialSource::Secret(secret) => {
+                    crate::credentials::read_podman_secret(&secret)?
+                }
+            };
+            let arg = crate::credentials::write_credential(
+                &credentials_dir,
+                &cred.name,
+                &value,
+                crate::arch::Arch::host(),
+            )?;
+            match arg {
+                CredentialArg::Smbios(_) => qemu_args.extend(arg.qemu_args()),
+                CredentialArg::FwCfg(name, path) => {
+                    domain_builder = domain_builder.add_fw_cfg(name, path.into_string())
+                }
+            }
+        }
+        debug!("Wrote credentials to {}", credentials_dir);
+        domain_builder =
+            domain_builder.with_metadata("bootc:credentials-dir", credentials_dir.as_str());
+    }
+
     // If journal output was requested, configure the DomainBuilder to emit a
     // <channel type='file'> element.  Libvirt attaches it to the existing
     // virtio-serial controller (created implicitly for the virtio console), so
@@ -1669,5 +1957,8 @@ fn create_libvirt_domain_from_disk(
         )?;
     }
 
+    if let Some(guard) = credentials_guard {
+        guard.disarm();
+    }
     Ok(())
 }
@@ -2,6 +2,8 @@
 //!
 //! This module provides utilities for loading existing UEFI Secure Boot
 //! keys (PK, KEK, db) and customizing OVMF firmware variables for VMs.
+//! Keys can be generated with `bcvk secureboot keygen`, see
+//! [`crate::secureboot`].
 
 use camino::{Utf8Path, Utf8PathBuf};
 use cap_std_ext::cap_std::fs::Dir;
@@ -11,6 +13,12 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::ops::ControlFlow;
 
+/// File holding the owner GUID of the keys in a key directory
+pub(crate) const GUID_FILE: &str = "GUID.txt";
+
+/// Keys of a key directory, each with a `.crt` certificate and a `.key` private key
+pub(crate) const KEY_NAMES: [&str; 3] = ["PK", "KEK", "db"];
+
 /// System-wide QEMU firmware descriptor search directories
 const QEMU_FIRMWARE_DIRS: &[&str] = &["/etc/qemu/firmware", "/usr/share/qemu/firmware"];
 
@@ -159,12 +167,12 @@ impl SecureBootKeys {
 
         if !test_dir.status()?.success() {
             return Err(eyre!(
-                "Secure boot key directory not found: {}. Please generate keys externally.",
+                "Secure boot key directory not found: {}. Generate keys with `bcvk secureboot keygen`.",
                 key_dir
             ));
         }
 
-        let guid_file = key_dir.join("GUID.txt");
+        let guid_file = key_dir.join(GUID_FILE);
 
         // Read GUID file
         let mut cat_guid = std::process::Command::new("cat");
@@ -272,7 +280,8 @@ pub fn setup_secure_boot(
     let keys = SecureBootKeys::load(key_dir)?;
 
     // Find the system firmware (includes format info)
-    let firmware_info = find_firmware_from_descriptors(true)?;
+    let firmware_info =
+        find_firmware_from_descriptors(SecureBootFilter::Required, get_qemu_architecture())?;
 
     // Check if custom vars template already exists at the output path
     if !vars_output_path.exists() {
@@ -373,15 +382,29 @@ pub(crate) fn get_qemu_architecture() -> &'static str {
     }
 }
 
+/// Secure boot constraint used when selecting a firmware descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SecureBootFilter {
+    /// Accept any firmware
+    Any,
+    /// Only accept firmware that supports secure boot
+    Required,
+    /// Only accept firmware without secure boot (and thus without the SMM
+    /// requirement that secure boot builds of OVMF have)
+    Disabled,
+}
+
 /// Find firmware using QEMU firmware interop JSON descriptors
 ///
 /// This follows the same approach as systemd-vmspawn:
 /// - Searches in $XDG_CONFIG_HOME/qemu/firmware, /etc/qemu/firmware, /usr/share/qemu/firmware
 /// - Filters by architecture and secure boot support
 /// - Skips firmware with enrolled keys (known to cause issues)
-fn find_firmware_from_descriptors(require_secure_boot: bool) -> Result<FirmwareInfo> {
+fn find_firmware_from_descriptors(
+    secure_boot: SecureBootFilter,
+    arch: &str,
+) -> Result<FirmwareInfo> {
     let descriptors = list_firmware_descriptors()?;
-    let arch = get_qemu_architecture();
 
     for descriptor_path in descriptors {
         let descriptor = load_firmware_descriptor(&descriptor_path)?;
@@ -406,12 +429,22 @@ fn find_firmware_from_descriptors(require_secure_boot: bool) -> Result<FirmwareI
         }
 
         // Check secure boot requirement
-        if require_secure_boot && !descriptor.supports_secure_boot() {
-            tracing::debug!(
-                "Skipping {}, firmware doesn't support secure boot",
-                descriptor_path
-            );
-            continue;
+        match (secure_boot, descriptor.supports_secure_boot()) {
+            (SecureBootFilter::Required, false) => {
+                tracing::debug!(
+                    "Skipping {}, firmware doesn't support secure boot",
+                    descriptor_path
+                );
+                continue;
+            }
+            (SecureBootFilter::Disabled, true) => {
+                tracing::debug!(
+                    "Skipping {}, firmware supports secure boot",
+                    descriptor_path
+                );
+                continue;
+            }
+            _ => {}
         }
 
         // Skip memory-mapped firmware (we need separate code and vars files)
@@ -448,15 +481,16 @@ fn find_firmware_from_descriptors(require_secure_boot: bool) -> Result<FirmwareI
     }
 
     Err(eyre!(
-        "No suitable firmware descriptor found for architecture {} with secure_boot={}",
+        "No suitable firmware descriptor found for architecture {} with secure boot {:?}",
         arch,
-        require_secure_boot
+        secure_boot
     ))
 }
 
 /// Find the system OVMF_VARS.fd file using QEMU firmware interop JSON descriptors
 pub(crate) fn find_ovmf_vars() -> Result<Utf8PathBuf> {
-    let firmware_info = find_firmware_from_descriptors(false)?;
+    let firmware_info =
+        find_firmware_from_descriptors(SecureBootFilter::Any, get_qemu_architecture())?;
 
     if !firmware_info.vars_path.exists() {
         return Err(eyre!(
@@ -476,7 +510,8 @@ pub(crate) fn find_ovmf_vars() -> Result<Utf8PathBuf> {
 ///
 /// Returns full firmware info including paths and formats for both CODE and VARS
 pub fn find_secure_boot_firmware() -> Result<FirmwareInfo> {
-    let firmware_info = find_firmware_from_descriptors(true)?;
+    let firmware_info =
+        find_firmware_from_descriptors(SecureBootFilter::Required, get_qemu_architecture())?;
 
     if !firmware_info.code_path.exists() {
         return Err(eyre!(
@@ -495,6 +530,33 @@ pub fn find_secure_boot_firmware() -> Result<FirmwareInfo> {
     Ok(firmware_info)
 }
 
+/// Find plain UEFI firmware (no secure boot) using QEMU firmware interop JSON descriptors
+///
+/// This is what ephemeral VMs use for `--boot uefi`; they run QEMU directly
+/// rather than through libvirt, so we avoid firmware that requires SMM.
+/// `arch` is the guest architecture in QEMU naming, as it may be emulated.
+pub(crate) fn find_uefi_firmware(arch: &str) -> Result<FirmwareInfo> {
+    let firmware_info = find_firmware_from_descriptors(SecureBootFilter::Disabled, arch)?;
+
+    for path in [&firmware_info.code_path, &firmware_info.vars_path] {
+        if !path.exists() {
+            return Err(eyre!(
+                "Firmware descriptor returned non-existent path: {}. Please verify your QEMU firmware installation.",
+                path
+            ));
+        }
+    }
+
+    tracing::debug!(
+        "Found UEFI firmware: code={} ({}), vars={} ({})",
+        firmware_info.code_path,
+        firmware_info.code_format,
+        firmware_info.vars_path,
+        firmware_info.vars_format
+    );
+    Ok(firmware_info)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -42,6 +42,9 @@ pub struct LibvirtUploadOpts {
     /// Number of vCPUs for installation VM
     #[clap(long)]
     pub vcpus: Option<u32>,
+
+    #[clap(flatten)]
+    pub pull: images::PullOpts,
 }
 
 impl LibvirtUploadOpts {
@@ -183,6 +186,8 @@ pub fn run(global_opts: &crate::libvirt::LibvirtOptions, opts: LibvirtUploadOpts
         opts.source_image
     );
 
+    images::pull(&opts.source_image, opts.pull.policy)?;
+
     // Phase 1: Extract image digest for caching
     let inspect = images::inspect(&opts.source_image)?;
     let image_digest = &inspect.digest.to_string();
@@ -18,26 +18,54 @@ mod xml_utils;
 #[cfg(target_os = "linux")]
 mod arch;
 #[cfg(target_os = "linux")]
+mod boot_failure;
+#[cfg(target_os = "linux")]
+mod boot_profile;
+#[cfg(target_os = "linux")]
 mod boot_progress;
 #[cfg(target_os = "linux")]
 mod cache_metadata;
 #[cfg(target_os = "linux")]
+mod cloud_init;
+#[cfg(target_os = "linux")]
+mod compose;
+#[cfg(target_os = "linux")]
 mod container_entrypoint;
 #[cfg(target_os = "linux")]
+mod cp;
+#[cfg(target_os = "linux")]
+mod crash_dump;
+#[cfg(target_os = "linux")]
 mod credentials;
 #[cfg(target_os = "linux")]
 mod domain_list;
 #[cfg(target_os = "linux")]
 mod ephemeral;
 #[cfg(target_os = "linux")]
+mod ephemeral_exec;
+#[cfg(target_os = "linux")]
+mod ephemeral_logs;
+#[cfg(target_os = "linux")]
+mod esp;
+#[cfg(target_os = "linux")]
+mod image_archive;
+#[cfg(target_os = "linux")]
 mod images;
 #[cfg(target_os = "linux")]
+mod internal_network;
+#[cfg(target_os = "linux")]
+mod iso_install;
+#[cfg(target_os = "linux")]
+mod journal_export;
+#[cfg(target_os = "linux")]
 mod kernel;
 #[cfg(target_os = "linux")]
 mod libvirt;
 #[cfg(target_os = "linux")]
 mod libvirt_upload_disk;
 #[cfg(target_os = "linux")]
+mod mount;
+#[cfg(target_os = "linux")]
 #[allow(dead_code)]
 mod podman;
 #[cfg(target_os = "linux")]
@@ -47,6 +75,8 @@ mod run_ephemeral;
 #[cfg(target_os = "linux")]
 mod run_ephemeral_ssh;
 #[cfg(target_os = "linux")]
+mod secureboot;
+#[cfg(target_os = "linux")]
 mod ssh;
 #[cfg(target_os = "linux")]
 mod status_monitor;
@@ -60,6 +90,8 @@ mod to_disk;
 mod utils;
 #[cfg(target_os = "linux")]
 mod varlink_ipc;
+#[cfg(target_os = "linux")]
+mod watchdog;
 
 /// Default state directory for bcvk container data
 #[cfg(target_os = "linux")]
@@ -88,7 +120,15 @@ struct DebugInternalsOpts {
 #[cfg(target_os = "linux")]
 #[derive(Subcommand)]
 enum DebugInternalsCmds {
-    OpenTree { path: std::path::PathBuf },
+    OpenTree {
+        path: std::path::PathBuf,
+    },
+    /// Copy vmlinux and System.map of the kernel of IMAGE to DEST; run in
+    /// the podman user namespace
+    CopyKernelDebugFiles {
+        image: String,
+        dest: camino::Utf8PathBuf,
+    },
 }
 
 /// Internal diagnostic and tooling commands for development
@@ -146,6 +186,20 @@ enum Commands {
     #[clap(subcommand)]
     Ephemeral(StubEphemeralCommands),
 
+    #[cfg(target_os = "linux")]
+    /// Run groups of ephemeral VMs described in a YAML file
+    Compose(compose::ComposeOpts),
+
+    #[cfg(target_os = "linux")]
+    /// Show and compare boot profiles recorded with --boot-profile
+    #[clap(name = "boot-profile")]
+    BootProfile(boot_profile::BootProfileOpts),
+
+    #[cfg(target_os = "linux")]
+    /// Generate Secure Boot keys and sign EFI binaries for --secure-boot-keys
+    #[clap(subcommand)]
+    Secureboot(secureboot::SecureBootOpts),
+
     #[cfg(target_os = "linux")]
     /// Install bootc images to persistent disk images
     #[clap(name = "to-disk")]
@@ -240,8 +294,6 @@ fn install_tracing(default_level: &str) {
 /// Initializes logging, error handling, and command dispatch for all
 /// bcvk operations including VM management, SSH access, and
 /// container image handling.
-// On non-Linux, all commands return errors so post-match code is unreachable
-#[cfg_attr(not(target_os = "linux"), allow(unreachable_code))]
 fn main() -> Result<(), Report> {
     // Detect varlink socket activation early to set a quieter default log
     // level. The varlink protocol runs on a separate fd so logging doesn't
@@ -272,6 +324,26 @@ fn main() -> Result<(), Report> {
 
     let cli = Cli::parse();
 
+    #[cfg(target_os = "linux")]
+    if let Err(e) = run(cli) {
+        // A guest that failed to boot gets a distinct exit code, so callers
+        // can tell it apart from other errors without parsing output.
+        if let Some(failure) = e.downcast_ref::<supervisor_status::GuestFailure>() {
+            eprintln!("error: {}", boot_failure::describe(failure).trim_end());
+            std::process::exit(failure.kind.exit_code());
+        }
+        return Err(e);
+    }
+    #[cfg(not(target_os = "linux"))]
+    run(cli)?;
+
+    Ok(())
+}
+
+/// Dispatch the parsed command line.
+// On non-Linux, all commands return errors so post-match code is unreachable
+#[cfg_attr(not(target_os = "linux"), allow(unreachable_code))]
+fn run(cli: Cli) -> Result<(), Report> {
     #[cfg(target_os = "linux")]
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -295,6 +367,15 @@ fn main() -> Result<(), Report> {
             ));
         }
 
+        #[cfg(target_os = "linux")]
+        Commands::Compose(opts) => opts.run()?,
+
+        #[cfg(target_os = "linux")]
+        Commands::BootProfile(opts) => opts.run()?,
+
+        #[cfg(target_os = "linux")]
+        Commands::Secureboot(opts) => opts.run()?,
+
         #[cfg(target_os = "linux")]
         Commands::ToDisk(opts) => {
             let target = opts.target_disk.clone();
@@ -318,6 +399,10 @@ fn main() -> Result<(), Report> {
             match command {
                 libvirt::LibvirtSubcommands::Run(opts) => libvirt::run::run(&options, opts)?,
                 libvirt::LibvirtSubcommands::Ssh(opts) => libvirt::ssh::run(&options, opts)?,
+                libvirt::LibvirtSubcommands::Cp(opts) => libvirt::cp::run(&options, opts)?,
+                libvirt::LibvirtSubcommands::Mount(command) => {
+                    libvirt::mount::run(&options, command)?
+                }
                 libvirt::LibvirtSubcommands::List(opts) => libvirt::list::run(&options, opts)?,
                 libvirt::LibvirtSubcommands::ListVolumes(opts) => {
                     libvirt::list_volumes::run(&options, opts)?
@@ -377,6 +462,9 @@ fn main() -> Result<(), Report> {
                 let fd = Dir::reopen_dir(&fd)?;
                 tracing::debug!("{:?}", fd.entries()?.into_iter().collect::<Vec<_>>());
             }
+            DebugInternalsCmds::CopyKernelDebugFiles { image, dest } => {
+                crash_dump::copy_kernel_debug_files_from_mounted_image(&image, &dest)?;
+            }
         },
 
         Commands::Internals(opts) => {
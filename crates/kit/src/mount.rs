@@ -0,0 +1,520 @@
+//! Hot-plugging virtiofs shares into running VMs
+//!
+//! `bcvk ephemeral mount` and `bcvk libvirt mount` share a host directory with
+//! a running VM, without the reboot `--bind` would need. A virtiofsd is started
+//! for the directory, a vhost-user-fs device is hot-added (via QMP for
+//! ephemeral VMs, `virsh attach-device` for libvirt) and the mount unit from
+//! [`crate::credentials::generate_virtiofs_mount_unit`] is started in the
+//! guest over SSH. `mount rm` reverses these steps.
+//!
+//! The virtiofs tag is derived from the guest path, so a share is identified
+//! by the VM name and guest path alone.
+//!
+//! For ephemeral VMs the host directory is not visible in the container, so
+//! it is opened on the host and passed to `podman exec` as a file descriptor;
+//! virtiofsd shares it via `/proc/<pid>/fd/<fd>`. The virtiofsd is supervised
+//! by a detached `mount-serve` entrypoint process and exits when QEMU closes
+//! the connection on removal.
+
+use std::process::Command;
+use std::time::Duration;
+
+use bootc_utils::CommandRunExt;
+use camino::Utf8PathBuf;
+use cap_std_ext::cmdext::{CapStdExtCommandExt, CmdFds};
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::qemu;
+use crate::run_ephemeral::{RunEphemeralOpts, ENTRYPOINT, QMP_SOCKET_PATH};
+use crate::ssh;
+
+/// Number of spare PCIe root ports VMs get for hot-plugged shares
+pub(crate) const HOTPLUG_PORTS: u32 = 4;
+
+/// Where the guest mount units of hot-plugged shares are written
+const GUEST_UNIT_DIR: &str = "/run/systemd/system";
+
+/// How long to retry starting the mount unit while the guest probes the device
+const GUEST_MOUNT_ATTEMPTS: u32 = 30;
+
+/// How long to wait for virtiofsd to create its socket
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for the guest to release an unplugged device
+const UNPLUG_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Options for sharing a host directory with a running VM.
+#[derive(Debug, Parser)]
+pub struct MountAddOpts {
+    /// Name of the VM
+    pub name: String,
+
+    /// Host directory to share
+    pub host_path: Utf8PathBuf,
+
+    /// Absolute path to mount the directory at in the guest
+    pub guest_path: String,
+
+    /// Share the directory read-only
+    #[clap(long)]
+    pub ro: bool,
+}
+
+impl MountAddOpts {
+    /// Validate the paths, returning the canonical host directory.
+    pub(crate) fn host_dir(&self) -> Result<Utf8PathBuf> {
+        validate_guest_path(&self.guest_path)?;
+        let host_dir = self
+            .host_path
+            .canonicalize_utf8()
+            .with_context(|| format!("Opening {}", self.host_path))?;
+        if !host_dir.is_dir() {
+            return Err(eyre!("{host_dir} is not a directory"));
+        }
+        Ok(host_dir)
+    }
+}
+
+/// Options for removing a share added with `mount add`.
+#[derive(Debug, Parser)]
+pub struct MountRmOpts {
+    /// Name of the VM
+    pub name: String,
+
+    /// Guest path the share is mounted at
+    pub guest_path: String,
+}
+
+/// Hot-plugged share operations
+#[derive(Debug, Subcommand)]
+pub enum MountCommands {
+    /// Share a host directory with a running VM
+    Add(MountAddOpts),
+
+    /// Unmount and remove a share added with `mount add`
+    #[clap(name = "rm")]
+    Remove(MountRmOpts),
+}
+
+/// Guest paths end up in unit names and files, so only accept normalized
+/// absolute paths below the root.
+pub(crate) fn validate_guest_path(guest_path: &str) -> Result<()> {
+    let valid = guest_path.len() > 1
+        && guest_path.starts_with('/')
+        && guest_path[1..]
+            .split('/')
+            .all(|c| !matches!(c, "" | "." | ".."))
+        && !guest_path.contains(|c: char| c.is_control());
+    if !valid {
+        return Err(eyre!(
+            "Invalid guest path '{guest_path}': use an absolute path like /mnt/data"
+        ));
+    }
+    Ok(())
+}
+
+/// The virtiofs tag of the share mounted at `guest_path`; tags are limited
+/// to 36 bytes, so the path is hashed.
+pub(crate) fn tag(guest_path: &str) -> String {
+    let digest = Sha256::digest(guest_path.as_bytes());
+    let hex: String = digest.iter().take(6).map(|b| format!("{b:02x}")).collect();
+    format!("bcvk-{hex}")
+}
+
+/// Shell script installing and starting the guest mount unit.
+///
+/// The device may not be probed yet when the script runs, so starting the
+/// unit is retried for a while.
+pub(crate) fn guest_add_script(tag: &str, guest_path: &str, readonly: bool) -> Result<String> {
+    let unit_name = crate::credentials::guest_path_to_unit_name(guest_path);
+    let unit = crate::credentials::generate_virtiofs_mount_unit(tag, guest_path, readonly);
+    let unit_name = shlex::try_quote(&unit_name)?;
+    Ok(format!(
+        "set -e
+unit={unit_name}
+mkdir -p {GUEST_UNIT_DIR}
+cat > {GUEST_UNIT_DIR}/\"$unit\" <<'EOF'
+{unit}EOF
+systemctl daemon-reload
+for i in $(seq {GUEST_MOUNT_ATTEMPTS}); do
+    systemctl start -- \"$unit\" && exit 0
+    systemctl reset-failed -- \"$unit\" || true
+    sleep 1
+done
+exit 1
+"
+    ))
+}
+
+/// Shell script stopping and removing the guest mount unit.
+pub(crate) fn guest_rm_script(guest_path: &str) -> Result<String> {
+    let unit_name = crate::credentials::guest_path_to_unit_name(guest_path);
+    let unit_name = shlex::try_quote(&unit_name)?;
+    Ok(format!(
+        "set -e
+unit={unit_name}
+systemctl stop -- \"$unit\"
+rm -f {GUEST_UNIT_DIR}/\"$unit\"
+systemctl daemon-reload
+"
+    ))
+}
+
+/// Run a guest script of an ephemeral VM over SSH.
+fn ephemeral_guest_script(container_name: &str, script: String) -> Result<()> {
+    let output = ssh::connect_captured(container_name, vec![script])?;
+    if output.exit_code != 0 {
+        return Err(eyre!(
+            "Command failed in the guest with exit code {}: {}",
+            output.exit_code,
+            output.stderr.trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Run an entrypoint subcommand in the container of an ephemeral VM.
+fn entrypoint_exec(container_name: &str, args: &[&str]) -> Result<()> {
+    Command::new("podman")
+        .args(["exec", "--", container_name, ENTRYPOINT])
+        .args(args)
+        .run_capture_stderr()
+        .map_err(|e| eyre!("{e}"))
+}
+
+/// Execute `bcvk ephemeral mount`.
+pub(crate) fn run_ephemeral(command: MountCommands) -> Result<()> {
+    match command {
+        MountCommands::Add(opts) => ephemeral_add(opts),
+        MountCommands::Remove(opts) => ephemeral_rm(opts),
+    }
+}
+
+fn ephemeral_add(opts: MountAddOpts) -> Result<()> {
+    let host_dir = opts.host_dir()?;
+    ssh::verify_container_running(&opts.name)?;
+    let tag = tag(&opts.guest_path);
+    debug!("Sharing {host_dir} at {} with tag {tag}", opts.guest_path);
+
+    // The directory is passed down as a file descriptor, as the container has
+    // no view of the host filesystem.
+    let dir = std::fs::File::open(&host_dir).with_context(|| format!("Opening {host_dir}"))?;
+    let dir = std::sync::Arc::new(rustix::fd::OwnedFd::from(dir));
+    let mut fds = CmdFds::new();
+    let fd_n = fds.take_fd(dir);
+    let mut cmd = Command::new("podman");
+    cmd.args(["exec", "--detach", "--preserve-fd", &fd_n.to_string()])
+        .args(["--", &opts.name, ENTRYPOINT, "mount-serve", "--tag", &tag])
+        .args(["--fd", &fd_n.to_string()]);
+    if opts.ro {
+        cmd.arg("--ro");
+    }
+    cmd.take_fds(fds);
+    cmd.run_capture_stderr()
+        .map_err(|e| eyre!("Failed to start virtiofsd: {e}"))?;
+
+    entrypoint_exec(&opts.name, &["mount-attach", "--tag", &tag])?;
+
+    let script = guest_add_script(&tag, &opts.guest_path, opts.ro)?;
+    if let Err(e) = ephemeral_guest_script(&opts.name, script) {
+        let _ = entrypoint_exec(&opts.name, &["mount-detach", "--tag", &tag]);
+        return Err(e.wrap_err(format!("Mounting {} in the guest", opts.guest_path)));
+    }
+    println!(
+        "Mounted {host_dir} at {} in VM '{}'",
+        opts.guest_path, opts.name
+    );
+    Ok(())
+}
+
+fn ephemeral_rm(opts: MountRmOpts) -> Result<()> {
+    validate_guest_path(&opts.guest_path)?;
+    ssh::verify_container_running(&opts.name)?;
+    let tag = tag(&opts.guest_path);
+
+    let script = guest_rm_script(&opts.guest_path)?;
+    ephemeral_guest_script(&opts.name, script)
+        .wrap_err_with(|| format!("Unmounting {} in the guest", opts.guest_path))?;
+    entrypoint_exec(&opts.name, &["mount-detach", "--tag", &tag])?;
+    println!("Removed {} from VM '{}'", opts.guest_path, opts.name);
+    Ok(())
+}
+
+/// Options of the container side of hot-plugging.
+#[derive(Debug, Parser)]
+pub struct HotplugOpts {
+    /// virtiofs tag of the share
+    #[clap(long)]
+    pub tag: String,
+}
+
+/// Options for supervising the virtiofsd of a hot-plugged share.
+#[derive(Debug, Parser)]
+pub struct ServeOpts {
+    /// virtiofs tag of the share
+    #[clap(long)]
+    pub tag: String,
+
+    /// File descriptor of the shared directory
+    #[clap(long)]
+    pub fd: i32,
+
+    /// Share the directory read-only
+    #[clap(long)]
+    pub ro: bool,
+}
+
+fn socket_path(tag: &str) -> Utf8PathBuf {
+    format!("/run/inner-shared/virtiofs-{tag}.sock").into()
+}
+
+fn pid_path(tag: &str) -> Utf8PathBuf {
+    format!("/run/virtiofsd-{tag}.pid").into()
+}
+
+/// Where `mount-serve` reports why it failed, for `mount-attach`
+fn error_path(tag: &str) -> Utf8PathBuf {
+    format!("/run/virtiofsd-{tag}.error").into()
+}
+
+fn chardev_id(tag: &str) -> String {
+    format!("char-{tag}")
+}
+
+fn device_id(tag: &str) -> String {
+    format!("fs-{tag}")
+}
+
+/// Whether a share with `tag` is attached to the VM.
+async fn is_attached(qmp: &mut qemu::QmpClient, tag: &str) -> Result<bool> {
+    let chardevs = qmp.execute("query-chardev", None).await?;
+    let id = chardev_id(tag);
+    Ok(chardevs
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|c| c["label"].as_str() == Some(id.as_str())))
+}
+
+/// Container side of `mount add`: run virtiofsd until QEMU disconnects.
+///
+/// As this runs detached, a failure is also written to [`error_path`], so
+/// that `mount-attach` reports it instead of waiting for the socket.
+pub(crate) async fn serve(opts: ServeOpts) -> Result<()> {
+    let error = error_path(&opts.tag);
+    let _ = std::fs::remove_file(&error);
+    let result = serve_share(&opts).await;
+    if let Err(e) = &result {
+        let _ = std::fs::write(&error, format!("{e:#}"));
+    }
+    result
+}
+
+async fn serve_share(opts: &ServeOpts) -> Result<()> {
+    let config: RunEphemeralOpts = serde_json::from_str(&std::env::var("BCK_CONFIG")?)?;
+    {
+        let mut qmp = qemu::QmpClient::connect(QMP_SOCKET_PATH).await?;
+        if is_attached(&mut qmp, &opts.tag).await? {
+            return Err(eyre!("Share {} is already attached", opts.tag));
+        }
+    }
+
+    let pid = std::process::id();
+    std::fs::write(pid_path(&opts.tag), pid.to_string())?;
+    let virtiofs_config = qemu::VirtiofsConfig {
+        socket_path: socket_path(&opts.tag),
+        shared_dir: format!("/proc/{pid}/fd/{}", opts.fd).into(),
+        debug: false,
+        readonly: opts.ro,
+        log_file: Some(format!("/run/virtiofsd-{}.log", opts.tag).into()),
+        virtiofsd_binary: config.common.virtiofsd_binary.as_deref().map(Into::into),
+    };
+    let result = async {
+        let mut virtiofsd = qemu::spawn_virtiofsd_async(&virtiofs_config).await?;
+        let status = virtiofsd.wait().await?;
+        debug!("virtiofsd for {} exited: {status}", opts.tag);
+        if !status.success() {
+            return Err(eyre!(
+                "virtiofsd {status}, see /run/virtiofsd-{}.log in the container",
+                opts.tag
+            ));
+        }
+        Ok::<_, color_eyre::Report>(())
+    }
+    .await;
+    let _ = std::fs::remove_file(pid_path(&opts.tag));
+    let _ = std::fs::remove_file(socket_path(&opts.tag));
+    result
+}
+
+/// Stop the `mount-serve` process of `tag`, e.g. if attaching failed.
+fn stop_serve(tag: &str) {
+    let Ok(pid) = std::fs::read_to_string(pid_path(tag)) else {
+        return;
+    };
+    let Some(pid) = pid
+        .trim()
+        .parse()
+        .ok()
+        .and_then(rustix::process::Pid::from_raw)
+    else {
+        return;
+    };
+    let _ = rustix::process::kill_process(pid, rustix::process::Signal::TERM);
+}
+
+/// Hot-add the vhost-user-fs device, trying each spare root port on PCIe
+/// machines.
+async fn device_add(qmp: &mut qemu::QmpClient, arch: &str, tag: &str) -> Result<()> {
+    let virtio_bus = if arch == "s390x" { "ccw" } else { "pci" };
+    let driver = format!("vhost-user-fs-{virtio_bus}");
+    let mut properties = Map::new();
+    properties.insert("chardev".into(), chardev_id(tag).into());
+    properties.insert("tag".into(), tag.into());
+    properties.insert("queue-size".into(), Value::from(1024));
+
+    if !qemu::MachineType::Auto.is_pcie(arch) {
+        return qmp.device_add(&driver, &device_id(tag), properties).await;
+    }
+    let mut last_err = None;
+    for idx in 0..HOTPLUG_PORTS {
+        let mut properties = properties.clone();
+        let bus = format!("{}{idx}", qemu::HOTPLUG_PORT_PREFIX);
+        properties.insert("bus".into(), bus.clone().into());
+        match qmp.device_add(&driver, &device_id(tag), properties).await {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                debug!("Cannot add device on {bus}: {e}");
+                last_err = Some(e);
+            }
+        }
+    }
+    Err(last_err
+        .unwrap_or_else(|| eyre!("No root ports"))
+        .wrap_err(format!(
+            "No free root port of {HOTPLUG_PORTS} for the share"
+        )))
+}
+
+/// Container side of `mount add`: attach the share served by `mount-serve`.
+pub(crate) async fn attach(opts: HotplugOpts) -> Result<()> {
+    let config: RunEphemeralOpts = serde_json::from_str(&std::env::var("BCK_CONFIG")?)?;
+    let arch = config
+        .common
+        .arch
+        .map(|a| a.qemu_arch())
+        .unwrap_or(qemu::host_arch());
+    let tag = opts.tag.as_str();
+
+    let mut qmp = qemu::QmpClient::connect(QMP_SOCKET_PATH).await?;
+    if is_attached(&mut qmp, tag).await? {
+        return Err(eyre!("A share is already mounted at this guest path"));
+    }
+
+    let socket = socket_path(tag);
+    let deadline = tokio::time::Instant::now() + SOCKET_TIMEOUT;
+    let error = error_path(tag);
+    while !socket.exists() {
+        if let Ok(e) = std::fs::read_to_string(&error) {
+            let _ = std::fs::remove_file(&error);
+            return Err(eyre!("Failed to start virtiofsd: {}", e.trim()));
+        }
+        if tokio::time::Instant::now() >= deadline {
+            stop_serve(tag);
+            return Err(eyre!(
+                "virtiofsd did not start, see /run/virtiofsd-{tag}.log in the container"
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    if let Err(e) = qmp.chardev_add_socket(&chardev_id(tag), &socket).await {
+        stop_serve(tag);
+        return Err(e);
+    }
+    if let Err(e) = device_add(&mut qmp, arch, tag).await {
+        // Closing the connection also stops virtiofsd
+        let _ = qmp.chardev_remove(&chardev_id(tag)).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Container side of `mount rm`: unplug the device and close the connection
+/// to virtiofsd, which makes it exit.
+pub(crate) async fn detach(opts: HotplugOpts) -> Result<()> {
+    let tag = opts.tag.as_str();
+    let id = device_id(tag);
+    let mut qmp = qemu::QmpClient::connect(QMP_SOCKET_PATH).await?;
+    if !is_attached(&mut qmp, tag).await? {
+        return Err(eyre!("No share is mounted at this guest path"));
+    }
+
+    qmp.device_del(&id).await?;
+    // Unplugging needs the guest's cooperation on PCI
+    tokio::time::timeout(UNPLUG_TIMEOUT, async {
+        loop {
+            let event = qmp.next_event().await?;
+            if event.event == "DEVICE_DELETED" && event.data["device"].as_str() == Some(id.as_str())
+            {
+                return Ok::<_, color_eyre::Report>(());
+            }
+        }
+    })
+    .await
+    .map_err(|_| eyre!("The guest did not release device {id} within {UNPLUG_TIMEOUT:?}"))??;
+    qmp.chardev_remove(&chardev_id(tag)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_guest_path() {
+        for path in ["/mnt/data", "/var/lib/my-data", "/data"] {
+            assert!(validate_guest_path(path).is_ok(), "{path}");
+        }
+        for path in [
+            "",
+            "/",
+            "mnt",
+            "/mnt/",
+            "/mnt/../etc",
+            "/mnt/./x",
+            "//mnt",
+            "/a\nb",
+        ] {
+            assert!(validate_guest_path(path).is_err(), "{path:?}");
+        }
+    }
+
+    #[test]
+    fn test_tag() {
+        let t = tag("/mnt/data");
+        assert_eq!(t, tag("/mnt/data"));
+        assert_ne!(t, tag("/mnt/other"));
+        assert!(t.starts_with("bcvk-"));
+        assert_eq!(t.len(), 17);
+    }
+
+    #[test]
+    fn test_guest_scripts() {
+        let script = guest_add_script("bcvk-0123456789ab", "/mnt/my-data", true).unwrap();
+        assert!(script.contains("unit='mnt-my\\x2ddata.mount'\n"));
+        assert!(script.contains("What=bcvk-0123456789ab\n"));
+        assert!(script.contains("Where=/mnt/my-data\n"));
+        assert!(script.contains("Options=ro,"));
+        // The heredoc terminator is on its own line
+        assert!(script.contains("\nEOF\nsystemctl daemon-reload\n"));
+
+        let script = guest_rm_script("/mnt/my-data").unwrap();
+        assert!(script.contains("systemctl stop -- \"$unit\"\n"));
+        assert!(script.contains(&format!("rm -f {GUEST_UNIT_DIR}/\"$unit\"\n")));
+    }
+}
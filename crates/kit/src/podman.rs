@@ -1,6 +1,7 @@
 use std::process::Command;
 
 use bootc_utils::CommandRunExt;
+use camino::Utf8Path;
 use color_eyre::{eyre::eyre, Result};
 use serde::Deserialize;
 
@@ -24,6 +25,29 @@ pub struct ImageInspect {
     pub size: u64,
 }
 
+/// podman, also using the images of the store at `image_store` (the
+/// containers-storage of an image imported from an archive) if given.
+pub(crate) fn command(image_store: Option<&Utf8Path>) -> Command {
+    let mut cmd = Command::new("podman");
+    if let Some(image_store) = image_store {
+        let current = std::env::var("STORAGE_OPTS").ok();
+        cmd.env(
+            "STORAGE_OPTS",
+            storage_opts(current.as_deref(), image_store),
+        );
+    }
+    cmd
+}
+
+/// `STORAGE_OPTS` with the additional image store `image_store` added to `current`
+fn storage_opts(current: Option<&str>, image_store: &Utf8Path) -> String {
+    let opt = format!("additionalimagestore={image_store}");
+    match current {
+        Some(v) if !v.is_empty() => format!("{v},{opt}"),
+        _ => opt,
+    }
+}
+
 pub fn get_system_info() -> Result<PodmanSystemInfo> {
     Command::new("podman")
         .arg("system")
@@ -33,9 +57,10 @@ pub fn get_system_info() -> Result<PodmanSystemInfo> {
         .map_err(|e| eyre!("podman system info failed: {}", e))
 }
 
-/// Get the size of a container image in bytes
-pub fn get_image_size(image: &str) -> Result<u64> {
-    let inspect_result: Vec<ImageInspect> = Command::new("podman")
+/// Get the size of a container image in bytes, optionally from an additional
+/// image store (see [`command`])
+pub fn get_image_size(image: &str, image_store: Option<&Utf8Path>) -> Result<u64> {
+    let inspect_result: Vec<ImageInspect> = command(image_store)
         .arg("inspect")
         .arg("--format=json")
         .arg("--type=image")
@@ -98,13 +98,13 @@ use cap_std_ext::cmdext::CapStdExtCommandExt as _;
 use clap::Parser;
 use color_eyre::eyre::{eyre, Context};
 use color_eyre::Result;
-use rustix::fd::FromRawFd as _;
 use rustix::path::Arg;
 use serde::{Deserialize, Serialize};
 use tokio::io::AsyncReadExt;
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
 
-const ENTRYPOINT: &str = "/var/lib/bcvk/entrypoint";
+/// Path of the entrypoint script inside the container
+pub(crate) const ENTRYPOINT: &str = "/var/lib/bcvk/entrypoint";
 
 /// Get default vCPU count (number of available processors, or 2 as fallback)
 pub fn default_vcpus() -> u32 {
@@ -113,12 +113,16 @@ pub fn default_vcpus() -> u32 {
         .unwrap_or(2)
 }
 
+use crate::libvirt::run::PortMapping;
 use crate::qemu::{self, QemuConfigExt};
 use crate::{
-    boot_progress,
+    arch::Arch,
+    boot_failure, boot_profile, boot_progress,
     common_opts::MemoryOpts,
-    podman,
-    supervisor_status::{StatusWriter, SupervisorState, SupervisorStatus},
+    journal_export, podman,
+    supervisor_status::{
+        GuestFailure, StatusWriter, SupervisorState, SupervisorStatus, SUPERVISOR_STATUS_PATH,
+    },
     systemd, utils, CONTAINER_STATEDIR,
 };
 
@@ -131,20 +135,60 @@ const IGNITION_SERIAL_NAME: &str = "ignition";
 /// Mount path for Ignition config inside the container
 const IGNITION_CONFIG_MOUNT_PATH: &str = "/run/ignition-config.json";
 
+/// Where the `--cloud-init-user-data` and `--cloud-init-meta-data` files are
+/// mounted inside the container
+const CLOUD_INIT_MOUNT_PATH: &str = "/run/cloud-init";
+
+/// Path of the generated cloud-init NoCloud seed disk
+const CLOUD_INIT_SEED_PATH: &str = "/run/qemu/cidata.img";
+
+/// Well-known path of the QMP control socket inside the container, for use
+/// by other bcvk commands via `podman exec`
+pub(crate) const QMP_SOCKET_PATH: &str = "/run/qmp.sock";
+
+/// Where the pristine image is mounted inside the container
+pub(crate) const SOURCE_IMAGE_PATH: &str = "/run/source-image";
+
+/// QMP socket the supervisor watches for guest events (e.g. panics)
+const QMP_EVENTS_SOCKET_PATH: &str = "/run/qmp-events.sock";
+
+/// Path of the swtpm control socket inside the container
+const SWTPM_SOCKET_PATH: &str = "/run/swtpm.sock";
+
+/// Where `--tpm-state` is mounted inside the container
+const TPM_STATE_MOUNT: &str = "/run/tpm-state";
+
+/// Where the user's credentials are written for QEMU inside the container
+pub(crate) const QEMU_CREDENTIALS_DIR: &str = "/run/qemu/credentials";
+
+/// Well-known path inside the container holding the guest's vsock CID, written
+/// once QEMU is started with vsock enabled
+pub(crate) const GUEST_CID_PATH: &str = "/run/guest-cid";
+
 // ---------------------------------------------------------------------------
 // Journal / output mode types
 // ---------------------------------------------------------------------------
 
 /// Parsed value of `--log-dir=STREAMS=PATH`.
 ///
-/// `STREAMS` is a comma-separated subset of `journal` and `console`; `PATH` is
-/// a directory to write log files into.  Files written:
+/// `STREAMS` is a comma-separated subset of `journal`, `journal-export`,
+/// `journal-file` and `console`; `PATH` is a directory to write log files
+/// into.  Files written:
 /// - `journal.json` when the `journal` stream is requested
+/// - `journal.export` when the `journal-export` stream is requested
+/// - `journal.journal` when the `journal-file` stream is requested
 /// - `console.txt`  when the `console` stream is requested
+///
+/// The journal streams also write the same for the initrd journal, e.g.
+/// `journal-initrd.json`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogDir {
     pub path: std::path::PathBuf,
     pub journal: bool,
+    /// Journal Export Format, see [`crate::journal_export`]
+    pub journal_export: bool,
+    /// Journal files written by `systemd-journal-remote`
+    pub journal_file: bool,
     pub console: bool,
 }
 
@@ -159,6 +203,28 @@ impl LogDir {
         self.journal.then(|| self.path.join("journal-initrd.json"))
     }
 
+    /// Whether the journal is converted to export format, for either of the
+    /// `journal-export` and `journal-file` streams.
+    pub fn wants_journal_export(&self) -> bool {
+        self.journal_export || self.journal_file
+    }
+
+    /// Returns the paths for the real-root and initrd journal in export format
+    /// (`journal.export`) or as journal files (`journal.journal`), if requested.
+    pub fn journal_export_paths(&self) -> Option<[std::path::PathBuf; 2]> {
+        let ext = if self.journal_file {
+            "journal"
+        } else if self.journal_export {
+            "export"
+        } else {
+            return None;
+        };
+        Some([
+            self.path.join(format!("journal.{ext}")),
+            self.path.join(format!("journal-initrd.{ext}")),
+        ])
+    }
+
     /// Returns the path for `console.txt` if the console stream was requested.
     pub fn console_path(&self) -> Option<std::path::PathBuf> {
         self.console.then(|| self.path.join("console.txt"))
@@ -186,28 +252,39 @@ impl std::str::FromStr for LogDir {
         let path = std::path::PathBuf::from(path_str);
 
         let mut journal = false;
+        let mut journal_export = false;
+        let mut journal_file = false;
         let mut console = false;
         for part in streams_str.split(',') {
             match part.trim() {
                 "journal" => journal = true,
+                "journal-export" => journal_export = true,
+                "journal-file" => journal_file = true,
                 "console" => console = true,
                 "" => {}
                 other => {
                     return Err(color_eyre::eyre::eyre!(
-                        "--log-dir unknown stream name {other:?}; expected `journal` or `console`"
+                        "--log-dir unknown stream name {other:?}; expected `journal`, `journal-export`, `journal-file` or `console`"
                     ))
                 }
             }
         }
-        if !journal && !console {
+        if !journal && !journal_export && !journal_file && !console {
             return Err(color_eyre::eyre::eyre!(
-                "--log-dir STREAMS must contain at least one of `journal`, `console`; got: {streams_str:?}"
+                "--log-dir STREAMS must contain at least one of `journal`, `journal-export`, `journal-file`, `console`; got: {streams_str:?}"
+            ));
+        }
+        if journal_export && journal_file {
+            return Err(color_eyre::eyre::eyre!(
+                "--log-dir streams `journal-export` and `journal-file` cannot be combined"
             ));
         }
 
         Ok(LogDir {
             path,
             journal,
+            journal_export,
+            journal_file,
             console,
         })
     }
@@ -251,6 +328,36 @@ mod logdir_tests {
         assert_eq!(ld.path, std::path::PathBuf::from("/tmp/run-001/"));
     }
 
+    #[test]
+    fn test_log_dir_journal_export() {
+        let ld = LogDir::from_str("journal-export,console=/tmp/logs").unwrap();
+        assert!(!ld.journal);
+        assert!(ld.wants_journal_export());
+        assert_eq!(ld.journal_path(), None);
+        assert_eq!(
+            ld.journal_export_paths(),
+            Some([
+                std::path::PathBuf::from("/tmp/logs/journal.export"),
+                std::path::PathBuf::from("/tmp/logs/journal-initrd.export")
+            ])
+        );
+
+        let ld = LogDir::from_str("journal,journal-file=/tmp/logs").unwrap();
+        assert!(ld.journal && ld.journal_file);
+        assert_eq!(
+            ld.journal_export_paths().unwrap()[0],
+            std::path::PathBuf::from("/tmp/logs/journal.journal")
+        );
+
+        assert!(LogDir::from_str("journal-export,journal-file=/tmp/logs").is_err());
+        assert_eq!(
+            LogDir::from_str("journal=/tmp/logs")
+                .unwrap()
+                .journal_export_paths(),
+            None
+        );
+    }
+
     #[test]
     fn test_log_dir_path_no_trailing_slash() {
         let ld = LogDir::from_str("journal=/var/tmp/run-001").unwrap();
@@ -298,6 +405,75 @@ pub enum OutputMode {
     Journal,
 }
 
+/// How the ephemeral VM is booted.
+///
+/// - `Direct` (default): QEMU loads the kernel and initramfs directly; for
+///   UKIs these are extracted from the PE sections, and bcvk appends its own
+///   units to the initramfs.
+/// - `Uefi`: boot the image's unmodified UKI via OVMF and systemd-boot from a
+///   throwaway ESP, exercising the same boot path as an installed system.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum BootKind {
+    #[default]
+    Direct,
+    Uefi,
+}
+
+/// Which CPU accelerator to use for the VM.
+///
+/// - `Auto` (default): use KVM if `/dev/kvm` is accessible, otherwise fall
+///   back to software emulation with a warning.
+/// - `Kvm`: require KVM.
+/// - `Tcg`: always use software emulation (multi-threaded TCG). This works in
+///   nested containers and on CI runners without nested virtualization, but
+///   is much slower.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum AccelMode {
+    #[default]
+    Auto,
+    Kvm,
+    Tcg,
+}
+
+impl AccelMode {
+    /// Resolve to the accelerator to use, given whether KVM is accessible.
+    pub(crate) fn resolve(self, kvm_available: bool) -> Result<qemu::Accel> {
+        match self {
+            AccelMode::Auto if kvm_available => Ok(qemu::Accel::Kvm),
+            AccelMode::Auto => {
+                warn!(
+                    "{} is not accessible; falling back to software emulation (TCG), which is much slower",
+                    qemu::DEV_KVM
+                );
+                Ok(qemu::Accel::Tcg)
+            }
+            AccelMode::Kvm if kvm_available => Ok(qemu::Accel::Kvm),
+            AccelMode::Kvm => Err(eyre!(
+                "KVM device not accessible; use --accel=tcg for software emulation"
+            )),
+            AccelMode::Tcg => Ok(qemu::Accel::Tcg),
+        }
+    }
+}
+
+impl From<qemu::Accel> for AccelMode {
+    fn from(accel: qemu::Accel) -> Self {
+        match accel {
+            qemu::Accel::Kvm => AccelMode::Kvm,
+            qemu::Accel::Tcg => AccelMode::Tcg,
+        }
+    }
+}
+
+/// Container label set on ephemeral VMs running under software emulation, so
+/// that readiness timeouts can be scaled by later commands.
+pub(crate) const TCG_LABEL: &str = "bcvk.accel=tcg";
+
+/// Factor by which boot and SSH readiness timeouts are scaled under TCG.
+pub(crate) const TCG_TIMEOUT_SCALE: u32 = 5;
+
 /// The guest-side systemd unit that streams the journal as JSON over virtio-serial.
 /// Always uses JSON format; the host converts to plain text for stdout as needed.
 pub(crate) const JOURNAL_STREAM_UNIT: &str = include_str!("units/bcvk-journal-stream.service");
@@ -388,6 +564,9 @@ pub struct CommonPodmanOptions {
         help = "Set environment variables in the container (key=value)"
     )]
     pub env: Vec<String>,
+
+    #[clap(flatten)]
+    pub pull: crate::images::PullOpts,
 }
 
 /// Common VM configuration options for hardware, networking, and features.
@@ -405,6 +584,14 @@ pub struct CommonVmOpts {
     #[clap(long, help = "Number of vCPUs (overridden by --itype if specified)")]
     pub vcpus: Option<u32>,
 
+    /// CPU accelerator: `kvm`, `tcg` (software emulation), or `auto` to fall back to TCG without KVM
+    #[clap(long, value_enum, default_value = "auto")]
+    pub accel: AccelMode,
+
+    /// Guest architecture (default: the host's); other architectures are emulated with TCG
+    #[clap(long, value_enum)]
+    pub arch: Option<Arch>,
+
     #[clap(
         long,
         help = "Connect the QEMU console to the container's stdio (visible via podman logs/attach)"
@@ -455,14 +642,57 @@ pub struct CommonVmOpts {
 
     /// Write VM log streams to files in DIR.
     ///
-    /// STREAMS is a comma-separated list of: `journal`, `console`
+    /// STREAMS is a comma-separated list of: `journal`, `journal-export`, `journal-file`, `console`
     /// - `journal` → `journal.json` (systemd journal as JSON)
+    /// - `journal-export` → `journal.export` (Journal Export Format)
+    /// - `journal-file` → `journal.journal` (for `journalctl --file`, needs systemd-journal-remote)
     /// - `console` → `console.txt` (VM serial console output)
     ///
     /// Examples: `--log-dir=journal,console=/tmp/run-001/`
     ///           `--log-dir=journal=/tmp/logs/`
     #[clap(long, value_name = "STREAMS=DIR")]
     pub log_dir: Option<LogDir>,
+
+    /// Pin QEMU to CPUS: a mask (e.g. 0xF) or a list (e.g. 0-3,8)
+    #[clap(long, value_name = "CPUS")]
+    pub cpu_affinity: Option<String>,
+
+    /// Best-effort I/O priority of QEMU (0=highest, 7=lowest)
+    #[clap(long, value_parser = clap::value_parser!(u8).range(0..=7))]
+    pub io_priority: Option<u8>,
+
+    /// Nice level of QEMU (-20=highest, 19=lowest)
+    #[clap(long, allow_negative_numbers = true, value_parser = clap::value_parser!(i8).range(-20..=19))]
+    pub nice: Option<i8>,
+
+    /// Limit the VM container to the CPU time of this many CPUs (cgroup cpu.max, e.g. 1.5)
+    #[clap(long, value_name = "CPUS")]
+    pub cpu_quota: Option<f64>,
+
+    /// Relative I/O weight of the VM container (cgroup io.weight, 10-1000)
+    #[clap(long, value_parser = clap::value_parser!(u16).range(10..=1000))]
+    pub io_weight: Option<u16>,
+
+    /// Hard memory limit of the VM container, including QEMU's overhead (cgroup memory.max, e.g. 6G)
+    #[clap(long, value_name = "SIZE")]
+    pub memory_max: Option<String>,
+
+    /// Attach a software TPM 2.0 emulated by swtpm (must be installed on the host)
+    #[clap(long)]
+    pub tpm: bool,
+
+    /// Keep the TPM state in this host directory, e.g. to reuse it across VMs (default: discarded with the VM)
+    #[clap(long, value_name = "DIR", requires = "tpm")]
+    pub tpm_state: Option<Utf8PathBuf>,
+
+    #[clap(flatten)]
+    pub credentials: crate::credentials::CredentialOpts,
+
+    #[clap(flatten)]
+    pub dump: crate::crash_dump::DumpOpts,
+
+    #[clap(flatten)]
+    pub liveness: crate::watchdog::WatchdogOpts,
 }
 
 impl CommonVmOpts {
@@ -483,6 +713,97 @@ impl CommonVmOpts {
             Ok(self.vcpus.unwrap_or_else(default_vcpus))
         }
     }
+
+    /// The guest architecture, or `None` for an unsupported host without `--arch`
+    pub(crate) fn guest_arch(&self) -> Option<Arch> {
+        self.arch.or_else(Arch::host)
+    }
+
+    /// Limits applied to the QEMU process inside the container
+    pub(crate) fn resource_limits(&self) -> qemu::ResourceLimits {
+        qemu::ResourceLimits {
+            cpu_affinity: self.cpu_affinity.clone(),
+            io_priority: self.io_priority,
+            nice_level: self.nice,
+        }
+    }
+
+    /// podman arguments applying the cgroup limits to the container hosting QEMU
+    pub(crate) fn podman_cgroup_args(&self) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        if let Some(cpus) = self.cpu_quota {
+            if !(cpus.is_finite() && cpus > 0.0) {
+                return Err(eyre!("Invalid --cpu-quota {cpus}: must be positive"));
+            }
+            args.push(format!("--cpus={cpus}"));
+        }
+        if let Some(weight) = self.io_weight {
+            args.push(format!("--blkio-weight={weight}"));
+        }
+        if let Some(memory_max) = self.memory_max.as_deref() {
+            let max_mb = crate::utils::parse_memory_to_mb(memory_max)?;
+            let memory_mb = self.memory_mb()?;
+            // QEMU would be OOM killed as soon as the guest uses its memory
+            if max_mb <= memory_mb {
+                return Err(eyre!(
+                    "--memory-max={memory_max} must be larger than the VM memory ({memory_mb}M)"
+                ));
+            }
+            args.push(format!("--memory={max_mb}m"));
+        }
+        Ok(args)
+    }
+
+    /// The swtpm configuration for `--tpm`, with paths inside the container
+    pub(crate) fn tpm_config(&self) -> Option<qemu::SwtpmConfig> {
+        // Without --tpm-state the state is in the container's /run tmpfs
+        let state_dir = if self.tpm_state.is_some() {
+            TPM_STATE_MOUNT
+        } else {
+            "/run/swtpm-state"
+        };
+        self.tpm.then(|| qemu::SwtpmConfig {
+            socket_path: SWTPM_SOCKET_PATH.into(),
+            state_dir: state_dir.into(),
+            log_file: Some("/run/swtpm.log".into()),
+            swtpm_binary: None,
+        })
+    }
+
+    /// podman arguments sharing the `--tpm-state` directory with the container
+    pub(crate) fn podman_tpm_args(&self) -> Result<Vec<String>> {
+        if self.tpm && self.guest_arch() == Some(Arch::S390x) {
+            return Err(eyre!("--tpm is not supported for s390x guests"));
+        }
+        let Some(dir) = self.tpm_state.as_deref() else {
+            return Ok(Vec::new());
+        };
+        std::fs::create_dir_all(dir).with_context(|| format!("Creating {dir}"))?;
+        let dir = dir.canonicalize_utf8()?;
+        Ok(vec!["-v".into(), format!("{dir}:{TPM_STATE_MOUNT}")])
+    }
+
+    /// podman arguments sharing the `--dump-on-panic` directory with the container
+    pub(crate) fn podman_dump_args(&self) -> Result<Vec<String>> {
+        let Some(dir) = self.dump.prepare_dir()? else {
+            return Ok(Vec::new());
+        };
+        Ok(vec![
+            "-v".into(),
+            format!("{dir}:{}:Z", crate::crash_dump::CONTAINER_DUMP_DIR),
+        ])
+    }
+
+    /// Resolve the accelerator; a foreign `--arch` can only be emulated.
+    pub(crate) fn resolve_accel(&self) -> Result<qemu::Accel> {
+        match self.arch.filter(|a| a.is_foreign()) {
+            Some(arch) if self.accel == AccelMode::Kvm => Err(eyre!(
+                "--accel=kvm cannot run a {arch} guest on this host; use --accel=tcg"
+            )),
+            Some(_) => Ok(qemu::Accel::Tcg),
+            None => self.accel.resolve(qemu::kvm_available()),
+        }
+    }
 }
 
 /// Ephemeral VM options: container-style flags, host bind mounts, systemd injection.
@@ -538,20 +859,102 @@ pub struct RunEphemeralOpts {
     )]
     pub mount_disk_files: Vec<String>,
 
+    /// Publish a guest port on the host (format: host_port:guest_port, e.g., 8080:80)
+    #[clap(long = "port", short = 'p', action = clap::ArgAction::Append)]
+    pub port_mappings: Vec<PortMapping>,
+
+    /// Join a private network shared by all VMs started with the same NAME;
+    /// VMs resolve each other by their --name
+    #[clap(
+        long,
+        value_name = "NAME",
+        requires = "name",
+        conflicts_with = "network"
+    )]
+    pub internal_network: Option<String>,
+
+    /// Another name the VM resolves as on its --internal-network (may be repeated)
+    #[clap(
+        long = "network-alias",
+        value_name = "NAME",
+        requires = "internal_network"
+    )]
+    pub network_aliases: Vec<String>,
+
     #[clap(long = "karg", help = "Additional kernel command line arguments")]
     pub kernel_args: Vec<String>,
 
+    /// Boot path: `direct` kernel boot, or `uefi` to boot the image's UKI via OVMF and systemd-boot
+    #[clap(long, value_enum, default_value = "direct")]
+    pub boot: BootKind,
+
     #[clap(
         long = "ignition",
         help = "Path to Ignition config file (JSON format) to inject via fw_cfg"
     )]
     pub ignition_config: Option<String>,
 
+    #[clap(flatten)]
+    pub cloud_init: crate::cloud_init::CloudInitOpts,
+
+    /// Write a boot-time profile to FILE once SSH is ready (JSON, or HTML if FILE ends in .html);
+    /// `ephemeral run` requires --detach and --ssh-keygen
+    #[clap(long, value_name = "FILE")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boot_profile: Option<Utf8PathBuf>,
+
     /// Host DNS servers (read on host, configured via podman --dns flags)
     /// Not a CLI option - populated automatically from host's /etc/resolv.conf
     #[clap(skip)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub host_dns_servers: Option<Vec<String>>,
+
+    /// Installer ISO to boot instead of the image, see `to-disk --via-iso`
+    /// Not a CLI option - the image only provides the container environment
+    #[clap(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub install_iso: Option<Utf8PathBuf>,
+
+    /// Additional image store holding the image, see [`crate::image_archive`]
+    /// Not a CLI option - set for images imported from archives
+    #[clap(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_store: Option<Utf8PathBuf>,
+}
+
+/// Validate `--port` mappings before launching the container.
+///
+/// QEMU listens on the guest port inside the container, so guest ports must
+/// not collide with the SSH forward, and a host port can only be published once.
+/// Publishing also needs the container to have its own network namespace, which
+/// is not the case with `--network` `host` or `none`.
+fn validate_port_mappings(mappings: &[PortMapping], network: Option<&str>) -> Result<()> {
+    if let Some(network @ ("host" | "none")) = network {
+        if !mappings.is_empty() {
+            return Err(eyre!("--port cannot be used with --network={network}"));
+        }
+    }
+    let mut host_ports = std::collections::HashSet::new();
+    for mapping in mappings {
+        if mapping.host_port == 0 || mapping.guest_port == 0 {
+            return Err(eyre!(
+                "Invalid port mapping '{mapping}': ports must be non-zero"
+            ));
+        }
+        // Used by enable_ssh_access() inside the container
+        if mapping.guest_port == 2222 {
+            return Err(eyre!(
+                "Invalid port mapping '{mapping}': guest port 2222 is reserved for SSH"
+            ));
+        }
+        if !host_ports.insert(mapping.host_port) {
+            return Err(eyre!(
+                "Host port {} is mapped more than once",
+                mapping.host_port
+            ));
+        }
+    }
+    Ok(())
 }
 
 /// Parse DNS servers from resolv.conf format content
@@ -630,7 +1033,8 @@ fn read_host_dns_servers() -> Option<Vec<String>> {
 /// Launch privileged container with QEMU+KVM for ephemeral VM, spawning as subprocess.
 /// Returns the container ID instead of executing the command.
 pub fn run_detached(opts: RunEphemeralOpts) -> Result<String> {
-    let (mut cmd, temp_dir, _journal_fds) = prepare_run_command_with_temp(opts)?;
+    // systemd-journal-remote outlives us with the container, which holds its pipe
+    let (mut cmd, temp_dir, _journal_fds, _journal_remotes) = prepare_run_command_with_temp(opts)?;
 
     // Leak the tempdir to keep it alive for the entire container lifetime.
     std::mem::forget(temp_dir);
@@ -649,6 +1053,10 @@ pub fn run_detached(opts: RunEphemeralOpts) -> Result<String> {
 
 /// Launch privileged container with QEMU+KVM for ephemeral VM.
 pub fn run(opts: RunEphemeralOpts) -> Result<()> {
+    if let Some(path) = opts.boot_profile.clone() {
+        return run_profiled(opts, &path);
+    }
+
     // Print helpful hint when running in foreground mode without console
     if !opts.podman.detach && !opts.common.console && std::io::stderr().is_terminal() {
         if let Some(name) = &opts.podman.name {
@@ -661,30 +1069,108 @@ pub fn run(opts: RunEphemeralOpts) -> Result<()> {
         }
     }
 
-    let (mut cmd, _temp_dir, _journal_fds) = prepare_run_command_with_temp(opts)?;
+    let (mut cmd, temp_dir, journal_fds, journal_remotes) = prepare_run_command_with_temp(opts)?;
+
+    if journal_remotes.is_empty() {
+        // Keep temp_dir and journal_fds alive until exec replaces our process.
+        // The journal fds (if any) are inherited across execve and reach podman
+        // via --preserve-fd; podman in turn passes them into the container.
+        return Err(cmd.exec()).context("execve");
+    }
+
+    // systemd-journal-remote only finishes writing the journal files once its
+    // pipes are closed, so run podman as a child and wait for it afterwards.
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Init tokio runtime")?;
+    let status = rt.block_on(async {
+        // Ctrl-C also reaches podman, which stops the VM; handling it here
+        // keeps us around until the journal files are written
+        let _sigint = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())?;
+        tokio::process::Command::from(cmd).status().await
+    });
+    drop(journal_fds);
+    drop(temp_dir);
+    for child in journal_remotes {
+        crate::journal_export::wait_journal_remote(child)?;
+    }
+    let status = status.context("Running podman")?;
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// Launch a detached VM and write its boot profile once SSH is ready.
+fn run_profiled(opts: RunEphemeralOpts, path: &Utf8Path) -> Result<()> {
+    if !opts.podman.detach || !opts.common.ssh_keygen {
+        return Err(eyre!("--boot-profile requires --detach and --ssh-keygen"));
+    }
+    boot_profile::validate_path(path)?;
+    let mut profile = boot_profile::Recorder::new(&opts.image);
+    profile.mark("podman-start");
+
+    let container_id = run_detached(opts)?;
+    println!("{container_id}");
 
-    // Keep _temp_dir and _journal_fds alive until exec replaces our process.
-    // The journal fds (if any) are inherited across execve and reach podman
-    // via --preserve-fd; podman in turn passes them into the container.
-    return Err(cmd.exec()).context("execve");
+    let progress_bar = crate::boot_progress::create_boot_progress_bar();
+    let (_duration, progress_bar) =
+        crate::run_ephemeral_ssh::wait_for_ssh_ready(&container_id, None, progress_bar)?;
+    progress_bar.finish_and_clear();
+    profile.mark("ssh-ready");
+    if let Err(e) = crate::run_ephemeral_ssh::write_boot_profile(profile, &container_id, path) {
+        warn!("Failed to write boot profile: {e:#}");
+    }
+    Ok(())
 }
 
-/// Returns `(cmd, tempdir, journal_fds)` where `journal_fds` holds open file
-/// descriptors for `journal.json` and `journal-initrd.json` (when
-/// `--log-dir=journal=…` was requested).  The caller must keep them alive until
-/// podman exits so the fds are not closed prematurely.
+/// Returns `(cmd, tempdir, journal_fds, journal_remotes)` where `journal_fds`
+/// holds open file descriptors for `journal.json` and `journal-initrd.json`
+/// (when `--log-dir=journal=…` was requested), and the lock of the store of an
+/// imported image.  The caller must keep them alive until podman exits so the
+/// fds are not closed prematurely.  `journal_remotes` are the
+/// `systemd-journal-remote` processes of `--log-dir journal-file=…`, which
+/// finish writing once `journal_fds` and the container are gone.
 fn prepare_run_command_with_temp(
-    opts: RunEphemeralOpts,
+    mut opts: RunEphemeralOpts,
 ) -> Result<(
     std::process::Command,
     tempfile::TempDir,
     Vec<std::sync::Arc<rustix::fd::OwnedFd>>,
+    Vec<std::process::Child>,
 )> {
     debug!("Running QEMU inside hybrid container for {}", opts.image);
 
+    validate_port_mappings(&opts.port_mappings, opts.podman.network.as_deref())?;
+
+    // Resolve the accelerator on the host, as it determines whether /dev/kvm
+    // is passed into the container
+    let accel = opts.common.resolve_accel()?;
+    opts.common.accel = accel.into();
+
+    // For a foreign architecture, make sure we boot (and mount) the matching
+    // variant of the image
+    let foreign_arch = opts.common.arch.filter(|a| a.is_foreign());
+    // Images from archives and OCI layouts are imported into a temporary
+    // store, as the image has to be in containers-storage
+    let imported = if crate::image_archive::is_archive_ref(&opts.image) {
+        let imported = crate::image_archive::ImportedImage::import(&opts.image)?;
+        opts.image = imported.name.clone();
+        opts.image_store = Some(imported.graph_root());
+        Some(imported)
+    } else if opts.image_store.is_some() {
+        // Imported by the caller, such as to-disk
+        None
+    } else {
+        if let Some(arch) = foreign_arch {
+            opts.image = ensure_image_arch(&opts.image, arch, opts.podman.pull.policy)?;
+        } else {
+            crate::images::pull(&opts.image, opts.podman.pull.policy)?;
+        }
+        None
+    };
+
     // Check Ignition support early (before launching container) if --ignition is specified
     if opts.ignition_config.is_some() {
-        let has_ignition = check_ignition_support(&opts.image)?;
+        let has_ignition = check_ignition_support(&opts.image, opts.image_store.as_deref())?;
         if !has_ignition {
             return Err(eyre!(
                 "Image does not support Ignition. See man bcvk-ephemeral-run for details."
@@ -693,6 +1179,15 @@ fn prepare_run_command_with_temp(
         debug!("Image {} supports Ignition", opts.image);
     }
 
+    let cloud_init_data = opts.cloud_init.resolve()?;
+    if cloud_init_data.is_some()
+        && !crate::cloud_init::check_cloud_init_support(&opts.image, opts.image_store.as_deref())?
+    {
+        return Err(eyre!(
+            "Image does not have cloud-init. See man bcvk-ephemeral-run for details."
+        ));
+    }
+
     let script = include_str!("../scripts/entrypoint.sh");
 
     let td = tempfile::tempdir()?;
@@ -713,7 +1208,11 @@ fn prepare_run_command_with_temp(
     let self_exe = self_exe.as_str()?;
 
     // Process disk files and create them if needed
-    let processed_disk_files = process_disk_files(&opts.mount_disk_files, &opts.image)?;
+    let processed_disk_files = process_disk_files(
+        &opts.mount_disk_files,
+        &opts.image,
+        opts.image_store.as_deref(),
+    )?;
 
     // Parse mount arguments (both bind and ro-bind)
     let mut host_mounts = Vec::new();
@@ -764,14 +1263,44 @@ fn prepare_run_command_with_temp(
         host_mounts.push((host_path, mount_name, true)); // true = read-only
     }
 
+    // Attach the container to the shared podman network, and share the hosts
+    // file with the guest
+    if let Some(network) = opts.internal_network.as_deref() {
+        let vm = opts
+            .podman
+            .name
+            .as_deref()
+            .ok_or_else(|| eyre!("--internal-network requires --name"))?;
+        let dir = crate::internal_network::join(network, vm, &opts.network_aliases)?;
+        opts.podman.label.push(format!(
+            "{}={network}",
+            crate::internal_network::NETWORK_LABEL
+        ));
+        host_mounts.push((
+            dir.to_string(),
+            crate::internal_network::HOSTS_MOUNT_NAME.to_string(),
+            true,
+        ));
+        opts.podman.network = Some(crate::internal_network::podman_network(network));
+    }
+
     // Run the container with the setup script
-    let mut cmd = Command::new("podman");
+    let mut cmd = crate::podman::command(opts.image_store.as_deref());
     cmd.arg("run");
     // We don't do pulling because then we'd have to propagate all the authfile
     // and status output for that in the general case.
     cmd.arg("--pull=never");
+    if let Some(arch) = foreign_arch {
+        cmd.arg(format!("--platform=linux/{}", arch.oci_arch()));
+    }
     // We always have a label
     cmd.arg("--label=bcvk.ephemeral=1");
+    if let Some(imported) = imported.as_ref() {
+        cmd.arg(format!("--label={}", imported.label()));
+    }
+    if accel == qemu::Accel::Tcg {
+        cmd.arg(format!("--label={TCG_LABEL}"));
+    }
     for label in opts.podman.label.iter() {
         cmd.arg(format!("--label={label}"));
     }
@@ -806,6 +1335,25 @@ fn prepare_run_command_with_temp(
     for env in opts.podman.env.iter() {
         cmd.arg(format!("--env={env}"));
     }
+    // QEMU forwards the guest port on the container side, podman publishes it
+    for mapping in opts.port_mappings.iter() {
+        cmd.arg(format!(
+            "--publish={}:{}",
+            mapping.host_port, mapping.guest_port
+        ));
+    }
+
+    // Fail early rather than when QEMU is spawned inside the container
+    opts.common.resource_limits().validate()?;
+    cmd.args(opts.common.podman_cgroup_args()?);
+    cmd.args(opts.common.podman_tpm_args()?);
+    opts.common.liveness.validate(opts.common.guest_arch())?;
+    cmd.args(opts.common.podman_dump_args()?);
+    cmd.args(
+        opts.common
+            .credentials
+            .podman_args(opts.common.guest_arch())?,
+    );
 
     let vhost_dev = Utf8Path::new(qemu::VHOST_VSOCK)
         .try_exists()?
@@ -831,8 +1379,10 @@ fn prepare_run_command_with_temp(
         // Ensure we can create large files on the host and not in the overlay
         "-v",
         "/var/tmp:/var/tmp",
-        "--device=/dev/kvm",
     ]);
+    if accel == qemu::Accel::Kvm {
+        cmd.arg(format!("--device={}", qemu::DEV_KVM));
+    }
     cmd.args(vhost_dev);
     cmd.args([
         "-v",
@@ -905,6 +1455,28 @@ fn prepare_run_command_with_temp(
         ]);
     }
 
+    // Mount the cloud-init data; the seed disk is created in the container
+    if let Some((user_data, meta_data)) = cloud_init_data {
+        cmd.args([
+            "-v",
+            &format!("{user_data}:{CLOUD_INIT_MOUNT_PATH}/user-data:ro"),
+        ]);
+        if let Some(meta_data) = meta_data {
+            cmd.args([
+                "-v",
+                &format!("{meta_data}:{CLOUD_INIT_MOUNT_PATH}/meta-data:ro"),
+            ]);
+        }
+    }
+
+    // Mount the installer ISO if specified
+    if let Some(ref iso) = opts.install_iso {
+        cmd.args([
+            "-v",
+            &format!("{}:{}:ro", iso, crate::iso_install::INSTALL_ISO_PATH),
+        ]);
+    }
+
     // Read host DNS servers and configure them via podman --dns flags
     // This fixes DNS resolution issues when QEMU runs inside containers.
     // QEMU's slirp reads /etc/resolv.conf from the container's network namespace,
@@ -931,42 +1503,61 @@ fn prepare_run_command_with_temp(
     // Open journal log files (from --log-dir) on the host and pass their fds into
     // the container via --preserve-fd / BCVK_JOURNAL_FDS=fd1,fd2 where fd1 is
     // journal.json (real-root) and fd2 is journal-initrd.json (initrd).
+    // Likewise BCVK_JOURNAL_EXPORT_FDS=fd1,fd2 receive the journal in export
+    // format, either into files or into pipes to systemd-journal-remote.
     // We use cap-std-ext's CmdFds / take_fds to handle fd duplication and
     // O_CLOEXEC clearing in a pre_exec hook; flatpak-spawn --forward-fd (in our
     // podman wrapper) ensures the fds also reach the real host podman from inside
     // a toolbox.
     let mut journal_fds: Vec<std::sync::Arc<rustix::fd::OwnedFd>> = Vec::new();
-    if opts.common.log_dir.as_ref().map_or(false, |d| d.journal) {
-        let mut fds = cap_std_ext::cmdext::CmdFds::new();
-        let mut fd_nums = Vec::new();
-        for dest in [
-            opts.common.log_dir.as_ref().and_then(|d| d.journal_path()),
-            opts.common
-                .log_dir
-                .as_ref()
-                .and_then(|d| d.journal_initrd_path()),
-        ]
-        .into_iter()
-        .flatten()
-        {
-            use std::os::unix::io::IntoRawFd as _;
+    let mut journal_remotes = Vec::new();
+    if let Some(log_dir) = opts.common.log_dir.as_ref() {
+        let open_log = |dest: &std::path::Path| -> Result<rustix::fd::OwnedFd> {
             let f = std::fs::OpenOptions::new()
                 .write(true)
                 .create(true)
                 .truncate(true)
-                .open(&dest)
+                .open(dest)
                 .with_context(|| format!("Opening journal log file {dest:?}"))?;
-            // SAFETY: we just opened this file and are transferring ownership into OwnedFd.
-            #[allow(unsafe_code)]
-            let owned = unsafe { rustix::fd::OwnedFd::from_raw_fd(f.into_raw_fd()) };
-            let owned = std::sync::Arc::new(owned);
-            let fd_n = fds.take_fd(owned.clone());
-            cmd.args(["--preserve-fd", &fd_n.to_string()]);
-            fd_nums.push(fd_n.to_string());
-            journal_fds.push(owned);
+            Ok(f.into())
+        };
+        let mut fds = cap_std_ext::cmdext::CmdFds::new();
+        let mut pass_fds = |owned: Vec<rustix::fd::OwnedFd>, var: &str| {
+            let mut fd_nums = Vec::new();
+            for owned in owned {
+                let owned = std::sync::Arc::new(owned);
+                let fd_n = fds.take_fd(owned.clone());
+                cmd.args(["--preserve-fd", &fd_n.to_string()]);
+                fd_nums.push(fd_n.to_string());
+                journal_fds.push(owned);
+            }
+            cmd.args(["-e", &format!("{var}={}", fd_nums.join(","))]);
+        };
+        if log_dir.journal {
+            let dests = [log_dir.journal_path(), log_dir.journal_initrd_path()];
+            let owned = dests
+                .into_iter()
+                .flatten()
+                .map(|dest| open_log(&dest))
+                .collect::<Result<Vec<_>>>()?;
+            pass_fds(owned, "BCVK_JOURNAL_FDS");
+        }
+        if let Some(dests) = log_dir.journal_export_paths() {
+            let owned = dests
+                .iter()
+                .map(|dest| {
+                    if log_dir.journal_file {
+                        let (fd, child) = crate::journal_export::spawn_journal_remote(dest)?;
+                        journal_remotes.push(child);
+                        Ok(fd)
+                    } else {
+                        open_log(dest)
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            pass_fds(owned, "BCVK_JOURNAL_EXPORT_FDS");
         }
         cmd.take_fds(fds);
-        cmd.args(["-e", &format!("BCVK_JOURNAL_FDS={}", fd_nums.join(","))]);
     }
 
     // If a console log path was requested via --log-dir, bind-mount its parent
@@ -1025,7 +1616,12 @@ fn prepare_run_command_with_temp(
     let entrypoint = opts.debug_entrypoint.as_deref().unwrap_or(ENTRYPOINT);
     cmd.args(["--", &opts.image, entrypoint]);
 
-    Ok((cmd, td, journal_fds))
+    // The imported image store is now used by the container
+    if let Some(imported) = imported {
+        journal_fds.push(std::sync::Arc::new(imported.keep()?));
+    }
+
+    Ok((cmd, td, journal_fds, journal_remotes))
 }
 
 /// Process --mount-disk-file specs: parse file:name format, create sparse files if needed (2x image size),
@@ -1033,6 +1629,7 @@ fn prepare_run_command_with_temp(
 pub(crate) fn process_disk_files(
     disk_specs: &[String],
     image: &str,
+    image_store: Option<&Utf8Path>,
 ) -> Result<Vec<(Utf8PathBuf, String, crate::to_disk::Format)>> {
     use std::fs::File;
 
@@ -1043,7 +1640,7 @@ pub(crate) fn process_disk_files(
     }
 
     // Get image size for auto-sizing new disk files (2x the image size)
-    let image_size = podman::get_image_size(image)?;
+    let image_size = podman::get_image_size(image, image_store)?;
     // Use minimum 4GB or 2x image size, whichever is larger
     let disk_size = std::cmp::max(image_size * 2, 4u64 * 1024 * 1024 * 1024);
 
@@ -1205,12 +1802,16 @@ fn parse_service_exit_code(status_content: &str) -> Result<i32> {
 
 /// These binaries must be present in the privileged container that runs bcvk,
 /// not the guest bootc image that gets booted inside the VM.
-fn check_required_container_binaries() -> Result<()> {
+fn check_required_container_binaries(boot: BootKind) -> Result<()> {
     // systemctl: used for checking cloud-init and other systemd operations
     // objcopy: for UKI kernel extraction (when using UKI images)
     // NOTE: bwrap is checked earlier in entrypoint.sh, not here, because by the
     // time run_impl() executes we're already inside the bwrap namespace
-    let required_binaries = ["systemctl", "objcopy"];
+    let mut required_binaries = vec!["systemctl", "objcopy"];
+    // mtools: for creating the ESP image without privileges
+    if boot == BootKind::Uefi {
+        required_binaries.extend(["mformat", "mmd", "mcopy"]);
+    }
 
     let mut missing = Vec::new();
 
@@ -1228,6 +1829,115 @@ fn check_required_container_binaries() -> Result<()> {
     Ok(())
 }
 
+/// The repository of `image`, without its tag or digest.
+fn image_repository(image: &str) -> &str {
+    if let Some((repo, _)) = image.split_once('@') {
+        return repo;
+    }
+    // A ':' before the last '/' separates a registry port
+    let name_start = image.rfind('/').map_or(0, |i| i + 1);
+    match image[name_start..].find(':') {
+        Some(i) => &image[..name_start + i],
+        None => image,
+    }
+}
+
+/// The local name of the `arch` variant of `image`, e.g.
+/// `quay.io/fedora/fedora-bootc:42-aarch64`.
+pub(crate) fn arch_image_name(image: &str, arch: Arch) -> String {
+    let repo = image_repository(image);
+    let tag = match image.split_once('@') {
+        Some((_, digest)) => {
+            let (algorithm, hex) = digest.split_once(':').unwrap_or(("sha256", digest));
+            format!("{algorithm}-{}", &hex[..hex.len().min(12)])
+        }
+        None => image[repo.len()..]
+            .strip_prefix(':')
+            .unwrap_or("latest")
+            .to_owned(),
+    };
+    format!("{repo}:{tag}-{arch}")
+}
+
+/// The digest of the manifest for `arch` in the output of `podman manifest inspect`.
+fn platform_digest(manifest_list: &serde_json::Value, arch: Arch) -> Option<&str> {
+    manifest_list
+        .get("manifests")?
+        .as_array()?
+        .iter()
+        .find(|m| {
+            let platform = &m["platform"];
+            platform["os"] == "linux" && platform["architecture"] == arch.oci_arch()
+        })?
+        .get("digest")?
+        .as_str()
+}
+
+/// Make sure the `arch` variant of `image` is in local storage, pulling it
+/// as `policy` says, returning the name to use for it.
+///
+/// Unless the local `image` already is for `arch`, the variant is pulled by
+/// digest and tagged as [`arch_image_name`], so the user's tag (normally for
+/// the host architecture) is left alone. Running a foreign image also
+/// requires qemu-user binfmt handlers on the host, since the container
+/// entrypoint executes the image's own shell and bubblewrap.
+pub(crate) fn ensure_image_arch(
+    image: &str,
+    arch: Arch,
+    policy: crate::images::PullPolicy,
+) -> Result<String> {
+    let binfmt = format!("/proc/sys/fs/binfmt_misc/qemu-{arch}");
+    if !Utf8Path::new(&binfmt).try_exists()? {
+        warn!(
+            "No binfmt handler found at {binfmt}; running a {arch} image likely requires qemu-user-static"
+        );
+    }
+
+    let current = if crate::images::exists(image)? {
+        let info = crate::images::inspect(image).with_context(|| format!("Inspecting {image}"))?;
+        Some(info.architecture)
+    } else {
+        None
+    };
+    let name = arch_image_name(image, arch);
+    let local = if current.as_deref() == Some(arch.oci_arch()) {
+        Some(image.to_owned())
+    } else {
+        crate::images::exists(&name)?.then(|| name.clone())
+    };
+    if !crate::images::should_pull(policy, local.is_some()) {
+        let Some(local) = local else {
+            return Err(eyre!(
+                "Image {image} has no {arch} variant in local storage and --pull=never was given"
+            ));
+        };
+        debug!("Using {local} for {arch} variant of {image}");
+        return Ok(local);
+    }
+
+    // The variant is pulled by digest from the manifest list, as a registry
+    // image is normally only present locally in the host's architecture; a
+    // newer image has a new digest.
+    debug!("Pulling {arch} variant of {image} (have {current:?})");
+    let manifest_list: serde_json::Value = Command::new("podman")
+        .args(["manifest", "inspect", "--", image])
+        .run_and_parse_json()
+        .map_err(|e| eyre!("Inspecting manifest list of {image}: {e}"))?;
+    let digest = platform_digest(&manifest_list, arch)
+        .ok_or_else(|| eyre!("Image {image} has no {arch} variant"))?;
+    let by_digest = format!("{}@{digest}", image_repository(image));
+    // Pulling by digest does not tag the image
+    let mut cmd = Command::new("podman");
+    cmd.args(["pull", "--", &by_digest]);
+    crate::images::run_pull(cmd, &by_digest)?;
+    Command::new("podman")
+        .args(["tag", &by_digest, &name])
+        .run_capture_stderr()
+        .map_err(|e| eyre!("Tagging {by_digest} as {name}: {e}"))?;
+    info!("Pulled {arch} variant of {image} as {name}");
+    Ok(name)
+}
+
 /// Check if the container image has Ignition support
 ///
 /// Checks for labels indicating Ignition support:
@@ -1235,12 +1945,12 @@ fn check_required_container_binaries() -> Result<()> {
 /// - 'com.coreos.osname' (heuristic: CoreOS-based images likely have Ignition)
 ///
 /// Returns true if the image is likely to support Ignition.
-fn check_ignition_support(image: &str) -> Result<bool> {
+fn check_ignition_support(image: &str, image_store: Option<&Utf8Path>) -> Result<bool> {
     use std::collections::HashMap;
     use std::process::Stdio;
 
     // Fetch all labels with a single podman inspect call
-    let output = Command::new("podman")
+    let output = crate::podman::command(image_store)
         .args(["image", "inspect", "--format", "{{json .Labels}}", image])
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -1284,6 +1994,184 @@ fn check_ignition_support(image: &str) -> Result<bool> {
     Ok(false)
 }
 
+/// Path of the generated ESP image used for `--boot uefi`
+const UEFI_ESP_PATH: &str = "/run/qemu/esp.img";
+
+/// Path of the writable UEFI variable store used for `--boot uefi`
+const UEFI_VARS_PATH: &str = "/run/qemu/OVMF_VARS";
+
+/// SMBIOS credentials injecting the `/etc` and `/var` overlay units into the
+/// initramfs of the UKI, which we cannot append to as for direct boot.
+///
+/// `systemd.extra-unit.*` credentials require systemd 256 in the initramfs.
+fn overlay_unit_smbios_creds() -> Vec<String> {
+    crate::cpio::OVERLAY_UNITS
+        .iter()
+        .flat_map(|(name, unit)| {
+            let stem = name.trim_end_matches(".service");
+            let dropin = format!("[Unit]\nWants={name}\n");
+            [
+                format!(
+                    "io.systemd.credential.binary:systemd.extra-unit.{name}={}",
+                    data_encoding::BASE64.encode(unit)
+                ),
+                format!(
+                    "io.systemd.credential.binary:systemd.unit-dropin.initrd-fs.target~{stem}={}",
+                    data_encoding::BASE64.encode(dropin.as_bytes())
+                ),
+            ]
+        })
+        .collect()
+}
+
+/// Parse a firmware image format from a QEMU firmware descriptor.
+fn parse_firmware_format(format: &str) -> Result<qemu::DiskFormat> {
+    match format {
+        "raw" => Ok(qemu::DiskFormat::Raw),
+        "qcow2" => Ok(qemu::DiskFormat::Qcow2),
+        o => Err(eyre!("Unsupported firmware image format: {o}")),
+    }
+}
+
+/// Prepare for booting the image's UKI through UEFI: build an ESP containing
+/// systemd-boot and the UKI, and set up a private copy of the UEFI variables.
+fn prepare_uefi_boot(
+    source_root: &cap_std_ext::cap_std::fs::Dir,
+    kernel_info: &crate::kernel::KernelInfo,
+    arch: Arch,
+) -> Result<qemu::UefiFirmware> {
+    if !kernel_info.is_uki {
+        return Err(eyre!(
+            "--boot uefi requires a UKI, but found a traditional kernel: {}",
+            kernel_info.kernel_path
+        ));
+    }
+
+    let systemd_boot = crate::esp::find_systemd_boot(source_root, arch.as_str())
+        .map(|p| Utf8Path::new(SOURCE_IMAGE_PATH).join(p));
+    if systemd_boot.is_none() {
+        warn!("systemd-boot not found in image; booting the UKI directly from the firmware");
+    }
+    crate::esp::create_esp(
+        Utf8Path::new(UEFI_ESP_PATH),
+        arch.as_str(),
+        &kernel_info.kernel_path,
+        systemd_boot.as_deref(),
+    )
+    .context("Creating ESP")?;
+
+    let firmware = crate::libvirt::secureboot::find_uefi_firmware(arch.qemu_arch())
+        .context("Finding UEFI firmware (is OVMF/edk2 installed?)")?;
+    std::fs::copy(&firmware.vars_path, UEFI_VARS_PATH)
+        .with_context(|| format!("Copying {}", firmware.vars_path))?;
+
+    Ok(qemu::UefiFirmware {
+        code_path: firmware.code_path.into_string(),
+        code_format: parse_firmware_format(&firmware.code_format)?,
+        vars_path: UEFI_VARS_PATH.to_string(),
+        vars_format: parse_firmware_format(&firmware.vars_format)?,
+    })
+}
+
+/// Parse the disk files passed in `BOOTC_DISK_FILES` by the host (see
+/// [`process_disk_files`]) into virtio-blk devices.
+pub(crate) fn disk_files_from_env() -> Result<Vec<qemu::VirtioBlkDevice>> {
+    let mut virtio_blk_devices = Vec::new();
+    if let Ok(disk_env) = std::env::var("BOOTC_DISK_FILES") {
+        debug!("Processing BOOTC_DISK_FILES: {}", disk_env);
+        for disk_spec in disk_env.split(',') {
+            // Parse disk_file:disk_name:format or disk_file:disk_name (auto-detect format)
+            let parts: Vec<&str> = disk_spec.splitn(3, ':').collect();
+            if parts.len() >= 2 {
+                let format = if parts.len() == 3 {
+                    match parts[2] {
+                        "qcow2" => crate::to_disk::Format::Qcow2,
+                        "raw" => crate::to_disk::Format::Raw,
+                        _ => {
+                            // Auto-detect from file extension as fallback
+                            if parts[0].ends_with(".qcow2") {
+                                crate::to_disk::Format::Qcow2
+                            } else {
+                                crate::to_disk::Format::Raw
+                            }
+                        }
+                    }
+                } else {
+                    // Auto-detect format from file extension
+                    if parts[0].ends_with(".qcow2") {
+                        crate::to_disk::Format::Qcow2
+                    } else {
+                        crate::to_disk::Format::Raw
+                    }
+                };
+
+                let disk_file = parts[0].to_string();
+                let serial = parts[1].to_string();
+
+                // Check if disk file exists and is accessible
+                if !Utf8Path::new(&disk_file).exists() {
+                    return Err(eyre!(
+                        "Disk file does not exist in bwrap namespace: {} (serial: {})",
+                        disk_file,
+                        serial
+                    ));
+                }
+
+                debug!(
+                    "Adding virtio-blk device: file={}, serial={}, format={:?}",
+                    disk_file, serial, format
+                );
+
+                virtio_blk_devices.push(crate::qemu::VirtioBlkDevice {
+                    disk_file,
+                    serial,
+                    format: format.into(),
+                    readonly: false,
+                });
+            }
+        }
+    }
+    Ok(virtio_blk_devices)
+}
+
+/// Reconstruct the writers for the real-root and initrd journal from the
+/// fds the host passed in `var` (`fd1,fd2`).
+fn journal_writers_from_env(var: &str) -> Result<(tokio::fs::File, tokio::fs::File)> {
+    use std::os::unix::io::FromRawFd as _;
+    let fds_str =
+        std::env::var(var).with_context(|| format!("{var} not set but --log-dir was given"))?;
+    let mut parts = fds_str.splitn(2, ',');
+    let fd1: i32 = parts
+        .next()
+        .unwrap_or("")
+        .parse()
+        .with_context(|| format!("{var}: invalid first fd"))?;
+    let fd2: i32 = parts
+        .next()
+        .unwrap_or("")
+        .parse()
+        .with_context(|| format!("{var}: invalid second fd"))?;
+    // SAFETY: fds were opened by the host process and preserved through
+    // execve / flatpak-spawn --forward-fd / podman --preserve-fd.
+    // We are the sole owner at this point.
+    #[allow(unsafe_code)]
+    let (f1, f2) = unsafe {
+        (
+            std::fs::File::from_raw_fd(fd1),
+            std::fs::File::from_raw_fd(fd2),
+        )
+    };
+    Ok((tokio::fs::File::from_std(f1), tokio::fs::File::from_std(f2)))
+}
+
+/// Fail with the guest failure detected while the VM ran, if any.
+fn check_guest_failure() -> Result<()> {
+    match GuestFailure::current() {
+        Some(failure) => Err(failure.clone().into()),
+        None => Ok(()),
+    }
+}
+
 /// VM execution inside container: extracts kernel/initramfs, starts virtiofsd processes,
 /// generates systemd mount units, sets up command execution, launches QEMU.
 pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
@@ -1291,12 +2179,16 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
     use std::fs;
 
     debug!("Running QEMU implementation inside container");
+    if opts.boot_profile.is_some() {
+        boot_profile::start_recording();
+    }
+    boot_profile::record("container-started");
 
     // Check for required binaries in the target container image early
-    check_required_container_binaries()?;
+    check_required_container_binaries(opts.boot)?;
 
     // Initialize status writer for supervisor monitoring
-    let status_writer = StatusWriter::new("/run/supervisor-status.json");
+    let status_writer = StatusWriter::new(SUPERVISOR_STATUS_PATH);
     status_writer.update_state(SupervisorState::WaitingForSystemd)?;
 
     // Check systemd version from the container image
@@ -1324,17 +2216,15 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
     };
     tracing::debug!("Target image has cloud-init: {cloudinit}");
 
-    // Verify KVM access
-    if !Utf8Path::new("/dev/kvm").exists() || !fs::File::open("/dev/kvm").is_ok() {
-        return Err(eyre!("KVM device not accessible"));
-    }
+    // Verify KVM access (this was resolved to kvm or tcg on the host)
+    let accel = opts.common.resolve_accel()?;
 
     // Create QEMU mount points
     fs::create_dir_all("/run/qemu")?;
 
     // Find kernel and initramfs using the kernel detection module
     let source_root = cap_std_ext::cap_std::fs::Dir::open_ambient_dir(
-        "/run/source-image",
+        SOURCE_IMAGE_PATH,
         cap_std_ext::cap_std::ambient_authority(),
     )
     .context("opening /run/source-image")?;
@@ -1352,7 +2242,7 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
 
     // Add the source-image prefix to get absolute paths
     let kernel_info =
-        crate::kernel::with_root_prefix(kernel_info, Utf8Path::new("/run/source-image"));
+        crate::kernel::with_root_prefix(kernel_info, Utf8Path::new(SOURCE_IMAGE_PATH));
 
     debug!(
         "Found kernel: {:?} (UKI: {})",
@@ -1362,8 +2252,22 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
     let kernel_mount = "/run/qemu/kernel";
     let initramfs_mount = "/run/qemu/initramfs";
 
-    // Extract from UKI if found, otherwise use traditional kernel
-    if kernel_info.is_uki {
+    // For UEFI boot the UKI is used as-is from a generated ESP; otherwise
+    // extract from UKI if found, or use the traditional kernel
+    let uefi_firmware = if opts.boot == BootKind::Uefi {
+        let arch = opts.common.guest_arch().ok_or_else(|| {
+            eyre!(
+                "--boot uefi is not supported on architecture {}",
+                std::env::consts::ARCH
+            )
+        })?;
+        Some(prepare_uefi_boot(&source_root, &kernel_info, arch)?)
+    } else {
+        None
+    };
+    if uefi_firmware.is_some() {
+        debug!("Using unmodified UKI for UEFI boot");
+    } else if kernel_info.is_uki {
         debug!(
             "Extracting kernel and initramfs from UKI: {:?}",
             kernel_info.kernel_path
@@ -1415,7 +2319,9 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
     // The Linux kernel's initramfs parser requires uncompressed CPIO archives to start
     // at a 4-byte aligned offset. We add NUL padding before our CPIO data to ensure
     // proper alignment. The kernel skips NUL bytes between archives.
-    {
+    //
+    // This is not possible for UEFI boot without breaking the UKI.
+    if uefi_firmware.is_none() {
         use std::io::{Seek, SeekFrom, Write};
         let cpio_data = crate::cpio::create_initramfs_units_cpio()
             .map_err(|e| eyre!("Failed to create initramfs CPIO: {e}"))?;
@@ -1446,10 +2352,15 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
         );
     }
 
+    boot_profile::record("kernel-extracted");
+
     // Process host mounts and prepare virtiofsd instances for each using async manager
     let mut additional_mounts = Vec::new();
     // Collect mount unit credentials to inject via SMBIOS instead of writing to filesystem
     let mut mount_unit_smbios_creds = Vec::new();
+    if uefi_firmware.is_some() {
+        mount_unit_smbios_creds.extend(overlay_unit_smbios_creds());
+    }
 
     debug!(
         "Checking for host mounts directory: /run/host-mounts exists = {}",
@@ -1542,12 +2453,11 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
     // Handle --execute: pipes will be created when adding to qemu_config later
     // No need to create files anymore as we're using pipes
 
-    // Inject the journal streaming unit when either --output=journal or
-    // --log-dir with journal stream is requested.  The guest always streams JSON;
-    // the host converts JSON→plain-text for stdout as needed.
-    let wants_journal_stream = opts.common.output == OutputMode::Journal
-        || opts.common.log_dir.as_ref().map_or(false, |d| d.journal);
-    if wants_journal_stream {
+    // Inject the journal streaming unit.  The journal is always captured into
+    // the ring read by `bcvk ephemeral logs`, and additionally for
+    // --output=journal and --log-dir with the journal stream.  The guest always
+    // streams JSON; the host converts JSON→plain-text for stdout as needed.
+    {
         let encoded_journal = data_encoding::BASE64.encode(JOURNAL_STREAM_UNIT.as_bytes());
         mount_unit_smbios_creds.push(format!(
             "io.systemd.credential.binary:systemd.extra-unit.bcvk-journal-stream.service={encoded_journal}"
@@ -1568,6 +2478,9 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
         debug!("Created sysinit.target dropin to enable journal streaming units");
     }
 
+    // With --watchdog, the guest's systemd pings the watchdog device
+    mount_unit_smbios_creds.extend(opts.common.liveness.smbios_credentials());
+
     // Create execute units via SMBIOS credentials if needed
     match opts.common.execute.as_slice() {
         [] => {}
@@ -1646,20 +2559,58 @@ StandardOutput=file:/dev/virtio-ports/executestatus
 
     std::fs::create_dir_all(CONTAINER_STATEDIR)?;
 
-    // Configure qemu for direct kernel boot
-    debug!("Configuring QEMU for direct kernel boot");
-    let mut qemu_config = crate::qemu::QemuConfig::new_direct_boot(
-        opts.common.memory_mb()?,
-        opts.common.vcpus()?,
-        "/run/qemu/kernel".to_string(),
-        "/run/qemu/initramfs".to_string(),
-        main_virtiofsd_config.socket_path.clone(),
-    );
+    let mut qemu_config = if let Some(firmware) = uefi_firmware {
+        debug!("Configuring QEMU for UEFI boot");
+        crate::qemu::QemuConfig::new_uefi_boot(
+            opts.common.memory_mb()?,
+            opts.common.vcpus()?,
+            firmware,
+            UEFI_ESP_PATH.to_string(),
+            main_virtiofsd_config.socket_path.clone(),
+        )
+    } else {
+        // Configure qemu for direct kernel boot
+        debug!("Configuring QEMU for direct kernel boot");
+        crate::qemu::QemuConfig::new_direct_boot(
+            opts.common.memory_mb()?,
+            opts.common.vcpus()?,
+            kernel_mount.to_string(),
+            initramfs_mount.to_string(),
+            main_virtiofsd_config.socket_path.clone(),
+        )
+    };
+    qemu_config.accel = accel;
+    qemu_config.arch = opts.common.arch.map(|a| a.qemu_arch().to_owned());
+    qemu_config.resource_limits = opts.common.resource_limits();
 
     // Check for BCVK_DEBUG=disable-vsock to force disabling vsock for testing
     let vsock_force_disabled = std::env::var("BCVK_DEBUG").as_deref() == Ok("disable-vsock");
     let vsock_enabled = !vsock_force_disabled && qemu_config.enable_vsock().is_ok();
 
+    // With vsock available, inject the listener for `bcvk ephemeral exec`;
+    // it is configured with SMBIOS credentials, so needs those too
+    let guest_arch = opts.common.guest_arch();
+    let exec_available = if vsock_enabled {
+        crate::credentials::check_arch(guest_arch)
+    } else {
+        Err(eyre!("vsock is not available"))
+    };
+    match exec_available {
+        Ok(()) => {
+            crate::ephemeral_exec::configure_guest(
+                &mut mount_unit_smbios_creds,
+                Utf8Path::new(QEMU_CREDENTIALS_DIR),
+                guest_arch,
+                &mut qemu_config,
+            )?;
+            debug!("Injected SMBIOS credentials for exec units");
+        }
+        Err(e) => {
+            debug!("Not enabling exec: {e}");
+            status_writer.set_exec_unavailable(e.to_string())?;
+        }
+    }
+
     // Handle SSH key generation and credential injection
     if opts.common.ssh_keygen {
         let key_pair = crate::ssh::generate_default_keypair()?;
@@ -1692,10 +2643,16 @@ StandardOutput=file:/dev/virtio-ports/executestatus
     if opts.common.console {
         kernel_cmdline.push("console=hvc0".to_string());
     }
-    if cloudinit {
-        // We don't provide any cloud-init datasource right now,
-        // though in the future it would make sense to do so,
-        // and switch over our SSH key injection.
+    if opts.cloud_init.is_enabled() {
+        if !cloudinit {
+            return Err(eyre!("cloud-init is not enabled in the image"));
+        }
+        // Only look for the seed disk, rather than probing cloud metadata
+        // services first
+        kernel_cmdline.push("ds=nocloud".to_string());
+    } else if cloudinit {
+        // Without a datasource, disable cloud-init; our SSH key injection
+        // uses systemd credentials instead.
         kernel_cmdline.push("ds=None".to_string());
     }
 
@@ -1705,6 +2662,14 @@ StandardOutput=file:/dev/virtio-ports/executestatus
         kernel_cmdline.push("ignition.platform.id=qemu".to_string());
     }
 
+    // With UEFI boot the initramfs is the unmodified one from the UKI, so it
+    // lacks our sysroot.mount. Let dracut mount the virtiofs root read-only
+    // instead; the /etc and /var overlay units come in as credentials, so
+    // writes stay in the VM as with direct boot.
+    if opts.boot == BootKind::Uefi {
+        kernel_cmdline.extend(["root=virtiofs:rootfs".to_string(), "ro".to_string()]);
+    }
+
     kernel_cmdline.extend(opts.kernel_args.clone());
     qemu_config.set_kernel_cmdline(kernel_cmdline);
 
@@ -1722,13 +2687,12 @@ StandardOutput=file:/dev/virtio-ports/executestatus
             ));
         }
 
-        let arch = std::env::consts::ARCH;
-        match arch {
-            "x86_64" | "aarch64" => {
+        match opts.common.guest_arch() {
+            Some(Arch::X86_64 | Arch::Aarch64) => {
                 debug!("Adding Ignition config via fw_cfg: {}", ignition_path);
                 qemu_config.add_fw_cfg(IGNITION_FW_CFG_NAME.to_string(), ignition_path.to_owned());
             }
-            "s390x" | "powerpc64le" => {
+            Some(Arch::S390x | Arch::Ppc64le) => {
                 debug!("Adding Ignition config via virtio-blk: {}", ignition_path);
                 qemu_config.add_virtio_blk_device_with_format_ro(
                     ignition_path.to_string(),
@@ -1737,16 +2701,35 @@ StandardOutput=file:/dev/virtio-ports/executestatus
                     true, // readonly as required by FCOS
                 );
             }
-            _ => {
+            None => {
                 return Err(eyre!(
                     "Ignition config injection not supported on architecture: {}\n\
-                     Supported architectures: x86_64, aarch64, s390x, powerpc64le",
-                    arch
+                     Supported architectures: x86_64, aarch64, s390x, ppc64le",
+                    std::env::consts::ARCH
                 ));
             }
         }
     }
 
+    if opts.cloud_init.is_enabled() {
+        let mount = Utf8Path::new(CLOUD_INIT_MOUNT_PATH);
+        let meta_data = mount.join("meta-data");
+        let hostname = rustix::system::uname();
+        let hostname = hostname.nodename().to_string_lossy();
+        crate::cloud_init::create_seed(
+            Utf8Path::new(CLOUD_INIT_SEED_PATH),
+            &mount.join("user-data"),
+            meta_data.exists().then_some(meta_data.as_path()),
+            &hostname,
+        )?;
+        qemu_config.add_virtio_blk_device_with_format_ro(
+            CLOUD_INIT_SEED_PATH.to_string(),
+            crate::cloud_init::CIDATA_LABEL.to_string(),
+            crate::to_disk::Format::Raw,
+            true,
+        );
+    }
+
     // TODO allocate unlinked unnamed file and pass via fd
     let mut tmp_swapfile = None;
     if let Some(size) = opts.add_swap {
@@ -1797,62 +2780,7 @@ Options=
         tmp_swapfile = Some(tmpf);
     }
 
-    // Parse disk files from environment variable
-    let mut virtio_blk_devices = Vec::new();
-    if let Ok(disk_env) = std::env::var("BOOTC_DISK_FILES") {
-        debug!("Processing BOOTC_DISK_FILES: {}", disk_env);
-        for disk_spec in disk_env.split(',') {
-            // Parse disk_file:disk_name:format or disk_file:disk_name (auto-detect format)
-            let parts: Vec<&str> = disk_spec.splitn(3, ':').collect();
-            if parts.len() >= 2 {
-                let format = if parts.len() == 3 {
-                    match parts[2] {
-                        "qcow2" => crate::to_disk::Format::Qcow2,
-                        "raw" => crate::to_disk::Format::Raw,
-                        _ => {
-                            // Auto-detect from file extension as fallback
-                            if parts[0].ends_with(".qcow2") {
-                                crate::to_disk::Format::Qcow2
-                            } else {
-                                crate::to_disk::Format::Raw
-                            }
-                        }
-                    }
-                } else {
-                    // Auto-detect format from file extension
-                    if parts[0].ends_with(".qcow2") {
-                        crate::to_disk::Format::Qcow2
-                    } else {
-                        crate::to_disk::Format::Raw
-                    }
-                };
-
-                let disk_file = parts[0].to_string();
-                let serial = parts[1].to_string();
-
-                // Check if disk file exists and is accessible
-                if !Utf8Path::new(&disk_file).exists() {
-                    return Err(eyre!(
-                        "Disk file does not exist in bwrap namespace: {} (serial: {})",
-                        disk_file,
-                        serial
-                    ));
-                }
-
-                debug!(
-                    "Adding virtio-blk device: file={}, serial={}, format={:?}",
-                    disk_file, serial, format
-                );
-
-                virtio_blk_devices.push(crate::qemu::VirtioBlkDevice {
-                    disk_file,
-                    serial,
-                    format: format.into(),
-                    readonly: false,
-                });
-            }
-        }
-    }
+    let virtio_blk_devices = disk_files_from_env()?;
 
     qemu_config.set_console(opts.common.console);
 
@@ -1875,9 +2803,12 @@ Options=
     //   org.bcvk.journal.initrd → journal-initrd.json
     //
     // The host files were opened before execve and their fds passed in via
-    // BCVK_JOURNAL_FDS=fd1,fd2 (fd1=journal.json, fd2=journal-initrd.json).
+    // BCVK_JOURNAL_FDS=fd1,fd2 (fd1=journal.json, fd2=journal-initrd.json),
+    // and BCVK_JOURNAL_EXPORT_FDS=fd1,fd2 for the export format.
+    //
+    // The real-root journal is also written to the ring for `bcvk ephemeral logs`.
     let mut worker_tasks = tokio::task::JoinSet::new();
-    if wants_journal_stream {
+    {
         let read_file: std::fs::File = qemu_config
             .add_virtio_serial_pipe("org.bcvk.journal")?
             .into();
@@ -1888,48 +2819,33 @@ Options=
 
         let stdout_wants_journal = opts.common.output == OutputMode::Journal;
 
-        // Parse BCVK_JOURNAL_FDS=fd1,fd2 to reconstruct the two host file writers.
-        let wants_journal_file = opts.common.log_dir.as_ref().map_or(false, |d| d.journal);
-        let (file_writer, initrd_file_writer): (Option<tokio::fs::File>, Option<tokio::fs::File>) =
-            if wants_journal_file {
-                use std::os::unix::io::FromRawFd as _;
-                let fds_str = std::env::var("BCVK_JOURNAL_FDS")
-                    .context("BCVK_JOURNAL_FDS not set but --log-dir=journal=... was given")?;
-                let mut parts = fds_str.splitn(2, ',');
-                let fd1: i32 = parts
-                    .next()
-                    .unwrap_or("")
-                    .parse()
-                    .context("BCVK_JOURNAL_FDS: invalid first fd")?;
-                let fd2: i32 = parts
-                    .next()
-                    .unwrap_or("")
-                    .parse()
-                    .context("BCVK_JOURNAL_FDS: invalid second fd")?;
-                // SAFETY: fds were opened by the host process and preserved through
-                // execve / flatpak-spawn --forward-fd / podman --preserve-fd.
-                // We are the sole owner at this point.
-                #[allow(unsafe_code)]
-                let (f1, f2) = unsafe {
-                    (
-                        std::fs::File::from_raw_fd(fd1),
-                        std::fs::File::from_raw_fd(fd2),
-                    )
-                };
-                (
-                    Some(tokio::fs::File::from_std(f1)),
-                    Some(tokio::fs::File::from_std(f2)),
-                )
+        // Parse BCVK_JOURNAL_FDS=fd1,fd2 to reconstruct the two host file writers,
+        // and BCVK_JOURNAL_EXPORT_FDS for the export format writers.
+        let log_dir = opts.common.log_dir.as_ref();
+        let (file_writer, initrd_file_writer) = if log_dir.map_or(false, |d| d.journal) {
+            let (f1, f2) = journal_writers_from_env("BCVK_JOURNAL_FDS")?;
+            (Some(f1), Some(f2))
+        } else {
+            (None, None)
+        };
+        let (export_writer, initrd_export_writer) =
+            if log_dir.map_or(false, |d| d.wants_journal_export()) {
+                let (f1, f2) = journal_writers_from_env("BCVK_JOURNAL_EXPORT_FDS")?;
+                (Some(f1), Some(f2))
             } else {
                 (None, None)
             };
 
+        let mut ring = Some(crate::ephemeral_logs::JournalRing::create().await?);
+
         // Spawn the real-root journal drain task.
         let reader = tokio::fs::File::from_std(read_file);
         worker_tasks.spawn(async move {
             use tokio::io::AsyncBufReadExt as _;
             use tokio::io::AsyncWriteExt as _;
             let mut file_writer = file_writer;
+            let mut export_writer = export_writer;
+            let mut export_dropped = 0u64;
             let mut stdout = tokio::io::stdout();
             let mut lines = tokio::io::BufReader::new(reader).lines();
             loop {
@@ -1940,12 +2856,33 @@ Options=
                         break;
                     }
                     Ok(Some(line)) => {
+                        boot_failure::observe_journal_line(&line);
+                        if let Some(ref mut r) = ring {
+                            if let Err(e) = r.write_line(&line).await {
+                                tracing::warn!("Failed to write journal JSON to ring: {e}");
+                                ring = None;
+                            }
+                        }
                         if let Some(ref mut fw) = file_writer {
                             if let Err(e) = fw.write_all(format!("{line}\n").as_bytes()).await {
                                 tracing::warn!("Failed to write journal JSON to file: {e}");
                                 file_writer = None;
                             }
                         }
+                        if let Some(ref mut ew) = export_writer {
+                            match journal_export::journal_json_to_export(&line) {
+                                Ok(entry) => {
+                                    if let Err(e) = ew.write_all(&entry).await {
+                                        tracing::warn!("Failed to write journal export: {e}");
+                                        export_writer = None;
+                                    }
+                                }
+                                Err(e) => {
+                                    tracing::debug!("Dropping journal line from export: {e:#}");
+                                    export_dropped += 1;
+                                }
+                            }
+                        }
                         if stdout_wants_journal {
                             if let Some(text) = journal_json_to_text(&line) {
                                 if let Err(e) = stdout.write_all(text.as_bytes()).await {
@@ -1956,15 +2893,20 @@ Options=
                     }
                 }
             }
+            if export_dropped > 0 {
+                tracing::warn!("Dropped {export_dropped} malformed lines from the journal export");
+            }
             tracing::debug!("journal copy task done");
         });
 
-        // Spawn the initrd journal drain task (writes to journal-initrd.json only).
+        // Spawn the initrd journal drain task (writes to the journal-initrd files only).
         let initrd_reader = tokio::fs::File::from_std(initrd_read_file);
         worker_tasks.spawn(async move {
             use tokio::io::AsyncBufReadExt as _;
             use tokio::io::AsyncWriteExt as _;
             let mut file_writer = initrd_file_writer;
+            let mut export_writer = initrd_export_writer;
+            let mut export_dropped = 0u64;
             let mut lines = tokio::io::BufReader::new(initrd_reader).lines();
             loop {
                 match lines.next_line().await {
@@ -1974,15 +2916,39 @@ Options=
                         break;
                     }
                     Ok(Some(line)) => {
+                        boot_failure::observe_journal_line(&line);
                         if let Some(ref mut fw) = file_writer {
                             if let Err(e) = fw.write_all(format!("{line}\n").as_bytes()).await {
                                 tracing::warn!("Failed to write initrd journal JSON to file: {e}");
                                 file_writer = None;
                             }
                         }
+                        if let Some(ref mut ew) = export_writer {
+                            match journal_export::journal_json_to_export(&line) {
+                                Ok(entry) => {
+                                    if let Err(e) = ew.write_all(&entry).await {
+                                        tracing::warn!(
+                                            "Failed to write initrd journal export: {e}"
+                                        );
+                                        export_writer = None;
+                                    }
+                                }
+                                Err(e) => {
+                                    tracing::debug!(
+                                        "Dropping initrd journal line from export: {e:#}"
+                                    );
+                                    export_dropped += 1;
+                                }
+                            }
+                        }
                     }
                 }
             }
+            if export_dropped > 0 {
+                tracing::warn!(
+                    "Dropped {export_dropped} malformed lines from the initrd journal export"
+                );
+            }
             tracing::debug!("initrd journal copy task done");
         });
     }
@@ -1997,6 +2963,45 @@ Options=
         warn!("No host DNS servers available, QEMU slirp will use container's resolv.conf which may not work");
     }
 
+    // Add the NIC on the internal network, configured in the guest by a unit
+    if let Some(network) = opts.internal_network.as_deref() {
+        let vm = opts
+            .podman
+            .name
+            .as_deref()
+            .ok_or_else(|| eyre!("--internal-network requires --name"))?;
+        let member = crate::internal_network::Member::new(network, vm);
+        qemu_config.network_mode = qemu::NetworkMode::Socket {
+            hostfwd: Vec::new(),
+            mcast: crate::internal_network::MCAST_GROUP.to_string(),
+            mac: member.mac.clone(),
+        };
+        let encoded_unit =
+            data_encoding::BASE64.encode(crate::internal_network::NETWORK_UNIT.as_bytes());
+        mount_unit_smbios_creds.push(format!(
+            "io.systemd.credential.binary:systemd.extra-unit.bcvk-internal-network.service={encoded_unit}"
+        ));
+        let encoded_config = data_encoding::BASE64.encode(member.unit_dropin().as_bytes());
+        mount_unit_smbios_creds.push(format!(
+            "io.systemd.credential.binary:systemd.unit-dropin.bcvk-internal-network.service~bcvk={encoded_config}"
+        ));
+        let encoded_script =
+            data_encoding::BASE64.encode(crate::internal_network::NETWORK_SCRIPT.as_bytes());
+        mount_unit_smbios_creds.push(format!(
+            "io.systemd.credential.binary:{}={encoded_script}",
+            crate::internal_network::NETWORK_SCRIPT_CREDENTIAL
+        ));
+        let encoded_dropin =
+            data_encoding::BASE64.encode(b"[Unit]\nWants=bcvk-internal-network.service\n");
+        mount_unit_smbios_creds.push(format!(
+            "io.systemd.credential.binary:systemd.unit-dropin.sysinit.target~bcvk-network={encoded_dropin}"
+        ));
+        debug!(
+            "Joined internal network {network} as {} ({})",
+            member.address, member.mac
+        );
+    }
+
     if opts.common.ssh_keygen {
         qemu_config.enable_ssh_access(None); // Use default port 2222
         debug!("Enabled SSH port forwarding: host port 2222 -> guest port 22");
@@ -2006,6 +3011,15 @@ Options=
         // TODO: Add proper SMBIOS credential injection if needed
     }
 
+    // The container port matches the guest port; see prepare_run_command_with_temp
+    for mapping in opts.port_mappings.iter() {
+        qemu_config.add_port_forward(mapping.guest_port, mapping.guest_port);
+        debug!(
+            "Enabled port forwarding: container port {0} -> guest port {0}",
+            mapping.guest_port
+        );
+    }
+
     // Set main virtiofs configuration for root filesystem (will be spawned by QEMU)
     qemu_config.set_main_virtiofs(main_virtiofsd_config.clone());
 
@@ -2031,7 +3045,7 @@ Options=
         );
     }
 
-    let status_writer_clone = StatusWriter::new("/run/supervisor-status.json");
+    let status_writer_clone = StatusWriter::new(SUPERVISOR_STATUS_PATH);
 
     // Only enable systemd notification debugging if the systemd version supports it
     // and the host has vsock enabled
@@ -2065,6 +3079,28 @@ Options=
     }
     debug!("Added {} SMBIOS credentials to QEMU config", cred_count);
 
+    qemu_config.set_qmp_socket(QMP_SOCKET_PATH);
+    // Kernel panics are reported by pvpanic on a separate QMP socket, keeping
+    // the control socket free for `bcvk ephemeral mount` and shutdown.
+    qemu_config.pvpanic = true;
+    qemu_config.qmp_events_socket = Some(QMP_EVENTS_SOCKET_PATH.into());
+    // Keep the guest memory around until it is dumped
+    let dump_format = opts
+        .common
+        .dump
+        .dump_on_panic
+        .is_some()
+        .then_some(opts.common.dump.dump_format);
+    qemu_config.pause_on_panic = dump_format.is_some();
+    qemu_config.watchdog = opts.common.liveness.watchdog;
+    qemu_config.hotplug_ports = crate::mount::HOTPLUG_PORTS;
+    qemu_config.tpm = opts.common.tpm_config();
+    opts.common.credentials.add_to_qemu_config(
+        Utf8Path::new(QEMU_CREDENTIALS_DIR),
+        opts.common.guest_arch(),
+        &mut qemu_config,
+    )?;
+
     debug!("Starting QEMU with systemd debugging enabled");
 
     // Spawn QEMU with all virtiofsd processes handled internally
@@ -2079,6 +3115,33 @@ Options=
         }
     };
 
+    if let Some(ready) = qemu.virtiofsd_ready() {
+        boot_profile::record_at("virtiofsd-ready", ready);
+    }
+    boot_profile::record("qemu-started");
+
+    let max_runtime = opts.common.liveness.max_runtime;
+    match qemu.qmp_events().await {
+        Ok(Some(qmp)) => {
+            worker_tasks.spawn(boot_failure::watch_qmp_events(
+                qmp,
+                dump_format,
+                max_runtime,
+            ));
+        }
+        Ok(None) => {}
+        // The --max-runtime deadline is enforced by the QMP event watcher
+        Err(e) if max_runtime.is_some() => {
+            return Err(e).context("Connecting to QMP events socket for --max-runtime");
+        }
+        Err(e) => warn!("Failed to connect to QMP events socket: {e}"),
+    }
+
+    if let Some(cid) = qemu.guest_cid() {
+        std::fs::write(GUEST_CID_PATH, cid.to_string())
+            .with_context(|| format!("Writing {GUEST_CID_PATH}"))?;
+    }
+
     // Handle execute command output streaming if needed
     if let Some((exec_pipefd, status_pipefd)) = exec_pipes {
         tracing::debug!("Starting execute output streaming with pipes");
@@ -2106,6 +3169,7 @@ Options=
 
         // Drain any remaining journal output (pipe write end closed when QEMU exits)
         worker_tasks.join_all().await;
+        check_guest_failure()?;
 
         // Parse exit code from systemd service status
         let exit_code = parse_service_exit_code(&status)?;
@@ -2119,11 +3183,12 @@ Options=
         // Wait for QEMU to complete
         tracing::debug!("Waiting for qemu exit");
         let exit_status = qemu.wait().await?;
+        // Drain any remaining journal output (pipe write end closed when QEMU exits)
+        worker_tasks.join_all().await;
+        check_guest_failure()?;
         if !exit_status.success() {
             return Err(eyre!("QEMU exited with non-zero status: {}", exit_status));
         }
-        // Drain any remaining journal output (pipe write end closed when QEMU exits)
-        worker_tasks.join_all().await;
     }
 
     drop(tmp_swapfile);
@@ -2138,6 +3203,217 @@ Options=
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_overlay_unit_smbios_creds() {
+        let creds = overlay_unit_smbios_creds();
+        let decode = |prefix: &str| {
+            let cred = creds
+                .iter()
+                .find_map(|c| c.strip_prefix(prefix))
+                .unwrap_or_else(|| panic!("missing {prefix}"));
+            String::from_utf8(data_encoding::BASE64.decode(cred.as_bytes()).unwrap()).unwrap()
+        };
+        assert_eq!(creds.len(), 4);
+        let unit =
+            decode("io.systemd.credential.binary:systemd.extra-unit.bcvk-etc-overlay.service=");
+        assert!(unit.contains("ConditionPathExists=/etc/initrd-release"));
+        assert_eq!(
+            decode("io.systemd.credential.binary:systemd.unit-dropin.initrd-fs.target~bcvk-var-ephemeral="),
+            "[Unit]\nWants=bcvk-var-ephemeral.service\n"
+        );
+    }
+
+    #[test]
+    fn test_validate_port_mappings() {
+        let parse =
+            |v: &[&str]| -> Vec<PortMapping> { v.iter().map(|s| s.parse().unwrap()).collect() };
+        for (mappings, network, ok) in [
+            (&[][..], None, true),
+            (&[][..], Some("host"), true),
+            (&["8080:80", "8443:443", "8081:80"][..], None, true),
+            (&["8080:80"][..], Some("bridge"), true),
+            (&["8080:80", "8080:81"][..], None, false),
+            (&["2222:2222"][..], None, false),
+            (&["0:80"][..], None, false),
+            (&["8080:80"][..], Some("host"), false),
+            (&["8080:80"][..], Some("none"), false),
+        ] {
+            assert_eq!(
+                validate_port_mappings(&parse(mappings), network).is_ok(),
+                ok,
+                "{mappings:?} {network:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_internal_network_opts() {
+        let opts = RunEphemeralOpts::try_parse_from([
+            "run",
+            "--internal-network=cluster",
+            "--name=node1",
+            "img",
+        ])
+        .unwrap();
+        assert_eq!(opts.internal_network.as_deref(), Some("cluster"));
+        assert!(opts.network_aliases.is_empty());
+        let opts = RunEphemeralOpts::try_parse_from([
+            "run",
+            "--internal-network=cluster",
+            "--name=cluster-node1",
+            "--network-alias=node1",
+            "img",
+        ])
+        .unwrap();
+        assert_eq!(opts.network_aliases, ["node1"]);
+        assert!(RunEphemeralOpts::try_parse_from([
+            "run",
+            "--name=node1",
+            "--network-alias=n1",
+            "img"
+        ])
+        .is_err());
+        // The VM name is used for addresses and name resolution
+        assert!(
+            RunEphemeralOpts::try_parse_from(["run", "--internal-network=cluster", "img"]).is_err()
+        );
+        assert!(RunEphemeralOpts::try_parse_from([
+            "run",
+            "--internal-network=cluster",
+            "--name=node1",
+            "--network=host",
+            "img",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_boot_profile_opts() {
+        let opts =
+            RunEphemeralOpts::try_parse_from(["run", "-d", "-K", "--boot-profile=p.json", "img"])
+                .unwrap();
+        assert_eq!(opts.boot_profile.as_deref(), Some(Utf8Path::new("p.json")));
+        // The profile is written once SSH is ready, which needs a detached VM
+        for args in [
+            &["run", "--boot-profile=p.json", "img"][..],
+            &["run", "-d", "--boot-profile=p.json", "img"],
+        ] {
+            let opts = RunEphemeralOpts::try_parse_from(args).unwrap();
+            let err = run(opts).unwrap_err();
+            assert!(err.to_string().contains("--detach and --ssh-keygen"));
+        }
+    }
+
+    #[test]
+    fn test_accel_mode() {
+        use qemu::Accel::{Kvm, Tcg};
+        for (mode, kvm_available, expected) in [
+            (AccelMode::Auto, true, Some(Kvm)),
+            (AccelMode::Auto, false, Some(Tcg)),
+            (AccelMode::Kvm, true, Some(Kvm)),
+            (AccelMode::Kvm, false, None),
+            (AccelMode::Tcg, true, Some(Tcg)),
+            (AccelMode::Tcg, false, Some(Tcg)),
+        ] {
+            let resolved = mode.resolve(kvm_available).ok();
+            assert_eq!(resolved, expected, "{mode:?} with KVM {kvm_available}");
+            // The resolved accelerator is passed into the container and must round-trip
+            if let Some(accel) = resolved {
+                assert_eq!(
+                    AccelMode::from(accel).resolve(kvm_available).unwrap(),
+                    accel
+                );
+            }
+        }
+
+        // A foreign architecture is always emulated
+        let foreign = [Arch::X86_64, Arch::Aarch64]
+            .into_iter()
+            .find(|a| a.is_foreign())
+            .unwrap();
+        let mut common = CommonVmOpts {
+            arch: Some(foreign),
+            ..Default::default()
+        };
+        assert_eq!(common.resolve_accel().unwrap(), qemu::Accel::Tcg);
+        common.accel = AccelMode::Kvm;
+        assert!(common.resolve_accel().is_err());
+    }
+
+    #[test]
+    fn test_resource_opts() {
+        let opts = RunEphemeralOpts::try_parse_from([
+            "run",
+            "--memory=4G",
+            "--cpu-affinity=0-3",
+            "--io-priority=7",
+            "--nice",
+            "-5",
+            "--cpu-quota=1.5",
+            "--io-weight=50",
+            "--memory-max=6G",
+            "img",
+        ])
+        .unwrap();
+        let limits = opts.common.resource_limits();
+        assert_eq!(limits.cpu_affinity.as_deref(), Some("0-3"));
+        assert_eq!(limits.io_priority, Some(7));
+        assert_eq!(limits.nice_level, Some(-5));
+        assert!(limits.validate().is_ok());
+        assert_eq!(
+            opts.common.podman_cgroup_args().unwrap(),
+            ["--cpus=1.5", "--blkio-weight=50", "--memory=6144m"]
+        );
+
+        // Out of range values are rejected when parsing
+        for arg in ["--io-priority=8", "--nice=20", "--io-weight=5"] {
+            assert!(RunEphemeralOpts::try_parse_from(["run", arg, "img"]).is_err());
+        }
+
+        // The memory limit must leave room for the guest memory
+        let opts =
+            RunEphemeralOpts::try_parse_from(["run", "--memory=4G", "--memory-max=4G", "img"])
+                .unwrap();
+        assert!(opts.common.podman_cgroup_args().is_err());
+        let opts = RunEphemeralOpts::try_parse_from(["run", "--cpu-quota=0", "img"]).unwrap();
+        assert!(opts.common.podman_cgroup_args().is_err());
+    }
+
+    #[test]
+    fn test_tpm_opts() {
+        let opts = RunEphemeralOpts::try_parse_from(["run", "img"]).unwrap();
+        assert!(opts.common.tpm_config().is_none());
+        assert!(opts.common.podman_tpm_args().unwrap().is_empty());
+
+        let opts = RunEphemeralOpts::try_parse_from(["run", "--tpm", "img"]).unwrap();
+        let tpm = opts.common.tpm_config().unwrap();
+        assert_eq!(tpm.socket_path, SWTPM_SOCKET_PATH);
+        assert_ne!(tpm.state_dir, TPM_STATE_MOUNT);
+
+        let td = tempfile::tempdir().unwrap();
+        let state = Utf8Path::from_path(td.path()).unwrap().join("tpm");
+        let opts = RunEphemeralOpts::try_parse_from([
+            "run",
+            "--tpm",
+            &format!("--tpm-state={state}"),
+            "img",
+        ])
+        .unwrap();
+        assert_eq!(opts.common.tpm_config().unwrap().state_dir, TPM_STATE_MOUNT);
+        let args = opts.common.podman_tpm_args().unwrap();
+        assert!(state.is_dir());
+        assert_eq!(
+            args[1],
+            format!("{}:{TPM_STATE_MOUNT}", state.canonicalize_utf8().unwrap())
+        );
+
+        // The state directory is only meaningful with a TPM
+        assert!(RunEphemeralOpts::try_parse_from(["run", "--tpm-state=/tmp", "img"]).is_err());
+        let opts =
+            RunEphemeralOpts::try_parse_from(["run", "--tpm", "--arch=s390x", "img"]).unwrap();
+        assert!(opts.common.podman_tpm_args().is_err());
+    }
+
     #[test]
     fn test_journal_json_to_text() {
         // _SYSTEMD_UNIT takes priority over SYSLOG_IDENTIFIER, with PID
@@ -2214,4 +3490,50 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_arch_image_name() {
+        let cases = [
+            (
+                "quay.io/fedora/fedora-bootc:42",
+                "quay.io/fedora/fedora-bootc:42-aarch64",
+            ),
+            (
+                "quay.io/fedora/fedora-bootc",
+                "quay.io/fedora/fedora-bootc:latest-aarch64",
+            ),
+            ("localhost:5000/os:v1", "localhost:5000/os:v1-aarch64"),
+            ("localhost:5000/os", "localhost:5000/os:latest-aarch64"),
+            (
+                "quay.io/os@sha256:0123456789abcdef0123",
+                "quay.io/os:sha256-0123456789ab-aarch64",
+            ),
+        ];
+        for (image, expected) in cases {
+            assert_eq!(arch_image_name(image, Arch::Aarch64), expected, "{image}");
+        }
+        assert_eq!(
+            image_repository("localhost:5000/os:v1"),
+            "localhost:5000/os"
+        );
+        assert_eq!(image_repository("quay.io/os@sha256:0123"), "quay.io/os");
+    }
+
+    #[test]
+    fn test_platform_digest() {
+        let list = serde_json::json!({
+            "schemaVersion": 2,
+            "manifests": [
+                {"digest": "sha256:amd", "platform": {"architecture": "amd64", "os": "linux"}},
+                {"digest": "sha256:arm", "platform": {"architecture": "arm64", "os": "linux", "variant": "v8"}},
+                {"digest": "sha256:att", "platform": {"architecture": "unknown", "os": "unknown"}},
+            ]
+        });
+        assert_eq!(platform_digest(&list, Arch::X86_64), Some("sha256:amd"));
+        assert_eq!(platform_digest(&list, Arch::Aarch64), Some("sha256:arm"));
+        assert_eq!(platform_digest(&list, Arch::S390x), None);
+        // A single manifest, not a list
+        let single = serde_json::json!({"schemaVersion": 2, "layers": []});
+        assert_eq!(platform_digest(&single, Arch::Aarch64), None);
+    }
 }
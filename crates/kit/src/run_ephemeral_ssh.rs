@@ -1,14 +1,17 @@
+use camino::Utf8Path;
 use color_eyre::eyre::{eyre, Context as _};
 use color_eyre::Result;
 use indicatif::ProgressBar;
+use std::collections::HashMap;
 use std::os::unix::process::CommandExt;
 use std::process::{Command, Stdio};
 use std::time::{Duration, Instant};
-use tracing::debug;
+use tracing::{debug, warn};
 
+use crate::boot_profile;
 use crate::run_ephemeral::{run_detached, RunEphemeralOpts};
 use crate::ssh;
-use crate::supervisor_status::{SupervisorState, SupervisorStatus};
+use crate::supervisor_status::{GuestFailure, SupervisorState, SupervisorStatus};
 
 /// Container state from podman inspect
 #[derive(Debug, serde::Deserialize)]
@@ -118,6 +121,9 @@ impl Drop for ContainerCleanup {
         if let Err(e) = result {
             tracing::warn!("Failed to remove container {}: {}", self.container_id, e);
         }
+        if let Err(e) = crate::image_archive::prune_stores() {
+            tracing::warn!("Failed to prune unused image stores: {e:#}");
+        }
     }
 }
 
@@ -135,7 +141,7 @@ pub struct RunEphemeralSshOpts {
 }
 
 /// Check if container is running
-fn is_container_running(container_name: &str) -> Result<bool> {
+pub(crate) fn is_container_running(container_name: &str) -> Result<bool> {
     let output = Command::new("podman")
         .args([
             "inspect",
@@ -151,17 +157,40 @@ fn is_container_running(container_name: &str) -> Result<bool> {
     Ok(state.trim() == "running")
 }
 
+/// Check if the VM in the container runs under software emulation (TCG)
+pub(crate) fn is_container_emulated(container_name: &str) -> bool {
+    let output = Command::new("podman")
+        .args([
+            "inspect",
+            "--format",
+            "{{json .Config.Labels}}",
+            "--",
+            container_name,
+        ])
+        .output();
+    let Ok(output) = output else {
+        return false;
+    };
+    // Labels are null when there are none
+    let labels: HashMap<String, String> =
+        serde_json::from_slice(&output.stdout).unwrap_or_default();
+    let (key, value) = crate::run_ephemeral::TCG_LABEL
+        .split_once('=')
+        .expect("label is key=value");
+    labels.get(key).map(String::as_str) == Some(value)
+}
+
 /// Spawn the status monitor subprocess and return the child process.
 ///
 /// The monitor watches /run/supervisor-status.json inside the container via
 /// inotify and streams JSON status lines to stdout.
-fn spawn_status_monitor(container_name: &str) -> Result<std::process::Child> {
+pub(crate) fn spawn_status_monitor(container_name: &str) -> Result<std::process::Child> {
     let mut cmd = Command::new("podman");
     cmd.args([
         "exec",
         "--",
         container_name,
-        "/var/lib/bcvk/entrypoint",
+        crate::run_ephemeral::ENTRYPOINT,
         "monitor-status",
     ]);
     // SAFETY: This API is safe to call in a forked child.
@@ -184,17 +213,34 @@ enum ReadinessEvent {
     SshReady,
 }
 
+/// The readiness timeout: `timeout` if given, otherwise [`SSH_TIMEOUT`],
+/// scaled for software emulation.
+fn readiness_timeout(timeout: Option<Duration>, emulated: bool) -> Duration {
+    match timeout {
+        Some(timeout) => timeout,
+        None if emulated => SSH_TIMEOUT * crate::run_ephemeral::TCG_TIMEOUT_SCALE,
+        None => SSH_TIMEOUT,
+    }
+}
+
 /// Wait for SSH to be ready, using vsock boot progress and SSH polling concurrently.
 ///
 /// Starts the vsock-based status monitor alongside SSH connectivity polling,
 /// each in their own thread. Both write to a shared channel; the main thread
 /// blocks on recv_timeout() so no CPU is burned polling.
+///
+/// An explicit `timeout` is used as is; only the default is scaled when the
+/// VM uses software emulation.
 pub fn wait_for_ssh_ready(
     container_name: &str,
     timeout: Option<Duration>,
     progress: ProgressBar,
 ) -> Result<(std::time::Duration, ProgressBar)> {
-    let timeout = timeout.unwrap_or(SSH_TIMEOUT);
+    let timeout = readiness_timeout(
+        timeout,
+        timeout.is_none() && is_container_emulated(container_name),
+    );
+    debug!("Readiness timeout: {}s", timeout.as_secs());
     let start = Instant::now();
 
     if !is_container_running(container_name)? {
@@ -308,6 +354,13 @@ pub fn wait_for_ssh_ready(
                                 SupervisorState::WaitingForSystemd => {
                                     progress.set_message("Waiting for systemd...");
                                 }
+                                SupervisorState::Failed(failure) => {
+                                    if let Some(ref mut child) = monitor_child {
+                                        let _ = child.kill();
+                                    }
+                                    progress.finish_and_clear();
+                                    return Err(failure.clone().into());
+                                }
                             }
                         }
                     }
@@ -320,13 +373,89 @@ pub fn wait_for_ssh_ready(
     }
 }
 
+/// Collect the phases recorded in the container and the guest's boot times,
+/// and write the profile.
+pub(crate) fn write_boot_profile(
+    mut profile: boot_profile::Recorder,
+    container_name: &str,
+    path: &Utf8Path,
+) -> Result<()> {
+    let output = Command::new("podman")
+        .args([
+            "exec",
+            "--",
+            container_name,
+            "cat",
+            boot_profile::EVENTS_PATH,
+        ])
+        .output()
+        .context("Reading boot profile events")?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "Failed to read boot profile events: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    profile.add_container_events(&String::from_utf8_lossy(&output.stdout));
+
+    eprintln!("Waiting for the guest to finish booting...");
+    let guest = ssh::connect_captured(
+        container_name,
+        vec![boot_profile::GUEST_COMMAND.to_string()],
+    )?;
+    if guest.exit_code != 0 {
+        return Err(eyre!("Failed to query guest boot times: {}", guest.stderr));
+    }
+    profile
+        .finish(boot_profile::GuestTimes::parse(&guest.stdout))
+        .write(path)
+}
+
+/// Exit status of `ssh` when the connection failed, used for guest failures
+const SSH_CONNECTION_FAILED: i32 = 255;
+
 /// Run an ephemeral pod and immediately SSH into it, with lifecycle binding
+///
+/// bcvk exits with the status of the SSH session. The distinct exit codes of
+/// guest failures could be those of the command run in the VM, so a guest
+/// failure is only described on stderr, and bcvk exits like ssh does when
+/// the connection fails.
 pub fn run_ephemeral_ssh(opts: RunEphemeralSshOpts) -> Result<()> {
+    let exit_code = match run_ssh_session(opts) {
+        Ok(exit_code) => exit_code,
+        Err(e) => match e.downcast_ref::<GuestFailure>() {
+            Some(failure) => {
+                eprintln!(
+                    "error: {}",
+                    crate::boot_failure::describe(failure).trim_end()
+                );
+                SSH_CONNECTION_FAILED
+            }
+            None => return Err(e),
+        },
+    };
+    std::process::exit(exit_code);
+}
+
+/// Run the VM and the SSH session, returning the exit code of the session.
+/// The container is removed on return.
+fn run_ssh_session(opts: RunEphemeralSshOpts) -> Result<i32> {
     // Start the ephemeral pod in detached mode with SSH enabled
     let mut ephemeral_opts = opts.run_opts.clone();
     ephemeral_opts.podman.detach = true;
     ephemeral_opts.common.ssh_keygen = true; // Enable SSH key generation and access
 
+    if let Some(path) = ephemeral_opts.boot_profile.as_deref() {
+        boot_profile::validate_path(path)?;
+    }
+    let mut profile = ephemeral_opts
+        .boot_profile
+        .is_some()
+        .then(|| boot_profile::Recorder::new(&ephemeral_opts.image));
+    if let Some(profile) = profile.as_mut() {
+        profile.mark("podman-start");
+    }
+
     debug!("Starting ephemeral VM...");
     let container_id = run_detached(ephemeral_opts)?;
     debug!("Ephemeral VM started with container ID: {}", container_id);
@@ -342,6 +471,13 @@ pub fn run_ephemeral_ssh(opts: RunEphemeralSshOpts) -> Result<()> {
     let (_duration, progress_bar) = wait_for_ssh_ready(&container_name, None, progress_bar)?;
     progress_bar.finish_and_clear();
 
+    if let (Some(mut profile), Some(path)) = (profile, opts.run_opts.boot_profile.as_deref()) {
+        profile.mark("ssh-ready");
+        if let Err(e) = write_boot_profile(profile, &container_name, path) {
+            warn!("Failed to write boot profile: {e:#}");
+        }
+    }
+
     // Execute SSH connection directly (no thread needed for this)
     // This allows SSH output to be properly forwarded to stdout/stderr
     debug!("Connecting to SSH with args: {:?}", opts.ssh_args);
@@ -355,10 +491,35 @@ pub fn run_ephemeral_ssh(opts: RunEphemeralSshOpts) -> Result<()> {
     let exit_code = status.code().unwrap_or(1);
     debug!("SSH exit code: {}", exit_code);
 
-    // Explicitly drop the cleanup guard before exit to ensure container removal
-    // (std::process::exit doesn't run drop handlers)
-    drop(_cleanup);
+    // The connection is lost when the watchdog or --max-runtime powered off the VM
+    if exit_code == SSH_CONNECTION_FAILED {
+        if let Some(failure) = crate::ephemeral::guest_failure_of(&container_name) {
+            return Err(failure.into());
+        }
+    }
+    Ok(exit_code)
+}
 
-    // Exit with SSH client's exit code
-    std::process::exit(exit_code);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness_timeout() {
+        let scaled = SSH_TIMEOUT * crate::run_ephemeral::TCG_TIMEOUT_SCALE;
+        let explicit = Duration::from_secs(30);
+        let cases = [
+            (None, false, SSH_TIMEOUT),
+            (None, true, scaled),
+            (Some(explicit), false, explicit),
+            (Some(explicit), true, explicit),
+        ];
+        for (timeout, emulated, expected) in cases {
+            assert_eq!(
+                readiness_timeout(timeout, emulated),
+                expected,
+                "{timeout:?} emulated={emulated}"
+            );
+        }
+    }
 }
@@ -0,0 +1,522 @@
+//! Secure Boot key generation and signing (`bcvk secureboot`)
+//!
+//! `keygen` creates a PK/KEK/db key hierarchy in the layout expected by
+//! `--secure-boot-keys` (see [`crate::libvirt::secureboot::SecureBootKeys`]),
+//! `sign` signs EFI binaries with the db key, and `verify` checks that the
+//! EFI binaries of an image are signed by it. Keys are generated with
+//! `openssl`; signing and verification use `sbsign` and `sbverify` from
+//! sbsigntools.
+
+use std::io::Write as _;
+use std::os::unix::fs::PermissionsExt as _;
+use std::process::{Command, Stdio};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use comfy_table::{presets::UTF8_FULL, Table};
+use tracing::debug;
+
+use crate::libvirt::secureboot::{GUID_FILE, KEY_NAMES};
+
+/// Shell script run in an image, writing a tar archive of the EFI binaries
+/// the firmware verifies to stdout. An unsigned `X.efi` is skipped if the
+/// signed `X.efi.signed` is installed instead. The root is `$1`, or `/`.
+const COLLECT_EFI_SCRIPT: &str = r#"cd "${1:-/}" || exit 1
+set --
+for f in usr/lib/bootupd/updates/EFI/BOOT/BOOT*.EFI \
+         usr/lib/systemd/boot/efi/systemd-boot*.efi \
+         usr/lib/systemd/boot/efi/systemd-boot*.efi.signed \
+         boot/EFI/Linux/*.efi boot/EFI/Linux/*.efi.extra.d/*.addon.efi \
+         usr/lib/modules/*/*.efi usr/lib/modules/*/*.efi.extra.d/*.addon.efi; do
+    if [ -f "$f" ] && [ ! -f "$f.signed" ]; then set -- "$@" "$f"; fi
+done
+if [ $# -gt 0 ]; then exec tar -cf - -- "$@"; fi
+"#;
+
+/// Manage Secure Boot keys and signatures
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum SecureBootOpts {
+    /// Generate a PK/KEK/db key hierarchy for --secure-boot-keys
+    Keygen(KeygenOpts),
+    /// Sign EFI binaries (UKIs, addons, systemd-boot) with the db key
+    Sign(SignOpts),
+    /// Check that the EFI binaries of an image are signed by the db key
+    Verify(VerifyOpts),
+}
+
+/// Options for `bcvk secureboot keygen`
+#[derive(Debug, clap::Parser)]
+pub(crate) struct KeygenOpts {
+    /// Directory to write the keys to (created if needed)
+    pub(crate) dir: Utf8PathBuf,
+
+    /// Common name prefix of the certificates
+    #[clap(long, default_value = "bcvk")]
+    pub(crate) common_name: String,
+
+    /// Validity of the certificates in days
+    #[clap(long, default_value_t = 3650)]
+    pub(crate) days: u32,
+}
+
+/// Options for `bcvk secureboot sign`
+#[derive(Debug, clap::Parser)]
+pub(crate) struct SignOpts {
+    /// Directory containing the keys, as created by `bcvk secureboot keygen`
+    #[clap(long)]
+    pub(crate) keys: Utf8PathBuf,
+
+    /// Write the signed binary here instead of signing in place (only with a single input)
+    #[clap(long, short = 'o')]
+    pub(crate) output: Option<Utf8PathBuf>,
+
+    /// EFI binaries to sign
+    #[clap(required = true)]
+    pub(crate) files: Vec<Utf8PathBuf>,
+}
+
+/// Options for `bcvk secureboot verify`
+#[derive(Debug, clap::Parser)]
+pub(crate) struct VerifyOpts {
+    /// Directory containing the keys, as created by `bcvk secureboot keygen`
+    #[clap(long)]
+    pub(crate) keys: Utf8PathBuf,
+
+    /// Container image to check
+    pub(crate) image: String,
+}
+
+impl SecureBootOpts {
+    pub(crate) fn run(self) -> Result<()> {
+        match self {
+            SecureBootOpts::Keygen(opts) => keygen(&opts),
+            SecureBootOpts::Sign(opts) => sign(&opts),
+            SecureBootOpts::Verify(opts) => {
+                let results = verify_image(&opts.image, &opts.keys)?;
+                print_results(&results);
+                check_results(&opts.image, &results)
+            }
+        }
+    }
+}
+
+/// Run a tool, returning an error with its stderr if it fails
+fn run_tool(cmd: &mut Command) -> Result<()> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    debug!("Running {cmd:?}");
+    let output = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => eyre!("{program} not found; please install it"),
+        _ => eyre!("Running {program}: {e}"),
+    })?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// The `openssl req` arguments creating the self-signed key `name` in `dir`
+fn openssl_req_args(dir: &Utf8Path, name: &str, common_name: &str, days: u32) -> Vec<String> {
+    vec![
+        "req".into(),
+        "-new".into(),
+        "-x509".into(),
+        "-newkey".into(),
+        "rsa:4096".into(),
+        "-nodes".into(),
+        "-sha256".into(),
+        "-days".into(),
+        days.to_string(),
+        "-subj".into(),
+        format!("/CN={common_name} {name}/"),
+        "-keyout".into(),
+        dir.join(format!("{name}.key")).into_string(),
+        "-out".into(),
+        dir.join(format!("{name}.crt")).into_string(),
+    ]
+}
+
+fn keygen(opts: &KeygenOpts) -> Result<()> {
+    let dir = &opts.dir;
+    if opts.common_name.contains('/') {
+        return Err(eyre!("--common-name cannot contain '/'"));
+    }
+    let files = std::iter::once(GUID_FILE.to_owned())
+        .chain(
+            KEY_NAMES
+                .iter()
+                .flat_map(|name| [format!("{name}.key"), format!("{name}.crt")]),
+        )
+        .map(|f| dir.join(f));
+    for path in files {
+        if path.try_exists()? {
+            return Err(eyre!("Refusing to overwrite existing {path}"));
+        }
+    }
+    std::fs::create_dir_all(dir).with_context(|| format!("Creating {dir}"))?;
+
+    for name in KEY_NAMES {
+        run_tool(Command::new("openssl").args(openssl_req_args(
+            dir,
+            name,
+            &opts.common_name,
+            opts.days,
+        )))
+        .with_context(|| format!("Generating {name} key"))?;
+        let key = dir.join(format!("{name}.key"));
+        std::fs::set_permissions(&key, std::fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("Setting permissions of {key}"))?;
+    }
+
+    let guid = uuid::Uuid::new_v4();
+    std::fs::write(dir.join(GUID_FILE), format!("{guid}\n"))
+        .with_context(|| format!("Writing {GUID_FILE}"))?;
+
+    println!("Generated Secure Boot keys with owner GUID {guid} in {dir}");
+    println!("Sign EFI binaries with: bcvk secureboot sign --keys {dir} FILE...");
+    println!(
+        "Use them with: bcvk libvirt run --firmware uefi-secure --secure-boot-keys {dir} IMAGE"
+    );
+    Ok(())
+}
+
+/// The db key and certificate in `keys`
+fn db_key(keys: &Utf8Path) -> Result<(Utf8PathBuf, Utf8PathBuf)> {
+    let cert = crate::libvirt::secureboot::SecureBootKeys::load(keys)?.db_cert;
+    let key = keys.join("db.key");
+    if !key.try_exists()? {
+        return Err(eyre!(
+            "db.key not found in {keys}; signing requires the private key"
+        ));
+    }
+    Ok((key, cert))
+}
+
+fn sign(opts: &SignOpts) -> Result<()> {
+    if opts.output.is_some() && opts.files.len() > 1 {
+        return Err(eyre!("--output can only be used with a single input"));
+    }
+    let (key, cert) = db_key(&opts.keys)?;
+
+    for file in &opts.files {
+        let dest = opts.output.clone().unwrap_or_else(|| file.clone());
+        // sbsign cannot write its input, so sign into a file next to the
+        // destination and rename it over.
+        let parent = match dest.parent() {
+            Some(p) if !p.as_str().is_empty() => p,
+            _ => Utf8Path::new("."),
+        };
+        let tmp = tempfile::NamedTempFile::new_in(parent)
+            .with_context(|| format!("Creating temporary file in {parent}"))?;
+        let tmp_path = Utf8Path::from_path(tmp.path()).ok_or_else(|| eyre!("Non-UTF8 path"))?;
+        run_tool(
+            Command::new("sbsign")
+                .arg("--key")
+                .arg(&key)
+                .arg("--cert")
+                .arg(&cert)
+                .arg("--output")
+                .arg(tmp_path)
+                .arg(file),
+        )
+        .with_context(|| format!("Signing {file}"))?;
+        let perms = std::fs::metadata(file)
+            .with_context(|| format!("Reading {file}"))?
+            .permissions();
+        std::fs::set_permissions(tmp_path, perms)?;
+        tmp.persist(&dest)
+            .with_context(|| format!("Writing {dest}"))?;
+        println!("Signed {dest}");
+    }
+    Ok(())
+}
+
+/// Role of an EFI binary in the boot chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EfiBinaryKind {
+    /// Removable media boot loader (e.g. shim), as installed by bootupd
+    BootLoader,
+    /// systemd-boot
+    SystemdBoot,
+    /// Unified kernel image
+    Uki,
+    /// UKI addon
+    Addon,
+}
+
+impl EfiBinaryKind {
+    /// Classify a path collected by [`COLLECT_EFI_SCRIPT`]
+    fn from_path(path: &str) -> Option<Self> {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        if name.ends_with(".addon.efi") {
+            Some(Self::Addon)
+        } else if name.starts_with("systemd-boot") {
+            Some(Self::SystemdBoot)
+        } else if path.starts_with("usr/lib/bootupd/") {
+            Some(Self::BootLoader)
+        } else if name.ends_with(".efi") {
+            Some(Self::Uki)
+        } else {
+            None
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::BootLoader => "boot loader",
+            Self::SystemdBoot => "systemd-boot",
+            Self::Uki => "UKI",
+            Self::Addon => "UKI addon",
+        }
+    }
+}
+
+/// Signature check result of an EFI binary of an image
+#[derive(Debug)]
+pub(crate) struct VerifyResult {
+    /// Path in the image, relative to its root
+    pub(crate) path: String,
+    /// Role of the binary
+    pub(crate) kind: EfiBinaryKind,
+    /// Whether it is signed by the db key
+    pub(crate) signed: bool,
+}
+
+/// Copy the EFI binaries of `image` into `dest`, returning their relative paths
+fn extract_efi_binaries(image: &str, dest: &Utf8Path) -> Result<Vec<String>> {
+    let output = Command::new("podman")
+        .args([
+            "run",
+            "--rm",
+            "--network=none",
+            "--entrypoint=/bin/sh",
+            "--",
+            image,
+            "-c",
+            COLLECT_EFI_SCRIPT,
+        ])
+        .stderr(Stdio::piped())
+        .output()
+        .context("Running podman")?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "Listing EFI binaries of {image} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    if output.stdout.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // GNU tar lists the extracted files with -v
+    let mut tar = Command::new("tar")
+        .args(["-xvf", "-", "-C", dest.as_str()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Running tar")?;
+    tar.stdin
+        .take()
+        .expect("piped stdin")
+        .write_all(&output.stdout)?;
+    let tar = tar.wait_with_output()?;
+    if !tar.status.success() {
+        return Err(eyre!("Extracting EFI binaries failed: {}", tar.status));
+    }
+    Ok(String::from_utf8_lossy(&tar.stdout)
+        .lines()
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+/// Check the signatures of the EFI binaries of `image` against the db
+/// certificate in `keys`.
+pub(crate) fn verify_image(image: &str, keys: &Utf8Path) -> Result<Vec<VerifyResult>> {
+    let cert = crate::libvirt::secureboot::SecureBootKeys::load(keys)?.db_cert;
+    let td = tempfile::tempdir()?;
+    let td_path = Utf8Path::from_path(td.path()).ok_or_else(|| eyre!("Non-UTF8 tempdir"))?;
+    let paths = extract_efi_binaries(image, td_path)?;
+
+    let mut results = Vec::new();
+    for path in paths {
+        let Some(kind) = EfiBinaryKind::from_path(&path) else {
+            continue;
+        };
+        let status = Command::new("sbverify")
+            .arg("--cert")
+            .arg(&cert)
+            .arg(td_path.join(&path))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => eyre!("sbverify not found; please install it"),
+                _ => eyre!("Running sbverify: {e}"),
+            })?;
+        debug!("{path}: sbverify {status}");
+        results.push(VerifyResult {
+            path,
+            kind,
+            signed: status.success(),
+        });
+    }
+    Ok(results)
+}
+
+fn print_results(results: &[VerifyResult]) {
+    let mut table = Table::new();
+    table
+        .load_style(UTF8_FULL)
+        .set_header(vec!["BINARY", "KIND", "SIGNED"]);
+    for r in results {
+        table.add_row(vec![
+            format!("/{}", r.path),
+            r.kind.as_str().to_owned(),
+            if r.signed { "yes" } else { "NO" }.to_owned(),
+        ]);
+    }
+    println!("{table}");
+}
+
+/// Turn the results into an error if the image would fail to boot
+pub(crate) fn check_results(image: &str, results: &[VerifyResult]) -> Result<()> {
+    if results.is_empty() {
+        return Err(eyre!("No EFI binaries found in {image}"));
+    }
+    let unsigned: Vec<_> = results
+        .iter()
+        .filter(|r| !r.signed)
+        .map(|r| format!("/{}", r.path))
+        .collect();
+    if !unsigned.is_empty() {
+        return Err(eyre!(
+            "EFI binaries of {image} are not signed by the db key: {}\n\
+             Sign them with `bcvk secureboot sign` when building the image.",
+            unsigned.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openssl_req_args() {
+        let args = openssl_req_args(Utf8Path::new("/keys"), "KEK", "Test", 30);
+        let pos = |flag: &str| args.iter().position(|a| a == flag).unwrap() + 1;
+        assert_eq!(args[pos("-subj")], "/CN=Test KEK/");
+        assert_eq!(args[pos("-days")], "30");
+        assert_eq!(args[pos("-keyout")], "/keys/KEK.key");
+        assert_eq!(args[pos("-out")], "/keys/KEK.crt");
+    }
+
+    #[test]
+    fn test_keygen_refuses_overwrite() {
+        let td = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(td.path()).unwrap().to_owned();
+        std::fs::write(dir.join("db.crt"), "").unwrap();
+        let opts = KeygenOpts {
+            dir,
+            common_name: "bcvk".into(),
+            days: 1,
+        };
+        let err = keygen(&opts).unwrap_err().to_string();
+        assert!(err.contains("Refusing to overwrite"), "{err}");
+    }
+
+    #[test]
+    fn test_efi_binary_kind() {
+        use EfiBinaryKind::*;
+        for (path, kind) in [
+            (
+                "usr/lib/bootupd/updates/EFI/BOOT/BOOTX64.EFI",
+                Some(BootLoader),
+            ),
+            (
+                "usr/lib/systemd/boot/efi/systemd-bootx64.efi.signed",
+                Some(SystemdBoot),
+            ),
+            (
+                "usr/lib/systemd/boot/efi/systemd-bootaa64.efi",
+                Some(SystemdBoot),
+            ),
+            ("usr/lib/modules/6.12.0/6.12.0.efi", Some(Uki)),
+            ("boot/EFI/Linux/fedora.efi", Some(Uki)),
+            (
+                "usr/lib/modules/6.12.0/6.12.0.efi.extra.d/debug.addon.efi",
+                Some(Addon),
+            ),
+            ("usr/lib/modules/6.12.0/vmlinuz", None),
+        ] {
+            assert_eq!(EfiBinaryKind::from_path(path), kind, "{path}");
+        }
+    }
+
+    #[test]
+    fn test_collect_efi_script() {
+        let td = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(td.path()).unwrap();
+        let files = [
+            "usr/lib/systemd/boot/efi/systemd-bootx64.efi",
+            "usr/lib/systemd/boot/efi/systemd-bootx64.efi.signed",
+            "usr/lib/systemd/boot/efi/systemd-bootaa64.efi",
+            "boot/EFI/Linux/uki.efi",
+        ];
+        for f in files {
+            let path = root.join(f);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let archive = Command::new("sh")
+            .args(["-c", COLLECT_EFI_SCRIPT, "sh", root.as_str()])
+            .output()
+            .unwrap();
+        assert!(archive.status.success());
+        let mut tar = Command::new("tar")
+            .arg("-tf-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        tar.stdin
+            .take()
+            .unwrap()
+            .write_all(&archive.stdout)
+            .unwrap();
+        let listing = tar.wait_with_output().unwrap();
+        let mut listed: Vec<_> = std::str::from_utf8(&listing.stdout)
+            .unwrap()
+            .lines()
+            .collect();
+        listed.sort();
+        assert_eq!(
+            listed,
+            [
+                "boot/EFI/Linux/uki.efi",
+                "usr/lib/systemd/boot/efi/systemd-bootaa64.efi",
+                "usr/lib/systemd/boot/efi/systemd-bootx64.efi.signed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_results() {
+        let result = |path: &str, signed| VerifyResult {
+            path: path.into(),
+            kind: EfiBinaryKind::Uki,
+            signed,
+        };
+        assert!(check_results("img", &[]).is_err());
+        assert!(check_results("img", &[result("a.efi", true)]).is_ok());
+        let err = check_results("img", &[result("a.efi", true), result("b.efi", false)])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("/b.efi"), "{err}");
+        assert!(!err.contains("/a.efi"), "{err}");
+    }
+}
@@ -23,6 +23,22 @@ pub fn shell_escape_command(args: &[String]) -> Result<String, shlex::QuoteError
     shlex::try_join(args.iter().map(|s| s.as_str()))
 }
 
+/// SSH user for ephemeral VMs
+pub(crate) const EPHEMERAL_SSH_USER: &str = "root";
+
+/// SSH host for ephemeral VMs, as seen from inside the container
+pub(crate) const EPHEMERAL_SSH_HOST: &str = "127.0.0.1";
+
+/// SSH port for ephemeral VMs, as seen from inside the container
+pub(crate) const EPHEMERAL_SSH_PORT: u16 = 2222;
+
+/// Path of the SSH private key for ephemeral VMs, inside the container
+pub(crate) fn ephemeral_ssh_key_path() -> Utf8PathBuf {
+    Utf8Path::new("/run/tmproot")
+        .join(CONTAINER_STATEDIR.trim_start_matches('/'))
+        .join("ssh")
+}
+
 /// Represents an SSH keypair with file paths and public key content
 #[derive(Debug, Clone)]
 pub struct SshKeyPair {
@@ -119,7 +135,7 @@ pub fn generate_default_keypair() -> Result<SshKeyPair> {
 ///
 /// Shared between [`connect`] (interactive/passthrough) and
 /// [`connect_captured`] (output capture for IPC).
-fn build_podman_ssh_command(
+pub(crate) fn build_podman_ssh_command(
     container_name: &str,
     args: &[String],
     options: &SshConnectionOptions,
@@ -127,13 +143,13 @@ fn build_podman_ssh_command(
     let mut cmd = Command::new("podman");
     if options.allocate_tty {
         cmd.args(["exec", "-it", "--", container_name, "ssh"]);
+    } else if options.forward_stdin {
+        cmd.args(["exec", "-i", "--", container_name, "ssh"]);
     } else {
         cmd.args(["exec", "--", container_name, "ssh"]);
     }
 
-    let keypath = Utf8Path::new("/run/tmproot")
-        .join(CONTAINER_STATEDIR.trim_start_matches('/'))
-        .join("ssh");
+    let keypath = ephemeral_ssh_key_path();
     cmd.args(["-i", keypath.as_str()]);
 
     options.common.apply_to_command(&mut cmd);
@@ -143,8 +159,8 @@ fn build_podman_ssh_command(
         cmd.arg("-t");
     }
 
-    cmd.arg("root@127.0.0.1");
-    cmd.args(["-p", "2222"]);
+    cmd.arg(format!("{EPHEMERAL_SSH_USER}@{EPHEMERAL_SSH_HOST}"));
+    cmd.args(["-p", &EPHEMERAL_SSH_PORT.to_string()]);
 
     let ssh_args = build_ssh_command(args)?;
     if !ssh_args.is_empty() {
@@ -201,6 +217,7 @@ pub fn connect_captured(container_name: &str, args: Vec<String>) -> Result<Captu
     let options = SshConnectionOptions {
         allocate_tty: false,
         suppress_output: false,
+        forward_stdin: false,
         common: CommonSshOptions::default(),
     };
     let mut cmd = build_podman_ssh_command(container_name, &args, &options)?;
@@ -230,7 +247,7 @@ pub fn connect_via_container(container_name: &str, args: Vec<String>) -> Result<
 }
 
 /// Verify that a container exists and is running
-fn verify_container_running(container_name: &str) -> Result<()> {
+pub(crate) fn verify_container_running(container_name: &str) -> Result<()> {
     let status = Command::new("podman")
         .args([
             "inspect",
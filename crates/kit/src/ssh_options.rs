@@ -72,6 +72,8 @@ pub struct SshConnectionOptions {
     pub allocate_tty: bool,
     /// Suppress output to stdout/stderr (default: false)
     pub suppress_output: bool,
+    /// Forward stdin to the remote command when not allocating a TTY (default: false)
+    pub forward_stdin: bool,
 }
 
 impl Default for SshConnectionOptions {
@@ -80,6 +82,7 @@ impl Default for SshConnectionOptions {
             common: CommonSshOptions::default(),
             allocate_tty: true,
             suppress_output: false,
+            forward_stdin: false,
         }
     }
 }
@@ -98,6 +101,7 @@ impl SshConnectionOptions {
             },
             allocate_tty: false,
             suppress_output: true,
+            forward_stdin: false,
         }
     }
 }
@@ -125,6 +129,7 @@ mod tests {
         assert_eq!(default_opts.common.log_level, "ERROR");
         assert!(default_opts.common.extra_options.is_empty());
         assert!(!default_opts.suppress_output);
+        assert!(!default_opts.forward_stdin);
 
         // Test connectivity test options
         let test_opts = SshConnectionOptions::for_connectivity_test();
@@ -104,7 +104,7 @@ impl StatusFileIterator {
 
 /// Monitor status and stream updates to stdout as JSON lines
 pub fn monitor_and_stream_status() -> Result<()> {
-    let path = "/run/supervisor-status.json";
+    let path = crate::supervisor_status::SUPERVISOR_STATUS_PATH;
 
     let monitor = monitor_status_file(path)?;
 
@@ -3,6 +3,16 @@ use color_eyre::Result;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
+use std::sync::OnceLock;
+
+/// Where the supervisor writes its status inside the container
+pub(crate) const SUPERVISOR_STATUS_PATH: &str = "/run/supervisor-status.json";
+
+/// The failure of the guest, once detected; it overrides later states.
+static FAILURE: OnceLock<GuestFailure> = OnceLock::new();
+
+/// Why `bcvk ephemeral exec` cannot be used with this VM, if it can't.
+static EXEC_UNAVAILABLE: OnceLock<String> = OnceLock::new();
 
 /// Status of the supervisor process and VM
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -14,6 +24,9 @@ pub struct SupervisorStatus {
     pub ssh_access: bool,
     /// True if qemu is running
     pub running: bool,
+    /// Why `bcvk ephemeral exec` is not available, if it isn't
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exec_unavailable: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -25,6 +38,100 @@ pub enum SupervisorState {
     ReachedTarget(String),
     /// VM is ready and accepting connections
     Ready,
+    /// The guest failed to boot, or crashed
+    Failed(GuestFailure),
+}
+
+/// How the guest failed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureKind {
+    /// The kernel panicked
+    KernelPanic,
+    /// The kernel oopsed or hit a BUG
+    KernelOops,
+    /// The guest entered an emergency shell (emergency.target or dracut)
+    EmergencyShell,
+    /// The hardware watchdog fired because the guest stopped responding
+    Watchdog,
+    /// The VM ran longer than `--max-runtime`
+    Timeout,
+}
+
+impl FailureKind {
+    /// The exit code of bcvk when the guest failed this way
+    pub fn exit_code(self) -> i32 {
+        match self {
+            FailureKind::KernelPanic => 3,
+            FailureKind::KernelOops => 4,
+            FailureKind::EmergencyShell => 5,
+            FailureKind::Watchdog => 6,
+            FailureKind::Timeout => 7,
+        }
+    }
+
+    /// The identifier of the failure, as serialized
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FailureKind::KernelPanic => "kernel-panic",
+            FailureKind::KernelOops => "kernel-oops",
+            FailureKind::EmergencyShell => "emergency-shell",
+            FailureKind::Watchdog => "watchdog",
+            FailureKind::Timeout => "timeout",
+        }
+    }
+
+    /// What happened to the VM, for messages
+    pub fn summary(self) -> &'static str {
+        match self {
+            FailureKind::Watchdog | FailureKind::Timeout => "VM was powered off",
+            _ => "Guest failed to boot",
+        }
+    }
+
+    /// The failure of a supervisor that exited with `code`, if it was one
+    pub fn from_exit_code(code: i32) -> Option<Self> {
+        [
+            FailureKind::KernelPanic,
+            FailureKind::KernelOops,
+            FailureKind::EmergencyShell,
+            FailureKind::Watchdog,
+            FailureKind::Timeout,
+        ]
+        .into_iter()
+        .find(|k| k.exit_code() == code)
+    }
+}
+
+impl std::fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FailureKind::KernelPanic => "kernel panic",
+            FailureKind::KernelOops => "kernel oops",
+            FailureKind::EmergencyShell => "emergency shell",
+            FailureKind::Watchdog => "watchdog",
+            FailureKind::Timeout => "timeout",
+        })
+    }
+}
+
+/// A detected guest failure, with the last lines logged before it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, thiserror::Error)]
+#[error("{reason}")]
+pub struct GuestFailure {
+    /// How the guest failed
+    pub kind: FailureKind,
+    /// Human-readable description
+    pub reason: String,
+    /// The last lines of guest output before the failure
+    pub console: Vec<String>,
+}
+
+impl GuestFailure {
+    /// The failure of the guest run by this supervisor, if any
+    pub fn current() -> Option<&'static GuestFailure> {
+        FAILURE.get()
+    }
 }
 
 impl SupervisorStatus {
@@ -69,10 +176,28 @@ impl StatusWriter {
         Self { path: path.into() }
     }
 
-    pub fn update(&self, status: SupervisorStatus) -> color_eyre::Result<()> {
+    pub fn update(&self, mut status: SupervisorStatus) -> color_eyre::Result<()> {
+        if let Some(failure) = GuestFailure::current() {
+            status.state = Some(SupervisorState::Failed(failure.clone()));
+        }
+        status.exec_unavailable = EXEC_UNAVAILABLE.get().cloned();
         status.write_to_file(&self.path)
     }
 
+    /// Record that `bcvk ephemeral exec` cannot be used, and why.
+    pub fn set_exec_unavailable(&self, reason: String) -> color_eyre::Result<()> {
+        let _ = EXEC_UNAVAILABLE.set(reason);
+        self.update(SupervisorStatus::read_from_file(&self.path)?)
+    }
+
+    /// Record that the guest failed; only the first failure is kept.
+    pub fn fail(&self, failure: GuestFailure) -> color_eyre::Result<()> {
+        if FAILURE.set(failure.clone()).is_err() {
+            return Ok(());
+        }
+        self.update_state(SupervisorState::Failed(failure))
+    }
+
     pub fn update_state(&self, state: SupervisorState) -> color_eyre::Result<()> {
         self.update(SupervisorStatus::new(state))
     }
@@ -84,3 +209,46 @@ impl StatusWriter {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_exit_codes() {
+        for (kind, code) in [
+            (FailureKind::KernelPanic, 3),
+            (FailureKind::KernelOops, 4),
+            (FailureKind::EmergencyShell, 5),
+            (FailureKind::Watchdog, 6),
+            (FailureKind::Timeout, 7),
+        ] {
+            assert_eq!(kind.exit_code(), code);
+            assert_eq!(FailureKind::from_exit_code(code), Some(kind));
+        }
+        assert_eq!(FailureKind::from_exit_code(0), None);
+        assert_eq!(FailureKind::from_exit_code(1), None);
+    }
+
+    #[test]
+    fn test_exec_unavailable() {
+        // Written by older versions
+        let status: SupervisorStatus =
+            serde_json::from_str(r#"{"state":"ready","ssh_access":true,"running":true}"#).unwrap();
+        assert_eq!(status.exec_unavailable, None);
+        assert!(!serde_json::to_string(&status)
+            .unwrap()
+            .contains("exec_unavailable"));
+
+        let status = SupervisorStatus {
+            exec_unavailable: Some("vsock is not available".into()),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&status).unwrap();
+        let parsed: SupervisorStatus = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            parsed.exec_unavailable.as_deref(),
+            Some("vsock is not available")
+        );
+    }
+}
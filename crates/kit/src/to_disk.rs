@@ -71,7 +71,14 @@
 //! # Custom filesystem and size
 //! bcvk to-disk --filesystem xfs --root-size 20G \
 //!     quay.io/centos-bootc/centos-bootc:stream10 output.img
+//!
+//! # Install with Anaconda from an installer ISO
+//! bcvk to-disk --via-iso boot.iso --kickstart ks.cfg \
+//!     quay.io/centos-bootc/centos-bootc:stream10 output.img
 //! ```
+//!
+//! With `--via-iso`, steps 3 and 4 are replaced by booting the installer ISO;
+//! see the `iso_install` module.
 
 use std::io::IsTerminal;
 
@@ -143,6 +150,9 @@ pub struct ToDiskAdditionalOpts {
     #[clap(long)]
     pub dry_run: bool,
 
+    #[clap(flatten)]
+    pub pull: images::PullOpts,
+
     /// Pass an extra argument to the inner `podman run` that executes `bootc
     /// install`.  May be specified multiple times.  Useful for testing edge
     /// cases; for example `--bootc-install-podman-arg=--read-only` stresses
@@ -151,6 +161,26 @@ pub struct ToDiskAdditionalOpts {
     /// read-only /boot (similar to osbuild sandbox environments).
     #[clap(long = "bootc-install-podman-arg")]
     pub bootc_install_podman_args: Vec<String>,
+
+    /// Install with Anaconda booted from this installer ISO instead of
+    /// running `bootc install` (requires --kickstart)
+    #[clap(long, value_name = "ISO", requires = "kickstart")]
+    pub via_iso: Option<Utf8PathBuf>,
+
+    /// Kickstart for the --via-iso installation; the `ostreecontainer` and
+    /// `poweroff` commands are added automatically
+    #[clap(long, value_name = "FILE", requires = "via_iso")]
+    pub kickstart: Option<Utf8PathBuf>,
+
+    /// The image the installed system is updated from, when the source image
+    /// is a local copy under another name (the variant for a foreign --arch)
+    #[clap(skip)]
+    pub(crate) target_imgref: Option<String>,
+
+    /// Additional image store holding the source image, when it was imported
+    /// from an archive
+    #[clap(skip)]
+    pub(crate) image_store: Option<Utf8PathBuf>,
 }
 
 /// Configuration options for installing a bootc container image to disk
@@ -181,6 +211,46 @@ impl ToDiskOpts {
         &self.source_image
     }
 
+    /// The image the installed system is updated from
+    pub(crate) fn target_imgref(&self) -> &str {
+        self.additional
+            .target_imgref
+            .as_deref()
+            .unwrap_or(&self.source_image)
+    }
+
+    /// The cache metadata of the disk this installs
+    ///
+    /// An ISO install is identified by the path of the ISO and the content
+    /// of the kickstart.
+    fn cache_metadata(&self) -> Result<DiskImageMetadata> {
+        let digest =
+            images::inspect_in(&self.source_image, self.additional.image_store.as_deref())?
+                .digest
+                .to_string();
+        let via_iso = self
+            .additional
+            .via_iso
+            .as_ref()
+            .map(|iso| {
+                iso.canonicalize_utf8()
+                    .with_context(|| format!("Opening installer ISO {iso}"))
+            })
+            .transpose()?;
+        let kickstart = self
+            .additional
+            .kickstart
+            .as_ref()
+            .map(|ks| std::fs::read(ks).with_context(|| format!("Reading kickstart {ks}")))
+            .transpose()?;
+        Ok(DiskImageMetadata {
+            arch: self.additional.common.guest_arch().map(|a| a.to_string()),
+            via_iso: via_iso.map(|p| p.into_string()),
+            kickstart: kickstart.map(|k| crate::cache_metadata::sha256_digest(&k)),
+            ..DiskImageMetadata::from(&self.install, &digest, self.target_imgref())
+        })
+    }
+
     /// Resolve and validate the container storage path
     ///
     /// Uses explicit storage_path if specified, otherwise auto-detects container storage.
@@ -198,8 +268,13 @@ impl ToDiskOpts {
         let source_imgref = format!("containers-storage:{}", self.source_image);
 
         // Quote each bootc argument individually to prevent shell injection
+        let mut bootc_args = self.install.to_bootc_args();
+        if let Some(target_imgref) = self.additional.target_imgref.as_ref() {
+            bootc_args.push("--target-imgref".to_owned());
+            bootc_args.push(target_imgref.clone());
+        }
         let mut quoted_bootc_args = Vec::new();
-        for arg in self.install.to_bootc_args() {
+        for arg in bootc_args {
             let quoted = shlex::try_quote(&arg)
                 .map_err(|e| eyre!("Failed to quote bootc argument '{}': {}", arg, e))?;
             quoted_bootc_args.push(quoted.to_string());
@@ -372,7 +447,10 @@ EOF
         }
 
         // Get the image size and multiply by 2 for installation space
-        let image_size = images::get_image_size(&self.source_image)?;
+        let image_size = crate::podman::get_image_size(
+            &self.source_image,
+            self.additional.image_store.as_deref(),
+        )?;
         debug!("Image size for {}: {} bytes", self.source_image, image_size);
 
         // Minimum 4GB, otherwise 2x the image size
@@ -416,6 +494,48 @@ pub fn run(mut opts: ToDiskOpts) -> Result<RunOutcome> {
         opts.source_image = stripped.to_string();
     }
 
+    // Images from archives and OCI layouts are imported into a temporary
+    // store, which the installer VM gets as its host storage; it is removed
+    // when the installation is done
+    let _imported = if crate::image_archive::is_archive_ref(&opts.source_image) {
+        if opts.additional.via_iso.is_some() {
+            return Err(eyre!(
+                "--via-iso requires an image in containers-storage, not {}",
+                opts.source_image
+            ));
+        }
+        if opts.install.storage_path.is_some() {
+            return Err(eyre!(
+                "--storage-path cannot be used with {}",
+                opts.source_image
+            ));
+        }
+        let imported = crate::image_archive::ImportedImage::import(&opts.source_image)?;
+        opts.source_image = imported.name.clone();
+        opts.install.storage_path = Some(imported.graph_root());
+        opts.additional.image_store = Some(imported.graph_root());
+        Some(imported)
+    } else {
+        // Install the variant for the target architecture, as the cache check
+        // and disk sizing inspect it; it has its own local name, and the
+        // installed system is still updated from the source image. A newer
+        // pulled image has a different digest, so the cached disk is regenerated.
+        if let Some(arch) = opts.additional.common.arch.filter(|a| a.is_foreign()) {
+            let local = crate::run_ephemeral::ensure_image_arch(
+                &opts.source_image,
+                arch,
+                opts.additional.pull.policy,
+            )?;
+            if local != opts.source_image {
+                opts.additional.target_imgref =
+                    Some(std::mem::replace(&mut opts.source_image, local));
+            }
+        } else {
+            images::pull(&opts.source_image, opts.additional.pull.policy)?;
+        }
+        None
+    };
+
     // Phase 0: Check for existing cached disk image
     let would_reuse = if opts.target_disk.exists() {
         debug!(
@@ -423,16 +543,10 @@ pub fn run(mut opts: ToDiskOpts) -> Result<RunOutcome> {
             opts.target_disk
         );
 
-        // Get the image digest for comparison
-        let inspect = images::inspect(&opts.source_image)?;
-        let image_digest = inspect.digest.to_string();
-
         // Check if cached disk matches our requirements
         match crate::cache_metadata::check_cached_disk(
             opts.target_disk.as_std_path(),
-            &image_digest,
-            &opts.source_image,
-            &opts.install,
+            &opts.cache_metadata()?,
         )? {
             Ok(()) => {
                 if opts.additional.dry_run {
@@ -517,6 +631,35 @@ pub fn run(mut opts: ToDiskOpts) -> Result<RunOutcome> {
         }
     }
 
+    let result = match (&opts.additional.via_iso, &opts.additional.kickstart) {
+        (Some(iso), Some(kickstart)) => crate::iso_install::install(&opts, iso, kickstart),
+        _ => install_with_bootc(&opts, disk_size),
+    };
+
+    // Handle the result - remove disk file on failure
+    match result {
+        Ok(()) => {
+            // Write metadata to the disk image for caching
+            // Extract values before they're potentially moved
+            let write_result = opts.cache_metadata().and_then(|metadata| {
+                write_disk_metadata(&opts.target_disk, &metadata, &opts.additional.format)
+            });
+            if let Err(e) = write_result {
+                debug!("Failed to write metadata to disk image: {}", e);
+                // Don't fail the operation just because metadata couldn't be written
+            }
+            Ok(RunOutcome::Created)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&opts.target_disk);
+            Err(e)
+        }
+    }
+}
+
+/// Install the image with `bootc install to-disk`, run over SSH in an
+/// ephemeral VM of the image itself.
+fn install_with_bootc(opts: &ToDiskOpts, disk_size: u64) -> Result<()> {
     // Phase 3: Installation command generation
     // Generate complete script including storage setup and bootc install
     let bootc_install_command = opts.generate_bootc_install_command(disk_size)?;
@@ -528,6 +671,13 @@ pub fn run(mut opts: ToDiskOpts) -> Result<RunOutcome> {
 
     let tty = std::io::stdout().is_terminal();
 
+    // An explicit storage path (such as the store of an imported image) is
+    // mounted in place of the auto-detected host storage
+    let (bind_storage_ro, ro_bind_mounts) = match opts.install.storage_path.as_ref() {
+        Some(path) => (false, vec![format!("{path}:hoststorage")]),
+        None => (true, Vec::new()),
+    };
+
     // Configure VM for installation:
     // - Use source image as installer environment
     // - Mount host storage read-only for image access
@@ -535,31 +685,43 @@ pub fn run(mut opts: ToDiskOpts) -> Result<RunOutcome> {
     // - Disable networking (using local storage only)
     let ephemeral_opts = RunEphemeralOpts {
         host_dns_servers: None,
+        install_iso: None,
         image: opts.get_installer_image().to_string(),
         common: common_opts,
         podman: crate::run_ephemeral::CommonPodmanOptions {
             rm: true,     // Clean up container after installation
             detach: true, // Run in detached mode for SSH approach
             tty,
-            label: opts.additional.label,
+            label: opts.additional.label.clone(),
+            // Already pulled by the policy
+            pull: images::PullOpts {
+                policy: images::PullPolicy::Never,
+            },
             ..Default::default()
         },
         // Workaround for https://github.com/containers/container-libs/issues/144#issuecomment-3300424410
         // Basically containers-libs allocates a tempfile for a whole serialization of a layer as a tarball
         // when fetching, so we need enough memory to do so.
         add_swap: Some(format!("{disk_size}")),
-        bind_mounts: Vec::new(),    // No additional bind mounts needed
-        ro_bind_mounts: Vec::new(), // No additional ro bind mounts needed
-        systemd_units_dir: None,    // No custom systemd units
-        bind_storage_ro: true,      // Mount host container storage read-only
+        bind_mounts: Vec::new(), // No additional bind mounts needed
+        ro_bind_mounts,
+        systemd_units_dir: None, // No custom systemd units
+        bind_storage_ro,
         mount_disk_files: vec![format!(
             "{}:output:{}",
             opts.target_disk,
             opts.additional.format.as_str()
         )], // Attach target disk
+        port_mappings: Vec::new(),
+        internal_network: None,
+        network_aliases: Vec::new(),
         kernel_args: Default::default(),
+        boot: Default::default(),
         ignition_config: None,
+        cloud_init: Default::default(),
+        boot_profile: None,
         debug_entrypoint: None,
+        image_store: opts.additional.image_store.clone(),
     };
 
     // Phase 5: SSH-based VM configuration and execution
@@ -604,48 +766,18 @@ pub fn run(mut opts: ToDiskOpts) -> Result<RunOutcome> {
     let _ = std::process::Command::new("podman")
         .args(["rm", "-f", "--", &container_id])
         .output();
-
-    // Handle the result - remove disk file on failure
-    match result {
-        Ok(()) => {
-            // Write metadata to the disk image for caching
-            // Extract values before they're potentially moved
-            let write_result = write_disk_metadata(
-                &opts.source_image,
-                &opts.target_disk,
-                &opts.install,
-                &opts.additional.format,
-            );
-            if let Err(e) = write_result {
-                debug!("Failed to write metadata to disk image: {}", e);
-                // Don't fail the operation just because metadata couldn't be written
-            }
-            Ok(RunOutcome::Created)
-        }
-        Err(e) => {
-            let _ = std::fs::remove_file(&opts.target_disk);
-            Err(e)
-        }
-    }
+    result
 }
 
 /// Write metadata to disk image for caching purposes
 fn write_disk_metadata(
-    source_image: &str,
     target_disk: &Utf8PathBuf,
-    install_options: &InstallOptions,
+    metadata: &DiskImageMetadata,
     format: &Format,
 ) -> Result<()> {
     // Note: xattrs work on regular files including raw and qcow2 images
     // as they're stored in the filesystem metadata, not inside the disk image
 
-    // Get the image digest
-    let inspect = images::inspect(source_image)?;
-    let digest = inspect.digest.to_string();
-
-    // Prepare metadata using the new helper method
-    let metadata = DiskImageMetadata::from(install_options, &digest, source_image);
-
     // Write metadata using rustix fsetxattr
     let file = std::fs::OpenOptions::new()
         .write(true)
@@ -731,4 +863,51 @@ mod tests {
             ["--read-only", "--env=FOO=bar"]
         );
     }
+
+    #[test]
+    fn test_via_iso_requires_kickstart() {
+        use clap::Parser;
+
+        let opts = ToDiskOpts::try_parse_from([
+            "bcvk",
+            "--via-iso=boot.iso",
+            "--kickstart=ks.cfg",
+            "test:latest",
+            "/tmp/test.img",
+        ])
+        .unwrap();
+        assert_eq!(
+            opts.additional.via_iso.as_ref().map(|p| p.as_str()),
+            Some("boot.iso")
+        );
+        assert_eq!(
+            opts.additional.kickstart.as_ref().map(|p| p.as_str()),
+            Some("ks.cfg")
+        );
+
+        for arg in ["--via-iso=boot.iso", "--kickstart=ks.cfg"] {
+            assert!(
+                ToDiskOpts::try_parse_from(["bcvk", arg, "test:latest", "/tmp/test.img"]).is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_target_imgref() -> Result<()> {
+        use clap::Parser;
+
+        let mut opts = ToDiskOpts::try_parse_from(["bcvk", "test:latest", "/tmp/test.img"])?;
+        assert_eq!(opts.target_imgref(), "test:latest");
+        let script = opts.generate_bootc_install_command(1 << 30)?.pop().unwrap();
+        assert!(!script.contains("--target-imgref"));
+
+        // A local copy for a foreign architecture
+        opts.source_image = "test:latest-aarch64".to_string();
+        opts.additional.target_imgref = Some("test:latest".to_string());
+        assert_eq!(opts.target_imgref(), "test:latest");
+        let script = opts.generate_bootc_install_command(1 << 30)?.pop().unwrap();
+        assert!(script.contains("containers-storage:test:latest-aarch64"));
+        assert!(script.contains("--target-imgref test:latest "));
+        Ok(())
+    }
 }
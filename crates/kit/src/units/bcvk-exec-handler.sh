@@ -0,0 +1,50 @@
+# Handler of a `bcvk ephemeral exec` connection, run by bcvk-exec@.service
+# with the connection as stdin and stdout; see ephemeral_exec.rs for the protocol.
+set -u
+WAIT_STEPS=300 # how long the two connections of a session wait for each other, in 0.1s
+
+expected=$(cat "$CREDENTIALS_DIRECTORY/bcvk.exec-token") && test -n "$expected" || exit 1
+IFS= read -r token && test "$token" = "$expected" || { echo "Rejecting exec connection without a valid token" >&2; exit 1; }
+IFS=' ' read -r kind session nonce || exit 1
+case "$session" in
+    -) fifo= ;;
+    "" | *[!0-9a-f]*) echo "Invalid exec session '$session'" >&2; exit 1 ;;
+    *) fifo=/run/bcvk-exec/$session ;;
+esac
+
+wait_for() {
+    i=0
+    until test -e "$1"; do
+        test $((i += 1)) -le $WAIT_STEPS || { echo "Timed out waiting for $1" >&2; exit 1; }
+        sleep 0.1
+    done
+}
+
+case "$kind" in
+    stderr)
+        test -n "$fifo" || exit 1
+        mkdir -p -m 0700 /run/bcvk-exec && mkfifo -m 0600 "$fifo" || exit 1
+        trap 'rm -f "$fifo" "$fifo.started"' EXIT
+        # Opening read-write does not block, and keeps a writer until the
+        # command has opened the FIFO, so reading it only ends with the command
+        exec 3<>"$fifo"
+        wait_for "$fifo.started"
+        exec 4<"$fifo" 3<&- && cat <&4 ;;
+    exec)
+        IFS= read -r count || exit 1
+        case "$count" in "" | *[!0-9]*) echo "Invalid argument count '$count'" >&2; exit 1 ;; esac
+        set --
+        while [ $# -lt "$count" ]; do # base64 encoded, one per line; "." keeps trailing newlines
+            IFS= read -r arg && arg=$(printf '%s' "$arg" | base64 -d && echo .) || exit 1
+            set -- "$@" "${arg%.}"
+        done
+        if [ -n "$fifo" ]; then
+            wait_for "$fifo"
+            exec 3>"$fifo" && : >"$fifo.started" || exit 1
+            systemd-run "$@" 2>&3; status=$?; exec 3>&-
+        else
+            systemd-run "$@" 2>&1; status=$?
+        fi
+        echo "$nonce:$status" ;;
+    *) echo "Invalid exec request '$kind'" >&2; exit 1 ;;
+esac
@@ -0,0 +1,39 @@
+# Configure the NIC of a bcvk internal network and keep /etc/hosts up to date
+# with the hosts file shared by bcvk; run by bcvk-internal-network.service.
+# BCVK_NET_MAC and BCVK_NET_ADDRESS are set in a drop-in by bcvk.
+set -u
+SHARED_HOSTS=/run/virtiofs-mnt-bcvk-network/hosts
+WAIT_STEPS=100 # how long to wait for the interface to appear, in 0.1s
+SYNC_INTERVAL=2 # how often to look for VMs joining or leaving, in s
+
+# Keep NetworkManager from running DHCP on the interface
+mkdir -p /run/NetworkManager/conf.d &&
+    printf '[keyfile]\nunmanaged-devices=mac:%s\n' "$BCVK_NET_MAC" \
+        >/run/NetworkManager/conf.d/90-bcvk-internal-network.conf || exit 1
+
+# The interface is found by its MAC address, as its name depends on the image
+i=0
+until dev=$(grep -lxF "$BCVK_NET_MAC" /sys/class/net/*/address); do
+    test $((i += 1)) -le $WAIT_STEPS || { echo "No interface with address $BCVK_NET_MAC" >&2; exit 1; }
+    sleep 0.1
+done
+dev=${dev%/address}
+dev=${dev##*/}
+ip link set "$dev" up && ip address add "$BCVK_NET_ADDRESS" dev "$dev" || exit 1
+
+# The entries of the network are added to the image's own hosts file, which
+# is kept as a copy
+state=/run/bcvk-internal-network
+mkdir -p "$state" || exit 1
+test -e "$state/hosts.orig" || cp /etc/hosts "$state/hosts.orig" 2>/dev/null || : >"$state/hosts.orig" || exit 1
+sync_hosts() {
+    cat "$state/hosts.orig" "$SHARED_HOSTS" >"$state/hosts" || return 1
+    # Written in place to keep the file's SELinux label; it is small enough
+    # for readers not to see partial updates in practice
+    cmp -s "$state/hosts" /etc/hosts || cat "$state/hosts" >/etc/hosts
+}
+sync_hosts || exit 1
+systemd-notify --ready
+while sleep $SYNC_INTERVAL; do
+    sync_hosts || echo "Failed to update /etc/hosts" >&2
+done
@@ -74,6 +74,10 @@ pub(crate) struct EphemeralRunOpts {
     ro_bind: Option<Vec<String>>,
     /// Disk files as virtio-blk devices (`FILE[:NAME]`).
     mount_disk_files: Option<Vec<String>>,
+    /// Publish guest ports on the host (`HOST_PORT:GUEST_PORT`).
+    port: Option<Vec<String>>,
+    /// Join the private network shared by VMs with the same name; requires `name`.
+    internal_network: Option<String>,
     /// Additional kernel command line arguments.
     kargs: Option<Vec<String>>,
     /// Allocate swap of the given size (e.g. `"1G"`).
@@ -102,6 +106,46 @@ pub(crate) struct GetSshConnectionInfoReply {
     port: u16,
 }
 
+/// Reply for the ephemeral `Stop` method.
+#[derive(Debug, Clone, Serialize, Deserialize, zlink::introspect::Type)]
+pub(crate) struct StopReply {
+    /// Whether the VM was running when the request was made.
+    was_running: bool,
+    /// Whether the guest powered off by itself rather than being killed.
+    graceful: bool,
+}
+
+impl From<crate::ephemeral::StopOutcome> for StopReply {
+    fn from(outcome: crate::ephemeral::StopOutcome) -> Self {
+        use crate::ephemeral::StopOutcome;
+        Self {
+            was_running: outcome != StopOutcome::NotRunning,
+            graceful: outcome == StopOutcome::PoweredOff,
+        }
+    }
+}
+
+/// Reply for the ephemeral `Status` method.
+#[derive(Debug, Clone, Serialize, Deserialize, zlink::introspect::Type)]
+pub(crate) struct StatusReply {
+    /// Container state as reported by podman (e.g. `running`, `exited`).
+    state: String,
+    /// Why the guest failed to boot, if it did.
+    failure: Option<GuestFailureInfo>,
+}
+
+/// A guest failure detected by the supervisor.
+#[derive(Debug, Clone, Serialize, Deserialize, zlink::introspect::Type)]
+pub(crate) struct GuestFailureInfo {
+    /// `kernel-panic`, `kernel-oops` or `emergency-shell`.
+    kind: String,
+    /// Human-readable description.
+    reason: String,
+    /// The last lines of guest output before the failure; empty once the
+    /// container exited.
+    console: Vec<String>,
+}
+
 /// Reply for the `io.bootc.vk.todisk` `ToDisk` method.
 #[derive(Debug, Clone, Serialize, Deserialize, zlink::introspect::Type)]
 pub(crate) struct ToDiskReply {
@@ -217,6 +261,37 @@ impl BcvkService {
         Ok(PsReply { container_ids })
     }
 
+    /// Get the state of an ephemeral VM, including whether its guest
+    /// failed to boot (kernel panic, oops or emergency shell).
+    #[zlink(interface = "io.bootc.vk.ephemeral")]
+    async fn status(&self, container_id: String) -> Result<StatusReply, EphemeralError> {
+        let (state, failure) = tokio::task::spawn_blocking(move || {
+            let containers = crate::ephemeral::list_ephemeral_containers()?;
+            let container = containers
+                .into_iter()
+                .find(|c| c.id.starts_with(&container_id) || c.names.contains(&container_id))
+                .ok_or_else(|| {
+                    color_eyre::eyre::eyre!("No ephemeral VM container {container_id}")
+                })?;
+            let failure = crate::ephemeral::container_guest_failure(&container);
+            color_eyre::Result::<_>::Ok((container.state, failure))
+        })
+        .await
+        .map_err(ephemeral_join_err)?
+        .map_err(|e| EphemeralError::PodmanError {
+            message: e.to_string(),
+        })?;
+
+        Ok(StatusReply {
+            state,
+            failure: failure.map(|f| GuestFailureInfo {
+                kind: f.kind.as_str().to_owned(),
+                reason: f.reason,
+                console: f.console,
+            }),
+        })
+    }
+
     /// Launch an ephemeral VM in detached mode.
     ///
     /// Always runs detached. The returned container ID can be used with
@@ -243,6 +318,12 @@ impl BcvkService {
                     })
                 })
                 .transpose()?;
+            let port_mappings = opts
+                .port
+                .unwrap_or_default()
+                .iter()
+                .map(|s| crate::libvirt::run::PortMapping::from_str(s))
+                .collect::<color_eyre::Result<Vec<_>>>()?;
 
             let run_opts = RunEphemeralOpts {
                 image,
@@ -274,9 +355,17 @@ impl BcvkService {
                 bind_storage_ro: false,
                 add_swap: opts.add_swap,
                 mount_disk_files: opts.mount_disk_files.unwrap_or_default(),
+                port_mappings,
+                internal_network: opts.internal_network,
+                network_aliases: Vec::new(),
                 kernel_args: opts.kargs.unwrap_or_default(),
+                boot: Default::default(),
                 ignition_config: None,
+                cloud_init: Default::default(),
+                boot_profile: None,
                 host_dns_servers: None,
+                install_iso: None,
+                image_store: None,
             };
 
             crate::run_ephemeral::run_detached(run_opts)
@@ -310,15 +399,40 @@ impl BcvkService {
     ) -> Result<GetSshConnectionInfoReply, EphemeralError> {
         // These are fixed conventions in bcvk's SSH setup.
         // The key path is inside the container (under the tmproot bind mount).
-        let key_path = format!("/run/tmproot{}/ssh", crate::CONTAINER_STATEDIR);
-
         Ok(GetSshConnectionInfoReply {
             container_id,
-            key_path,
-            user: "root".to_string(),
-            host: "127.0.0.1".to_string(),
-            port: 2222,
+            key_path: crate::ssh::ephemeral_ssh_key_path().into_string(),
+            user: crate::ssh::EPHEMERAL_SSH_USER.to_string(),
+            host: crate::ssh::EPHEMERAL_SSH_HOST.to_string(),
+            port: crate::ssh::EPHEMERAL_SSH_PORT,
+        })
+    }
+
+    /// Stop an ephemeral VM, asking the guest to power off first.
+    ///
+    /// Waits up to `timeout` seconds (default 60) for the guest to power
+    /// off before killing it. With `force`, the VM is killed immediately.
+    /// The container itself is not removed unless it was started with `rm`.
+    #[zlink(interface = "io.bootc.vk.ephemeral")]
+    async fn stop(
+        &self,
+        container_id: String,
+        timeout: Option<u32>,
+        force: Option<bool>,
+    ) -> Result<StopReply, EphemeralError> {
+        let timeout = timeout.unwrap_or(crate::ephemeral::DEFAULT_STOP_TIMEOUT);
+        let timeout = std::time::Duration::from_secs(timeout.into());
+        let force = force.unwrap_or(false);
+        let outcome = tokio::task::spawn_blocking(move || {
+            crate::ephemeral::stop_ephemeral_vm(&container_id, timeout, force)
         })
+        .await
+        .map_err(ephemeral_join_err)?
+        .map_err(|e| EphemeralError::PodmanError {
+            message: e.to_string(),
+        })?;
+
+        Ok(outcome.into())
     }
 
     /// Create a bootable disk image from a container image.
@@ -419,6 +533,12 @@ trait EphemeralProxy {
     /// List ephemeral VM container IDs.
     async fn ps(&mut self) -> zlink::Result<Result<PsReply, EphemeralError>>;
 
+    /// Get the state of an ephemeral VM and any guest failure.
+    async fn status(
+        &mut self,
+        container_id: String,
+    ) -> zlink::Result<Result<StatusReply, EphemeralError>>;
+
     /// Launch an ephemeral VM in detached mode.
     async fn run(
         &mut self,
@@ -431,6 +551,14 @@ trait EphemeralProxy {
         &mut self,
         container_id: String,
     ) -> zlink::Result<Result<GetSshConnectionInfoReply, EphemeralError>>;
+
+    /// Stop an ephemeral VM, asking the guest to power off first.
+    async fn stop(
+        &mut self,
+        container_id: String,
+        timeout: Option<u32>,
+        force: Option<bool>,
+    ) -> zlink::Result<Result<StopReply, EphemeralError>>;
 }
 
 /// Proxy for calling todisk methods on a remote bcvk service.
@@ -538,7 +666,8 @@ pub(crate) async fn try_serve_varlink() -> color_eyre::Result<bool> {
 
 #[cfg(test)]
 mod tests {
-    use super::VARLINK_API_VERSION;
+    use super::{StopReply, VARLINK_API_VERSION};
+    use crate::ephemeral::StopOutcome;
 
     #[test]
     fn varlink_version_is_consistent() {
@@ -550,4 +679,17 @@ mod tests {
             "VARLINK_API_VERSION must match the #[zlink::service] version attribute"
         );
     }
+
+    #[test]
+    fn stop_reply_from_outcome() {
+        for (outcome, was_running, graceful) in [
+            (StopOutcome::NotRunning, false, false),
+            (StopOutcome::PoweredOff, true, true),
+            (StopOutcome::Killed, true, false),
+        ] {
+            let reply = StopReply::from(outcome);
+            assert_eq!(reply.was_running, was_running, "{outcome:?}");
+            assert_eq!(reply.graceful, graceful, "{outcome:?}");
+        }
+    }
 }
@@ -0,0 +1,154 @@
+//! Liveness limits for unattended ephemeral VMs
+//!
+//! With `--watchdog`, the VM gets a hardware watchdog (i6300esb, or diag288
+//! on s390x) and the guest's systemd is told to ping it, via a unit injected
+//! as a credential; a guest that stops responding is powered off by QEMU.
+//! With `--max-runtime`, the supervisor powers off the VM once the deadline
+//! passes. Either way the reason is recorded as a [`FailureKind`] in the
+//! supervisor status and bcvk exits with its exit code.
+//!
+//! [`FailureKind`]: crate::supervisor_status::FailureKind
+
+use std::time::Duration;
+
+use clap::Parser;
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::arch::Arch;
+
+/// The guest unit setting systemd's `RuntimeWatchdogSec` to 30s
+const WATCHDOG_UNIT: &str = include_str!("units/bcvk-watchdog.service");
+
+/// Options bounding how long a VM may run or hang
+#[derive(Parser, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WatchdogOpts {
+    /// Attach a hardware watchdog pinged by the guest's systemd; the VM is powered off if the guest stops responding
+    #[clap(long)]
+    pub watchdog: bool,
+
+    /// Power off the VM after it ran for DURATION (e.g. 90s, 30m, 1h30m or "1h 30m")
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub max_runtime: Option<Duration>,
+}
+
+impl WatchdogOpts {
+    /// Check that the guest architecture, as resolved by `guest_arch()`, has
+    /// a watchdog device
+    pub(crate) fn validate(&self, arch: Option<Arch>) -> Result<()> {
+        if !self.watchdog {
+            return Ok(());
+        }
+        match arch {
+            Some(Arch::X86_64 | Arch::Aarch64 | Arch::S390x) => Ok(()),
+            Some(arch) => Err(eyre!("--watchdog is not supported for {arch} guests")),
+            None => Err(eyre!(
+                "--watchdog is not supported for guests of this host's architecture"
+            )),
+        }
+    }
+
+    /// SMBIOS credentials injecting the unit that enables the watchdog
+    pub(crate) fn smbios_credentials(&self) -> Vec<String> {
+        if !self.watchdog {
+            return Vec::new();
+        }
+        let encoded_unit = data_encoding::BASE64.encode(WATCHDOG_UNIT.as_bytes());
+        let dropin = "[Unit]\nWants=bcvk-watchdog.service\n";
+        let encoded_dropin = data_encoding::BASE64.encode(dropin.as_bytes());
+        vec![
+            format!(
+                "io.systemd.credential.binary:systemd.extra-unit.bcvk-watchdog.service={encoded_unit}"
+            ),
+            format!(
+                "io.systemd.credential.binary:systemd.unit-dropin.multi-user.target~bcvk-watchdog={encoded_dropin}"
+            ),
+        ]
+    }
+}
+
+/// Parse a duration such as `90`, `90s`, `30m`, `1h30m` or, like systemd time
+/// spans, `1h 30m`; plain numbers are seconds.
+pub(crate) fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let total = match s.parse::<u64>() {
+        Ok(secs) => secs,
+        Err(_) => s.split_whitespace().try_fold(0u64, |total, part| {
+            total
+                .checked_add(parse_duration_part(s, part)?)
+                .ok_or_else(|| eyre!("Duration {s:?} is too large"))
+        })?,
+    };
+    if total == 0 {
+        return Err(eyre!("Duration {s:?} must be positive"));
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// Parse `part` of the duration `s`, e.g. `1h30m`, into seconds.
+fn parse_duration_part(s: &str, mut part: &str) -> Result<u64> {
+    let mut total = 0u64;
+    while !part.is_empty() {
+        let split = part
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| eyre!("Missing unit in duration {s:?}"))?;
+        let (n, tail) = part.split_at(split);
+        let n: u64 = n.parse().map_err(|_| eyre!("Invalid duration {s:?}"))?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let scale = match unit {
+            "s" | "sec" => 1,
+            "m" | "min" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            other => return Err(eyre!("Invalid time unit {other:?} in {s:?}")),
+        };
+        total = n
+            .checked_mul(scale)
+            .and_then(|v| total.checked_add(v))
+            .ok_or_else(|| eyre!("Duration {s:?} is too large"))?;
+        part = tail;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        for (s, secs) in [
+            ("90", 90),
+            ("90s", 90),
+            ("30m", 30 * 60),
+            ("1h30m", 90 * 60),
+            ("1h 30m", 90 * 60),
+            (" 1h  30min 5sec ", 90 * 60 + 5),
+            ("2d", 2 * 24 * 60 * 60),
+        ] {
+            assert_eq!(parse_duration(s).unwrap().as_secs(), secs, "{s:?}");
+        }
+        for s in ["", "0", "0s", "10x", "h", "5m3", "1h 30", "90 30", "1 h"] {
+            assert!(parse_duration(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn test_watchdog_opts() {
+        let opts =
+            WatchdogOpts::try_parse_from(["test", "--watchdog", "--max-runtime=10m"]).unwrap();
+        assert_eq!(opts.max_runtime, Some(Duration::from_secs(600)));
+        assert_eq!(opts.smbios_credentials().len(), 2);
+        assert!(opts.validate(Some(Arch::X86_64)).is_ok());
+        assert!(opts.validate(Some(Arch::Aarch64)).is_ok());
+        assert!(opts.validate(Some(Arch::S390x)).is_ok());
+        assert!(opts.validate(Some(Arch::Ppc64le)).is_err());
+        assert!(opts.validate(None).is_err());
+        assert!(WatchdogOpts::default().validate(None).is_ok());
+        assert!(WatchdogOpts::default().smbios_credentials().is_empty());
+    }
+}